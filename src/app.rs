@@ -1,19 +1,22 @@
-use crate::backup::BackupManager;
-use crate::buffer::{Change, Position};
+use crate::backup::{BackupManager, LocalHistory, Snapshot, SwapManager};
+use crate::buffer::byte_buffer::{looks_binary, parse_search_pattern};
+use crate::buffer::{display, Change, FileEncoding, Indentation, Position, TextBuffer};
 use crate::config::Config;
 use crate::editor::movement::Movement;
 use crate::editor::state::Cursor;
 use crate::logger;
 use crate::ai::{AiManager, AiResponse};
-use crate::lsp::{DiagnosticsStore, LspManager, LspResponse};
-use crate::render::{BufferView, FilePicker, StatusBar, Terminal};
-use crate::search::{FileSearch, FileSearchResult};
-use crate::syntax::{HighlightSpan, Highlighter, SupportedLanguage};
-use crate::workspace::{FileTree, Workspace};
+use crate::lsp::{utf16_to_char, DiagnosticsStore, HoverPopup, LspManager, LspResponse, TextChange};
+use crate::diff::DiffView;
+use crate::render::{BufferView, DiffHighlights, DiffRenderer, FileHistoryPanel, FilePicker, HexView, StatusBar, Terminal, ThemePickerPanel, UndoTreePanel};
+use crate::search::{find_in_lines, FileSearch, FileSearchResult};
+use crate::language::LanguageRegistry;
+use crate::syntax::{HighlightSpan, Highlighter};
+use crate::workspace::{FileFollower, FileTree, FileWatcher, FollowUpdate, HexBuffer, Workspace};
 use anyhow::Result;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind, MouseButton};
 use regex::RegexBuilder;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
@@ -38,6 +41,21 @@ pub enum AppMode {
     ReplaceConfirm,     // Confirming each replacement
     Completion,         // Showing completion suggestions
     SaveAsPrompt,       // Prompting for filename to save as
+    ConfirmReloadFile,  // Modified buffer changed on disk: keep mine / take theirs
+    ConfirmRecoverSwap, // Swap file with unsaved changes found for an opened file
+    DiffView,           // Full-screen side-by-side diff
+    FileHistory,        // Browsing the local history of the current file
+    EncodingPrompt,     // Prompting for an encoding to reopen with or convert to
+    HexSearch,          // Searching a hex buffer for bytes or text
+    IndentationPrompt,  // Prompting for the indentation of the active buffer
+    UndoTree,           // Browsing the undo tree of the current buffer
+    LanguagePrompt,     // Prompting for the language of the active buffer
+    ThemePicker,        // Choosing a color theme, previewed while browsing
+    Hover,              // Scrolling the hover documentation of the symbol at the cursor
+    References,         // Browsing the references to a symbol
+    RenamePrompt,       // Prompting for the new name of the symbol at the cursor
+    CodeActions,        // Choosing a code action for the cursor or selection
+    ConfirmWorkspaceEdit, // Confirming edits to files that aren't open
 }
 
 #[derive(Debug, Clone)]
@@ -86,6 +104,75 @@ pub enum CommandAction {
     ToggleIndentGuides,
     ToggleBackups,
     AddCursorsToLineEnds,
+    FileHistory,
+    ReopenWithEncoding,
+    ConvertEncoding,
+    ToggleFollow,
+    SetIndentation,
+    UndoTree,
+    SetBufferLanguage,
+    SelectTheme,
+    ShowHover,
+    FindReferences,
+    RenameSymbol,
+    CodeActions,
+}
+
+/// What the encoding prompt does with the chosen encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EncodingAction {
+    /// Re-read the file from disk with the encoding
+    Reopen,
+    /// Keep the text and write it with the encoding on the next save
+    Convert,
+}
+
+/// Why the in-editor diff view was opened, which decides the actions it offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffPurpose {
+    /// Disk content (left) against a modified buffer (right)
+    ExternalChange(crate::workspace::BufferId),
+    /// Buffer content (left) against the content of its swap file (right)
+    SwapRecovery(crate::workspace::BufferId),
+    /// A local history snapshot (left) against the buffer (right)
+    FileHistory(crate::workspace::BufferId),
+}
+
+/// A hover request waiting for the language server's answer
+#[derive(Debug, Clone, Copy)]
+struct PendingHover {
+    buffer_id: crate::workspace::BufferId,
+    position: Position,
+    /// Opened with F1, so the popup takes the keys for scrolling (otherwise
+    /// it was opened by resting the cursor and closes at the next key)
+    focused: bool,
+}
+
+/// An edit from the language server waiting for edits to files that aren't
+/// open to be confirmed
+#[derive(Debug, Clone, Default)]
+struct PendingWorkspaceEdit {
+    /// What the edit does, for messages
+    description: String,
+    edit: crate::lsp::WorkspaceEdit,
+    /// `workspace/applyEdit` request to answer once the edit is applied or
+    /// cancelled: language id of the server and request id
+    reply: Option<(String, serde_json::Value)>,
+    /// Command of a code action to run after its edit, with the buffer the
+    /// action was for
+    command: Option<(crate::workspace::BufferId, crate::lsp::LspCommand)>,
+}
+
+/// How long editing must pause before swap files are written
+const SWAP_IDLE_DELAY: Duration = Duration::from_secs(2);
+/// Typing pause after which the project is searched for the new pattern
+const PROJECT_SEARCH_DELAY: Duration = Duration::from_millis(200);
+
+/// State of the in-editor diff view
+struct DiffSession {
+    view: DiffView,
+    highlights: DiffHighlights,
+    purpose: DiffPurpose,
 }
 
 pub struct App {
@@ -112,11 +199,6 @@ pub struct App {
     file_picker_scroll_offset: usize,
     // Track if we've logged highlighting info for this file
     logged_highlighting: bool,
-    // Cache highlight spans to avoid re-parsing every frame
-    cached_highlights: Option<Vec<HighlightSpan>>,
-    cached_text_hash: u64,
-    // Per-buffer highlight cache for split mode
-    buffer_highlight_cache: std::collections::HashMap<crate::workspace::BufferId, (u64, Vec<HighlightSpan>)>,
     // Search state
     search_pattern: String,
     search_start_pos: Option<Position>,
@@ -133,6 +215,7 @@ pub struct App {
     save_as_filename: String,
     // LSP state
     lsp_manager: Option<LspManager>,
+    lsp_synced_revisions: std::collections::HashMap<crate::workspace::BufferId, u64>, // Text revision each buffer was last synced at
     lsp_receiver: Option<mpsc::UnboundedReceiver<LspResponse>>,
     diagnostics_store: DiagnosticsStore,
     navigation_history: crate::lsp::NavigationHistory,
@@ -142,11 +225,23 @@ pub struct App {
     ai_suggestion: Option<String>,
     ai_last_keystroke: Option<Instant>,
     ai_pending_request: bool,
+    ai_request_revision: Option<(crate::workspace::BufferId, u64)>, // Buffer and text revision of the pending request
+    edit_revisions: std::collections::HashMap<crate::workspace::BufferId, u64>, // Revision up to which diagnostics, marks and AI followed each buffer's edits
     ai_completions_enabled: bool,
     // Completion state
     completion_items: Vec<crate::lsp::CompletionItem>,
     completion_selected: usize,
     completion_scroll_offset: usize,
+    // Hover documentation state
+    hover_lines: Vec<crate::lsp::HoverLine>,
+    hover_scroll: usize,
+    hover_request: Option<PendingHover>,
+    hover_delay: Option<Duration>, // Idle time before documentation shows by itself (None = only on F1)
+    hover_idle_since: Option<Instant>,
+    hover_idle_position: Option<(crate::workspace::BufferId, Position)>, // Last position documentation was shown for by itself
+    // Signature help state
+    signature_help: Option<(crate::workspace::BufferId, crate::lsp::Signature)>,
+    signature_help_request: Option<(crate::workspace::BufferId, Position)>, // Cursor position of the latest request
     // Command panel state
     command_panel_pattern: String,
     command_panel_results: Vec<Command>,
@@ -157,6 +252,25 @@ pub struct App {
     project_search_results: Vec<ProjectSearchResult>,
     project_search_selected: usize,
     project_search_scroll_offset: usize,
+    project_search_last_keystroke: Option<Instant>, // Pattern changed and not searched yet
+    // References panel state
+    references_symbol: String,
+    references_results: Vec<ProjectSearchResult>,
+    references_locations: Vec<crate::lsp::Location>, // Where each result is, for jumping to it
+    references_selected: usize,
+    references_scroll_offset: usize,
+    // Rename state
+    rename_input: String,
+    rename_request: Option<(crate::workspace::BufferId, Position)>, // Position of the symbol being renamed
+    pending_workspace_edit: Option<PendingWorkspaceEdit>,
+    // Code actions state
+    code_actions: Vec<crate::lsp::CodeAction>,
+    code_action_selected: usize,
+    code_action_scroll_offset: usize,
+    code_action_request: Option<(crate::workspace::BufferId, usize)>, // Buffer and line of the latest request for the picker
+    code_action_bulb_request: Option<(crate::workspace::BufferId, usize)>, // Buffer and line of the latest lightbulb check
+    code_action_checked: Option<(crate::workspace::BufferId, usize)>, // Last cursor line checked for the lightbulb
+    code_action_bulb: Option<(crate::workspace::BufferId, usize)>, // Line the lightbulb is on
     // Position marks state
     position_marks: Vec<PositionMark>,
     current_mark_index: usize,
@@ -168,24 +282,60 @@ pub struct App {
     execute_sudo_save_on_render: bool,
     // Mouse drag selection state
     mouse_drag_start: Option<Position>,
+    // External file change state
+    file_watcher: FileWatcher,
+    pending_disk_changes: Vec<crate::workspace::BufferId>,
+    diff_session: Option<DiffSession>,
+    // Crash recovery state
+    swap_manager: SwapManager,
+    swap_checked: std::collections::HashSet<crate::workspace::BufferId>,
+    swap_last_edit: Option<Instant>,
+    pending_swap_recoveries: Vec<crate::workspace::BufferId>,
+    deferred_swap_recoveries: std::collections::HashSet<crate::workspace::BufferId>, // "Decide later": their crash leftovers must not be overwritten
+    // Local history state (for FileHistory mode)
+    local_history: LocalHistory,
+    file_history_buffer: Option<crate::workspace::BufferId>,
+    file_history_snapshots: Vec<Snapshot>,
+    file_history_selected: usize,
+    file_history_scroll_offset: usize,
+    // Encoding prompt state
+    encoding_action: EncodingAction,
+    encoding_input: String,
+    // Buffers following their growing file (Toggle Follow Mode)
+    followers: std::collections::HashMap<crate::workspace::BufferId, FileFollower>,
+    // Hex buffer search state (for HexSearch mode)
+    hex_search_pattern: String,
+    hex_search_reverse: bool,
+    hex_search_text: bool, // Search pattern is text rather than hex bytes
+    hex_search_match: Option<(usize, usize)>, // (offset, length)
+    // Indentation prompt input (for IndentationPrompt mode)
+    indentation_input: String,
+    // Language prompt input (for LanguagePrompt mode)
+    language_input: String,
+    // Undo tree browser state (for UndoTree mode)
+    undo_tree_buffer: Option<crate::workspace::BufferId>,
+    undo_tree_origin: usize, // State to go back to on cancel
+    undo_tree_selected: usize,
+    undo_tree_scroll_offset: usize,
+    // Theme picker state (for ThemePicker mode)
+    theme_picker_names: Vec<String>,
+    theme_picker_origin: Option<crate::syntax::Theme>, // Theme to go back to on cancel
+    theme_picker_selected: usize,
+    theme_picker_scroll_offset: usize,
 }
 
 impl App {
-    /// Simple hash function for text
-    fn simple_hash(text: &str) -> u64 {
-        let mut hash: u64 = 5381;
-        for byte in text.bytes() {
-            hash = hash.wrapping_mul(33).wrapping_add(byte as u64);
-        }
-        hash
-    }
-
     /// Create a new app instance
     pub fn new() -> Result<Self> {
         let (width, height) = crossterm::terminal::size()?;
         // Tab bar (1) + Path bar (1) + Status bar (1) = 3 lines to subtract
         let content_height = height.saturating_sub(3);
+        let config = Config::load().unwrap_or_default();
         let mut workspace = Workspace::new(width, content_height);
+        workspace.set_large_file_config(config.large_file.clone());
+        workspace.set_undo_config(config.undo.clone());
+        workspace.set_languages_config(&config.languages);
+        workspace.set_tab_width(config.ui.tab_width);
 
         // Create an empty buffer
         workspace.new_buffer();
@@ -202,12 +352,9 @@ impl App {
             backup_manager: BackupManager::new(),
             file_tree: None,
             file_search: FileSearch::new(),
-            highlighter: Highlighter::new(),
+            highlighter: Highlighter::with_theme(Self::load_theme(&config.ui.theme)),
             mode: AppMode::Normal,
             logged_highlighting: false,
-            cached_highlights: None,
-            cached_text_hash: 0,
-            buffer_highlight_cache: std::collections::HashMap::new(),
             message: None,
             show_line_numbers: true,
             enable_syntax_highlighting: true,
@@ -231,6 +378,7 @@ impl App {
             replace_count: 0,
             save_as_filename: String::new(),
             lsp_manager: None,
+            lsp_synced_revisions: std::collections::HashMap::new(),
             lsp_receiver: None,
             diagnostics_store: DiagnosticsStore::new(),
             navigation_history: crate::lsp::NavigationHistory::new(),
@@ -239,10 +387,20 @@ impl App {
             ai_suggestion: None,
             ai_last_keystroke: None,
             ai_pending_request: false,
+            ai_request_revision: None,
+            edit_revisions: std::collections::HashMap::new(),
             ai_completions_enabled: false,
             completion_items: Vec::new(),
             completion_selected: 0,
             completion_scroll_offset: 0,
+            hover_lines: Vec::new(),
+            hover_scroll: 0,
+            hover_request: None,
+            hover_delay: (config.lsp.hover_delay_ms > 0).then(|| Duration::from_millis(config.lsp.hover_delay_ms)),
+            hover_idle_since: None,
+            hover_idle_position: None,
+            signature_help: None,
+            signature_help_request: None,
             command_panel_pattern: String::new(),
             command_panel_results: Vec::new(),
             command_panel_selected: 0,
@@ -251,6 +409,22 @@ impl App {
             project_search_results: Vec::new(),
             project_search_selected: 0,
             project_search_scroll_offset: 0,
+            project_search_last_keystroke: None,
+            references_symbol: String::new(),
+            references_results: Vec::new(),
+            references_locations: Vec::new(),
+            references_selected: 0,
+            references_scroll_offset: 0,
+            rename_input: String::new(),
+            rename_request: None,
+            pending_workspace_edit: None,
+            code_actions: Vec::new(),
+            code_action_selected: 0,
+            code_action_scroll_offset: 0,
+            code_action_request: None,
+            code_action_bulb_request: None,
+            code_action_checked: None,
+            code_action_bulb: None,
             position_marks: Vec::new(),
             current_mark_index: 0,
             pending_close_buffer_id: None,
@@ -258,6 +432,36 @@ impl App {
             pending_sudo_save_content: None,
             execute_sudo_save_on_render: false,
             mouse_drag_start: None,
+            file_watcher: FileWatcher::new(),
+            pending_disk_changes: Vec::new(),
+            diff_session: None,
+            swap_manager: SwapManager::new(),
+            swap_checked: std::collections::HashSet::new(),
+            swap_last_edit: None,
+            pending_swap_recoveries: Vec::new(),
+            deferred_swap_recoveries: std::collections::HashSet::new(),
+            local_history: LocalHistory::new(config.history.clone()),
+            file_history_buffer: None,
+            file_history_snapshots: Vec::new(),
+            file_history_selected: 0,
+            file_history_scroll_offset: 0,
+            encoding_action: EncodingAction::Reopen,
+            encoding_input: String::new(),
+            followers: std::collections::HashMap::new(),
+            hex_search_pattern: String::new(),
+            hex_search_reverse: false,
+            hex_search_text: false,
+            hex_search_match: None,
+            indentation_input: String::new(),
+            language_input: String::new(),
+            undo_tree_buffer: None,
+            undo_tree_origin: 0,
+            undo_tree_selected: 0,
+            undo_tree_scroll_offset: 0,
+            theme_picker_names: Vec::new(),
+            theme_picker_origin: None,
+            theme_picker_selected: 0,
+            theme_picker_scroll_offset: 0,
         })
     }
 
@@ -266,13 +470,19 @@ impl App {
         let (width, height) = crossterm::terminal::size()?;
         // Tab bar (1) + Path bar (1) + Status bar (1) = 3 lines to subtract
         let content_height = height.saturating_sub(3);
+        let config = Config::load().unwrap_or_default();
         let mut workspace = Workspace::new(width, content_height);
+        workspace.set_large_file_config(config.large_file.clone());
+        workspace.set_undo_config(config.undo.clone());
+        workspace.set_languages_config(&config.languages);
+        workspace.set_tab_width(config.ui.tab_width);
 
         // Determine if it's a file or directory
         if path.is_file() {
             workspace.open_file(path)?; // Result ignored in initialization
         } else if path.is_dir() {
             // Open directory as project
+            workspace.set_project_root(path.clone());
             let mut file_tree = FileTree::new(path.clone());
             file_tree.scan()?;
 
@@ -290,7 +500,18 @@ impl App {
                 if let Some(&active_id) = buffer_ids.get(session.active_buffer_index) {
                     workspace.set_active_buffer(active_id);
                 }
-            } else {
+            }
+
+            // Reopen files with unsaved changes left behind by a crash;
+            // recovery is offered once the app is running
+            let swap_manager = SwapManager::new();
+            for file_path in swap_manager.recoverable_in(&path) {
+                if let Err(e) = workspace.open_file(file_path.clone()) {
+                    eprintln!("Failed to reopen file {:?}: {}", file_path, e);
+                }
+            }
+
+            if workspace.buffer_count() == 0 {
                 // No session to restore, create empty buffer
                 workspace.new_buffer();
             }
@@ -307,7 +528,7 @@ impl App {
                 backup_manager: BackupManager::new(),
                 file_tree: Some(file_tree),
                 file_search: FileSearch::new(),
-                highlighter: Highlighter::new(),
+                highlighter: Highlighter::with_theme(Self::load_theme(&config.ui.theme)),
                 mode: AppMode::Normal,
                 message: None,
                 show_line_numbers: true,
@@ -322,9 +543,6 @@ impl App {
                 file_picker_selected: 0,
                 file_picker_scroll_offset: 0,
                 logged_highlighting: false,
-                cached_highlights: None,
-                cached_text_hash: 0,
-                buffer_highlight_cache: std::collections::HashMap::new(),
                 search_pattern: String::new(),
                 search_start_pos: None,
                 search_is_reverse: false,
@@ -336,6 +554,7 @@ impl App {
                 replace_count: 0,
                 save_as_filename: String::new(),
                 lsp_manager: None,
+                lsp_synced_revisions: std::collections::HashMap::new(),
                 lsp_receiver: None,
                 diagnostics_store: DiagnosticsStore::new(),
                 navigation_history: crate::lsp::NavigationHistory::new(),
@@ -344,10 +563,20 @@ impl App {
                 ai_suggestion: None,
                 ai_last_keystroke: None,
                 ai_pending_request: false,
+                ai_request_revision: None,
+                edit_revisions: std::collections::HashMap::new(),
                 ai_completions_enabled: false,
                 completion_items: Vec::new(),
                 completion_selected: 0,
                 completion_scroll_offset: 0,
+                hover_lines: Vec::new(),
+                hover_scroll: 0,
+                hover_request: None,
+                hover_delay: (config.lsp.hover_delay_ms > 0).then(|| Duration::from_millis(config.lsp.hover_delay_ms)),
+                hover_idle_since: None,
+                hover_idle_position: None,
+                signature_help: None,
+                signature_help_request: None,
                 command_panel_pattern: String::new(),
                 command_panel_results: Vec::new(),
                 command_panel_selected: 0,
@@ -356,6 +585,22 @@ impl App {
                 project_search_results: Vec::new(),
                 project_search_selected: 0,
                 project_search_scroll_offset: 0,
+                project_search_last_keystroke: None,
+                references_symbol: String::new(),
+                references_results: Vec::new(),
+                references_locations: Vec::new(),
+                references_selected: 0,
+                references_scroll_offset: 0,
+                rename_input: String::new(),
+                rename_request: None,
+                pending_workspace_edit: None,
+                code_actions: Vec::new(),
+                code_action_selected: 0,
+                code_action_scroll_offset: 0,
+                code_action_request: None,
+                code_action_bulb_request: None,
+                code_action_checked: None,
+                code_action_bulb: None,
                 position_marks: Vec::new(),
                 current_mark_index: 0,
                 pending_close_buffer_id: None,
@@ -363,6 +608,36 @@ impl App {
                 pending_sudo_save_content: None,
                 execute_sudo_save_on_render: false,
                 mouse_drag_start: None,
+                file_watcher: FileWatcher::new(),
+                pending_disk_changes: Vec::new(),
+                diff_session: None,
+                swap_manager,
+                swap_checked: std::collections::HashSet::new(),
+                swap_last_edit: None,
+                pending_swap_recoveries: Vec::new(),
+                deferred_swap_recoveries: std::collections::HashSet::new(),
+                local_history: LocalHistory::new(config.history.clone()),
+                file_history_buffer: None,
+                file_history_snapshots: Vec::new(),
+                file_history_selected: 0,
+                file_history_scroll_offset: 0,
+                encoding_action: EncodingAction::Reopen,
+                encoding_input: String::new(),
+                followers: std::collections::HashMap::new(),
+                hex_search_pattern: String::new(),
+                hex_search_reverse: false,
+                hex_search_text: false,
+                hex_search_match: None,
+                indentation_input: String::new(),
+                language_input: String::new(),
+                undo_tree_buffer: None,
+                undo_tree_origin: 0,
+                undo_tree_selected: 0,
+                undo_tree_scroll_offset: 0,
+                theme_picker_names: Vec::new(),
+                theme_picker_origin: None,
+                theme_picker_selected: 0,
+                theme_picker_scroll_offset: 0,
             });
         }
 
@@ -378,12 +653,9 @@ impl App {
             backup_manager: BackupManager::new(),
             file_tree: None,
             file_search: FileSearch::new(),
-            highlighter: Highlighter::new(),
+            highlighter: Highlighter::with_theme(Self::load_theme(&config.ui.theme)),
             mode: AppMode::Normal,
             logged_highlighting: false,
-            cached_highlights: None,
-            cached_text_hash: 0,
-            buffer_highlight_cache: std::collections::HashMap::new(),
             message: None,
             show_line_numbers: true,
             enable_syntax_highlighting: true,
@@ -407,6 +679,7 @@ impl App {
             replace_count: 0,
             save_as_filename: String::new(),
             lsp_manager: None,
+            lsp_synced_revisions: std::collections::HashMap::new(),
             lsp_receiver: None,
             diagnostics_store: DiagnosticsStore::new(),
             navigation_history: crate::lsp::NavigationHistory::new(),
@@ -415,10 +688,20 @@ impl App {
             ai_suggestion: None,
             ai_last_keystroke: None,
             ai_pending_request: false,
+            ai_request_revision: None,
+            edit_revisions: std::collections::HashMap::new(),
             ai_completions_enabled: false,
             completion_items: Vec::new(),
             completion_selected: 0,
             completion_scroll_offset: 0,
+            hover_lines: Vec::new(),
+            hover_scroll: 0,
+            hover_request: None,
+            hover_delay: (config.lsp.hover_delay_ms > 0).then(|| Duration::from_millis(config.lsp.hover_delay_ms)),
+            hover_idle_since: None,
+            hover_idle_position: None,
+            signature_help: None,
+            signature_help_request: None,
             command_panel_pattern: String::new(),
             command_panel_results: Vec::new(),
             command_panel_selected: 0,
@@ -427,6 +710,22 @@ impl App {
             project_search_results: Vec::new(),
             project_search_selected: 0,
             project_search_scroll_offset: 0,
+            project_search_last_keystroke: None,
+            references_symbol: String::new(),
+            references_results: Vec::new(),
+            references_locations: Vec::new(),
+            references_selected: 0,
+            references_scroll_offset: 0,
+            rename_input: String::new(),
+            rename_request: None,
+            pending_workspace_edit: None,
+            code_actions: Vec::new(),
+            code_action_selected: 0,
+            code_action_scroll_offset: 0,
+            code_action_request: None,
+            code_action_bulb_request: None,
+            code_action_checked: None,
+            code_action_bulb: None,
             position_marks: Vec::new(),
             current_mark_index: 0,
             pending_close_buffer_id: None,
@@ -434,6 +733,36 @@ impl App {
             pending_sudo_save_content: None,
             execute_sudo_save_on_render: false,
             mouse_drag_start: None,
+            file_watcher: FileWatcher::new(),
+            pending_disk_changes: Vec::new(),
+            diff_session: None,
+            swap_manager: SwapManager::new(),
+            swap_checked: std::collections::HashSet::new(),
+            swap_last_edit: None,
+            pending_swap_recoveries: Vec::new(),
+            deferred_swap_recoveries: std::collections::HashSet::new(),
+            local_history: LocalHistory::new(config.history.clone()),
+            file_history_buffer: None,
+            file_history_snapshots: Vec::new(),
+            file_history_selected: 0,
+            file_history_scroll_offset: 0,
+            encoding_action: EncodingAction::Reopen,
+            encoding_input: String::new(),
+            followers: std::collections::HashMap::new(),
+            hex_search_pattern: String::new(),
+            hex_search_reverse: false,
+            hex_search_text: false,
+            hex_search_match: None,
+            indentation_input: String::new(),
+            language_input: String::new(),
+            undo_tree_buffer: None,
+            undo_tree_origin: 0,
+            undo_tree_selected: 0,
+            undo_tree_scroll_offset: 0,
+            theme_picker_names: Vec::new(),
+            theme_picker_origin: None,
+            theme_picker_selected: 0,
+            theme_picker_scroll_offset: 0,
         })
     }
 
@@ -566,6 +895,78 @@ impl App {
                 keybinding: None,
                 action: CommandAction::AddCursorsToLineEnds,
             },
+            Command {
+                name: "File History".to_string(),
+                description: "Browse, diff and restore saved versions of the current file".to_string(),
+                keybinding: None,
+                action: CommandAction::FileHistory,
+            },
+            Command {
+                name: "Reopen with Encoding".to_string(),
+                description: "Read the file again using another encoding (e.g. latin1, utf-16le)".to_string(),
+                keybinding: None,
+                action: CommandAction::ReopenWithEncoding,
+            },
+            Command {
+                name: "Convert Encoding".to_string(),
+                description: "Write the file in another encoding on the next save".to_string(),
+                keybinding: None,
+                action: CommandAction::ConvertEncoding,
+            },
+            Command {
+                name: "Toggle Follow Mode".to_string(),
+                description: "Follow the file as it grows, like tail -f (read-only)".to_string(),
+                keybinding: None,
+                action: CommandAction::ToggleFollow,
+            },
+            Command {
+                name: "Set Indentation".to_string(),
+                description: "Indent this buffer with tabs or N spaces (or 'auto')".to_string(),
+                keybinding: None,
+                action: CommandAction::SetIndentation,
+            },
+            Command {
+                name: "Undo Tree".to_string(),
+                description: "Browse every undo branch, preview a past state and jump to it".to_string(),
+                keybinding: Some("Ctrl+X U".to_string()),
+                action: CommandAction::UndoTree,
+            },
+            Command {
+                name: "Set Buffer Language".to_string(),
+                description: "Highlight and lint this buffer as another language (or 'auto')".to_string(),
+                keybinding: None,
+                action: CommandAction::SetBufferLanguage,
+            },
+            Command {
+                name: "Select Theme".to_string(),
+                description: "Preview the color themes and switch to one".to_string(),
+                keybinding: None,
+                action: CommandAction::SelectTheme,
+            },
+            Command {
+                name: "Show Hover".to_string(),
+                description: "Show the language server's documentation of the symbol at the cursor".to_string(),
+                keybinding: Some("F1".to_string()),
+                action: CommandAction::ShowHover,
+            },
+            Command {
+                name: "Find References".to_string(),
+                description: "List every reference to the symbol at the cursor".to_string(),
+                keybinding: Some("Shift+F12".to_string()),
+                action: CommandAction::FindReferences,
+            },
+            Command {
+                name: "Rename Symbol".to_string(),
+                description: "Rename the symbol at the cursor in every file that uses it".to_string(),
+                keybinding: Some("F2".to_string()),
+                action: CommandAction::RenameSymbol,
+            },
+            Command {
+                name: "Code Actions".to_string(),
+                description: "List the quick fixes and refactorings for the cursor or selection".to_string(),
+                keybinding: Some("Alt+Enter".to_string()),
+                action: CommandAction::CodeActions,
+            },
         ]
     }
 
@@ -622,16 +1023,37 @@ impl App {
     pub fn render(&mut self, terminal: &Terminal) -> Result<()> {
         // Hide cursor during rendering to prevent flickering
         terminal.hide_cursor()?;
+        let ui = &self.highlighter.theme().ui;
+        terminal.set_base_colors(ui.foreground, ui.background);
+        // Forget the syntax trees of closed buffers
+        self.highlighter.retain_buffers(&self.workspace.buffer_ids());
+
+        // The diff view takes over the whole screen
+        if self.mode == AppMode::DiffView {
+            if let Some(session) = &self.diff_session {
+                DiffRenderer::render(
+                    terminal,
+                    &session.view,
+                    self.highlighter.theme(),
+                    &session.highlights,
+                    Self::diff_view_hints(session.purpose),
+                )?;
+                return Ok(());
+            }
+        }
 
         // Render tab bar at top
         let buffer_list = self.workspace.buffer_list();
         let active_buffer_id = self.layout.active_buffer().unwrap_or(crate::workspace::BufferId(0));
-        crate::render::TabBar::render(terminal, &buffer_list, active_buffer_id)?;
+        crate::render::TabBar::render(terminal, &buffer_list, active_buffer_id, self.highlighter.theme())?;
 
         // Render path bar (line 1)
         let (term_width, term_height) = terminal.size();
         self.render_path_bar(terminal, term_width)?;
 
+        // Now that the width of the text is known, scroll the cursor into view
+        self.scroll_to_cursor_columns(term_width, term_height);
+
         // Check if we're in split mode
 
         if self.layout.mode() == crate::workspace::LayoutMode::VerticalSplit {
@@ -656,6 +1078,9 @@ impl App {
             if let Some(left_id) = self.layout.left_buffer() {
                 if let Some(buffer) = self.workspace.get_buffer(left_id) {
                     self.render_buffer_in_pane(terminal, buffer, &pane_dims.left, left_id == active_buffer_id, left_highlights.as_deref())?;
+                } else if let Some(hex) = self.workspace.get_hex_buffer(left_id) {
+                    let search_match = self.hex_search_match.filter(|_| left_id == active_buffer_id);
+                    HexView::render(terminal, hex, &pane_dims.left, search_match, self.highlighter.theme())?;
                 }
             }
 
@@ -664,12 +1089,26 @@ impl App {
                 if let Some(right_id) = self.layout.right_buffer() {
                     if let Some(buffer) = self.workspace.get_buffer(right_id) {
                         self.render_buffer_in_pane(terminal, buffer, right_rect, right_id == active_buffer_id, right_highlights.as_deref())?;
+                    } else if let Some(hex) = self.workspace.get_hex_buffer(right_id) {
+                        let search_match = self.hex_search_match.filter(|_| right_id == active_buffer_id);
+                        HexView::render(terminal, hex, right_rect, search_match, self.highlighter.theme())?;
                     }
                 }
             }
 
             // Render status bar for split mode
             if let Some(active_id) = self.layout.active_buffer() {
+                if let Some(hex) = self.workspace.get_hex_buffer(active_id) {
+                    StatusBar::render_hex(terminal, hex, self.message.as_deref(), self.highlighter.theme())?;
+                    let pane_rect = if self.layout.active_pane() == crate::workspace::PaneId::Left {
+                        &pane_dims.left
+                    } else {
+                        pane_dims.right.as_ref().unwrap_or(&pane_dims.left)
+                    };
+                    if let Some((x, y)) = HexView::cursor_position(hex, pane_rect) {
+                        terminal.move_cursor(x, y)?;
+                    }
+                }
                 if let Some(buffer) = self.workspace.get_buffer(active_id) {
                     let buffer_diagnostics = self.diagnostics_store.get(buffer.id().0);
                     StatusBar::render(
@@ -678,6 +1117,7 @@ impl App {
                         buffer.editor_state(),
                         self.message.as_deref(),
                         buffer_diagnostics,
+                        self.highlighter.theme(),
                     )?;
 
                     // Position cursor in the active pane (simplified for now)
@@ -692,14 +1132,10 @@ impl App {
 
                     // Only position cursor if it's within visible area
                     if screen_line < pane_rect.height as usize {
-                        let line_num_width = if self.show_line_numbers {
-                            let max_line = buffer.text_buffer().len_lines();
-                            (if max_line == 0 { 1u16 } else { (max_line as f64).log10().floor() as u16 + 1 }) + 1
-                        } else {
-                            0
-                        };
+                        let line_num_width = self.pane_gutter_width(buffer.text_buffer()) as u16;
                         // Apply horizontal scrolling offset
-                        let screen_col = editor_state.cursor.column.saturating_sub(editor_state.viewport.left_column) as u16 + line_num_width;
+                        let display_col = buffer.text_buffer().display_column(editor_state.cursor.position(), editor_state.tab_width);
+                        let screen_col = display_col.saturating_sub(editor_state.viewport.left_column) as u16 + line_num_width;
                         terminal.move_cursor(pane_rect.x + screen_col, pane_rect.y + screen_line as u16)?;
                     }
                 }
@@ -707,59 +1143,50 @@ impl App {
         } else {
             // Single pane mode - render just the active buffer
             let buffer_id = self.layout.active_buffer();
-            if let Some(id) = buffer_id {
+            if let Some(hex) = buffer_id.and_then(|id| self.workspace.get_hex_buffer(id)) {
+                let rect = self.layout.pane_dimensions(term_width, term_height).left;
+                HexView::render(terminal, hex, &rect, self.hex_search_match, self.highlighter.theme())?;
+                StatusBar::render_hex(terminal, hex, self.message.as_deref(), self.highlighter.theme())?;
+                if let Some((x, y)) = HexView::cursor_position(hex, &rect) {
+                    terminal.move_cursor(x, y)?;
+                }
+            } else if let Some(id) = buffer_id {
                 let buffer = self.workspace.get_buffer(id);
                 if let Some(buffer) = buffer {
             // Get syntax highlighting if supported (with caching)
-            let highlight_spans = if self.enable_syntax_highlighting {
+            let highlight_spans = if self.enable_syntax_highlighting && !buffer.text_buffer().is_large_file() {
                 if let Some(path) = buffer.file_path() {
-                if let Some(lang) = SupportedLanguage::from_path(path) {
-                    // Compute hash of current text
-                    let text = buffer.text_buffer().to_string();
-                    let text_hash = Self::simple_hash(&text);
-
-                    // Check if we can use cached highlights
-                    if self.cached_text_hash == text_hash && self.cached_highlights.is_some() {
-                        // Use cached highlights
-                        self.cached_highlights.clone()
-                    } else {
-                        // Need to recompute
+                if let Some(lang) = buffer.syntax_language() {
+                    if !self.logged_highlighting {
+                        logger::log(&format!("File path detected: {:?}", path));
+                        logger::log(&format!("Language detected: {:?}", lang));
+                    }
+
+                    match (|| -> anyhow::Result<Vec<HighlightSpan>> {
                         if !self.logged_highlighting {
-                            logger::log(&format!("File path detected: {:?}", path));
-                            logger::log(&format!("Language detected: {:?}", lang));
+                            logger::log("Computing syntax highlighting...");
                         }
-
-                        match (|| -> anyhow::Result<Vec<HighlightSpan>> {
+                        // Only the visible lines; the highlighter caches them per line
+                        let lines = Self::visible_lines(buffer);
+                        let result = self.highlighter.highlight_lines(buffer.text_buffer(), id, lang, lines)?;
+                        if !self.logged_highlighting {
+                            logger::log(&format!("Got {} highlight spans", result.len()));
+                        }
+                        Ok(result)
+                    })() {
+                        Ok(spans) => {
                             if !self.logged_highlighting {
-                                logger::log("Computing syntax highlighting...");
+                                logger::log("Highlighting successful!");
+                                self.logged_highlighting = true;
                             }
-                            self.highlighter.set_language(&lang.language())?;
-                            let file_id = path.to_string_lossy().to_string();
-                            let query = lang.query()?;
-                            let capture_names = lang.capture_names()?;
-                            let result = self.highlighter.highlight(&text, &file_id, &query, &capture_names)?;
+                            Some(spans)
+                        },
+                        Err(e) => {
                             if !self.logged_highlighting {
-                                logger::log(&format!("Got {} highlight spans", result.len()));
-                            }
-                            Ok(result)
-                        })() {
-                            Ok(spans) => {
-                                if !self.logged_highlighting {
-                                    logger::log("Highlighting successful!");
-                                    self.logged_highlighting = true;
-                                }
-                                // Cache the results
-                                self.cached_highlights = Some(spans.clone());
-                                self.cached_text_hash = text_hash;
-                                Some(spans)
-                            },
-                            Err(e) => {
-                                if !self.logged_highlighting {
-                                    logger::log(&format!("ERROR: Syntax highlighting failed: {}", e));
-                                    self.logged_highlighting = true;
-                                }
-                                None
+                                logger::log(&format!("ERROR: Syntax highlighting failed: {}", e));
+                                self.logged_highlighting = true;
                             }
+                            None
                         }
                     }
                 } else {
@@ -806,6 +1233,9 @@ impl App {
                 self.show_indent_guides,
                 &position_marks_positions,
                 search_matches,
+                self.code_action_bulb
+                    .filter(|(buffer_id, _)| self.show_diagnostics && *buffer_id == buffer.id())
+                    .map(|(_, line)| line),
             )?;
             StatusBar::render(
                 terminal,
@@ -813,6 +1243,7 @@ impl App {
                 buffer.editor_state(),
                 self.message.as_deref(),
                 buffer_diagnostics,
+                self.highlighter.theme(),
             )?;
             // Position cursor (but don't show yet)
             BufferView::position_cursor(
@@ -833,6 +1264,7 @@ impl App {
                 &self.file_picker_results,
                 self.file_picker_selected,
                 self.file_picker_scroll_offset,
+                self.highlighter.theme(),
             )?;
         }
 
@@ -844,6 +1276,51 @@ impl App {
                 &self.command_panel_results,
                 self.command_panel_selected,
                 self.command_panel_scroll_offset,
+                self.highlighter.theme(),
+            )?;
+        }
+
+        // Render file history overlay if active
+        if self.mode == AppMode::FileHistory {
+            let file_name = self
+                .file_history_buffer
+                .and_then(|id| self.workspace.get_buffer(id))
+                .map(|b| b.display_name())
+                .unwrap_or_default();
+            FileHistoryPanel::render(
+                terminal,
+                &file_name,
+                &self.file_history_snapshots,
+                self.file_history_selected,
+                self.file_history_scroll_offset,
+                self.highlighter.theme(),
+            )?;
+        }
+
+        // Render undo tree browser if active
+        if self.mode == AppMode::UndoTree {
+            if let Some(buffer) = self.undo_tree_buffer.and_then(|id| self.workspace.get_buffer(id)) {
+                UndoTreePanel::render(
+                    terminal,
+                    &buffer.display_name(),
+                    &buffer.undo_manager().tree_rows(),
+                    self.undo_tree_selected,
+                    self.undo_tree_scroll_offset,
+                    self.highlighter.theme(),
+                )?;
+            }
+        }
+
+        // Render theme picker if active
+        if self.mode == AppMode::ThemePicker {
+            let current = self.theme_picker_origin.as_ref().map(|theme| theme.name.as_str()).unwrap_or_default();
+            ThemePickerPanel::render(
+                terminal,
+                &self.theme_picker_names,
+                current,
+                self.theme_picker_selected,
+                self.theme_picker_scroll_offset,
+                self.highlighter.theme(),
             )?;
         }
 
@@ -851,43 +1328,73 @@ impl App {
         if self.mode == AppMode::ProjectSearch {
             crate::render::ProjectSearch::render(
                 terminal,
-                &self.project_search_pattern,
+                &format!("Project Search: {}", self.project_search_pattern),
                 &self.project_search_results,
                 self.project_search_selected,
                 self.project_search_scroll_offset,
+                self.highlighter.theme(),
+            )?;
+        }
+
+        // Render the references panel
+        if self.mode == AppMode::References {
+            crate::render::ProjectSearch::render(
+                terminal,
+                &format!("References to {}", self.references_symbol),
+                &self.references_results,
+                self.references_selected,
+                self.references_scroll_offset,
+                self.highlighter.theme(),
             )?;
         }
 
         // Render completion popup if active
         if self.mode == AppMode::Completion {
-            let buffer_id = self.layout.active_buffer();
-            if let Some(id) = buffer_id {
-                if let Some(buffer) = self.workspace.get_buffer(id) {
-                // Calculate screen position of cursor
-                let editor_state = buffer.editor_state();
-                let viewport = &editor_state.viewport;
-                let cursor = &editor_state.cursor;
-
-                // Calculate cursor screen position
-                let show_line_numbers = self.show_line_numbers;
-                let gutter_width = if show_line_numbers {
-                    // Line numbers + space + diagnostic marker
-                    format!("{}", buffer.text_buffer().len_lines()).len() + 3
-                } else {
-                    0
-                };
-
-                let screen_x = (gutter_width + cursor.column).saturating_sub(viewport.left_column);
-                let screen_y = cursor.line.saturating_sub(viewport.top_line) + 2; // +2 for tab bar and path bar offset
-
+            if let Some(cursor_screen_pos) = self.cursor_screen_position() {
                 crate::lsp::CompletionPopup::render(
                     terminal,
                     &self.completion_items,
                     self.completion_selected,
                     self.completion_scroll_offset,
-                    (screen_x as u16, screen_y as u16),
+                    cursor_screen_pos,
+                    self.highlighter.theme(),
+                )?;
+            }
+        }
+
+        // Render the signature of the call being typed
+        if let Some((buffer_id, signature)) = &self.signature_help {
+            let shown = matches!(self.mode, AppMode::Normal | AppMode::Completion)
+                && self.layout.active_buffer() == Some(*buffer_id);
+            if let Some(cursor_screen_pos) = self.cursor_screen_position().filter(|_| shown) {
+                crate::lsp::SignatureHelpPopup::render(terminal, signature, cursor_screen_pos, self.highlighter.theme())?;
+            }
+        }
+
+        // Render the code action picker
+        if self.mode == AppMode::CodeActions {
+            if let Some(cursor_screen_pos) = self.cursor_screen_position() {
+                crate::lsp::CodeActionPopup::render(
+                    terminal,
+                    &self.code_actions,
+                    self.code_action_selected,
+                    self.code_action_scroll_offset,
+                    cursor_screen_pos,
+                    self.highlighter.theme(),
+                )?;
+            }
+        }
+
+        // Render hover documentation, focused (F1) or shown after resting the cursor
+        if !self.hover_lines.is_empty() && matches!(self.mode, AppMode::Hover | AppMode::Normal) {
+            if let Some(cursor_screen_pos) = self.cursor_screen_position() {
+                HoverPopup::render(
+                    terminal,
+                    &self.hover_lines,
+                    self.hover_scroll,
+                    cursor_screen_pos,
+                    self.highlighter.theme(),
                 )?;
-                }
             }
         }
 
@@ -901,30 +1408,46 @@ impl App {
         Ok(())
     }
 
+    /// Screen position of the cursor of the active buffer, for popups
+    fn cursor_screen_position(&self) -> Option<(u16, u16)> {
+        let buffer = self.workspace.get_buffer(self.layout.active_buffer()?)?;
+        let editor_state = buffer.editor_state();
+        let viewport = &editor_state.viewport;
+        let cursor = &editor_state.cursor;
+
+        let gutter_width = BufferView::gutter_width(buffer.text_buffer(), self.show_line_numbers) as usize;
+
+        let display_col = buffer.text_buffer().display_column(cursor.position(), editor_state.tab_width);
+        let screen_x = (gutter_width + display_col).saturating_sub(viewport.left_column);
+        let screen_y = cursor.line.saturating_sub(viewport.top_line) + 2; // +2 for tab bar and path bar offset
+        Some((screen_x as u16, screen_y as u16))
+    }
+
     /// Render the path bar showing the current file path relative to project root
     fn render_path_bar(&self, terminal: &Terminal, term_width: u16) -> Result<()> {
         terminal.move_cursor(0, 1)?;
-        terminal.set_bg(crossterm::style::Color::DarkGrey)?;
-        terminal.set_fg(crossterm::style::Color::White)?;
+        let ui = &self.highlighter.theme().ui;
+        terminal.set_bg(ui.status_bg)?;
+        terminal.set_fg(ui.status_fg)?;
 
         // Get the active buffer and its path
         let path_text = if let Some(buffer_id) = self.layout.active_buffer() {
-            if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
-                if let Some(file_path) = buffer.file_path() {
-                    // Try to get relative path from project root
-                    if let Some(file_tree) = &self.file_tree {
-                        let project_root = file_tree.root();
-                        if let Ok(relative) = file_path.strip_prefix(project_root) {
-                            format!(" {}", relative.display())
-                        } else {
-                            format!(" {}", file_path.display())
-                        }
+            let hex_path = self.workspace.get_hex_buffer(buffer_id).map(|hex| hex.file_path());
+            let buffer_path = self.workspace.get_buffer(buffer_id).map(|buffer| buffer.file_path());
+            if let Some(file_path) = hex_path.or(buffer_path.flatten()) {
+                // Try to get relative path from project root
+                if let Some(file_tree) = &self.file_tree {
+                    let project_root = file_tree.root();
+                    if let Ok(relative) = file_path.strip_prefix(project_root) {
+                        format!(" {}", relative.display())
                     } else {
                         format!(" {}", file_path.display())
                     }
                 } else {
-                    " [No file]".to_string()
+                    format!(" {}", file_path.display())
                 }
+            } else if buffer_path.is_some() {
+                " [No file]".to_string()
             } else {
                 " [No buffer]".to_string()
             }
@@ -952,37 +1475,69 @@ impl App {
         Ok(())
     }
 
-    /// Get cached syntax highlights for a buffer (with caching to avoid recomputing every frame)
+    /// Lines of a buffer shown on screen
+    fn visible_lines(buffer: &crate::workspace::Buffer) -> std::ops::Range<usize> {
+        let viewport = &buffer.editor_state().viewport;
+        viewport.top_line..viewport.top_line + viewport.height as usize
+    }
+
+    /// Get syntax highlights for the visible lines of a buffer (the highlighter
+    /// reparses only edited text and caches spans per line)
     fn get_cached_highlights(&mut self, buffer_id: crate::workspace::BufferId) -> Option<Vec<crate::syntax::HighlightSpan>> {
         if !self.enable_syntax_highlighting {
             return None;
         }
         let buffer = self.workspace.get_buffer(buffer_id)?;
-        let path = buffer.file_path()?;
-        let lang = crate::syntax::SupportedLanguage::from_path(path)?;
+        // Large files are not highlighted
+        if buffer.text_buffer().is_large_file() {
+            return None;
+        }
+        // Like the active pane, only buffers of files are highlighted
+        buffer.file_path()?;
+        let lang = buffer.syntax_language()?;
 
-        let text = buffer.text_buffer().to_string();
-        let text_hash = Self::simple_hash(&text);
+        let lines = Self::visible_lines(buffer);
+        self.highlighter.highlight_lines(buffer.text_buffer(), buffer_id, lang, lines).ok()
+    }
 
-        // Check if we have cached highlights for this buffer
-        if let Some((cached_hash, cached_highlights)) = self.buffer_highlight_cache.get(&buffer_id) {
-            if *cached_hash == text_hash {
-                return Some(cached_highlights.clone());
+    /// Scroll the visible buffers horizontally so their cursors are in view
+    fn scroll_to_cursor_columns(&mut self, term_width: u16, term_height: u16) {
+        let show_line_numbers = self.show_line_numbers;
+        let mut visible = Vec::new();
+        if self.layout.mode() == crate::workspace::LayoutMode::VerticalSplit {
+            let pane_dims = self.layout.pane_dimensions(term_width, term_height);
+            if let Some(left_id) = self.layout.left_buffer() {
+                visible.push((left_id, pane_dims.left.width, true));
+            }
+            if let (Some(right_id), Some(right_rect)) = (self.layout.right_buffer(), pane_dims.right) {
+                visible.push((right_id, right_rect.width, true));
             }
+        } else if let Some(id) = self.layout.active_buffer() {
+            visible.push((id, term_width, false));
         }
 
-        // Compute new highlights
-        let file_id = path.to_string_lossy().to_string();
-        let query = lang.query().ok()?;
-        let capture_names = lang.capture_names().ok()?;
-
-        self.highlighter.set_language(&lang.language()).ok()?;
-        let highlights = self.highlighter.highlight(&text, &file_id, &query, &capture_names).ok()?;
-
-        // Cache the results
-        self.buffer_highlight_cache.insert(buffer_id, (text_hash, highlights.clone()));
+        for (id, width, in_pane) in visible {
+            let gutter_width = match self.workspace.get_buffer(id) {
+                Some(buffer) if in_pane => self.pane_gutter_width(buffer.text_buffer()),
+                Some(buffer) => BufferView::gutter_width(buffer.text_buffer(), show_line_numbers) as usize,
+                None => continue,
+            };
+            if let Some(buffer) = self.workspace.get_buffer_mut(id) {
+                let (text_buffer, editor_state, _) = buffer.split_mut();
+                editor_state.scroll_to_cursor_column(text_buffer, (width as usize).saturating_sub(gutter_width));
+            }
+        }
+    }
 
-        Some(highlights)
+    /// Width of the line numbers (and the space after them) in a split pane
+    fn pane_gutter_width(&self, text_buffer: &TextBuffer) -> usize {
+        if self.show_line_numbers {
+            let max_line = text_buffer.len_lines();
+            let digits = if max_line == 0 { 1 } else { (max_line as f64).log10().floor() as usize + 1 };
+            digits + 1
+        } else {
+            0
+        }
     }
 
     /// Render a buffer in a specific pane
@@ -1009,8 +1564,8 @@ impl App {
         };
 
         // Mark colors
-        let mark_bg = crossterm::style::Color::Rgb { r: 255, g: 100, b: 255 }; // Magenta
-        let mark_fg = crossterm::style::Color::White;
+        let ui = &self.highlighter.theme().ui;
+        let (mark_bg, mark_fg) = (ui.mark_bg, ui.mark_fg);
 
         // Render each line in the pane
         for screen_row in 0..pane_rect.height {
@@ -1026,22 +1581,43 @@ impl App {
             if buffer_line >= text_buffer.len_lines() {
                 // Empty line beyond buffer
                 if is_active {
-                    terminal.set_fg(crossterm::style::Color::DarkGrey)?;
+                    terminal.set_fg(ui.line_number)?;
                 } else {
-                    terminal.set_fg(crossterm::style::Color::Grey)?;
+                    terminal.set_fg(ui.inactive_text)?;
                 }
                 terminal.print("~")?;
                 terminal.reset_color()?;
                 continue;
             }
 
-            // Get the line content
-            if let Some(line) = text_buffer.get_line(buffer_line) {
+            // Get the line content (large files: only the visible part)
+            if text_buffer.is_large_file() {
+                let line_num_width = if self.show_line_numbers {
+                    let max_line = text_buffer.len_lines();
+                    let digits = if max_line == 0 { 1 } else { (max_line as f64).log10().floor() as usize + 1 };
+                    terminal.set_fg(ui.line_number)?;
+                    terminal.print(&format!("{:>width$} ", buffer_line + 1, width = digits))?;
+                    terminal.reset_color()?;
+                    digits + 1
+                } else {
+                    0
+                };
+                let available_width = (pane_rect.width as usize).saturating_sub(line_num_width);
+                BufferView::render_line_window(
+                    terminal,
+                    text_buffer,
+                    buffer_line,
+                    editor_state,
+                    available_width,
+                    &self.search_all_matches,
+                    self.highlighter.theme(),
+                )?;
+            } else if let Some(line) = text_buffer.get_line(buffer_line) {
                 let line_num_width = if self.show_line_numbers {
                     let max_line = text_buffer.len_lines();
                     let digits = if max_line == 0 { 1 } else { (max_line as f64).log10().floor() as usize + 1 };
                     let line_num_str = format!("{:>width$} ", buffer_line + 1, width = digits);
-                    terminal.set_fg(crossterm::style::Color::DarkGrey)?;
+                    terminal.set_fg(ui.line_number)?;
                     terminal.print(&line_num_str)?;
                     terminal.reset_color()?;
                     digits + 1
@@ -1068,108 +1644,52 @@ impl App {
                 };
 
                 // Selection colors
-                let selection_bg = crossterm::style::Color::Rgb { r: 100, g: 180, b: 255 };
-                let selection_fg = crossterm::style::Color::Black;
-
-                if let Some(highlight_spans) = highlights {
-                    // Render with syntax highlighting
-                    let chars: Vec<char> = line.chars().collect();
-                    let mut current_col = 0;
+                let (selection_bg, selection_fg) = (ui.selection_bg, ui.selection_fg);
 
-                    for col_idx in editor_state.viewport.left_column..(editor_state.viewport.left_column + available_width) {
-                        if col_idx >= chars.len() {
-                            break;
-                        }
+                // Screen columns of the line that are visible (horizontal scrolling)
+                let left_column = editor_state.viewport.left_column;
+                let right_column = left_column + available_width;
 
-                        let ch = chars[col_idx];
-                        let byte_offset = line_start_byte + line.chars().take(col_idx).map(|c| c.len_utf8()).sum::<usize>();
-
-                        // Check if this position is marked (highest priority)
-                        let is_marked = mark_positions.contains(&(buffer_line, col_idx));
-
-                        // Check if this character is selected
-                        let is_selected = selection_range
-                            .map(|(start, end)| col_idx >= start && col_idx < end)
-                            .unwrap_or(false);
-
-                        if is_marked {
-                            // Render with mark highlighting (highest priority)
-                            terminal.set_bg(mark_bg)?;
-                            terminal.set_fg(mark_fg)?;
-                        } else if is_selected {
-                            // Render with selection highlighting
-                            terminal.set_bg(selection_bg)?;
-                            terminal.set_fg(selection_fg)?;
-                        } else {
-                            // Find highlight color for this position
-                            let color = highlight_spans
-                                .iter()
-                                .find(|span| byte_offset >= span.start_byte && byte_offset < span.end_byte)
-                                .map(|span| self.highlighter.theme().color_for(span.token_type))
-                                .unwrap_or(crossterm::style::Color::Reset);
-
-                            // Use syntax highlighting color for both active and inactive panes
-                            terminal.set_fg(color)?;
-                        }
-                        terminal.print(&ch.to_string())?;
-                        terminal.reset_color()?;
-                        current_col += 1;
+                for grapheme in display::graphemes(&line, editor_state.tab_width) {
+                    if grapheme.display_column + grapheme.width <= left_column {
+                        continue;
                     }
-                    terminal.reset_color()?;
-                } else {
-                    // Render without syntax highlighting but with selection
-                    let chars: Vec<char> = line.chars().collect();
-
-                    if let Some((start_col, end_col)) = selection_range {
-                        // Render with selection highlighting
-                        for (col_idx, &ch) in chars.iter().enumerate() {
-                            if col_idx < editor_state.viewport.left_column {
-                                continue;
-                            }
-                            if col_idx >= editor_state.viewport.left_column + available_width {
-                                break;
-                            }
-
-                            // Check if this position is marked (highest priority)
-                            let is_marked = mark_positions.contains(&(buffer_line, col_idx));
-
-                            if is_marked {
-                                terminal.set_bg(mark_bg)?;
-                                terminal.set_fg(mark_fg)?;
-                            } else if col_idx >= start_col && col_idx < end_col {
-                                terminal.set_bg(selection_bg)?;
-                                terminal.set_fg(selection_fg)?;
-                            } else if !is_active {
-                                terminal.set_fg(crossterm::style::Color::DarkGrey)?;
-                            }
-                            terminal.print(&ch.to_string())?;
-                            terminal.reset_color()?;
-                        }
-                    } else {
-                        // No selection, render with mark highlighting
-                        let chars: Vec<char> = line.chars().collect();
+                    if grapheme.display_column >= right_column {
+                        break;
+                    }
+                    let col_idx = grapheme.column;
 
-                        for (col_idx, &ch) in chars.iter().enumerate() {
-                            if col_idx < editor_state.viewport.left_column {
-                                continue;
-                            }
-                            if col_idx >= editor_state.viewport.left_column + available_width {
-                                break;
-                            }
+                    // Check if this position is marked (highest priority)
+                    let is_marked = mark_positions.contains(&(buffer_line, col_idx));
 
-                            // Check if this position is marked
-                            let is_marked = mark_positions.contains(&(buffer_line, col_idx));
+                    // Check if this character is selected
+                    let is_selected = selection_range
+                        .map(|(start, end)| col_idx >= start && col_idx < end)
+                        .unwrap_or(false);
 
-                            if is_marked {
-                                terminal.set_bg(mark_bg)?;
-                                terminal.set_fg(mark_fg)?;
-                            } else if !is_active {
-                                terminal.set_fg(crossterm::style::Color::DarkGrey)?;
-                            }
-                            terminal.print(&ch.to_string())?;
-                            terminal.reset_color()?;
-                        }
+                    if is_marked {
+                        // Render with mark highlighting (highest priority)
+                        terminal.set_bg(mark_bg)?;
+                        terminal.set_fg(mark_fg)?;
+                    } else if is_selected {
+                        // Render with selection highlighting
+                        terminal.set_bg(selection_bg)?;
+                        terminal.set_fg(selection_fg)?;
+                    } else if let Some(highlight_spans) = highlights {
+                        // Find highlight color for this position; syntax
+                        // colors are used for both active and inactive panes
+                        let byte_offset = line_start_byte + grapheme.byte;
+                        let color = highlight_spans
+                            .iter()
+                            .find(|span| byte_offset >= span.start_byte && byte_offset < span.end_byte)
+                            .map(|span| self.highlighter.theme().color_for(span.token_type))
+                            .unwrap_or(ui.foreground);
+                        terminal.set_fg(color)?;
+                    } else if !is_active {
+                        terminal.set_fg(ui.inactive_text)?;
                     }
+                    terminal.print(&grapheme.clipped_text(left_column, right_column))?;
+                    terminal.reset_color()?;
                 }
             }
         }
@@ -1179,7 +1699,7 @@ impl App {
             let separator_x = pane_rect.x + pane_rect.width;
             for y in pane_rect.y..(pane_rect.y + pane_rect.height) {
                 terminal.move_cursor(separator_x.saturating_sub(1), y)?;
-                terminal.set_fg(crossterm::style::Color::DarkGrey)?;
+                terminal.set_fg(ui.separator)?;
                 terminal.print("│")?;
                 terminal.reset_color()?;
             }
@@ -1202,6 +1722,50 @@ impl App {
 
     /// Handle an input event
     pub fn handle_event(&mut self, event: Event) -> Result<ControlFlow> {
+        if matches!(event, Event::Key(_) | Event::Paste(_)) {
+            // Restart the idle timer for swap files
+            self.swap_last_edit = Some(Instant::now());
+        }
+        let is_input = match &event {
+            Event::Key(_) | Event::Paste(_) => true,
+            Event::Mouse(mouse) => mouse.kind != MouseEventKind::Moved,
+            _ => false,
+        };
+        if is_input {
+            // Documentation shown after resting the cursor goes away at the
+            // next input, which also restarts the wait
+            if self.mode != AppMode::Hover {
+                self.hover_lines.clear();
+            }
+            self.hover_idle_since = Some(Instant::now());
+        }
+
+        // Edits made while handling one input event are one undo step
+        let undo_buffer = match event {
+            Event::Key(_) | Event::Mouse(_) | Event::Paste(_) => self.layout.active_buffer(),
+            _ => None,
+        };
+        if let Some(buffer) = undo_buffer.and_then(|id| self.workspace.get_buffer_mut(id)) {
+            buffer.begin_undo_transaction();
+        }
+
+        let result = match self.dispatch_event(event) {
+            // Typing into a read-only buffer (e.g. a followed log) is refused by
+            // the buffer itself; tell the user instead of failing
+            Err(e) if e.is::<crate::buffer::ReadOnlyError>() => {
+                self.message = Some(e.to_string());
+                Ok(ControlFlow::Continue)
+            }
+            result => result,
+        };
+
+        if let Some(buffer) = undo_buffer.and_then(|id| self.workspace.get_buffer_mut(id)) {
+            buffer.end_undo_transaction();
+        }
+        result
+    }
+
+    fn dispatch_event(&mut self, event: Event) -> Result<ControlFlow> {
         match event {
             Event::Key(key_event) => {
                 self.handle_key(key_event)
@@ -1216,6 +1780,12 @@ impl App {
             Event::Paste(text) => {
                 self.handle_paste(text)
             }
+            Event::FocusGained => {
+                // Another program may have touched our files while we were away
+                let buffer_ids = self.workspace.buffer_ids();
+                self.check_disk_changes(buffer_ids);
+                Ok(ControlFlow::Continue)
+            }
             _ => Ok(ControlFlow::Continue),
         }
     }
@@ -1233,38 +1803,29 @@ impl App {
         let content_row = mouse_row - top_offset;
 
         // Calculate line number width
-        let line_number_width = if show_line_numbers {
-            let line_count = buffer.text_buffer().len_lines();
-            let digits = if line_count == 0 {
-                1
-            } else {
-                (line_count as f64).log10().floor() as usize + 1
-            };
-            digits.max(3) + 2 // +1 for diagnostic marker, +1 for trailing space
-        } else {
-            0
-        };
+        let line_number_width = BufferView::gutter_width(buffer.text_buffer(), show_line_numbers) as usize;
 
         // Check if click is in the line number area
         if mouse_col < line_number_width {
             return None;
         }
 
-        let content_col = mouse_col - line_number_width;
+        // Screen column in the line, after horizontal scrolling
+        let editor_state = buffer.editor_state();
+        let display_col = mouse_col - line_number_width + editor_state.viewport.left_column;
 
         // Calculate buffer line and column
-        let buffer_line = buffer.editor_state().viewport.top_line + content_row;
+        let buffer_line = editor_state.viewport.top_line + content_row;
 
         // Make sure the line exists
         if buffer_line >= buffer.text_buffer().len_lines() {
             return None;
         }
 
-        // Clamp column to line length
-        let line_len = buffer.text_buffer().line_len(buffer_line);
-        let clamped_col = content_col.min(line_len);
+        // The character drawn at that screen column, or the end of the line
+        let column = buffer.text_buffer().column_at_display(buffer_line, display_col, editor_state.tab_width);
 
-        Some(Position::new(buffer_line, clamped_col))
+        Some(Position::new(buffer_line, column))
     }
 
     /// Handle a mouse event
@@ -1456,6 +2017,10 @@ impl App {
         let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
             return Ok(ControlFlow::Continue);
         };
+        if buffer.text_buffer().is_read_only() {
+            self.message = Some("Buffer is read-only".to_string());
+            return Ok(ControlFlow::Continue);
+        }
 
         let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
 
@@ -1484,14 +2049,6 @@ impl App {
         editor_state.cursor.set_position(text_buffer.char_to_pos(char_idx));
         editor_state.ensure_cursor_visible();
 
-        // Invalidate syntax highlighting caches to force re-parse
-        self.cached_highlights = None;
-        self.cached_text_hash = 0;
-        self.buffer_highlight_cache.clear();
-
-        // Completely recreate the highlighter to ensure no stale state
-        self.highlighter = crate::syntax::Highlighter::new();
-
         // Trigger AI completion debouncing (only if enabled)
         if self.ai_completions_enabled {
             // Clear any existing suggestion and reset timer
@@ -1560,6 +2117,21 @@ impl App {
             AppMode::ReplaceConfirm => self.handle_replace_confirm_mode(key),
             AppMode::Completion => self.handle_completion_mode(key),
             AppMode::SaveAsPrompt => self.handle_save_as_prompt_mode(key),
+            AppMode::ConfirmReloadFile => self.handle_confirm_reload_file_mode(key),
+            AppMode::ConfirmRecoverSwap => self.handle_confirm_recover_swap_mode(key),
+            AppMode::DiffView => self.handle_diff_view_mode(key),
+            AppMode::FileHistory => self.handle_file_history_mode(key),
+            AppMode::EncodingPrompt => self.handle_encoding_prompt_mode(key),
+            AppMode::HexSearch => self.handle_hex_search_mode(key),
+            AppMode::IndentationPrompt => self.handle_indentation_prompt_mode(key),
+            AppMode::LanguagePrompt => self.handle_language_prompt_mode(key),
+            AppMode::UndoTree => self.handle_undo_tree_mode(key),
+            AppMode::ThemePicker => self.handle_theme_picker_mode(key),
+            AppMode::Hover => self.handle_hover_mode(key),
+            AppMode::References => self.handle_references_mode(key),
+            AppMode::RenamePrompt => self.handle_rename_prompt_mode(key),
+            AppMode::CodeActions => self.handle_code_actions_mode(key),
+            AppMode::ConfirmWorkspaceEdit => self.handle_confirm_workspace_edit_mode(key),
         }
     }
 
@@ -1594,9 +2166,6 @@ impl App {
 
                             // Show message based on whether it's new or existing
                             if open_result.is_new() {
-                                // Invalidate highlight cache when opening a new file
-                                self.cached_highlights = None;
-                                self.cached_text_hash = 0;
                                 self.logged_highlighting = false;
                                 // Notify LSP about newly opened file
                                 self.notify_lsp_did_open();
@@ -1739,6 +2308,10 @@ impl App {
                 self.message = None;
             }
             KeyCode::Enter => {
+                // Results for the pattern as typed, even without a pause
+                if self.project_search_last_keystroke.take().is_some() {
+                    self.update_project_search_results();
+                }
                 // Jump to selected result
                 if let Some(result) = self.project_search_results.get(self.project_search_selected) {
                     let file_path = result.file_path.clone();
@@ -1791,13 +2364,14 @@ impl App {
                     }
                 }
             }
+            // Every file is read, so the search waits for a pause in typing
             KeyCode::Char(c) => {
                 self.project_search_pattern.push(c);
-                self.update_project_search_results();
+                self.project_search_last_keystroke = Some(Instant::now());
             }
             KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
                 self.project_search_pattern.pop();
-                self.update_project_search_results();
+                self.project_search_last_keystroke = Some(Instant::now());
             }
             _ => {}
         }
@@ -1829,44 +2403,43 @@ impl App {
         // Search through all files in the project
         if let Some(file_tree) = &self.file_tree {
             for file_path in file_tree.files() {
-                // Skip binary and large files
-                if let Ok(metadata) = std::fs::metadata(file_path) {
-                    // Skip files larger than 10MB to avoid slowdown
-                    if metadata.len() > 10_000_000 {
+                // Stream the file line by line, so large files don't have to fit in memory
+                let Ok(file) = std::fs::File::open(file_path) else {
+                    continue;
+                };
+                let mut reader = std::io::BufReader::new(file);
+
+                // Skip binary files, judging by their first block. UTF-16 can't
+                // be split at '\n' bytes, so it is decoded whole; other text is
+                // decoded line by line, as UTF-8 or else Windows-1252.
+                let is_utf16 = match std::io::BufRead::fill_buf(&mut reader) {
+                    Ok(head) if !looks_binary(head) => FileEncoding::has_utf16_bom(head),
+                    _ => continue,
+                };
+                let lines: Box<dyn Iterator<Item = String>> = if is_utf16 {
+                    let mut bytes = Vec::new();
+                    if std::io::Read::read_to_end(&mut reader, &mut bytes).is_err() {
                         continue;
                     }
-                }
+                    let (text, _) = FileEncoding::detect(&bytes);
+                    Box::new(text.lines().map(str::to_string).collect::<Vec<_>>().into_iter())
+                } else {
+                    Box::new(
+                        std::io::BufRead::split(reader, b'\n')
+                            .map_while(|line| line.ok())
+                            .map(FileEncoding::decode_line),
+                    )
+                };
 
-                // Skip common binary file extensions
-                if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
-                    match ext {
-                        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "ico" | "svg" |
-                        "pdf" | "zip" | "tar" | "gz" | "bz2" | "xz" | "7z" |
-                        "exe" | "dll" | "so" | "dylib" | "a" | "o" |
-                        "woff" | "woff2" | "ttf" | "eot" |
-                        "mp3" | "mp4" | "avi" | "mov" | "wav" |
-                        "pyc" | "pyo" | "class" | "jar" => continue,
-                        _ => {}
-                    }
-                }
+                for (line_idx, line) in lines.enumerate() {
+                    let line = line.strip_suffix('\r').unwrap_or(&line);
 
-                // Read file contents
-                if let Ok(contents) = std::fs::read_to_string(file_path) {
-                    // Search each line
-                    for (line_idx, line) in contents.lines().enumerate() {
-                        if let Some(mat) = pattern.find(line) {
-                            results.push(ProjectSearchResult {
-                                file_path: file_path.clone(),
-                                line_number: line_idx,
-                                line_content: line.to_string(),
-                                match_start: mat.start(),
-                                match_end: mat.end(),
-                            });
+                    if let Some(mat) = pattern.find(line) {
+                        results.push(Self::project_search_result(file_path, line_idx, line, mat.start(), mat.end()));
 
-                            // Limit results to prevent UI slowdown
-                            if results.len() >= 1000 {
-                                break;
-                            }
+                        // Limit results to prevent UI slowdown
+                        if results.len() >= 1000 {
+                            break;
                         }
                     }
                 }
@@ -1883,8 +2456,56 @@ impl App {
         self.project_search_scroll_offset = 0;
     }
 
+    /// Build a project search result, keeping only the part of very long
+    /// lines (e.g. minified files) around the match
+    fn project_search_result(
+        file_path: &Path,
+        line_number: usize,
+        line: &str,
+        match_start: usize,
+        match_end: usize,
+    ) -> ProjectSearchResult {
+        const MAX_LINE_CONTENT: usize = 1000;
+        let (start, end) = if line.len() > MAX_LINE_CONTENT {
+            let mut start = match_start.saturating_sub(MAX_LINE_CONTENT / 4);
+            while !line.is_char_boundary(start) {
+                start -= 1;
+            }
+            let mut end = (start + MAX_LINE_CONTENT).max(match_end).min(line.len());
+            while !line.is_char_boundary(end) {
+                end += 1;
+            }
+            (start, end)
+        } else {
+            (0, line.len())
+        };
+
+        ProjectSearchResult {
+            file_path: file_path.to_path_buf(),
+            line_number,
+            line_content: line[start..end].to_string(),
+            match_start: match_start - start,
+            match_end: match_end - start,
+        }
+    }
+
     /// Execute a command from the command panel
     fn execute_command(&mut self, action: CommandAction) -> Result<ControlFlow> {
+        let edits_buffer = matches!(
+            action,
+            CommandAction::SearchAndReplace
+                | CommandAction::FormatDocument
+                | CommandAction::OrganizeImports
+                | CommandAction::FileHistory
+                | CommandAction::ReopenWithEncoding
+                | CommandAction::ConvertEncoding
+                | CommandAction::UndoTree
+        );
+        if edits_buffer && self.active_buffer_is_read_only() {
+            self.message = Some("Buffer is read-only".to_string());
+            return Ok(ControlFlow::Continue);
+        }
+
         match action {
             CommandAction::Search => {
                 // Enter search mode
@@ -1945,11 +2566,14 @@ impl App {
                             // Try to create backup (ignore errors - backup is optional)
                             let _ = self.backup_manager.create_backup(&path);
 
+                            buffer.apply_save_transforms()?;
+
                             // Store content before attempting save (for sudo retry)
                             let content = buffer.text_buffer().to_string();
 
                             match buffer.text_buffer_mut().save() {
                                 Ok(_) => {
+                                    self.record_history(&path, &content);
                                     self.message = Some("Saved".to_string());
                                     self.notify_lsp_did_save();
                                 }
@@ -2081,10 +2705,13 @@ impl App {
                                 if buffer_mut.text_buffer().is_modified() {
                                     let _ = self.backup_manager.create_backup(&path);
 
+                                    buffer_mut.apply_save_transforms()?;
                                     if let Err(e) = buffer_mut.text_buffer_mut().save() {
                                         self.message = Some(format!("Failed to save: {}", e));
                                         return Ok(ControlFlow::Continue);
                                     }
+                                    let content = buffer_mut.text_buffer().to_string();
+                                    self.record_history(&path, &content);
                                 }
                             }
 
@@ -2097,8 +2724,9 @@ impl App {
                                 Ok(result) if result.status.success() => {
                                     // Reload the file
                                     if let Some(buffer_mut) = self.workspace.get_buffer_mut(buffer_id) {
-                                        let reloaded = crate::buffer::TextBuffer::from_file(path)?;
-                                        *buffer_mut.text_buffer_mut() = reloaded;
+                                        if let Some(content) = buffer_mut.text_buffer().read_disk_change() {
+                                            buffer_mut.reload_from_disk(&content);
+                                        }
                                         self.message = Some("Formatted with black".to_string());
                                     }
                                 }
@@ -2133,10 +2761,13 @@ impl App {
                                 if buffer_mut.text_buffer().is_modified() {
                                     let _ = self.backup_manager.create_backup(&path);
 
+                                    buffer_mut.apply_save_transforms()?;
                                     if let Err(e) = buffer_mut.text_buffer_mut().save() {
                                         self.message = Some(format!("Failed to save: {}", e));
                                         return Ok(ControlFlow::Continue);
                                     }
+                                    let content = buffer_mut.text_buffer().to_string();
+                                    self.record_history(&path, &content);
                                 }
                             }
 
@@ -2149,8 +2780,9 @@ impl App {
                                 Ok(result) if result.status.success() => {
                                     // Reload the file
                                     if let Some(buffer_mut) = self.workspace.get_buffer_mut(buffer_id) {
-                                        let reloaded = crate::buffer::TextBuffer::from_file(path)?;
-                                        *buffer_mut.text_buffer_mut() = reloaded;
+                                        if let Some(content) = buffer_mut.text_buffer().read_disk_change() {
+                                            buffer_mut.reload_from_disk(&content);
+                                        }
                                         self.message = Some("Organized imports with isort".to_string());
                                     }
                                 }
@@ -2230,717 +2862,1357 @@ impl App {
                     }
                 }
             }
-        }
-        Ok(ControlFlow::Continue)
-    }
+            CommandAction::FileHistory => {
+                if let Some(buffer_id) = self.layout.active_buffer() {
+                    let path_opt = self.workspace.get_buffer(buffer_id)
+                        .and_then(|b| b.file_path().cloned());
 
-    /// Handle key in confirm exit mode
-    fn handle_confirm_exit_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
-        match key.code {
-            KeyCode::Char('y') | KeyCode::Char('Y') => {
-                // Save all modified buffers then exit
-                self.workspace.save_all_modified_buffers(&self.backup_manager)?;
-                self.message = Some("Saved all buffers".to_string());
-                return Ok(ControlFlow::Exit);
+                    if let Some(path) = path_opt {
+                        let project_root = self.file_tree.as_ref().map(|t| t.root());
+                        let snapshots = self.local_history.snapshots(project_root, &path);
+                        if !self.local_history.is_enabled() {
+                            self.message = Some("Local history is disabled".to_string());
+                        } else if snapshots.is_empty() {
+                            self.message = Some("No saved versions of this file yet".to_string());
+                        } else {
+                            self.mode = AppMode::FileHistory;
+                            self.file_history_buffer = Some(buffer_id);
+                            self.file_history_snapshots = snapshots;
+                            self.file_history_selected = 0;
+                            self.file_history_scroll_offset = 0;
+                        }
+                    } else {
+                        self.message = Some("Buffer has no file path".to_string());
+                    }
+                }
             }
-            KeyCode::Char('n') | KeyCode::Char('N') => {
-                // Exit without saving
-                return Ok(ControlFlow::Exit);
+            CommandAction::ReopenWithEncoding => {
+                self.start_encoding_prompt(EncodingAction::Reopen);
+            }
+            CommandAction::ConvertEncoding => {
+                self.start_encoding_prompt(EncodingAction::Convert);
+            }
+            CommandAction::ToggleFollow => {
+                self.toggle_follow();
+            }
+            CommandAction::UndoTree => {
+                self.open_undo_tree();
             }
+            CommandAction::SetIndentation => {
+                if self.workspace.active_buffer().is_some() {
+                    self.indentation_input.clear();
+                    self.mode = AppMode::IndentationPrompt;
+                    self.message = Some(self.indentation_prompt_message());
+                }
+            }
+            CommandAction::SetBufferLanguage => {
+                if self.workspace.active_buffer().is_some() {
+                    self.language_input.clear();
+                    self.mode = AppMode::LanguagePrompt;
+                    self.message = Some(self.language_prompt_message());
+                }
+            }
+            CommandAction::SelectTheme => {
+                self.open_theme_picker();
+            }
+            CommandAction::ShowHover => {
+                self.request_hover(true);
+            }
+            CommandAction::FindReferences => {
+                self.request_references();
+            }
+            CommandAction::RenameSymbol => {
+                self.request_rename();
+            }
+            CommandAction::CodeActions => {
+                self.request_code_actions(false);
+            }
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn indentation_prompt_message(&self) -> String {
+        let current = self
+            .workspace
+            .active_buffer()
+            .map(|b| b.indentation().label())
+            .unwrap_or_default();
+        format!("Indentation (current {}; 'spaces 2', 'tabs', 'auto'): {}", current, self.indentation_input)
+    }
+
+    /// Handle key in the indentation prompt
+    fn handle_indentation_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
             KeyCode::Esc => {
-                // Cancel and return to normal mode
                 self.mode = AppMode::Normal;
                 self.message = None;
             }
             KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+G: Cancel and return to normal mode (Emacs style)
                 self.mode = AppMode::Normal;
                 self.message = None;
             }
-            _ => {
-                // Any other key cancels
+            KeyCode::Enter => {
                 self.mode = AppMode::Normal;
-                self.message = None;
+                let input = std::mem::take(&mut self.indentation_input);
+                self.apply_indentation(&input);
+            }
+            KeyCode::Char(c) => {
+                self.indentation_input.push(c);
+                self.message = Some(self.indentation_prompt_message());
             }
+            KeyCode::Backspace => {
+                self.indentation_input.pop();
+                self.message = Some(self.indentation_prompt_message());
+            }
+            _ => {}
         }
         Ok(ControlFlow::Continue)
     }
 
-    /// Handle key in confirm close tab mode
-    fn handle_confirm_close_tab_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
-        match key.code {
-            KeyCode::Char('y') | KeyCode::Char('Y') => {
-                // Save buffer then close
-                if let Some(buffer_id) = self.pending_close_buffer_id {
-                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                        if let Some(path) = buffer.file_path().cloned() {
-                            // Try to create backup (ignore errors - backup is optional)
-                            let _ = self.backup_manager.create_backup(&path);
-
-                            // Store content before attempting save (for sudo retry)
-                            let content = buffer.text_buffer().to_string();
-
-                            match buffer.text_buffer_mut().save() {
-                                Ok(_) => {
-                                    self.message = Some("Saved".to_string());
-                                    self.notify_lsp_did_save();
-                                }
-                                Err(e) => {
-                                    if self.is_permission_denied(&e) {
-                                        // Set sudo save state
-                                        self.pending_sudo_save_path = Some(path);
-                                        self.pending_sudo_save_content = Some(content);
-                                        self.mode = AppMode::ConfirmSudoSave;
-                                        self.message = Some(
-                                            "Permission denied. Save with sudo? (y/n)".to_string()
-                                        );
-                                        // Keep the close pending
-                                        return Ok(ControlFlow::Continue);
-                                    } else {
-                                        self.message = Some(format!("Save failed: {}", e));
-                                        self.mode = AppMode::Normal;
-                                        self.pending_close_buffer_id = None;
-                                        return Ok(ControlFlow::Continue);
-                                    }
-                                }
-                            }
-                        } else {
-                            self.message = Some("No file path set".to_string());
-                            self.mode = AppMode::Normal;
-                            self.pending_close_buffer_id = None;
-                            return Ok(ControlFlow::Continue);
-                        }
-                    }
-
-                    // Now close the buffer
-                    if let Err(e) = self.workspace.close_buffer(buffer_id) {
-                        self.message = Some(format!("Error closing buffer: {}", e));
-                    } else {
-                        // If there are remaining buffers, switch to another one
-                        let buffer_ids: Vec<_> = self.workspace.buffer_ids();
-                        if let Some(&next_id) = buffer_ids.first() {
-                            let pane = self.layout.active_pane();
-                            self.layout.set_buffer(pane, next_id);
-                            self.message = Some("Buffer saved and closed".to_string());
-                        } else {
-                            // No more buffers, open a new empty one
-                            let new_id = self.workspace.new_buffer();
-                            let pane = self.layout.active_pane();
-                            self.layout.set_buffer(pane, new_id);
-                            self.message = Some("New buffer".to_string());
-                            self.mode = AppMode::Normal;
-                            self.pending_close_buffer_id = None;
-                            return Ok(ControlFlow::Continue);
-                        }
-                    }
+    /// Override the active buffer's indentation; "auto" goes back to
+    /// EditorConfig or the detected style
+    fn apply_indentation(&mut self, input: &str) {
+        let indentation = if input.trim().eq_ignore_ascii_case("auto") {
+            None
+        } else {
+            match Indentation::parse(input) {
+                Some(indentation) => Some(indentation),
+                None => {
+                    self.message = Some(format!("Invalid indentation: {}", input));
+                    return;
                 }
-                self.mode = AppMode::Normal;
-                self.pending_close_buffer_id = None;
             }
-            KeyCode::Char('n') | KeyCode::Char('N') => {
-                // Close without saving (force close)
-                if let Some(buffer_id) = self.pending_close_buffer_id {
-                    self.workspace.force_close_buffer(buffer_id);
+        };
+        let Some(buffer_id) = self.layout.active_buffer() else {
+            return;
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return;
+        };
+        buffer.set_indent_override(indentation);
+        self.message = Some(format!("Indentation: {}", buffer.indentation().label()));
+    }
 
-                    // If there are remaining buffers, switch to another one
-                    let buffer_ids: Vec<_> = self.workspace.buffer_ids();
-                    if let Some(&next_id) = buffer_ids.first() {
-                        let pane = self.layout.active_pane();
-                        self.layout.set_buffer(pane, next_id);
-                        self.message = Some("Buffer closed without saving".to_string());
-                    } else {
-                        // No more buffers, open a new empty one
-                        let new_id = self.workspace.new_buffer();
-                        let pane = self.layout.active_pane();
-                        self.layout.set_buffer(pane, new_id);
-                        self.message = Some("New buffer".to_string());
-                        self.mode = AppMode::Normal;
-                        self.pending_close_buffer_id = None;
-                        return Ok(ControlFlow::Continue);
-                    }
-                }
-                self.mode = AppMode::Normal;
-                self.pending_close_buffer_id = None;
-            }
+    fn language_prompt_message(&self) -> String {
+        let current = self
+            .workspace
+            .active_buffer()
+            .and_then(|b| b.language())
+            .map_or("none", |lang| lang.name);
+        format!("Language (current {}; Tab completes, 'auto' detects): {}", current, self.language_input)
+    }
+
+    /// Handle key in the language prompt
+    fn handle_language_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
             KeyCode::Esc => {
-                // Cancel and return to normal mode
                 self.mode = AppMode::Normal;
-                self.message = Some("Close cancelled".to_string());
-                self.pending_close_buffer_id = None;
+                self.message = None;
             }
             KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+G: Cancel and return to normal mode (Emacs style)
                 self.mode = AppMode::Normal;
-                self.message = Some("Close cancelled".to_string());
-                self.pending_close_buffer_id = None;
+                self.message = None;
             }
-            _ => {
-                // Any other key cancels
+            KeyCode::Enter => {
                 self.mode = AppMode::Normal;
-                self.message = Some("Close cancelled".to_string());
-                self.pending_close_buffer_id = None;
+                let input = std::mem::take(&mut self.language_input);
+                self.apply_buffer_language(&input);
             }
-        }
-        Ok(ControlFlow::Continue)
-    }
-
-    /// Handle key in confirm sudo save mode
-    fn handle_confirm_sudo_save_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
-        match key.code {
-            KeyCode::Char('y') | KeyCode::Char('Y') => {
-                // User confirmed - set flag to execute on next cycle
-                self.execute_sudo_save_on_render = true;
-                self.mode = AppMode::Normal;
-                self.message = Some("Executing sudo save...".to_string());
+            KeyCode::Tab => {
+                // Complete to the first language starting with the input
+                let prefix = self.language_input.to_ascii_lowercase();
+                if let Some(lang) = LanguageRegistry::all().iter().find(|lang| lang.name.starts_with(&prefix)) {
+                    self.language_input = lang.name.to_string();
+                }
+                self.message = Some(self.language_prompt_message());
             }
-            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
-                // Cancel sudo save
-                self.mode = AppMode::Normal;
-                self.message = Some("Save cancelled".to_string());
-                self.pending_sudo_save_path = None;
-                self.pending_sudo_save_content = None;
+            KeyCode::Char(c) => {
+                self.language_input.push(c);
+                self.message = Some(self.language_prompt_message());
             }
-            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+G: Cancel (Emacs style)
-                self.mode = AppMode::Normal;
-                self.message = None;
-                self.pending_sudo_save_path = None;
-                self.pending_sudo_save_content = None;
+            KeyCode::Backspace => {
+                self.language_input.pop();
+                self.message = Some(self.language_prompt_message());
             }
             _ => {}
         }
         Ok(ControlFlow::Continue)
     }
 
-    /// Handle key in jump to line mode
-    fn handle_jump_to_line_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+    /// Set the language of the active buffer; "auto" detects it again
+    fn apply_buffer_language(&mut self, input: &str) {
+        let Some(buffer_id) = self.layout.active_buffer() else {
+            return;
+        };
+        let previous = self.workspace.get_buffer(buffer_id).and_then(|b| b.lsp_language());
+        let path = self.workspace.get_buffer(buffer_id).and_then(|b| b.file_path().cloned());
+        if input.trim().eq_ignore_ascii_case("auto") {
+            self.workspace.detect_language(buffer_id);
+        } else {
+            let Some(language) = LanguageRegistry::by_name(input) else {
+                self.message = Some(format!("Unknown language: {}", input));
+                return;
+            };
+            if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                buffer.set_language(Some(language));
+            }
+        }
+
+        let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+            return;
+        };
+        let current = buffer.lsp_language();
+        self.message = Some(format!("Language: {}", buffer.language().map_or("none", |lang| lang.name)));
+        if current == previous {
+            return;
+        }
+        // Hand the buffer from the old language server to the new one
+        if let (Some(path), Some(_)) = (path, previous) {
+            self.notify_lsp_did_close(buffer_id, path);
+        }
+        if current.is_some() {
+            self.notify_lsp_did_open();
+        }
+    }
+
+    /// Enter the encoding prompt for the active buffer
+    fn start_encoding_prompt(&mut self, action: EncodingAction) {
+        let Some(buffer) = self.workspace.active_buffer() else {
+            return;
+        };
+        if action == EncodingAction::Reopen && buffer.file_path().is_none() {
+            self.message = Some("Buffer has no file path".to_string());
+            return;
+        }
+        self.encoding_action = action;
+        self.encoding_input.clear();
+        self.mode = AppMode::EncodingPrompt;
+        self.message = Some(self.encoding_prompt_message());
+    }
+
+    fn encoding_prompt_message(&self) -> String {
+        let current = self
+            .workspace
+            .active_buffer()
+            .map(|b| b.text_buffer().encoding().name())
+            .unwrap_or_default();
+        let verb = match self.encoding_action {
+            EncodingAction::Reopen => "Reopen with",
+            EncodingAction::Convert => "Convert to",
+        };
+        format!("{} encoding (current {}): {}", verb, current, self.encoding_input)
+    }
+
+    /// Handle key in the encoding prompt
+    fn handle_encoding_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
             KeyCode::Esc => {
-                // Cancel jump to line
                 self.mode = AppMode::Normal;
                 self.message = None;
-                self.jump_to_line_input.clear();
             }
             KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+G: Cancel jump to line (Emacs style)
                 self.mode = AppMode::Normal;
                 self.message = None;
-                self.jump_to_line_input.clear();
             }
             KeyCode::Enter => {
-                // Jump to the specified line
-                if let Ok(line_num) = self.jump_to_line_input.parse::<usize>() {
-                    if let Some(buffer) = self.workspace.active_buffer_mut() {
-                        let text_buffer = buffer.text_buffer();
-                        let max_line = text_buffer.len_lines().saturating_sub(1);
-
-                        // Line numbers are 1-indexed for users, but 0-indexed internally
-                        let target_line = if line_num > 0 {
-                            (line_num - 1).min(max_line)
-                        } else {
-                            0
-                        };
-
-                        // Jump to the line
-                        let line_len = text_buffer.line_len(target_line);
-                        buffer.editor_state_mut().cursor.move_to(target_line, 0);
-                        // Center the view on the target line after jumping
-                        buffer.editor_state_mut().viewport.center_on_line(target_line);
-
-                        self.message = Some(format!("Line {}", target_line + 1));
-                    }
-                } else {
-                    self.message = Some("Invalid line number".to_string());
-                }
-
                 self.mode = AppMode::Normal;
-                self.jump_to_line_input.clear();
+                let label = std::mem::take(&mut self.encoding_input);
+                self.apply_encoding(&label);
             }
-            KeyCode::Char(c) if c.is_ascii_digit() => {
-                // Add digit to input
-                self.jump_to_line_input.push(c);
-                self.message = Some(format!("Go to line: {}", self.jump_to_line_input));
+            KeyCode::Char(c) => {
+                self.encoding_input.push(c);
+                self.message = Some(self.encoding_prompt_message());
             }
-            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.jump_to_line_input.pop();
-                if self.jump_to_line_input.is_empty() {
-                    self.message = Some("Go to line:".to_string());
-                } else {
-                    self.message = Some(format!("Go to line: {}", self.jump_to_line_input));
-                }
+            KeyCode::Backspace => {
+                self.encoding_input.pop();
+                self.message = Some(self.encoding_prompt_message());
             }
             _ => {}
         }
         Ok(ControlFlow::Continue)
     }
 
-    /// Handle key in save-as prompt mode
-    fn handle_save_as_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
-        match key.code {
-            KeyCode::Esc => {
-                // Cancel save-as
-                self.mode = AppMode::Normal;
-                self.message = Some("Save cancelled".to_string());
-                self.save_as_filename.clear();
-            }
-            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+G: Cancel save-as (Emacs style)
-                self.mode = AppMode::Normal;
-                self.message = Some("Save cancelled".to_string());
-                self.save_as_filename.clear();
+    /// Reopen or convert the active buffer with the encoding named `label`
+    fn apply_encoding(&mut self, label: &str) {
+        let Some(encoding) = FileEncoding::for_label(label) else {
+            self.message = Some(format!("Unknown encoding: {}", label));
+            return;
+        };
+        let Some(buffer_id) = self.layout.active_buffer() else {
+            return;
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return;
+        };
+
+        match self.encoding_action {
+            EncodingAction::Reopen => {
+                if buffer.is_modified() {
+                    self.message = Some("Buffer has unsaved changes; save or undo them first".to_string());
+                    return;
+                }
+                match buffer.reopen_with_encoding(encoding) {
+                    Ok(()) => {
+                        self.message = Some(format!("Reopened as {}", encoding.name()));
+                        self.notify_lsp_buffer_changed(buffer_id);
+                    }
+                    Err(e) => self.message = Some(format!("Failed to reopen: {}", e)),
+                }
             }
-            KeyCode::Enter => {
-                // Save with the specified filename
-                if self.save_as_filename.is_empty() {
-                    self.message = Some("Filename cannot be empty".to_string());
-                    return Ok(ControlFlow::Continue);
+            EncodingAction::Convert => {
+                // Refuse up front rather than failing on save
+                if let Err(e) = encoding.encode(&buffer.text_buffer().to_string()) {
+                    self.message = Some(format!("Cannot convert: {}", e));
+                    return;
                 }
+                buffer.text_buffer_mut().set_encoding(encoding);
+                self.message = Some(format!("Will be saved as {}", encoding.name()));
+            }
+        }
+    }
 
-                // Get current working directory
-                let current_dir = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
-                let file_path = current_dir.join(&self.save_as_filename);
+    /// Rows of a hex buffer visible on screen
+    fn hex_visible_rows() -> usize {
+        let (_, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        height.saturating_sub(3) as usize
+    }
 
-                // Get the active buffer and save it
-                if let Some(buffer_id) = self.layout.active_buffer() {
-                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                        // Get the content to save
-                        let content = buffer.text_buffer().to_string();
+    /// Write a hex buffer back to its file, with a backup like text buffers
+    fn save_hex_buffer(&mut self, buffer_id: crate::workspace::BufferId) -> Result<()> {
+        let Some(hex) = self.workspace.get_hex_buffer_mut(buffer_id) else {
+            return Ok(());
+        };
+        // Try to create backup (ignore errors - backup is optional)
+        let _ = self.backup_manager.create_backup(hex.file_path());
+        hex.data_mut().save()
+    }
 
-                        // Try to write the file
-                        match std::fs::write(&file_path, &content) {
-                            Ok(_) => {
-                                // Update the buffer with the new file path
-                                buffer.text_buffer_mut().set_file_path(file_path.clone());
-                                buffer.text_buffer_mut().set_modified(false);
+    /// Handle key in normal mode when the active buffer is a hex buffer.
+    /// Typing overwrites bytes; Tab switches between the hex and ASCII columns.
+    fn handle_hex_buffer_key(&mut self, buffer_id: crate::workspace::BufferId, key: KeyEvent) -> Result<ControlFlow> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        self.hex_search_match = None;
 
-                                self.message = Some(format!("Saved as {}", file_path.display()));
-                                self.notify_lsp_did_save();
-                            }
-                            Err(e) => {
-                                self.message = Some(format!("Save failed: {}", e));
-                            }
-                        }
+        // Ctrl+X chords: save, exit and the command palette
+        if self.waiting_for_second_key {
+            self.waiting_for_second_key = false;
+            match key.code {
+                KeyCode::Char('s') if ctrl => match self.save_hex_buffer(buffer_id) {
+                    Ok(()) => self.message = Some("Saved".to_string()),
+                    Err(e) => self.message = Some(format!("Save failed: {}", e)),
+                },
+                KeyCode::Char('c') if ctrl => {
+                    if self.workspace.has_modified_buffers() {
+                        self.mode = AppMode::ConfirmExit;
+                        self.message = Some("Save modified buffers? (y/n)".to_string());
+                        return Ok(ControlFlow::Continue);
                     }
+                    return Ok(ControlFlow::Exit);
                 }
+                KeyCode::Char('p') | KeyCode::Char('P') if ctrl => {
+                    self.mode = AppMode::CommandPanel;
+                    self.command_panel_pattern.clear();
+                    self.command_panel_results = self.filter_commands("");
+                    self.command_panel_selected = 0;
+                    self.command_panel_scroll_offset = 0;
+                    self.message = Some("Command Palette (Ctrl+X Ctrl+P)".to_string());
+                }
+                _ => self.message = Some("Not available in hex buffers".to_string()),
+            }
+            return Ok(ControlFlow::Continue);
+        }
 
-                self.mode = AppMode::Normal;
-                self.save_as_filename.clear();
+        match (key.code, key.modifiers) {
+            (KeyCode::Char('x'), KeyModifiers::CONTROL) => {
+                self.waiting_for_second_key = true;
+                return Ok(ControlFlow::Continue);
             }
-            KeyCode::Char(c) => {
-                // Add character to filename
-                self.save_as_filename.push(c);
-                self.message = Some(format!("Save as: {}", self.save_as_filename));
+            (KeyCode::Char('q'), KeyModifiers::CONTROL) => {
+                if self.workspace.has_modified_buffers() {
+                    self.mode = AppMode::ConfirmExit;
+                    self.message = Some("Save modified buffers? (y/n)".to_string());
+                    return Ok(ControlFlow::Continue);
+                }
+                return Ok(ControlFlow::Exit);
             }
-            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.save_as_filename.pop();
-                if self.save_as_filename.is_empty() {
-                    self.message = Some("Save as:".to_string());
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                if self.workspace.get_hex_buffer(buffer_id).is_some_and(|hex| hex.is_modified()) {
+                    self.mode = AppMode::ConfirmCloseTab;
+                    self.pending_close_buffer_id = Some(buffer_id);
+                    self.message = Some("Save buffer before closing? (y/n)".to_string());
+                    return Ok(ControlFlow::Continue);
+                }
+                self.workspace.force_close_buffer(buffer_id);
+                let next_id = match self.workspace.buffer_ids().first() {
+                    Some(&next_id) => next_id,
+                    None => self.workspace.new_buffer(),
+                };
+                let pane = self.layout.active_pane();
+                self.layout.set_buffer(pane, next_id);
+                self.message = Some("Buffer closed".to_string());
+                return Ok(ControlFlow::Continue);
+            }
+            (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                if self.file_tree.is_some() {
+                    self.mode = AppMode::FilePicker;
+                    self.file_picker_pattern.clear();
+                    self.file_picker_selected = 0;
+                    self.file_picker_scroll_offset = 0;
+                    self.update_file_picker_results();
                 } else {
-                    self.message = Some(format!("Save as: {}", self.save_as_filename));
+                    self.message = Some("No project directory open".to_string());
+                }
+                return Ok(ControlFlow::Continue);
+            }
+            (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                let new_buffer_id = self.workspace.new_buffer();
+                let pane = self.layout.active_pane();
+                self.layout.set_buffer(pane, new_buffer_id);
+                self.message = Some("New buffer created".to_string());
+                return Ok(ControlFlow::Continue);
+            }
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                self.start_hex_search(false);
+                return Ok(ControlFlow::Continue);
+            }
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.start_hex_search(true);
+                return Ok(ControlFlow::Continue);
+            }
+            _ => {}
+        }
+
+        let Some(hex) = self.workspace.get_hex_buffer_mut(buffer_id) else {
+            return Ok(ControlFlow::Continue);
+        };
+        let row = HexBuffer::BYTES_PER_ROW as isize;
+        let page = row * Self::hex_visible_rows() as isize;
+        let row_start = hex.cursor() - hex.cursor() % HexBuffer::BYTES_PER_ROW;
+
+        self.message = None;
+        match (key.code, key.modifiers) {
+            (KeyCode::Left, _) => hex.move_cursor(-1),
+            (KeyCode::Right, _) => hex.move_cursor(1),
+            (KeyCode::Up, _) => hex.move_cursor(-row),
+            (KeyCode::Down, _) => hex.move_cursor(row),
+            (KeyCode::PageUp, _) => hex.move_cursor(-page),
+            (KeyCode::PageDown, _) => hex.move_cursor(page),
+            (KeyCode::Home, KeyModifiers::CONTROL) => hex.set_cursor(0),
+            (KeyCode::End, KeyModifiers::CONTROL) => hex.set_cursor(usize::MAX),
+            (KeyCode::Home, _) | (KeyCode::Char('a'), KeyModifiers::CONTROL) => hex.set_cursor(row_start),
+            (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                hex.set_cursor(row_start + HexBuffer::BYTES_PER_ROW - 1)
+            }
+            (KeyCode::Tab, _) => hex.toggle_column(),
+            (KeyCode::Char(c), mods) if !mods.contains(KeyModifiers::CONTROL) && hex.ascii_column() => {
+                if c.is_ascii() {
+                    hex.type_byte(c as u8)?;
+                } else {
+                    self.message = Some("Only ASCII characters can be typed in the ASCII column".to_string());
                 }
             }
+            (KeyCode::Char(c), mods) if !mods.contains(KeyModifiers::CONTROL) => match c.to_digit(16) {
+                Some(digit) => hex.type_hex_digit(digit as u8)?,
+                None => self.message = Some("Type hex digits, or Tab to edit the ASCII column".to_string()),
+            },
             _ => {}
         }
+        hex.ensure_cursor_visible(Self::hex_visible_rows());
         Ok(ControlFlow::Continue)
     }
 
-    /// Handle key in replace prompt mode (entering search pattern)
-    fn handle_replace_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+    /// Enter the search prompt for the active hex buffer
+    fn start_hex_search(&mut self, reverse: bool) {
+        self.mode = AppMode::HexSearch;
+        self.hex_search_pattern.clear();
+        self.hex_search_reverse = reverse;
+        self.message = Some(self.hex_search_message());
+    }
+
+    fn hex_search_message(&self) -> String {
+        let direction = if self.hex_search_reverse { "Reverse hex search" } else { "Hex search" };
+        let mode = if self.hex_search_text { "text, Tab: hex bytes" } else { "hex bytes, Tab: text" };
+        format!("{} ({}): {}", direction, mode, self.hex_search_pattern)
+    }
+
+    /// Handle key in the hex search prompt. Enter finds the next match and
+    /// leaves the prompt; Ctrl+S/Ctrl+R find the next/previous match; Tab
+    /// switches between searching hex bytes and text.
+    fn handle_hex_search_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
         match key.code {
-            KeyCode::Esc | KeyCode::Char('g') if key.code == KeyCode::Esc || key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Cancel replace
+            KeyCode::Esc => {
                 self.mode = AppMode::Normal;
+                self.hex_search_match = None;
                 self.message = None;
-                self.replace_pattern.clear();
             }
-            KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+T: Toggle regex mode
-                self.search_use_regex = !self.search_use_regex;
-                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("Replace{}: {}", regex_indicator, self.replace_pattern));
+            KeyCode::Char('g') if ctrl => {
+                self.mode = AppMode::Normal;
+                self.hex_search_match = None;
+                self.message = None;
             }
             KeyCode::Enter => {
-                if !self.replace_pattern.is_empty() {
-                    // Move to replacement string prompt
-                    self.mode = AppMode::ReplaceEnterRepl;
-                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                    self.message = Some(format!("Replace{} \"{}\" with:", regex_indicator, self.replace_pattern));
-                } else {
-                    // Empty pattern, cancel
-                    self.mode = AppMode::Normal;
-                    self.message = None;
+                self.mode = AppMode::Normal;
+                if self.hex_search_match.is_none() {
+                    self.hex_search_next();
                 }
             }
-            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Add character to pattern
-                self.replace_pattern.push(c);
-                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("Replace{}: {}", regex_indicator, self.replace_pattern));
+            KeyCode::Char('s') if ctrl => {
+                self.hex_search_reverse = false;
+                self.hex_search_next();
             }
-            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.replace_pattern.pop();
-                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("Replace{}: {}", regex_indicator, self.replace_pattern));
+            KeyCode::Char('r') if ctrl => {
+                self.hex_search_reverse = true;
+                self.hex_search_next();
+            }
+            KeyCode::Tab => {
+                self.hex_search_text = !self.hex_search_text;
+                self.hex_search_match = None;
+                self.message = Some(self.hex_search_message());
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.hex_search_pattern.push(c);
+                self.hex_search_match = None;
+                self.message = Some(self.hex_search_message());
+            }
+            KeyCode::Backspace => {
+                self.hex_search_pattern.pop();
+                self.hex_search_match = None;
+                self.message = Some(self.hex_search_message());
             }
             _ => {}
         }
         Ok(ControlFlow::Continue)
     }
 
-    /// Handle key in replace enter replacement mode (entering replacement string)
-    fn handle_replace_enter_repl_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+    /// Move the hex cursor to the next match of the search pattern
+    fn hex_search_next(&mut self) {
+        let Some(pattern) = parse_search_pattern(&self.hex_search_pattern, self.hex_search_text) else {
+            self.hex_search_match = None;
+            self.message = Some(format!("{} [not hex bytes]", self.hex_search_message()));
+            return;
+        };
+        let Some(hex) = self
+            .layout
+            .active_buffer()
+            .and_then(|id| self.workspace.get_hex_buffer_mut(id))
+        else {
+            return;
+        };
+
+        match hex.data().find(&pattern, hex.cursor(), self.hex_search_reverse) {
+            Some(offset) => {
+                hex.set_cursor(offset);
+                hex.ensure_cursor_visible(Self::hex_visible_rows());
+                self.hex_search_match = Some((offset, pattern.len()));
+                self.message = Some(format!("{} [found at {:#x}]", self.hex_search_message(), offset));
+            }
+            None => {
+                self.hex_search_match = None;
+                self.message = Some(format!("{} [not found]", self.hex_search_message()));
+            }
+        }
+    }
+
+    /// Handle key in confirm exit mode
+    fn handle_confirm_exit_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
-            KeyCode::Esc | KeyCode::Char('g') if key.code == KeyCode::Esc || key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Cancel replace
-                self.mode = AppMode::Normal;
-                self.message = None;
-                self.replace_pattern.clear();
-                self.replace_with.clear();
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                // Save all modified buffers then exit
+                let project_root = self.file_tree.as_ref().map(|t| t.root());
+                self.workspace.save_all_modified_buffers(&self.backup_manager, &self.local_history, project_root)?;
+                self.message = Some("Saved all buffers".to_string());
+                return Ok(ControlFlow::Exit);
             }
-            KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+T: Toggle regex mode
-                self.search_use_regex = !self.search_use_regex;
-                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("Replace{} \"{}\" with: {}", regex_indicator, self.replace_pattern, self.replace_with));
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                // Exit without saving
+                return Ok(ControlFlow::Exit);
             }
-            KeyCode::Enter => {
-                // Start replacing
-                self.replace_count = 0;
-                // Find first occurrence
-                if self.find_next_replace_match()? {
-                    self.mode = AppMode::ReplaceConfirm;
-                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                    self.message = Some(format!(
-                        "Replace{} \"{}\" with \"{}\"? (y)es, (n)o, (a)ll, (q)uit",
-                        regex_indicator, self.replace_pattern, self.replace_with
-                    ));
-                } else {
-                    // No matches found
-                    self.mode = AppMode::Normal;
-                    self.message = Some("No matches found".to_string());
-                    self.replace_pattern.clear();
-                    self.replace_with.clear();
-                }
+            KeyCode::Esc => {
+                // Cancel and return to normal mode
+                self.mode = AppMode::Normal;
+                self.message = None;
             }
-            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Add character to replacement
-                self.replace_with.push(c);
-                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("Replace{} \"{}\" with: {}", regex_indicator, self.replace_pattern, self.replace_with));
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+G: Cancel and return to normal mode (Emacs style)
+                self.mode = AppMode::Normal;
+                self.message = None;
             }
-            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.replace_with.pop();
-                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("Replace{} \"{}\" with: {}", regex_indicator, self.replace_pattern, self.replace_with));
+            _ => {
+                // Any other key cancels
+                self.mode = AppMode::Normal;
+                self.message = None;
             }
-            _ => {}
         }
         Ok(ControlFlow::Continue)
     }
 
-    /// Handle key in replace confirm mode (confirming each replacement)
-    fn handle_replace_confirm_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+    /// Handle key in confirm close tab mode
+    fn handle_confirm_close_tab_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
             KeyCode::Char('y') | KeyCode::Char('Y') => {
-                // Yes, replace current match
-                self.perform_replace()?;
-                self.replace_count += 1;
-                // Find next match
-                if self.find_next_replace_match()? {
-                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                    self.message = Some(format!(
-                        "Replace{} \"{}\" with \"{}\"? (y)es, (n)o, (a)ll, (q)uit [{} replaced]",
-                        regex_indicator, self.replace_pattern, self.replace_with, self.replace_count
-                    ));
-                } else {
-                    // No more matches
-                    self.mode = AppMode::Normal;
-                    self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
-                    self.replace_pattern.clear();
-                    self.replace_with.clear();
-                    if let Some(buffer) = self.workspace.active_buffer_mut() {
-                        buffer.editor_state_mut().clear_selection();
-                    }
-                }
-            }
-            KeyCode::Char('n') | KeyCode::Char('N') => {
-                // No, skip this match
-                // Find next match
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    let text_buffer = buffer.text_buffer();
-                    let current_pos = buffer.editor_state().cursor.position();
-                    let current_char = text_buffer.pos_to_char(current_pos)?;
-                    let new_pos = text_buffer.char_to_pos(current_char + 1);
-                    buffer.editor_state_mut().cursor.set_position(new_pos);
-                }
+                // Save buffer then close
+                if let Some(buffer_id) = self.pending_close_buffer_id {
+                    if self.workspace.get_hex_buffer(buffer_id).is_some() {
+                        if let Err(e) = self.save_hex_buffer(buffer_id) {
+                            self.message = Some(format!("Save failed: {}", e));
+                            self.mode = AppMode::Normal;
+                            self.pending_close_buffer_id = None;
+                            return Ok(ControlFlow::Continue);
+                        }
+                    }
+                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                        if let Some(path) = buffer.file_path().cloned() {
+                            // Try to create backup (ignore errors - backup is optional)
+                            let _ = self.backup_manager.create_backup(&path);
 
-                if self.find_next_replace_match()? {
-                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                    self.message = Some(format!(
-                        "Replace{} \"{}\" with \"{}\"? (y)es, (n)o, (a)ll, (q)uit [{} replaced]",
-                        regex_indicator, self.replace_pattern, self.replace_with, self.replace_count
-                    ));
-                } else {
-                    // No more matches
-                    self.mode = AppMode::Normal;
-                    self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
-                    self.replace_pattern.clear();
-                    self.replace_with.clear();
-                    if let Some(buffer) = self.workspace.active_buffer_mut() {
-                        buffer.editor_state_mut().clear_selection();
+                            buffer.apply_save_transforms()?;
+
+                            // Store content before attempting save (for sudo retry)
+                            let content = buffer.text_buffer().to_string();
+
+                            match buffer.text_buffer_mut().save() {
+                                Ok(_) => {
+                                    self.record_history(&path, &content);
+                                    self.message = Some("Saved".to_string());
+                                    self.notify_lsp_did_save();
+                                }
+                                Err(e) => {
+                                    if self.is_permission_denied(&e) {
+                                        // Set sudo save state
+                                        self.pending_sudo_save_path = Some(path);
+                                        self.pending_sudo_save_content = Some(content);
+                                        self.mode = AppMode::ConfirmSudoSave;
+                                        self.message = Some(
+                                            "Permission denied. Save with sudo? (y/n)".to_string()
+                                        );
+                                        // Keep the close pending
+                                        return Ok(ControlFlow::Continue);
+                                    } else {
+                                        self.message = Some(format!("Save failed: {}", e));
+                                        self.mode = AppMode::Normal;
+                                        self.pending_close_buffer_id = None;
+                                        return Ok(ControlFlow::Continue);
+                                    }
+                                }
+                            }
+                        } else {
+                            self.message = Some("No file path set".to_string());
+                            self.mode = AppMode::Normal;
+                            self.pending_close_buffer_id = None;
+                            return Ok(ControlFlow::Continue);
+                        }
                     }
-                }
-            }
-            KeyCode::Char('a') | KeyCode::Char('A') => {
-                // Replace all remaining matches
-                loop {
-                    self.perform_replace()?;
-                    self.replace_count += 1;
 
-                    // Move cursor forward
-                    if let Some(buffer) = self.workspace.active_buffer_mut() {
-                        let text_buffer = buffer.text_buffer();
-                        let current_pos = buffer.editor_state().cursor.position();
-                        let current_char = text_buffer.pos_to_char(current_pos)?;
-                        let new_pos = text_buffer.char_to_pos(current_char + self.replace_with.chars().count());
-                        buffer.editor_state_mut().cursor.set_position(new_pos);
+                    // Now close the buffer
+                    if let Err(e) = self.workspace.close_buffer(buffer_id) {
+                        self.message = Some(format!("Error closing buffer: {}", e));
+                    } else {
+                        // If there are remaining buffers, switch to another one
+                        let buffer_ids: Vec<_> = self.workspace.buffer_ids();
+                        if let Some(&next_id) = buffer_ids.first() {
+                            let pane = self.layout.active_pane();
+                            self.layout.set_buffer(pane, next_id);
+                            self.message = Some("Buffer saved and closed".to_string());
+                        } else {
+                            // No more buffers, open a new empty one
+                            let new_id = self.workspace.new_buffer();
+                            let pane = self.layout.active_pane();
+                            self.layout.set_buffer(pane, new_id);
+                            self.message = Some("New buffer".to_string());
+                            self.mode = AppMode::Normal;
+                            self.pending_close_buffer_id = None;
+                            return Ok(ControlFlow::Continue);
+                        }
                     }
+                }
+                self.mode = AppMode::Normal;
+                self.pending_close_buffer_id = None;
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                // Close without saving (force close)
+                if let Some(buffer_id) = self.pending_close_buffer_id {
+                    self.workspace.force_close_buffer(buffer_id);
 
-                    if !self.find_next_replace_match()? {
-                        break;
+                    // If there are remaining buffers, switch to another one
+                    let buffer_ids: Vec<_> = self.workspace.buffer_ids();
+                    if let Some(&next_id) = buffer_ids.first() {
+                        let pane = self.layout.active_pane();
+                        self.layout.set_buffer(pane, next_id);
+                        self.message = Some("Buffer closed without saving".to_string());
+                    } else {
+                        // No more buffers, open a new empty one
+                        let new_id = self.workspace.new_buffer();
+                        let pane = self.layout.active_pane();
+                        self.layout.set_buffer(pane, new_id);
+                        self.message = Some("New buffer".to_string());
+                        self.mode = AppMode::Normal;
+                        self.pending_close_buffer_id = None;
+                        return Ok(ControlFlow::Continue);
                     }
                 }
+                self.mode = AppMode::Normal;
+                self.pending_close_buffer_id = None;
+            }
+            KeyCode::Esc => {
+                // Cancel and return to normal mode
+                self.mode = AppMode::Normal;
+                self.message = Some("Close cancelled".to_string());
+                self.pending_close_buffer_id = None;
+            }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+G: Cancel and return to normal mode (Emacs style)
+                self.mode = AppMode::Normal;
+                self.message = Some("Close cancelled".to_string());
+                self.pending_close_buffer_id = None;
+            }
+            _ => {
+                // Any other key cancels
+                self.mode = AppMode::Normal;
+                self.message = Some("Close cancelled".to_string());
+                self.pending_close_buffer_id = None;
+            }
+        }
+        Ok(ControlFlow::Continue)
+    }
 
-                // Done replacing all
+    /// Handle key in confirm sudo save mode
+    fn handle_confirm_sudo_save_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                // User confirmed - set flag to execute on next cycle
+                self.execute_sudo_save_on_render = true;
                 self.mode = AppMode::Normal;
-                self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
-                self.replace_pattern.clear();
-                self.replace_with.clear();
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    buffer.editor_state_mut().clear_selection();
-                }
+                self.message = Some("Executing sudo save...".to_string());
             }
-            KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => {
-                // Quit replacing
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                // Cancel sudo save
                 self.mode = AppMode::Normal;
-                self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
-                self.replace_pattern.clear();
-                self.replace_with.clear();
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    buffer.editor_state_mut().clear_selection();
-                }
+                self.message = Some("Save cancelled".to_string());
+                self.pending_sudo_save_path = None;
+                self.pending_sudo_save_content = None;
             }
             KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+G: Quit (Emacs style)
+                // Ctrl+G: Cancel (Emacs style)
                 self.mode = AppMode::Normal;
-                self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
-                self.replace_pattern.clear();
-                self.replace_with.clear();
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    buffer.editor_state_mut().clear_selection();
-                }
+                self.message = None;
+                self.pending_sudo_save_path = None;
+                self.pending_sudo_save_content = None;
             }
             _ => {}
         }
         Ok(ControlFlow::Continue)
     }
 
-    /// Handle key in search mode
-    fn handle_search_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+    /// Handle key in jump to line mode
+    fn handle_jump_to_line_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
             KeyCode::Esc => {
-                // Cancel search
+                // Cancel jump to line
                 self.mode = AppMode::Normal;
                 self.message = None;
-                self.search_pattern.clear();
-                // Clear selection when exiting search
-                if let Some(buffer_id) = self.layout.active_buffer() {
-                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                        buffer.editor_state_mut().clear_selection();
-                    }
-                }
+                self.jump_to_line_input.clear();
             }
             KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+G: Cancel search (Emacs style)
+                // Ctrl+G: Cancel jump to line (Emacs style)
                 self.mode = AppMode::Normal;
                 self.message = None;
-                self.search_pattern.clear();
-                // Clear selection when exiting search
-                if let Some(buffer_id) = self.layout.active_buffer() {
-                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                        buffer.editor_state_mut().clear_selection();
-                    }
-                }
+                self.jump_to_line_input.clear();
             }
-            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+S in search mode: search forward for next occurrence
-                if !self.search_pattern.is_empty() {
-                    self.search_is_reverse = false;
+            KeyCode::Enter => {
+                // Jump to the specified line
+                if let Ok(line_num) = self.jump_to_line_input.parse::<usize>() {
+                    if let Some(buffer) = self.workspace.active_buffer_mut() {
+                        let text_buffer = buffer.text_buffer();
+                        let max_line = text_buffer.len_lines().saturating_sub(1);
 
-                    // Move cursor forward by one character to find next match
-                    if let Some(buffer_id) = self.layout.active_buffer() {
-                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                            let current_pos = buffer.editor_state().cursor.position();
-                            let text_buffer = buffer.text_buffer();
-                            let current_char = text_buffer.pos_to_char(current_pos)?;
-                            let new_pos = text_buffer.char_to_pos(current_char + 1);
-                            buffer.editor_state_mut().cursor.set_position(new_pos);
-                        }
-                    }
+                        // Line numbers are 1-indexed for users, but 0-indexed internally
+                        let target_line = if line_num > 0 {
+                            (line_num - 1).min(max_line)
+                        } else {
+                            0
+                        };
 
-                    // Search (will wrap automatically if needed)
-                    self.perform_search()?;
+                        // Jump to the line
+                        let line_len = text_buffer.line_len(target_line);
+                        buffer.editor_state_mut().cursor.move_to(target_line, 0);
+                        // Center the view on the target line after jumping
+                        buffer.editor_state_mut().viewport.center_on_line(target_line);
+
+                        self.message = Some(format!("Line {}", target_line + 1));
+                    }
+                } else {
+                    self.message = Some("Invalid line number".to_string());
                 }
+
+                self.mode = AppMode::Normal;
+                self.jump_to_line_input.clear();
             }
-            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+R in search mode: search backward for previous occurrence
-                if !self.search_pattern.is_empty() {
-                    self.search_is_reverse = true;
+            KeyCode::Char(c) if c.is_ascii_digit() => {
+                // Add digit to input
+                self.jump_to_line_input.push(c);
+                self.message = Some(format!("Go to line: {}", self.jump_to_line_input));
+            }
+            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.jump_to_line_input.pop();
+                if self.jump_to_line_input.is_empty() {
+                    self.message = Some("Go to line:".to_string());
+                } else {
+                    self.message = Some(format!("Go to line: {}", self.jump_to_line_input));
+                }
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
 
-                    // Move cursor backward by one character to find previous match
-                    if let Some(buffer_id) = self.layout.active_buffer() {
-                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                            let current_pos = buffer.editor_state().cursor.position();
-                            let text_buffer = buffer.text_buffer();
-                            let current_char = text_buffer.pos_to_char(current_pos)?;
-                            if current_char > 0 {
-                                let new_pos = text_buffer.char_to_pos(current_char - 1);
-                                buffer.editor_state_mut().cursor.set_position(new_pos);
+    /// Handle key in save-as prompt mode
+    fn handle_save_as_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Esc => {
+                // Cancel save-as
+                self.mode = AppMode::Normal;
+                self.message = Some("Save cancelled".to_string());
+                self.save_as_filename.clear();
+            }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+G: Cancel save-as (Emacs style)
+                self.mode = AppMode::Normal;
+                self.message = Some("Save cancelled".to_string());
+                self.save_as_filename.clear();
+            }
+            KeyCode::Enter => {
+                // Save with the specified filename
+                if self.save_as_filename.is_empty() {
+                    self.message = Some("Filename cannot be empty".to_string());
+                    return Ok(ControlFlow::Continue);
+                }
+
+                // Get current working directory
+                let current_dir = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
+                let file_path = current_dir.join(&self.save_as_filename);
+
+                // Get the active buffer and save it
+                if let Some(buffer_id) = self.layout.active_buffer() {
+                    let editorconfig = self.workspace.editorconfig_for(&file_path);
+                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                        // A new buffer is written in the charset configured for its file
+                        if buffer.file_path().is_none() {
+                            if let Some(charset) = editorconfig.charset {
+                                buffer.text_buffer_mut().set_encoding(charset);
+                            }
+                        }
+                        buffer.set_editorconfig(editorconfig);
+                        buffer.apply_save_transforms()?;
+
+                        // Try to write the file; this also updates the buffer's path
+                        match buffer.text_buffer_mut().save_as(file_path.clone()) {
+                            Ok(_) => {
+                                let content = buffer.text_buffer().to_string();
+                                self.record_history(&file_path, &content);
+                                self.message = Some(format!("Saved as {}", file_path.display()));
+                                // The new name may mean a new language
+                                let language = self.workspace.get_buffer(buffer_id).and_then(|b| b.language());
+                                self.workspace.detect_language(buffer_id);
+                                if self.workspace.get_buffer(buffer_id).and_then(|b| b.language()) != language {
+                                    self.notify_lsp_did_open();
+                                }
+                                self.notify_lsp_did_save();
+                            }
+                            Err(e) => {
+                                self.message = Some(format!("Save failed: {}", e));
                             }
                         }
                     }
+                }
 
-                    // Search (will wrap automatically if needed)
-                    self.perform_search()?;
+                self.mode = AppMode::Normal;
+                self.save_as_filename.clear();
+            }
+            KeyCode::Char(c) => {
+                // Add character to filename
+                self.save_as_filename.push(c);
+                self.message = Some(format!("Save as: {}", self.save_as_filename));
+            }
+            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.save_as_filename.pop();
+                if self.save_as_filename.is_empty() {
+                    self.message = Some("Save as:".to_string());
+                } else {
+                    self.message = Some(format!("Save as: {}", self.save_as_filename));
                 }
             }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Handle key in replace prompt mode (entering search pattern)
+    fn handle_replace_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('g') if key.code == KeyCode::Esc || key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Cancel replace
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.replace_pattern.clear();
+            }
             KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 // Ctrl+T: Toggle regex mode
                 self.search_use_regex = !self.search_use_regex;
-                let search_type = if self.search_is_reverse { "Reverse search" } else { "Search" };
                 let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("{}{}: {}", search_type, regex_indicator, self.search_pattern));
-                // Re-search with new mode if pattern exists
-                if !self.search_pattern.is_empty() {
-                    if let Some(start_pos) = self.search_start_pos {
-                        if let Some(buffer_id) = self.layout.active_buffer() {
-                            if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                                buffer.editor_state_mut().cursor.set_position(start_pos);
-                            }
-                        }
-                    }
-                    let _ = self.perform_search();
-                }
+                self.message = Some(format!("Replace{}: {}", regex_indicator, self.replace_pattern));
             }
             KeyCode::Enter => {
-                // Enter: exit search mode
-                self.mode = AppMode::Normal;
-                self.message = None;
-                self.search_pattern.clear();
-                // Clear selection when exiting search
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    buffer.editor_state_mut().clear_selection();
+                if !self.replace_pattern.is_empty() {
+                    // Move to replacement string prompt
+                    self.mode = AppMode::ReplaceEnterRepl;
+                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                    self.message = Some(format!("Replace{} \"{}\" with:", regex_indicator, self.replace_pattern));
+                } else {
+                    // Empty pattern, cancel
+                    self.mode = AppMode::Normal;
+                    self.message = None;
                 }
             }
-            KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
-                // Arrow keys: exit search mode and handle the movement in normal mode
-                self.mode = AppMode::Normal;
-                self.message = None;
-                self.search_pattern.clear();
-                // Clear selection when exiting search
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    buffer.editor_state_mut().clear_selection();
-                }
-                // Re-handle the key event in normal mode
-                return self.handle_normal_mode(key);
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Add character to pattern
+                self.replace_pattern.push(c);
+                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                self.message = Some(format!("Replace{}: {}", regex_indicator, self.replace_pattern));
             }
-            KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+A: exit search mode and move to beginning of line
-                self.mode = AppMode::Normal;
-                self.message = None;
-                self.search_pattern.clear();
-                // Clear selection when exiting search
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    buffer.editor_state_mut().clear_selection();
-                }
-                // Re-handle the key event in normal mode
-                return self.handle_normal_mode(key);
+            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.replace_pattern.pop();
+                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                self.message = Some(format!("Replace{}: {}", regex_indicator, self.replace_pattern));
             }
-            KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Ctrl+E: exit search mode and move to end of line
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Handle key in replace enter replacement mode (entering replacement string)
+    fn handle_replace_enter_repl_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('g') if key.code == KeyCode::Esc || key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Cancel replace
                 self.mode = AppMode::Normal;
                 self.message = None;
-                self.search_pattern.clear();
-                // Clear selection when exiting search
-                if let Some(buffer) = self.workspace.active_buffer_mut() {
-                    buffer.editor_state_mut().clear_selection();
+                self.replace_pattern.clear();
+                self.replace_with.clear();
+            }
+            KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+T: Toggle regex mode
+                self.search_use_regex = !self.search_use_regex;
+                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                self.message = Some(format!("Replace{} \"{}\" with: {}", regex_indicator, self.replace_pattern, self.replace_with));
+            }
+            KeyCode::Enter => {
+                // Start replacing
+                self.replace_count = 0;
+                // Find first occurrence
+                if self.find_next_replace_match()? {
+                    self.mode = AppMode::ReplaceConfirm;
+                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                    self.message = Some(format!(
+                        "Replace{} \"{}\" with \"{}\"? (y)es, (n)o, (a)ll, (q)uit",
+                        regex_indicator, self.replace_pattern, self.replace_with
+                    ));
+                } else {
+                    // No matches found
+                    self.mode = AppMode::Normal;
+                    self.message = Some("No matches found".to_string());
+                    self.replace_pattern.clear();
+                    self.replace_with.clear();
                 }
-                // Re-handle the key event in normal mode
-                return self.handle_normal_mode(key);
             }
             KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Add character to search pattern
-                self.search_pattern.push(c);
-                // Update message to show current pattern
-                let search_type = if self.search_is_reverse { "Reverse search" } else { "Search" };
+                // Add character to replacement
+                self.replace_with.push(c);
                 let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("{}{}: {}", search_type, regex_indicator, self.search_pattern));
-                // Incremental search - search as we type
-                // Reset to start position before searching to stay on current match
-                if !self.search_pattern.is_empty() {
-                    if let Some(start_pos) = self.search_start_pos {
-                        if let Some(buffer_id) = self.layout.active_buffer() {
-                            if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                                buffer.editor_state_mut().cursor.set_position(start_pos);
-                            }
-                        }
-                    }
-                    let _ = self.perform_search(); // Ignore errors for incremental search
-                }
+                self.message = Some(format!("Replace{} \"{}\" with: {}", regex_indicator, self.replace_pattern, self.replace_with));
             }
             KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.search_pattern.pop();
-                // Update message to show current pattern
-                let search_type = if self.search_is_reverse { "Reverse search" } else { "Search" };
+                self.replace_with.pop();
                 let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
-                self.message = Some(format!("{}{}: {}", search_type, regex_indicator, self.search_pattern));
-                // Reset to start position and re-search with shorter pattern
-                if let Some(start_pos) = self.search_start_pos {
-                    if let Some(buffer_id) = self.layout.active_buffer() {
-                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                            buffer.editor_state_mut().cursor.set_position(start_pos);
-                        }
-                    }
-                }
-                if !self.search_pattern.is_empty() {
-                    let _ = self.perform_search();
-                }
+                self.message = Some(format!("Replace{} \"{}\" with: {}", regex_indicator, self.replace_pattern, self.replace_with));
             }
             _ => {}
         }
         Ok(ControlFlow::Continue)
     }
 
-    /// Perform search from current cursor position
-    /// Returns true if a match was found, false otherwise
-    fn perform_search(&mut self) -> Result<bool> {
-        let Some(buffer_id) = self.layout.active_buffer() else {
-            return Ok(false);
-        };
-        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
-            return Ok(false);
-        };
-
-        let text = buffer.text_buffer().to_string();
-        let current_pos = buffer.editor_state().cursor.position();
-        let current_char_idx = buffer.text_buffer().pos_to_char(current_pos)?;
-
-        // Find ALL matches in the buffer
-        let all_matches: Vec<(usize, usize)> = if self.search_use_regex {
+    /// Handle key in replace confirm mode (confirming each replacement)
+    fn handle_replace_confirm_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                // Yes, replace current match
+                self.perform_replace()?;
+                self.replace_count += 1;
+                // Find next match
+                if self.find_next_replace_match()? {
+                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                    self.message = Some(format!(
+                        "Replace{} \"{}\" with \"{}\"? (y)es, (n)o, (a)ll, (q)uit [{} replaced]",
+                        regex_indicator, self.replace_pattern, self.replace_with, self.replace_count
+                    ));
+                } else {
+                    // No more matches
+                    self.mode = AppMode::Normal;
+                    self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
+                    self.replace_pattern.clear();
+                    self.replace_with.clear();
+                    if let Some(buffer) = self.workspace.active_buffer_mut() {
+                        buffer.editor_state_mut().clear_selection();
+                    }
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                // No, skip this match
+                // Find next match
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    let text_buffer = buffer.text_buffer();
+                    let current_pos = buffer.editor_state().cursor.position();
+                    let current_char = text_buffer.pos_to_char(current_pos)?;
+                    let new_pos = text_buffer.char_to_pos(current_char + 1);
+                    buffer.editor_state_mut().cursor.set_position(new_pos);
+                }
+
+                if self.find_next_replace_match()? {
+                    let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                    self.message = Some(format!(
+                        "Replace{} \"{}\" with \"{}\"? (y)es, (n)o, (a)ll, (q)uit [{} replaced]",
+                        regex_indicator, self.replace_pattern, self.replace_with, self.replace_count
+                    ));
+                } else {
+                    // No more matches
+                    self.mode = AppMode::Normal;
+                    self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
+                    self.replace_pattern.clear();
+                    self.replace_with.clear();
+                    if let Some(buffer) = self.workspace.active_buffer_mut() {
+                        buffer.editor_state_mut().clear_selection();
+                    }
+                }
+            }
+            KeyCode::Char('a') | KeyCode::Char('A') => {
+                // Replace all remaining matches
+                loop {
+                    self.perform_replace()?;
+                    self.replace_count += 1;
+
+                    // Move cursor forward
+                    if let Some(buffer) = self.workspace.active_buffer_mut() {
+                        let text_buffer = buffer.text_buffer();
+                        let current_pos = buffer.editor_state().cursor.position();
+                        let current_char = text_buffer.pos_to_char(current_pos)?;
+                        let new_pos = text_buffer.char_to_pos(current_char + self.replace_with.chars().count());
+                        buffer.editor_state_mut().cursor.set_position(new_pos);
+                    }
+
+                    if !self.find_next_replace_match()? {
+                        break;
+                    }
+                }
+
+                // Done replacing all
+                self.mode = AppMode::Normal;
+                self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
+                self.replace_pattern.clear();
+                self.replace_with.clear();
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    buffer.editor_state_mut().clear_selection();
+                }
+            }
+            KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => {
+                // Quit replacing
+                self.mode = AppMode::Normal;
+                self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
+                self.replace_pattern.clear();
+                self.replace_with.clear();
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    buffer.editor_state_mut().clear_selection();
+                }
+            }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+G: Quit (Emacs style)
+                self.mode = AppMode::Normal;
+                self.message = Some(format!("Replaced {} occurrence(s)", self.replace_count));
+                self.replace_pattern.clear();
+                self.replace_with.clear();
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    buffer.editor_state_mut().clear_selection();
+                }
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Handle key in search mode
+    fn handle_search_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Esc => {
+                // Cancel search
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.search_pattern.clear();
+                // Clear selection when exiting search
+                if let Some(buffer_id) = self.layout.active_buffer() {
+                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                        buffer.editor_state_mut().clear_selection();
+                    }
+                }
+            }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+G: Cancel search (Emacs style)
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.search_pattern.clear();
+                // Clear selection when exiting search
+                if let Some(buffer_id) = self.layout.active_buffer() {
+                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                        buffer.editor_state_mut().clear_selection();
+                    }
+                }
+            }
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+S in search mode: search forward for next occurrence
+                if !self.search_pattern.is_empty() {
+                    self.search_is_reverse = false;
+
+                    // Move cursor forward by one character to find next match
+                    if let Some(buffer_id) = self.layout.active_buffer() {
+                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                            let current_pos = buffer.editor_state().cursor.position();
+                            let text_buffer = buffer.text_buffer();
+                            let current_char = text_buffer.pos_to_char(current_pos)?;
+                            let new_pos = text_buffer.char_to_pos(current_char + 1);
+                            buffer.editor_state_mut().cursor.set_position(new_pos);
+                        }
+                    }
+
+                    // Search (will wrap automatically if needed)
+                    self.perform_search()?;
+                }
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+R in search mode: search backward for previous occurrence
+                if !self.search_pattern.is_empty() {
+                    self.search_is_reverse = true;
+
+                    // Move cursor backward by one character to find previous match
+                    if let Some(buffer_id) = self.layout.active_buffer() {
+                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                            let current_pos = buffer.editor_state().cursor.position();
+                            let text_buffer = buffer.text_buffer();
+                            let current_char = text_buffer.pos_to_char(current_pos)?;
+                            if current_char > 0 {
+                                let new_pos = text_buffer.char_to_pos(current_char - 1);
+                                buffer.editor_state_mut().cursor.set_position(new_pos);
+                            }
+                        }
+                    }
+
+                    // Search (will wrap automatically if needed)
+                    self.perform_search()?;
+                }
+            }
+            KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+T: Toggle regex mode
+                self.search_use_regex = !self.search_use_regex;
+                let search_type = if self.search_is_reverse { "Reverse search" } else { "Search" };
+                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                self.message = Some(format!("{}{}: {}", search_type, regex_indicator, self.search_pattern));
+                // Re-search with new mode if pattern exists
+                if !self.search_pattern.is_empty() {
+                    if let Some(start_pos) = self.search_start_pos {
+                        if let Some(buffer_id) = self.layout.active_buffer() {
+                            if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                                buffer.editor_state_mut().cursor.set_position(start_pos);
+                            }
+                        }
+                    }
+                    let _ = self.perform_search();
+                }
+            }
+            KeyCode::Enter => {
+                // Enter: exit search mode
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.search_pattern.clear();
+                // Clear selection when exiting search
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    buffer.editor_state_mut().clear_selection();
+                }
+            }
+            KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+                // Arrow keys: exit search mode and handle the movement in normal mode
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.search_pattern.clear();
+                // Clear selection when exiting search
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    buffer.editor_state_mut().clear_selection();
+                }
+                // Re-handle the key event in normal mode
+                return self.handle_normal_mode(key);
+            }
+            KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+A: exit search mode and move to beginning of line
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.search_pattern.clear();
+                // Clear selection when exiting search
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    buffer.editor_state_mut().clear_selection();
+                }
+                // Re-handle the key event in normal mode
+                return self.handle_normal_mode(key);
+            }
+            KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Ctrl+E: exit search mode and move to end of line
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.search_pattern.clear();
+                // Clear selection when exiting search
+                if let Some(buffer) = self.workspace.active_buffer_mut() {
+                    buffer.editor_state_mut().clear_selection();
+                }
+                // Re-handle the key event in normal mode
+                return self.handle_normal_mode(key);
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Add character to search pattern
+                self.search_pattern.push(c);
+                // Update message to show current pattern
+                let search_type = if self.search_is_reverse { "Reverse search" } else { "Search" };
+                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                self.message = Some(format!("{}{}: {}", search_type, regex_indicator, self.search_pattern));
+                // Incremental search - search as we type
+                // Reset to start position before searching to stay on current match
+                if !self.search_pattern.is_empty() {
+                    if let Some(start_pos) = self.search_start_pos {
+                        if let Some(buffer_id) = self.layout.active_buffer() {
+                            if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                                buffer.editor_state_mut().cursor.set_position(start_pos);
+                            }
+                        }
+                    }
+                    let _ = self.perform_search(); // Ignore errors for incremental search
+                }
+            }
+            KeyCode::Backspace if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.search_pattern.pop();
+                // Update message to show current pattern
+                let search_type = if self.search_is_reverse { "Reverse search" } else { "Search" };
+                let regex_indicator = if self.search_use_regex { " [REGEX]" } else { "" };
+                self.message = Some(format!("{}{}: {}", search_type, regex_indicator, self.search_pattern));
+                // Reset to start position and re-search with shorter pattern
+                if let Some(start_pos) = self.search_start_pos {
+                    if let Some(buffer_id) = self.layout.active_buffer() {
+                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                            buffer.editor_state_mut().cursor.set_position(start_pos);
+                        }
+                    }
+                }
+                if !self.search_pattern.is_empty() {
+                    let _ = self.perform_search();
+                }
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Perform search from current cursor position
+    /// Returns true if a match was found, false otherwise
+    fn perform_search(&mut self) -> Result<bool> {
+        let Some(buffer_id) = self.layout.active_buffer() else {
+            return Ok(false);
+        };
+        let is_large_file = self.workspace.get_buffer(buffer_id)
+            .map(|b| b.text_buffer().is_large_file())
+            .unwrap_or(false);
+        if is_large_file {
+            return self.perform_line_search(buffer_id);
+        }
+        let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+            return Ok(false);
+        };
+
+        let text = buffer.text_buffer().to_string();
+        let current_pos = buffer.editor_state().cursor.position();
+        let current_char_idx = buffer.text_buffer().pos_to_char(current_pos)?;
+
+        // Find ALL matches in the buffer
+        let Some(all_matches) = self.search_match_ranges(&text) else {
+            return Ok(false);
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return Ok(false);
+        };
+
+        if all_matches.is_empty() {
+            self.search_all_matches.clear();
+            return Ok(false);
+        }
+
+        // Store all match positions for rendering highlights
+        self.search_all_matches = all_matches.iter()
+            .map(|&(char_idx, match_len)| {
+                let start = buffer.text_buffer().char_to_pos(char_idx);
+                let end = buffer.text_buffer().char_to_pos(char_idx + match_len);
+                (start, end)
+            })
+            .collect();
+
+        // Find the next match based on direction
+        let found_match = if self.search_is_reverse {
+            // Reverse search - find the last match that ends at or before cursor (or wrap to end)
+            all_matches.iter()
+                .filter(|(start, len)| *start + *len <= current_char_idx)
+                .last()
+                .or_else(|| all_matches.last()) // Wrap to last match if nothing before
+        } else {
+            // Forward search - find the first match at or after cursor (or wrap to beginning)
+            all_matches.iter()
+                .find(|(start, _)| *start >= current_char_idx)
+                .or_else(|| all_matches.first()) // Wrap to first match if nothing after
+        };
+
+        if let Some(&(char_idx, match_len)) = found_match {
+            let pos = buffer.text_buffer().char_to_pos(char_idx);
+            let end_char_idx = char_idx + match_len;
+            let end_pos = buffer.text_buffer().char_to_pos(end_char_idx);
+
+            // Move cursor to END of match (not start) to avoid interfering with first character highlight
+            buffer.editor_state_mut().cursor.set_position(end_pos);
+            // Center the view on the search result
+            buffer.editor_state_mut().viewport.center_on_line(end_pos.line);
+
+            // Select the found text (anchor at start, head at end)
+            buffer.editor_state_mut().selection = Some(crate::editor::state::Selection::new(pos, end_pos));
+            self.copy_selection_to_primary();
+
+            // Calculate current match index (1-based)
+            let current_index = all_matches.iter().position(|(start, _)| *start == char_idx).unwrap() + 1;
+            let total_count = all_matches.len();
+
+            self.message = Some(format!("Found: {} ({}/{})", self.search_pattern, current_index, total_count));
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Find every match of the search pattern in `text`, as (char index,
+    /// char length) pairs. Returns None (with a message) for an invalid regex.
+    fn search_match_ranges(&mut self, text: &str) -> Option<Vec<(usize, usize)>> {
+        let matches = if self.search_use_regex {
             // Regex search (case-insensitive by default)
             match RegexBuilder::new(&self.search_pattern).case_insensitive(true).build() {
                 Ok(re) => {
-                    re.find_iter(&text)
+                    re.find_iter(text)
                         .map(|m| {
                             let char_start = text[..m.start()].chars().count();
                             let char_len = text[m.start()..m.end()].chars().count();
@@ -2948,284 +4220,1098 @@ impl App {
                         })
                         .collect()
                 }
-                Err(_) => {
-                    // Invalid regex, show error
-                    self.message = Some(format!("Invalid regex: {}", self.search_pattern));
-                    return Ok(false);
+                Err(_) => {
+                    // Invalid regex, show error
+                    self.message = Some(format!("Invalid regex: {}", self.search_pattern));
+                    return None;
+                }
+            }
+        } else {
+            // Plain string search (case-insensitive)
+            let text_lower = text.to_lowercase();
+            let pattern_lower = self.search_pattern.to_lowercase();
+            let mut matches = Vec::new();
+            let mut search_pos = 0;
+
+            while let Some(byte_offset) = text_lower[search_pos..].find(&pattern_lower) {
+                let absolute_byte_pos = search_pos + byte_offset;
+                let char_idx = text_lower[..absolute_byte_pos].chars().count();
+                let match_len = self.search_pattern.chars().count();
+                matches.push((char_idx, match_len));
+                search_pos = absolute_byte_pos + pattern_lower.len();
+            }
+            matches
+        };
+        Some(matches)
+    }
+
+    /// Update the search highlights after text was appended to the active
+    /// buffer, or jump to the first match if there was none yet
+    fn refresh_search_matches(&mut self) -> Result<()> {
+        if self.search_pattern.is_empty() {
+            return Ok(());
+        }
+        if self.search_all_matches.is_empty() {
+            self.perform_search()?;
+            return Ok(());
+        }
+        let Some(buffer_id) = self.layout.active_buffer() else {
+            return Ok(());
+        };
+        // Large files only ever highlight the current match
+        let text = match self.workspace.get_buffer(buffer_id) {
+            Some(buffer) if !buffer.text_buffer().is_large_file() => buffer.text_buffer().to_string(),
+            _ => return Ok(()),
+        };
+        let Some(matches) = self.search_match_ranges(&text) else {
+            return Ok(());
+        };
+        if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
+            self.search_all_matches = matches
+                .iter()
+                .map(|&(char_idx, match_len)| {
+                    let start = buffer.text_buffer().char_to_pos(char_idx);
+                    let end = buffer.text_buffer().char_to_pos(char_idx + match_len);
+                    (start, end)
+                })
+                .collect();
+        }
+        Ok(())
+    }
+
+    /// Incremental search for large files: find only the next match, one
+    /// line at a time, instead of collecting every match in the whole text
+    fn perform_line_search(&mut self, buffer_id: crate::workspace::BufferId) -> Result<bool> {
+        let pattern = if self.search_use_regex {
+            self.search_pattern.clone()
+        } else {
+            regex::escape(&self.search_pattern)
+        };
+        let re = match RegexBuilder::new(&pattern).case_insensitive(true).build() {
+            Ok(re) => re,
+            Err(_) => {
+                self.message = Some(format!("Invalid regex: {}", self.search_pattern));
+                return Ok(false);
+            }
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return Ok(false);
+        };
+
+        let current_pos = buffer.editor_state().cursor.position();
+        let Some((start, end)) = find_in_lines(buffer.text_buffer(), &re, current_pos, self.search_is_reverse) else {
+            self.search_all_matches.clear();
+            return Ok(false);
+        };
+
+        // Same placement as the regular search: cursor at the end, match selected
+        let editor_state = buffer.editor_state_mut();
+        editor_state.cursor.set_position(end);
+        editor_state.viewport.center_on_line(end.line);
+        editor_state.ensure_cursor_visible();
+        editor_state.selection = Some(crate::editor::state::Selection::new(start, end));
+        self.search_all_matches = vec![(start, end)];
+        self.copy_selection_to_primary();
+
+        self.message = Some(format!("Found: {}", self.search_pattern));
+        Ok(true)
+    }
+
+    /// Find the next match for replacement (forward search only)
+    /// Returns true if a match was found, false otherwise
+    fn find_next_replace_match(&mut self) -> Result<bool> {
+        let Some(buffer) = self.workspace.active_buffer_mut() else {
+            return Ok(false);
+        };
+
+        let text = buffer.text_buffer().to_string();
+        let current_pos = buffer.editor_state().cursor.position();
+        let current_char_idx = buffer.text_buffer().pos_to_char(current_pos)?;
+
+        // Search using regex or plain string
+        let found_match: Option<(usize, usize)> = if self.search_use_regex {
+            // Regex search (case-insensitive by default)
+            match RegexBuilder::new(&self.replace_pattern).case_insensitive(true).build() {
+                Ok(re) => {
+                    // Forward search only
+                    let after_chars: String = text.chars().skip(current_char_idx).collect();
+                    re.find(&after_chars).map(|m| {
+                        let char_start = current_char_idx + after_chars[..m.start()].chars().count();
+                        let char_len = after_chars[m.start()..m.end()].chars().count();
+                        (char_start, char_len)
+                    })
+                }
+                Err(_) => {
+                    // Invalid regex, return no match
+                    return Ok(false);
+                }
+            }
+        } else {
+            // Plain string search (case-insensitive)
+            let text_lower = text.to_lowercase();
+            let pattern_lower = self.replace_pattern.to_lowercase();
+
+            // Forward search only
+            let after_chars: String = text_lower.chars().skip(current_char_idx).collect();
+            after_chars.find(&pattern_lower).map(|byte_offset| {
+                let char_idx = current_char_idx + after_chars[..byte_offset].chars().count();
+                let match_len = self.replace_pattern.chars().count();
+                (char_idx, match_len)
+            })
+        };
+
+        if let Some((char_idx, match_len)) = found_match {
+            let pos = buffer.text_buffer().char_to_pos(char_idx);
+            let end_char_idx = char_idx + match_len;
+            let end_pos = buffer.text_buffer().char_to_pos(end_char_idx);
+
+            // Move cursor to END of match (not start) to avoid interfering with first character highlight
+            buffer.editor_state_mut().cursor.set_position(end_pos);
+            buffer.editor_state_mut().ensure_cursor_visible();
+
+            // Select the found text (anchor at start, head at end)
+            buffer.editor_state_mut().selection = Some(crate::editor::state::Selection::new(pos, end_pos));
+            self.copy_selection_to_primary();
+
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Perform the replacement of currently selected text
+    fn perform_replace(&mut self) -> Result<()> {
+        let Some(buffer) = self.workspace.active_buffer_mut() else {
+            return Ok(());
+        };
+
+        // Delete the selected text and insert replacement
+        if let Some(selection) = buffer.editor_state().selection {
+            let (start, end) = selection.range();
+            let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+
+            // Delete the matched text
+            if let Ok(deleted) = text_buffer.delete_range(start, end) {
+                undo_manager.record(Change::Delete {
+                    pos: start,
+                    text: deleted,
+                });
+
+                // Insert the replacement
+                text_buffer.insert(start, &self.replace_with)?;
+                undo_manager.record(Change::Insert {
+                    pos: start,
+                    text: self.replace_with.clone(),
+                });
+
+                // Move cursor to end of replacement
+                editor_state.cursor.set_position(start);
+                editor_state.ensure_cursor_visible();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle key in completion mode
+    fn handle_completion_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        const MAX_VISIBLE: usize = 10;
+
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // Cancel completion
+                self.mode = AppMode::Normal;
+                self.completion_items.clear();
+                self.completion_selected = 0;
+                self.completion_scroll_offset = 0;
+                self.message = None;
+            }
+            KeyCode::Up => {
+                // Move selection up
+                if self.completion_selected > 0 {
+                    self.completion_selected -= 1;
+                    // Scroll up if needed
+                    if self.completion_selected < self.completion_scroll_offset {
+                        self.completion_scroll_offset = self.completion_selected;
+                    }
+                }
+            }
+            KeyCode::Down => {
+                // Move selection down
+                if self.completion_selected < self.completion_items.len().saturating_sub(1) {
+                    self.completion_selected += 1;
+                    // Scroll down if needed
+                    if self.completion_selected >= self.completion_scroll_offset + MAX_VISIBLE {
+                        self.completion_scroll_offset = self.completion_selected - MAX_VISIBLE + 1;
+                    }
                 }
             }
-        } else {
-            // Plain string search (case-insensitive)
-            let text_lower = text.to_lowercase();
-            let pattern_lower = self.search_pattern.to_lowercase();
-            let mut matches = Vec::new();
-            let mut search_pos = 0;
+            KeyCode::Enter | KeyCode::Tab => {
+                // Insert selected completion
+                if let Some(item) = self.completion_items.get(self.completion_selected) {
+                    if let Some(buffer) = self.workspace.active_buffer_mut() {
+                        let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+                        let pos = editor_state.cursor.position();
 
-            while let Some(byte_offset) = text_lower[search_pos..].find(&pattern_lower) {
-                let absolute_byte_pos = search_pos + byte_offset;
-                let char_idx = text_lower[..absolute_byte_pos].chars().count();
-                let match_len = self.search_pattern.chars().count();
-                matches.push((char_idx, match_len));
-                search_pos = absolute_byte_pos + pattern_lower.len();
+                        // Find the start of the current word (go back while alphanumeric)
+                        let line = text_buffer.get_line(pos.line).unwrap_or_default();
+                        let mut word_start_col = pos.column;
+                        let chars: Vec<char> = line.chars().collect();
+
+                        while word_start_col > 0 {
+                            let idx = word_start_col - 1;
+                            if idx < chars.len() {
+                                let ch = chars[idx];
+                                if ch.is_alphanumeric() || ch == '_' {
+                                    word_start_col -= 1;
+                                } else {
+                                    break;
+                                }
+                            } else {
+                                break;
+                            }
+                        }
+
+                        // Delete the partial word
+                        if word_start_col < pos.column {
+                            let delete_start = crate::buffer::Position::new(pos.line, word_start_col);
+                            let delete_end = pos;
+                            if let Ok(deleted) = text_buffer.delete_range(delete_start, delete_end) {
+                                undo_manager.record(Change::Delete {
+                                    pos: delete_start,
+                                    text: deleted,
+                                });
+                                editor_state.cursor.set_position(delete_start);
+                            }
+                        }
+
+                        // Insert the completion text (use insert_text if available, otherwise label)
+                        let completion_text = item.insert_text.as_ref()
+                            .unwrap_or(&item.label);
+                        let insert_pos = editor_state.cursor.position();
+                        text_buffer.insert(insert_pos, completion_text)?;
+                        undo_manager.record(Change::Insert {
+                            pos: insert_pos,
+                            text: completion_text.to_string(),
+                        });
+
+                        // Move cursor to end of inserted text
+                        editor_state.cursor.column += completion_text.chars().count();
+                        editor_state.ensure_cursor_visible();
+                    }
+
+                    self.message = Some(format!("Inserted: {}", item.label));
+                }
+
+                // Exit completion mode
+                self.mode = AppMode::Normal;
+                self.completion_items.clear();
+                self.completion_selected = 0;
+                self.completion_scroll_offset = 0;
             }
-            matches
+            _ => {
+                // Any other key cancels completion and processes normally
+                self.mode = AppMode::Normal;
+                self.completion_items.clear();
+                self.message = None;
+                return self.handle_normal_mode(key);
+            }
+        }
+
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Handle key while the hover popup is focused: arrows and page keys
+    /// scroll it, any other key closes it and is handled as usual
+    fn handle_hover_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let term_size = crossterm::terminal::size().unwrap_or((80, 24));
+        let cursor_screen_pos = self.cursor_screen_position().unwrap_or((0, 0));
+        let max_scroll = HoverPopup::max_scroll(&self.hover_lines, cursor_screen_pos, term_size);
+        let page = HoverPopup::visible_rows(&self.hover_lines, cursor_screen_pos, term_size).max(1);
+
+        match key.code {
+            KeyCode::Up => self.hover_scroll = self.hover_scroll.saturating_sub(1),
+            KeyCode::Down => self.hover_scroll = (self.hover_scroll + 1).min(max_scroll),
+            KeyCode::PageUp => self.hover_scroll = self.hover_scroll.saturating_sub(page),
+            KeyCode::PageDown => self.hover_scroll = (self.hover_scroll + page).min(max_scroll),
+            KeyCode::Esc => self.close_hover(),
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => self.close_hover(),
+            _ => {
+                self.close_hover();
+                return self.handle_normal_mode(key);
+            }
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Close the hover popup
+    fn close_hover(&mut self) {
+        self.mode = AppMode::Normal;
+        self.hover_lines.clear();
+        self.hover_scroll = 0;
+        self.message = None;
+    }
+
+    /// Ask the language server for every reference to the symbol at the cursor
+    fn request_references(&mut self) {
+        let Some(buffer) = self.workspace.active_buffer() else {
+            return;
+        };
+        let (Some(lsp), Some(path), Some(_)) = (&mut self.lsp_manager, buffer.file_path(), buffer.lsp_language()) else {
+            self.message = Some("No language server for this buffer".to_string());
+            return;
         };
+        let pos = buffer.editor_state().cursor.position();
+        let lsp_pos = crate::lsp::Position::new(pos.line, pos.column);
+        if lsp.references(buffer.id().0, path.clone(), lsp_pos).is_ok() {
+            self.message = Some("Finding references...".to_string());
+        }
+    }
 
-        if all_matches.is_empty() {
-            self.search_all_matches.clear();
-            return Ok(false);
+    /// Open the references panel on the locations the server found, each
+    /// with a preview of its line
+    fn show_references(&mut self, mut locations: Vec<crate::lsp::LocationRange>) {
+        if locations.is_empty() {
+            self.message = Some("No references found".to_string());
+            return;
         }
+        locations.sort_by(|a, b| {
+            (&a.path, a.start.line, a.start.column).cmp(&(&b.path, b.start.line, b.start.column))
+        });
 
-        // Store all match positions for rendering highlights
-        self.search_all_matches = all_matches.iter()
-            .map(|&(char_idx, match_len)| {
-                let start = buffer.text_buffer().char_to_pos(char_idx);
-                let end = buffer.text_buffer().char_to_pos(char_idx + match_len);
-                (start, end)
-            })
-            .collect();
+        // Lines of files that aren't open, read once per file
+        let mut file_lines: std::collections::HashMap<PathBuf, Vec<String>> = std::collections::HashMap::new();
+        self.references_results.clear();
+        self.references_locations.clear();
+        for location in locations {
+            let line = match self.workspace.find_buffer(&location.path).and_then(|id| self.workspace.get_buffer(id)) {
+                Some(buffer) => buffer.text_buffer().get_line(location.start.line).unwrap_or_default(),
+                None => file_lines
+                    .entry(location.path.clone())
+                    .or_insert_with(|| {
+                        std::fs::read_to_string(&location.path)
+                            .map(|text| text.lines().map(str::to_string).collect())
+                            .unwrap_or_default()
+                    })
+                    .get(location.start.line)
+                    .cloned()
+                    .unwrap_or_default(),
+            };
+            let line = line.trim_end_matches(['\n', '\r']);
 
-        // Find the next match based on direction
-        let found_match = if self.search_is_reverse {
-            // Reverse search - find the last match that ends at or before cursor (or wrap to end)
-            all_matches.iter()
-                .filter(|(start, len)| *start + *len <= current_char_idx)
-                .last()
-                .or_else(|| all_matches.last()) // Wrap to last match if nothing before
-        } else {
-            // Forward search - find the first match at or after cursor (or wrap to beginning)
-            all_matches.iter()
-                .find(|(start, _)| *start >= current_char_idx)
-                .or_else(|| all_matches.first()) // Wrap to first match if nothing after
+            // Servers count columns in UTF-16 code units
+            let start_column = utf16_to_char(line, location.start.column);
+            let end_column = if location.end.line == location.start.line {
+                utf16_to_char(line, location.end.column).max(start_column)
+            } else {
+                line.chars().count()
+            };
+            let byte = |column: usize| line.char_indices().nth(column).map_or(line.len(), |(byte, _)| byte);
+            self.references_results.push(Self::project_search_result(
+                &location.path,
+                location.start.line,
+                line,
+                byte(start_column),
+                byte(end_column),
+            ));
+            self.references_locations.push(crate::lsp::Location {
+                path: location.path,
+                position: crate::lsp::Position::new(location.start.line, start_column),
+            });
+        }
+
+        self.references_symbol = self
+            .references_results
+            .first()
+            .and_then(|result| result.line_content.get(result.match_start..result.match_end))
+            .unwrap_or_default()
+            .to_string();
+        self.references_selected = 0;
+        self.references_scroll_offset = 0;
+        self.mode = AppMode::References;
+        self.message = None;
+    }
+
+    /// Handle key in the references panel
+    fn handle_references_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let (_, term_height) = crossterm::terminal::size().unwrap_or((80, 24));
+        // Same height as the project search panel
+        let visible_lines = ((term_height as f32 * 0.7) as usize).saturating_sub(2).max(1);
+        let last = self.references_results.len().saturating_sub(1);
+
+        match key.code {
+            KeyCode::Esc => self.close_references(),
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => self.close_references(),
+            KeyCode::Up => self.references_selected = self.references_selected.saturating_sub(1),
+            KeyCode::Down => self.references_selected = (self.references_selected + 1).min(last),
+            KeyCode::PageUp => self.references_selected = self.references_selected.saturating_sub(visible_lines),
+            KeyCode::PageDown => self.references_selected = (self.references_selected + visible_lines).min(last),
+            KeyCode::Enter => {
+                if let Some(target) = self.references_locations.get(self.references_selected).cloned() {
+                    // Alt+F12 comes back here
+                    if let Some(buffer) = self.workspace.active_buffer() {
+                        if let Some(path) = buffer.file_path() {
+                            let pos = buffer.editor_state().cursor.position();
+                            self.navigation_history.push(crate::lsp::Location {
+                                path: path.clone(),
+                                position: crate::lsp::Position::new(pos.line, pos.column),
+                            });
+                        }
+                    }
+                    self.close_references();
+                    self.jump_to_location(&target);
+                }
+                return Ok(ControlFlow::Continue);
+            }
+            _ => {}
+        }
+
+        // Keep the selection in view
+        if self.references_selected < self.references_scroll_offset {
+            self.references_scroll_offset = self.references_selected;
+        } else if self.references_selected >= self.references_scroll_offset + visible_lines {
+            self.references_scroll_offset = self.references_selected + 1 - visible_lines;
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Close the references panel
+    fn close_references(&mut self) {
+        self.mode = AppMode::Normal;
+        self.references_results.clear();
+        self.references_locations.clear();
+        self.message = None;
+    }
+
+    /// Show a location in the active pane, opening its file if needed
+    fn jump_to_location(&mut self, location: &crate::lsp::Location) {
+        let buffer_id = match self.workspace.find_buffer(&location.path) {
+            Some(id) => id,
+            None => match self.workspace.open_file(location.path.clone()) {
+                Ok(open_result) => open_result.buffer_id(),
+                Err(e) => {
+                    self.message = Some(format!("Failed to open file: {}", e));
+                    return;
+                }
+            },
         };
+        let pane = self.layout.active_pane();
+        self.layout.set_buffer(pane, buffer_id);
+        self.workspace.set_active_buffer(buffer_id);
 
-        if let Some(&(char_idx, match_len)) = found_match {
-            let pos = buffer.text_buffer().char_to_pos(char_idx);
-            let end_char_idx = char_idx + match_len;
-            let end_pos = buffer.text_buffer().char_to_pos(end_char_idx);
+        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+            let (text_buffer, editor_state, _) = buffer.split_mut();
+            let line = location.position.line.min(text_buffer.len_lines().saturating_sub(1));
+            let column = location.position.column.min(text_buffer.line_len(line));
+            editor_state.cursor.set_position(Position::new(line, column));
+            editor_state.clear_selection();
+            editor_state.viewport.center_on_line(line);
+        }
+        self.message = Some(format!(
+            "Jumped to {}:{}:{}",
+            location.path.display(),
+            location.position.line + 1,
+            location.position.column + 1
+        ));
+    }
 
-            // Move cursor to END of match (not start) to avoid interfering with first character highlight
-            buffer.editor_state_mut().cursor.set_position(end_pos);
-            // Center the view on the search result
-            buffer.editor_state_mut().viewport.center_on_line(end_pos.line);
+    /// Start renaming the symbol at the cursor: the language server checks
+    /// there is one before the new name is asked for
+    fn request_rename(&mut self) {
+        let Some(buffer) = self.workspace.active_buffer() else {
+            return;
+        };
+        let (Some(lsp), Some(path), Some(_)) = (&mut self.lsp_manager, buffer.file_path(), buffer.lsp_language()) else {
+            self.message = Some("No language server for this buffer".to_string());
+            return;
+        };
+        let buffer_id = buffer.id();
+        let position = buffer.editor_state().cursor.position();
+        let lsp_pos = crate::lsp::Position::new(position.line, position.column);
+        if lsp.prepare_rename(buffer_id.0, path.clone(), lsp_pos).is_ok() {
+            self.rename_request = Some((buffer_id, position));
+            self.message = Some("Checking symbol...".to_string());
+        }
+    }
 
-            // Select the found text (anchor at start, head at end)
-            buffer.editor_state_mut().selection = Some(crate::editor::state::Selection::new(pos, end_pos));
-            self.copy_selection_to_primary();
+    /// Handle key in the rename prompt
+    fn handle_rename_prompt_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Esc => {
+                self.mode = AppMode::Normal;
+                self.rename_request = None;
+                self.message = None;
+            }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.mode = AppMode::Normal;
+                self.rename_request = None;
+                self.message = None;
+            }
+            KeyCode::Enter => {
+                self.mode = AppMode::Normal;
+                let new_name = self.rename_input.trim().to_string();
+                let request = self.rename_request.and_then(|(buffer_id, position)| {
+                    let path = self.workspace.get_buffer(buffer_id)?.file_path()?.clone();
+                    Some((buffer_id, path, position))
+                });
+                let (Some(lsp), Some((buffer_id, path, position))) = (&mut self.lsp_manager, request) else {
+                    self.rename_request = None;
+                    self.message = None;
+                    return Ok(ControlFlow::Continue);
+                };
+                if new_name.is_empty() {
+                    self.rename_request = None;
+                    self.message = Some("Rename cancelled".to_string());
+                    return Ok(ControlFlow::Continue);
+                }
+                let lsp_pos = crate::lsp::Position::new(position.line, position.column);
+                if lsp.rename(buffer_id.0, path, lsp_pos, new_name.clone()).is_ok() {
+                    self.rename_input = new_name;
+                    self.message = Some("Renaming...".to_string());
+                }
+            }
+            KeyCode::Char(c) => {
+                self.rename_input.push(c);
+                self.message = Some(format!("Rename to: {}", self.rename_input));
+            }
+            KeyCode::Backspace => {
+                self.rename_input.pop();
+                self.message = Some(format!("Rename to: {}", self.rename_input));
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
 
-            // Calculate current match index (1-based)
-            let current_index = all_matches.iter().position(|(start, _)| *start == char_idx).unwrap() + 1;
-            let total_count = all_matches.len();
+    /// Apply an edit from the language server. Files that aren't open are
+    /// only changed once a summary of the files is confirmed.
+    fn apply_workspace_edit(&mut self, pending: PendingWorkspaceEdit) {
+        let mut unopened: Vec<&PathBuf> = Vec::new();
+        for file in &pending.edit.files {
+            if !unopened.contains(&&file.path) && self.workspace.find_buffer(&file.path).is_none() {
+                unopened.push(&file.path);
+            }
+        }
+        if unopened.is_empty() {
+            self.finish_workspace_edit(pending, false);
+            return;
+        }
+        if self.pending_workspace_edit.is_some() {
+            // Another edit is being confirmed
+            self.message = Some(format!("{}: skipped while another edit waits for confirmation", pending.description));
+            self.answer_apply_edit(pending.reply, false);
+            return;
+        }
+        if self.mode != AppMode::Normal {
+            // Asking would replace the prompt or panel that is open, and
+            // so would a message
+            logger::log(&format!("{}: skipped while {:?} is open", pending.description, self.mode));
+            self.answer_apply_edit(pending.reply, false);
+            return;
+        }
+
+        const MAX_LISTED: usize = 5;
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let mut names: Vec<String> = unopened
+            .iter()
+            .take(MAX_LISTED)
+            .map(|path| path.strip_prefix(&cwd).unwrap_or(path).display().to_string())
+            .collect();
+        if unopened.len() > MAX_LISTED {
+            names.push(format!("{} more", unopened.len() - MAX_LISTED));
+        }
+        let edit_count: usize = pending.edit.files.iter().map(|file| file.edits.len()).sum();
+        self.message = Some(format!(
+            "{}: {} edits in {} files, {} not open ({}). Save them (y), open them for review (r) or cancel (n)?",
+            pending.description,
+            edit_count,
+            Self::edited_file_count(&pending.edit),
+            unopened.len(),
+            names.join(", ")
+        ));
+        self.pending_workspace_edit = Some(pending);
+        self.mode = AppMode::ConfirmWorkspaceEdit;
+    }
+
+    /// Handle key while confirming edits to files that aren't open
+    fn handle_confirm_workspace_edit_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let save = match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => true,
+            KeyCode::Char('r') | KeyCode::Char('R') => false,
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                self.cancel_workspace_edit();
+                return Ok(ControlFlow::Continue);
+            }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.cancel_workspace_edit();
+                return Ok(ControlFlow::Continue);
+            }
+            _ => return Ok(ControlFlow::Continue),
+        };
+        self.mode = AppMode::Normal;
+        if let Some(pending) = self.pending_workspace_edit.take() {
+            self.finish_workspace_edit(pending, save);
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Drop the edit waiting for confirmation
+    fn cancel_workspace_edit(&mut self) {
+        self.mode = AppMode::Normal;
+        if let Some(pending) = self.pending_workspace_edit.take() {
+            self.answer_apply_edit(pending.reply, false);
+        }
+        self.message = Some("Cancelled".to_string());
+    }
 
-            self.message = Some(format!("Found: {} ({}/{})", self.search_pattern, current_index, total_count));
-            Ok(true)
-        } else {
-            Ok(false)
+    /// Tell the language server that asked for an edit whether it was applied
+    fn answer_apply_edit(&mut self, reply: Option<(String, serde_json::Value)>, applied: bool) {
+        if let (Some(lsp), Some((language, id))) = (&mut self.lsp_manager, reply) {
+            let _ = lsp.apply_edit_result(language, id, applied);
         }
     }
 
-    /// Find the next match for replacement (forward search only)
-    /// Returns true if a match was found, false otherwise
-    fn find_next_replace_match(&mut self) -> Result<bool> {
-        let Some(buffer) = self.workspace.active_buffer_mut() else {
-            return Ok(false);
+    /// Number of different files a workspace edit changes
+    fn edited_file_count(edit: &crate::lsp::WorkspaceEdit) -> usize {
+        let mut paths: Vec<&PathBuf> = edit.files.iter().map(|file| &file.path).collect();
+        paths.sort();
+        paths.dedup();
+        paths.len()
+    }
+
+    /// Check whether a step of a workspace edit was computed for another
+    /// version of its file than the language server has now, or than the
+    /// buffer holds if it has edits the server hasn't seen yet
+    fn is_stale_file_edit(&self, file: &crate::lsp::FileEdit) -> bool {
+        let (Some(version), Some(lsp)) = (file.version, &self.lsp_manager) else {
+            return false;
         };
+        let buffer = self.workspace.find_buffer(&file.path).and_then(|id| self.workspace.get_buffer(id));
+        match buffer {
+            Some(buffer) => {
+                let synced = self.lsp_synced_revisions.get(&buffer.id()) == Some(&buffer.text_buffer().revision());
+                let path = buffer.file_path().unwrap_or(&file.path);
+                !synced || lsp.document_version(path) != Some(version)
+            }
+            None => lsp.document_version(&file.path).is_some_and(|known| known != version),
+        }
+    }
 
-        let text = buffer.text_buffer().to_string();
-        let current_pos = buffer.editor_state().cursor.position();
-        let current_char_idx = buffer.text_buffer().pos_to_char(current_pos)?;
+    /// Make the edits of a workspace edit, step by step in order. Open
+    /// buffers are edited in place; other files are opened in the background
+    /// and, with `save_unopened`, saved and closed again, or else left open
+    /// for review. The language server sees these files opened, changed,
+    /// saved and closed like any other. Nothing is changed if the edit was
+    /// computed for other versions of the files, or if any file can't be
+    /// opened or edited.
+    fn finish_workspace_edit(&mut self, pending: PendingWorkspaceEdit, save_unopened: bool) {
+        let PendingWorkspaceEdit { description, edit, reply, command } = pending;
+        if edit.files.iter().any(|file| self.is_stale_file_edit(file)) {
+            self.message = Some(format!("{}: files changed since the edit was computed, not applied", description));
+            self.answer_apply_edit(reply, false);
+            return;
+        }
 
-        // Search using regex or plain string
-        let found_match: Option<(usize, usize)> = if self.search_use_regex {
-            // Regex search (case-insensitive by default)
-            match RegexBuilder::new(&self.replace_pattern).case_insensitive(true).build() {
-                Ok(re) => {
-                    // Forward search only
-                    let after_chars: String = text.chars().skip(current_char_idx).collect();
-                    re.find(&after_chars).map(|m| {
-                        let char_start = current_char_idx + after_chars[..m.start()].chars().count();
-                        let char_len = after_chars[m.start()..m.end()].chars().count();
-                        (char_start, char_len)
-                    })
-                }
-                Err(_) => {
-                    // Invalid regex, return no match
-                    return Ok(false);
-                }
+        let active = self.workspace.active_buffer_id();
+        let mut opened = Vec::new();
+
+        // Check every step before changing anything: open the files, refuse
+        // read-only ones, and work out each step's char edits on a copy of
+        // the text as the steps before it leave it
+        let mut steps = Vec::new();
+        let mut texts: std::collections::HashMap<crate::workspace::BufferId, ropey::Rope> =
+            std::collections::HashMap::new();
+        let mut problem = None;
+        for file in &edit.files {
+            let buffer_id = match self.workspace.find_buffer(&file.path) {
+                Some(id) => id,
+                None => match self.workspace.open_file(file.path.clone()) {
+                    Ok(open_result) => {
+                        let id = open_result.buffer_id();
+                        opened.push(id);
+                        self.notify_lsp_did_open();
+                        id
+                    }
+                    Err(e) => {
+                        problem = Some(format!("Failed to open {:?} for edit: {}", file.path, e));
+                        break;
+                    }
+                },
+            };
+            let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+                problem = Some(format!("Failed to edit {:?}: not a text buffer", file.path));
+                break;
+            };
+            if buffer.text_buffer().is_read_only() {
+                problem = Some(format!("Failed to edit {:?}: buffer is read-only", file.path));
+                break;
             }
-        } else {
-            // Plain string search (case-insensitive)
-            let text_lower = text.to_lowercase();
-            let pattern_lower = self.replace_pattern.to_lowercase();
+            let text = texts.entry(buffer_id).or_insert_with(|| buffer.text_buffer().rope().clone());
+            let char_edits = crate::lsp::char_edits(text, &file.edits);
+            for change in &char_edits {
+                text.remove(change.start..change.end);
+                text.insert(change.start, &change.new_text);
+            }
+            steps.push((buffer_id, file, char_edits));
+        }
 
-            // Forward search only
-            let after_chars: String = text_lower.chars().skip(current_char_idx).collect();
-            after_chars.find(&pattern_lower).map(|byte_offset| {
-                let char_idx = current_char_idx + after_chars[..byte_offset].chars().count();
-                let match_len = self.replace_pattern.chars().count();
-                (char_idx, match_len)
-            })
-        };
+        if let Some(problem) = problem {
+            logger::error(&problem);
+            // Files opened only for this edit are closed again
+            for buffer_id in opened {
+                if let Some(path) = self.workspace.get_buffer(buffer_id).and_then(|b| b.file_path().cloned()) {
+                    self.notify_lsp_did_close(buffer_id, path);
+                }
+                self.workspace.force_close_buffer(buffer_id);
+            }
+            if let Some(active) = active {
+                self.workspace.set_active_buffer(active);
+            }
+            self.message = Some(format!("{}: a file can't be edited (see log), not applied", description));
+            self.answer_apply_edit(reply, false);
+            return;
+        }
 
-        if let Some((char_idx, match_len)) = found_match {
-            let pos = buffer.text_buffer().char_to_pos(char_idx);
-            let end_char_idx = char_idx + match_len;
-            let end_pos = buffer.text_buffer().char_to_pos(end_char_idx);
+        let mut edit_count = 0;
+        let mut failed: Vec<PathBuf> = Vec::new();
+        for (buffer_id, file, char_edits) in steps {
+            match self.apply_char_edits(buffer_id, char_edits) {
+                Ok(()) => {
+                    edit_count += file.edits.len();
+                    self.notify_lsp_buffer_changed(buffer_id);
+                }
+                Err(e) => {
+                    // Checked above, so only an unexpected error gets here;
+                    // the edits made so far stay, and the server hears so
+                    logger::error(&format!("Failed to edit {:?}: {}", file.path, e));
+                    if !failed.contains(&file.path) {
+                        failed.push(file.path.clone());
+                    }
+                    self.notify_lsp_buffer_changed(buffer_id);
+                }
+            }
+        }
 
-            // Move cursor to END of match (not start) to avoid interfering with first character highlight
-            buffer.editor_state_mut().cursor.set_position(end_pos);
-            buffer.editor_state_mut().ensure_cursor_visible();
+        // Files opened for the edit are saved and closed, or left open for review
+        let mut saved = 0;
+        if save_unopened {
+            for &buffer_id in &opened {
+                let Some(path) = self.workspace.get_buffer(buffer_id).and_then(|b| b.file_path().cloned()) else {
+                    continue;
+                };
+                if self.save_buffer_in_background(buffer_id) {
+                    saved += 1;
+                    self.notify_lsp_buffer_saved(buffer_id);
+                    self.notify_lsp_did_close(buffer_id, path);
+                    let _ = self.workspace.close_buffer(buffer_id);
+                }
+            }
+        }
+        if let Some(active) = active {
+            self.workspace.set_active_buffer(active);
+        }
 
-            // Select the found text (anchor at start, head at end)
-            buffer.editor_state_mut().selection = Some(crate::editor::state::Selection::new(pos, end_pos));
-            self.copy_selection_to_primary();
+        let file_count = Self::edited_file_count(&edit) - failed.len();
+        let mut message = format!("{}: {} edits in {} files", description, edit_count, file_count);
+        if save_unopened && saved > 0 {
+            message.push_str(&format!(", {} saved", saved));
+        } else if !save_unopened && !opened.is_empty() {
+            message.push_str(&format!(", {} opened for review", opened.len()));
+        }
+        if !failed.is_empty() {
+            message.push_str(&format!("; partly applied, {} files failed (see log)", failed.len()));
+        }
+        if edit.skipped_operations > 0 {
+            message.push_str(&format!("; {} file operations not supported", edit.skipped_operations));
+        }
+        self.message = Some(message);
 
-            Ok(true)
-        } else {
-            Ok(false)
+        self.answer_apply_edit(reply, failed.is_empty());
+        if let Some((buffer_id, command)) = command {
+            self.execute_lsp_command(buffer_id, command);
         }
     }
 
-    /// Perform the replacement of currently selected text
-    fn perform_replace(&mut self) -> Result<()> {
-        let Some(buffer) = self.workspace.active_buffer_mut() else {
-            return Ok(());
+    /// Apply a file's edits from the language server, as char ranges (see
+    /// `lsp::char_edits`), to a buffer as one undo step, keeping the cursor
+    /// on the same text
+    fn apply_char_edits(&mut self, buffer_id: crate::workspace::BufferId, char_edits: Vec<crate::lsp::CharEdit>) -> Result<()> {
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            anyhow::bail!("Not a text buffer");
         };
 
-        // Delete the selected text and insert replacement
-        if let Some(selection) = buffer.editor_state().selection {
-            let (start, end) = selection.range();
-            let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+        buffer.begin_undo_transaction();
+        let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+        let mut cursor = text_buffer.pos_to_char(editor_state.cursor.position())?;
+        let mut result = Ok(());
+        for edit in char_edits {
+            let start = text_buffer.char_to_pos(edit.start);
+            if edit.end > edit.start {
+                match text_buffer.delete_range(start, text_buffer.char_to_pos(edit.end)) {
+                    Ok(deleted) => undo_manager.record(Change::Delete { pos: start, text: deleted }),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            if !edit.new_text.is_empty() {
+                if let Err(e) = text_buffer.insert(start, &edit.new_text) {
+                    result = Err(e);
+                    break;
+                }
+                undo_manager.record(Change::Insert { pos: start, text: edit.new_text.clone() });
+            }
 
-            // Delete the matched text
-            if let Ok(deleted) = text_buffer.delete_range(start, end) {
-                undo_manager.record(Change::Delete {
-                    pos: start,
-                    text: deleted,
-                });
+            let new_len = edit.new_text.chars().count();
+            if cursor >= edit.end {
+                cursor = cursor - (edit.end - edit.start) + new_len;
+            } else if cursor > edit.start {
+                cursor = edit.start + (cursor - edit.start).min(new_len);
+            }
+        }
+        editor_state.cursor.set_position(text_buffer.char_to_pos(cursor));
+        editor_state.clear_selection();
+        buffer.end_undo_transaction();
+        result
+    }
 
-                // Insert the replacement
-                text_buffer.insert(start, &self.replace_with)?;
-                undo_manager.record(Change::Insert {
-                    pos: start,
-                    text: self.replace_with.clone(),
-                });
+    /// Save a buffer that isn't shown, as a regular save does. Returns
+    /// whether it was saved.
+    fn save_buffer_in_background(&mut self, buffer_id: crate::workspace::BufferId) -> bool {
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return false;
+        };
+        let Some(path) = buffer.file_path().cloned() else {
+            return false;
+        };
+        // Try to create backup (ignore errors - backup is optional)
+        let _ = self.backup_manager.create_backup(&path);
+        let saved = buffer.apply_save_transforms().and_then(|_| buffer.text_buffer_mut().save());
+        match saved {
+            Ok(()) => {
+                let content = buffer.text_buffer().to_string();
+                self.record_history(&path, &content);
+                true
+            }
+            Err(e) => {
+                logger::error(&format!("Failed to save {:?}: {}", path, e));
+                false
+            }
+        }
+    }
 
-                // Move cursor to end of replacement
-                editor_state.cursor.set_position(start);
-                editor_state.ensure_cursor_visible();
+    /// Ask the language server for the code actions at the cursor or
+    /// selection, with the diagnostics on its lines. They are listed in a
+    /// picker, or only light the bulb in the gutter when asked `quietly`.
+    fn request_code_actions(&mut self, quietly: bool) {
+        let Some(buffer) = self.workspace.active_buffer() else {
+            return;
+        };
+        let (Some(lsp), Some(path), Some(_)) = (&mut self.lsp_manager, buffer.file_path(), buffer.lsp_language()) else {
+            if !quietly {
+                self.message = Some("No language server for this buffer".to_string());
+            }
+            return;
+        };
+        let editor_state = buffer.editor_state();
+        let cursor = editor_state.cursor.position();
+        let (start, end) = editor_state.selection.map_or((cursor, cursor), |selection| selection.range());
+
+        // Diagnostic columns are in UTF-16 code units, so they are matched
+        // by line
+        let diagnostics: Vec<crate::lsp::Diagnostic> = self
+            .diagnostics_store
+            .get(buffer.id().0)
+            .unwrap_or_default()
+            .iter()
+            .filter(|diagnostic| diagnostic.range.0.line <= end.line && diagnostic.range.1.line >= start.line)
+            .cloned()
+            .collect();
+
+        let range = (
+            crate::lsp::Position::new(start.line, start.column),
+            crate::lsp::Position::new(end.line, end.column),
+        );
+        if lsp.code_actions(buffer.id().0, path.clone(), range, diagnostics, quietly).is_ok() {
+            if quietly {
+                self.code_action_bulb_request = Some((buffer.id(), cursor.line));
+            } else {
+                self.code_action_request = Some((buffer.id(), cursor.line));
+                self.message = Some("Looking for code actions...".to_string());
             }
         }
+    }
 
-        Ok(())
+    /// Light the bulb in the gutter when the cursor is on a line with
+    /// diagnostics that the language server has code actions for
+    pub fn check_code_actions(&mut self) {
+        if self.mode != AppMode::Normal || !self.show_diagnostics {
+            return;
+        }
+        let Some(buffer) = self.workspace.active_buffer() else {
+            return;
+        };
+        let line = (buffer.id(), buffer.editor_state().cursor.line);
+        if self.code_action_checked == Some(line) {
+            return;
+        }
+        self.code_action_checked = Some(line);
+        if self.code_action_bulb != Some(line) {
+            self.code_action_bulb = None;
+        }
+        if self.diagnostics_store.get_for_line(line.0 .0, line.1).is_empty() {
+            self.code_action_bulb = None;
+            return;
+        }
+        self.request_code_actions(true);
     }
 
-    /// Handle key in completion mode
-    fn handle_completion_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
-        const MAX_VISIBLE: usize = 10;
+    /// Handle key in the code action picker
+    fn handle_code_actions_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let last = self.code_actions.len().saturating_sub(1);
+        let page = crate::lsp::CodeActionPopup::MAX_ROWS;
 
         match key.code {
-            KeyCode::Esc | KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Cancel completion
-                self.mode = AppMode::Normal;
-                self.completion_items.clear();
-                self.completion_selected = 0;
-                self.completion_scroll_offset = 0;
-                self.message = None;
-            }
-            KeyCode::Up => {
-                // Move selection up
-                if self.completion_selected > 0 {
-                    self.completion_selected -= 1;
-                    // Scroll up if needed
-                    if self.completion_selected < self.completion_scroll_offset {
-                        self.completion_scroll_offset = self.completion_selected;
-                    }
-                }
-            }
-            KeyCode::Down => {
-                // Move selection down
-                if self.completion_selected < self.completion_items.len().saturating_sub(1) {
-                    self.completion_selected += 1;
-                    // Scroll down if needed
-                    if self.completion_selected >= self.completion_scroll_offset + MAX_VISIBLE {
-                        self.completion_scroll_offset = self.completion_selected - MAX_VISIBLE + 1;
-                    }
+            KeyCode::Esc => self.close_code_actions(),
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => self.close_code_actions(),
+            KeyCode::Up => self.code_action_selected = self.code_action_selected.saturating_sub(1),
+            KeyCode::Down => self.code_action_selected = (self.code_action_selected + 1).min(last),
+            KeyCode::PageUp => self.code_action_selected = self.code_action_selected.saturating_sub(page),
+            KeyCode::PageDown => self.code_action_selected = (self.code_action_selected + page).min(last),
+            KeyCode::Enter => {
+                let action = self.code_actions.get(self.code_action_selected).cloned();
+                self.close_code_actions();
+                if let Some(action) = action {
+                    self.run_code_action(action);
                 }
+                return Ok(ControlFlow::Continue);
             }
-            KeyCode::Enter | KeyCode::Tab => {
-                // Insert selected completion
-                if let Some(item) = self.completion_items.get(self.completion_selected) {
-                    if let Some(buffer) = self.workspace.active_buffer_mut() {
-                        let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
-                        let pos = editor_state.cursor.position();
-
-                        // Find the start of the current word (go back while alphanumeric)
-                        let line = text_buffer.get_line(pos.line).unwrap_or_default();
-                        let mut word_start_col = pos.column;
-                        let chars: Vec<char> = line.chars().collect();
-
-                        while word_start_col > 0 {
-                            let idx = word_start_col - 1;
-                            if idx < chars.len() {
-                                let ch = chars[idx];
-                                if ch.is_alphanumeric() || ch == '_' {
-                                    word_start_col -= 1;
-                                } else {
-                                    break;
-                                }
-                            } else {
-                                break;
-                            }
-                        }
-
-                        // Delete the partial word
-                        if word_start_col < pos.column {
-                            let delete_start = crate::buffer::Position::new(pos.line, word_start_col);
-                            let delete_end = pos;
-                            if let Ok(deleted) = text_buffer.delete_range(delete_start, delete_end) {
-                                undo_manager.record(Change::Delete {
-                                    pos: delete_start,
-                                    text: deleted,
-                                });
-                                editor_state.cursor.set_position(delete_start);
-                            }
-                        }
+            _ => {}
+        }
 
-                        // Insert the completion text (use insert_text if available, otherwise label)
-                        let completion_text = item.insert_text.as_ref()
-                            .unwrap_or(&item.label);
-                        let insert_pos = editor_state.cursor.position();
-                        text_buffer.insert(insert_pos, completion_text)?;
-                        undo_manager.record(Change::Insert {
-                            pos: insert_pos,
-                            text: completion_text.to_string(),
-                        });
+        // Keep the selection in view
+        if self.code_action_selected < self.code_action_scroll_offset {
+            self.code_action_scroll_offset = self.code_action_selected;
+        } else if self.code_action_selected >= self.code_action_scroll_offset + page {
+            self.code_action_scroll_offset = self.code_action_selected + 1 - page;
+        }
+        Ok(ControlFlow::Continue)
+    }
 
-                        // Move cursor to end of inserted text
-                        editor_state.cursor.column += completion_text.chars().count();
-                        editor_state.ensure_cursor_visible();
-                    }
+    /// Close the code action picker
+    fn close_code_actions(&mut self) {
+        self.mode = AppMode::Normal;
+        self.code_actions.clear();
+        self.message = None;
+    }
 
-                    self.message = Some(format!("Inserted: {}", item.label));
+    /// Apply a code action's edit, then run its command
+    fn run_code_action(&mut self, action: crate::lsp::CodeAction) {
+        if let Some(reason) = action.disabled {
+            self.message = Some(format!("{}: {}", action.title, reason));
+            return;
+        }
+        let command = action
+            .command
+            .and_then(|command| Some((self.workspace.active_buffer_id()?, command)));
+        match action.edit {
+            Some(edit) => self.apply_workspace_edit(PendingWorkspaceEdit {
+                description: action.title,
+                edit,
+                reply: None,
+                command,
+            }),
+            None => {
+                if let Some((buffer_id, command)) = command {
+                    self.execute_lsp_command(buffer_id, command);
                 }
+            }
+        }
+    }
 
-                // Exit completion mode
-                self.mode = AppMode::Normal;
-                self.completion_items.clear();
-                self.completion_selected = 0;
-                self.completion_scroll_offset = 0;
+    /// Run a command of the language server handling a buffer. Its effects
+    /// come back as requests of the server, such as edits to apply.
+    fn execute_lsp_command(&mut self, buffer_id: crate::workspace::BufferId, command: crate::lsp::LspCommand) {
+        let Some(path) = self.workspace.get_buffer(buffer_id).and_then(|buffer| buffer.file_path()) else {
+            return;
+        };
+        let Some(lsp) = &mut self.lsp_manager else {
+            return;
+        };
+        let title = command.title.clone();
+        if lsp.execute_command(buffer_id.0, path.clone(), command).is_ok() {
+            self.message = Some(format!("Running {}...", title));
+        }
+    }
+
+    /// Ask the language server for documentation of the symbol at the
+    /// cursor. A `focused` popup takes the keys for scrolling; otherwise it
+    /// shows until the next key.
+    fn request_hover(&mut self, focused: bool) {
+        let Some(buffer) = self.workspace.active_buffer() else {
+            return;
+        };
+        let (Some(lsp), Some(path), Some(_)) = (&mut self.lsp_manager, buffer.file_path(), buffer.lsp_language()) else {
+            if focused {
+                self.message = Some("No language server for this buffer".to_string());
             }
-            _ => {
-                // Any other key cancels completion and processes normally
-                self.mode = AppMode::Normal;
-                self.completion_items.clear();
-                self.message = None;
-                return self.handle_normal_mode(key);
+            return;
+        };
+        let buffer_id = buffer.id();
+        let position = buffer.editor_state().cursor.position();
+        let lsp_pos = crate::lsp::Position::new(position.line, position.column);
+        if lsp.hover(buffer_id.0, path.clone(), lsp_pos).is_ok() {
+            self.hover_request = Some(PendingHover { buffer_id, position, focused });
+            if focused {
+                self.message = Some("Looking up documentation...".to_string());
             }
         }
+    }
 
-        Ok(ControlFlow::Continue)
+    /// Show documentation by itself once the cursor has rested on a symbol
+    /// for the configured delay (`[lsp] hover_delay_ms`)
+    pub fn check_hover_idle(&mut self) {
+        let (Some(delay), Some(since)) = (self.hover_delay, self.hover_idle_since) else {
+            return;
+        };
+        if since.elapsed() < delay {
+            return;
+        }
+        self.hover_idle_since = None;
+
+        // Once per position, so closing the popup doesn't bring it back
+        let current = self
+            .workspace
+            .active_buffer()
+            .map(|buffer| (buffer.id(), buffer.editor_state().cursor.position()));
+        if self.mode != AppMode::Normal || current.is_none() || current == self.hover_idle_position {
+            return;
+        }
+        self.hover_idle_position = current;
+        self.request_hover(false);
+    }
+
+    /// Check whether the active buffer is read-only
+    fn active_buffer_is_read_only(&self) -> bool {
+        self.layout
+            .active_buffer()
+            .and_then(|id| self.workspace.get_buffer(id))
+            .is_some_and(|buffer| buffer.text_buffer().is_read_only())
+    }
+
+    /// Column Backspace dedents to when the cursor is in a line's leading
+    /// spaces: the previous indent stop. None elsewhere, or when indenting with tabs.
+    fn dedent_column(text_buffer: &TextBuffer, pos: Position, indentation: Indentation) -> Option<usize> {
+        if pos.column == 0 || indentation.use_tabs {
+            return None;
+        }
+        let line = text_buffer.get_line(pos.line)?;
+        let leading_spaces = line.chars().take_while(|&c| c == ' ').count();
+        if pos.column > leading_spaces {
+            return None;
+        }
+        Some((pos.column - 1) / indentation.width * indentation.width)
     }
 
     /// Handle key in normal mode
@@ -3249,8 +5335,6 @@ impl App {
                     let pane = self.layout.active_pane();
                     self.layout.set_buffer(pane, next_id);
 
-                    self.cached_highlights = None;
-                    self.cached_text_hash = 0;
                     self.logged_highlighting = false;
 
                     if let Some(buf) = self.workspace.get_buffer(next_id) {
@@ -3281,8 +5365,6 @@ impl App {
                     self.layout.set_buffer(pane, next_id);
 
                     // Clear highlight cache when switching buffers
-                    self.cached_highlights = None;
-                    self.cached_text_hash = 0;
                     self.logged_highlighting = false;
 
                     // Get buffer name for message
@@ -3314,8 +5396,6 @@ impl App {
                     self.layout.set_buffer(pane, prev_id);
 
                     // Clear highlight cache when switching buffers
-                    self.cached_highlights = None;
-                    self.cached_text_hash = 0;
                     self.logged_highlighting = false;
 
                     // Get buffer name for message
@@ -3336,6 +5416,9 @@ impl App {
         let Some(buffer_id) = active_buffer_id else {
             return Ok(ControlFlow::Continue);
         };
+        if self.workspace.get_hex_buffer(buffer_id).is_some() {
+            return self.handle_hex_buffer_key(buffer_id, key);
+        }
         let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
             return Ok(ControlFlow::Continue);
         };
@@ -3363,6 +5446,18 @@ impl App {
             }
         }
 
+        // Don't open the replace prompt (Ctrl+X Ctrl+H) in a read-only buffer;
+        // other edits are refused by the buffer itself
+        if buffer.text_buffer().is_read_only()
+            && self.waiting_for_second_key
+            && matches!(key.code, KeyCode::Char('h'))
+            && key.modifiers.contains(KeyModifiers::CONTROL)
+        {
+            self.waiting_for_second_key = false;
+            self.message = Some("Buffer is read-only".to_string());
+            return Ok(ControlFlow::Continue);
+        }
+
         // Handle Ctrl+X Ctrl+S (Emacs-style save) and Ctrl+X Ctrl+C (Emacs-style exit)
         if self.waiting_for_second_key {
             self.waiting_for_second_key = false;
@@ -3394,11 +5489,14 @@ impl App {
                     // Try to create backup (ignore errors - backup is optional)
                     let _ = self.backup_manager.create_backup(&path);
 
+                    buffer.apply_save_transforms()?;
+
                     // Store content before attempting save (for sudo retry)
                     let content = buffer.text_buffer().to_string();
 
                     match buffer.text_buffer_mut().save() {
                         Ok(_) => {
+                            self.record_history(&path, &content);
                             self.message = Some("Saved".to_string());
                             self.notify_lsp_did_save();
                         }
@@ -3495,6 +5593,9 @@ impl App {
                 self.smart_indentation = !self.smart_indentation;
                 self.message = Some(format!("Smart indentation: {}", if self.smart_indentation { "ON" } else { "OFF" }));
                 return Ok(ControlFlow::Continue);
+            } else if matches!(key.code, KeyCode::Char('u') | KeyCode::Char('U')) && !key.modifiers.contains(KeyModifiers::CONTROL) {
+                // Ctrl+X U - Undo tree browser (emacs undo-tree style)
+                return self.execute_command(CommandAction::UndoTree);
             } else if matches!(key.code, KeyCode::Char('d') | KeyCode::Char('D')) && !key.modifiers.contains(KeyModifiers::CONTROL) {
                 // Ctrl+X D - Toggle diagnostic dots
                 self.show_diagnostics = !self.show_diagnostics;
@@ -4015,48 +6116,16 @@ impl App {
 
             // Ctrl+Z - Undo
             (KeyCode::Char('z'), KeyModifiers::CONTROL) => {
-                if let Some(change) = buffer.undo_manager_mut().undo() {
-                    // Save cursor position before undo
-                    let saved_line = buffer.editor_state().cursor.line;
-                    let saved_col = buffer.editor_state().cursor.column;
-
-                    buffer.apply_change(&change)?;
-                    buffer.undo_manager_mut().finish_undo_redo();
-
-                    // For compound changes (like line movements), try to restore cursor position
-                    if let Change::Compound(changes) = &change {
-                        if let Some(Change::Delete { text, .. }) = changes.first() {
-                            // Count lines in deleted text to determine offset
-                            let line_count = text.matches('\n').count();
-                            if line_count > 0 {
-                                // Cursor was on line N, moved to line N-1 or N+1
-                                // After undo, adjust back
-                                let target_line = if saved_line == buffer.editor_state().cursor.line {
-                                    // Line didn't change, might need adjustment
-                                    saved_line + 1
-                                } else {
-                                    saved_line
-                                };
-
-                                let total_lines = buffer.text_buffer().len_lines();
-                                if target_line < total_lines {
-                                    let line_len = buffer.text_buffer().line_len(target_line);
-                                    buffer.editor_state_mut().cursor.line = target_line;
-                                    buffer.editor_state_mut().cursor.column = saved_col.min(line_len);
-                                }
-                            }
-                        }
-                    }
-
+                let applied = buffer.undo()?;
+                if applied {
                     self.message = Some("Undo".to_string());
                 }
             }
 
             // Ctrl+Y - Redo
             (KeyCode::Char('y'), KeyModifiers::CONTROL) => {
-                if let Some(change) = buffer.undo_manager_mut().redo() {
-                    buffer.apply_change(&change)?;
-                    buffer.undo_manager_mut().finish_undo_redo();
+                let applied = buffer.redo()?;
+                if applied {
                     self.message = Some("Redo".to_string());
                 }
             }
@@ -4307,11 +6376,9 @@ impl App {
                     .fold(editor_state.cursor.line, |a, b| a.min(b));
                 if min_line > 0 {
                     let new_line = min_line - 1;
-                    let desired_col = editor_state.cursor.desired_column;
-                    let col = desired_col.min(text_buffer.line_len(new_line));
-                    let mut sc = Cursor::new(new_line, col);
-                    sc.desired_column = desired_col;
-                    editor_state.secondary_cursors.push(sc);
+                    let goal = Movement::goal_display_column(editor_state, text_buffer);
+                    let col = text_buffer.column_at_display(new_line, goal, editor_state.tab_width);
+                    editor_state.secondary_cursors.push(Cursor::new(new_line, col));
                 }
             }
             // Alt+Shift+Down: add cursor below
@@ -4322,11 +6389,9 @@ impl App {
                     .fold(editor_state.cursor.line, |a, b| a.max(b));
                 if max_line + 1 < text_buffer.len_lines() {
                     let new_line = max_line + 1;
-                    let desired_col = editor_state.cursor.desired_column;
-                    let col = desired_col.min(text_buffer.line_len(new_line));
-                    let mut sc = Cursor::new(new_line, col);
-                    sc.desired_column = desired_col;
-                    editor_state.secondary_cursors.push(sc);
+                    let goal = Movement::goal_display_column(editor_state, text_buffer);
+                    let col = text_buffer.column_at_display(new_line, goal, editor_state.tab_width);
+                    editor_state.secondary_cursors.push(Cursor::new(new_line, col));
                 }
             }
             (KeyCode::Up, mods) => {
@@ -4432,7 +6497,7 @@ impl App {
             (KeyCode::F(12), KeyModifiers::NONE) => {
                 if let Some(lsp) = &mut self.lsp_manager {
                     if let Some(path) = buffer.file_path() {
-                        if crate::lsp::Language::from_path(path).is_some() {
+                        if buffer.lsp_language().is_some() {
                             let pos = buffer.editor_state().cursor.position();
                             let buffer_id = buffer.id().0;
 
@@ -4452,6 +6517,26 @@ impl App {
                 }
             }
 
+            // F1 - Show hover documentation
+            (KeyCode::F(1), KeyModifiers::NONE) => {
+                self.request_hover(true);
+            }
+
+            // Shift+F12 - Find references
+            (KeyCode::F(12), KeyModifiers::SHIFT) => {
+                self.request_references();
+            }
+
+            // F2 - Rename symbol
+            (KeyCode::F(2), KeyModifiers::NONE) => {
+                self.request_rename();
+            }
+
+            // Alt+Enter - Code actions
+            (KeyCode::Enter, KeyModifiers::ALT) => {
+                self.request_code_actions(false);
+            }
+
             // Alt+F12 - Jump back to previous location
             (KeyCode::F(12), KeyModifiers::ALT) => {
                 if let Some(location) = self.navigation_history.pop() {
@@ -4538,7 +6623,7 @@ impl App {
                 let path = path.unwrap();
 
                 // Debug: Check if language is detected
-                let language = crate::lsp::Language::from_path(path);
+                let language = buffer.lsp_language();
                 if language.is_none() {
                     self.message = Some(format!("DEBUG: No language detected for {:?}", path));
                     return Ok(ControlFlow::Continue);
@@ -4562,6 +6647,7 @@ impl App {
 
             // Backspace
             (KeyCode::Backspace, KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                let indentation = buffer.indentation();
                 let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
 
                 if editor_state.has_secondary_cursors() {
@@ -4570,11 +6656,11 @@ impl App {
 
                     // Primary cursor
                     if editor_state.cursor.column > 0 {
-                        let col = editor_state.cursor.column - 1;
-                        let pos = Position::new(editor_state.cursor.line, col);
-                        if let Ok(Some(ch)) = text_buffer.delete_char(pos) {
-                            undo_manager.record(Change::Delete { pos, text: ch.to_string() });
-                            editor_state.cursor.move_horizontal(col);
+                        let end = editor_state.cursor.position();
+                        let pos = Position::new(end.line, text_buffer.prev_grapheme_column(end));
+                        if let Ok(deleted) = text_buffer.delete_range(pos, end) {
+                            undo_manager.record(Change::Delete { pos, text: deleted });
+                            editor_state.cursor.move_horizontal(pos.column);
                         }
                     }
                     editor_state.ensure_cursor_visible();
@@ -4582,11 +6668,11 @@ impl App {
                     // Secondary cursors
                     for sc in editor_state.secondary_cursors.iter_mut() {
                         if sc.column > 0 {
-                            let col = sc.column - 1;
-                            let pos = Position::new(sc.line, col);
-                            if let Ok(Some(ch)) = text_buffer.delete_char(pos) {
-                                undo_manager.record(Change::Delete { pos, text: ch.to_string() });
-                                sc.move_horizontal(col);
+                            let end = sc.position();
+                            let pos = Position::new(end.line, text_buffer.prev_grapheme_column(end));
+                            if let Ok(deleted) = text_buffer.delete_range(pos, end) {
+                                undo_manager.record(Change::Delete { pos, text: deleted });
+                                sc.move_horizontal(pos.column);
                             }
                         }
                     }
@@ -4600,14 +6686,23 @@ impl App {
                             editor_state.clear_selection();
                             editor_state.ensure_cursor_visible();
                         }
+                    } else if let Some(column) = Self::dedent_column(text_buffer, editor_state.cursor.position(), indentation) {
+                        // In the leading spaces: dedent back to the previous indent stop
+                        let end = editor_state.cursor.position();
+                        let start = Position::new(end.line, column);
+                        if let Ok(deleted) = text_buffer.delete_range(start, end) {
+                            undo_manager.record(Change::Delete { pos: start, text: deleted });
+                            editor_state.cursor.move_horizontal(column);
+                            editor_state.ensure_cursor_visible();
+                        }
                     } else if editor_state.cursor.column > 0 {
-                        let pos = Position::new(
-                            editor_state.cursor.line,
-                            editor_state.cursor.column - 1,
-                        );
-                        if let Ok(Some(ch)) = text_buffer.delete_char(pos) {
-                            undo_manager.record(Change::Delete { pos, text: ch.to_string() });
-                            Movement::move_left(editor_state, text_buffer);
+                        // Delete the whole grapheme cluster, e.g. a letter with its accents
+                        let end = editor_state.cursor.position();
+                        let pos = Position::new(end.line, text_buffer.prev_grapheme_column(end));
+                        if let Ok(deleted) = text_buffer.delete_range(pos, end) {
+                            undo_manager.record(Change::Delete { pos, text: deleted });
+                            editor_state.cursor.move_horizontal(pos.column);
+                            editor_state.ensure_cursor_visible();
                         }
                     } else if editor_state.cursor.line > 0 {
                         let prev_line_len = text_buffer.line_len(editor_state.cursor.line - 1);
@@ -4669,1067 +6764,2245 @@ impl App {
                     editor_state.clear_selection();
 
                     let pos = editor_state.cursor.position();
-                    if let Ok(Some(ch)) = text_buffer.delete_char(pos) {
-                        undo_manager.record(Change::Delete { pos, text: ch.to_string() });
+                    if let Ok(Some(deleted)) = text_buffer.delete_grapheme(pos) {
+                        undo_manager.record(Change::Delete { pos, text: deleted });
+                    }
+                    editor_state.ensure_cursor_visible();
+
+                    for sc in editor_state.secondary_cursors.iter_mut() {
+                        let pos = Position::new(sc.line, sc.column);
+                        if let Ok(Some(deleted)) = text_buffer.delete_grapheme(pos) {
+                            undo_manager.record(Change::Delete { pos, text: deleted });
+                        }
+                    }
+                } else if let Some(selection) = editor_state.selection {
+                    // Single cursor with selection: delete selection
+                    let (start, end) = selection.range();
+                    if let Ok(deleted) = text_buffer.delete_range(start, end) {
+                        undo_manager.record(Change::Delete { pos: start, text: deleted });
+                        editor_state.cursor.set_position(start);
+                        editor_state.clear_selection();
+                        editor_state.ensure_cursor_visible();
+                    }
+                } else {
+                    // Single cursor, no selection: delete the grapheme cluster at cursor
+                    let pos = editor_state.cursor.position();
+                    if let Ok(Some(deleted)) = text_buffer.delete_grapheme(pos) {
+                        undo_manager.record(Change::Delete { pos, text: deleted });
+                    }
+                }
+            }
+
+            // Enter - Smart indentation
+            (KeyCode::Enter, _) => {
+                let indent_unit = buffer.indent_unit();
+                let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+
+                // If there's a selection, delete it first
+                if let Some(selection) = editor_state.selection {
+                    let (start, end) = selection.range();
+                    if let Ok(deleted) = text_buffer.delete_range(start, end) {
+                        undo_manager.record(Change::Delete {
+                            pos: start,
+                            text: deleted,
+                        });
+                        editor_state.cursor.set_position(start);
+                        editor_state.clear_selection();
+                    }
+                }
+
+                let pos = editor_state.cursor.position();
+
+                // Smart indentation (if enabled)
+                if self.smart_indentation {
+                    // Get current line to calculate indentation
+                    let current_line = text_buffer.get_line(pos.line).unwrap_or_default();
+
+                    // Calculate base indentation (leading spaces/tabs)
+                    let base_indent = current_line.chars()
+                        .take_while(|c| *c == ' ' || *c == '\t')
+                        .collect::<String>();
+
+                    // Check the part of the line before cursor to see if it ends with ':'
+                    let line_chars: Vec<char> = current_line.chars().collect();
+                    let line_before_cursor = if pos.column <= line_chars.len() {
+                        line_chars.iter().take(pos.column).collect::<String>()
+                    } else {
+                        current_line.clone()
+                    };
+                    let line_before_cursor_trimmed = line_before_cursor.trim_end();
+                    let needs_extra_indent = line_before_cursor_trimmed.ends_with(':');
+
+                    // Build the newline + indentation string
+                    let mut indent_str = String::from("\n");
+                    indent_str.push_str(&base_indent);
+                    if needs_extra_indent {
+                        indent_str.push_str(&indent_unit); // One more level for Python blocks
+                    }
+
+                    // Insert newline with indentation
+                    text_buffer.insert(pos, &indent_str)?;
+                    undo_manager.record(Change::Insert {
+                        pos,
+                        text: indent_str.clone(),
+                    });
+
+                    // Move cursor to end of inserted text
+                    let indent_len = indent_str.chars().count() - 1;
+                    editor_state.cursor.line += 1;
+                    editor_state.cursor.move_horizontal(indent_len);
+                } else {
+                    // Simple newline without smart indentation
+                    text_buffer.insert(pos, "\n")?;
+                    undo_manager.record(Change::Insert {
+                        pos,
+                        text: String::from("\n"),
+                    });
+                    editor_state.cursor.line += 1;
+                    editor_state.cursor.move_horizontal(0);
+                }
+                editor_state.ensure_cursor_visible();
+            }
+
+            // Tab - Accept AI suggestion, smart completion, or indentation (but not Ctrl+Tab)
+            (KeyCode::Tab, mods) if !mods.contains(KeyModifiers::CONTROL) && !mods.contains(KeyModifiers::SHIFT) => {
+                // Check if there's an AI suggestion to accept
+                if let Some(suggestion) = self.ai_suggestion.take() {
+                    // Accept the AI suggestion by inserting it at cursor
+                    let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+                    let pos = editor_state.cursor.position();
+
+                    // Insert the full suggestion (can be multi-line)
+                    text_buffer.insert(pos, &suggestion)?;
+                    undo_manager.record(Change::Insert {
+                        pos,
+                        text: suggestion.clone(),
+                    });
+
+                    // Move cursor to end of inserted text
+                    // Count newlines to update line number
+                    let newline_count = suggestion.matches('\n').count();
+                    if newline_count > 0 {
+                        editor_state.cursor.line += newline_count;
+                        // Get the last line to determine column position
+                        let last_line = suggestion.lines().last().unwrap_or("");
+                        editor_state.cursor.column = last_line.chars().count();
+                    } else {
+                        editor_state.cursor.column += suggestion.chars().count();
+                    }
+                    editor_state.ensure_cursor_visible();
+
+                    self.message = Some("AI suggestion accepted".to_string());
+                    return Ok(ControlFlow::Continue);
+                }
+
+                // Determine if we should trigger completion or indent
+                let should_complete = if buffer.file_path().is_some() {
+                    if buffer.lsp_language().is_some() {
+                        // Check the character before the cursor
+                        let pos = buffer.editor_state().cursor.position();
+                        if let Some(line) = buffer.text_buffer().get_line(pos.line) {
+                            let chars: Vec<char> = line.chars().collect();
+                            if pos.column > 0 && pos.column <= chars.len() {
+                                let prev_char = chars[pos.column - 1];
+                                // Complete after dot or if we're in the middle of a word
+                                prev_char == '.' || prev_char.is_alphanumeric()
+                            } else {
+                                false
+                            }
+                        } else {
+                            false
+                        }
+                    } else {
+                        false
+                    }
+                } else {
+                    false
+                };
+
+                if should_complete && self.lsp_manager.is_some() {
+                    // Trigger completion (same logic as Ctrl+Space)
+                    if let Some(path) = buffer.file_path() {
+                        if let Some(lsp) = &mut self.lsp_manager {
+                            let pos = buffer.editor_state().cursor.position();
+                            let buffer_id = buffer.id().0;
+                            let lsp_pos = crate::lsp::Position::new(pos.line, pos.column);
+                            match lsp.completion(buffer_id, path.clone(), lsp_pos) {
+                                Ok(_) => {
+                                    self.message = Some(format!("Requesting completions at {}:{}...", pos.line, pos.column));
+                                }
+                                Err(e) => {
+                                    self.message = Some(format!("Completion error: {}", e));
+                                }
+                            }
+                        }
+                    }
+                } else {
+                    // Insert one level of indentation (spaces or a tab, per EditorConfig)
+                    let indent_unit = buffer.indent_unit();
+                    let indent_len = indent_unit.chars().count();
+                    let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+
+                    if editor_state.has_secondary_cursors() {
+                        // Multi-cursor: insert the indent at all cursor positions bottom-to-top
+                        editor_state.clear_selection();
+                        let positions = editor_state.all_positions_bottom_to_top();
+                        for pos in &positions {
+                            text_buffer.insert(*pos, &indent_unit)?;
+                            undo_manager.record(Change::Insert { pos: *pos, text: indent_unit.clone() });
+                        }
+                        editor_state.cursor.column += indent_len;
+                        editor_state.ensure_cursor_visible();
+                        for sc in editor_state.secondary_cursors.iter_mut() {
+                            sc.column += indent_len;
+                        }
+                    } else {
+                        // Single cursor
+                        if let Some(selection) = editor_state.selection {
+                            let (start, end) = selection.range();
+                            if let Ok(deleted) = text_buffer.delete_range(start, end) {
+                                undo_manager.record(Change::Delete { pos: start, text: deleted });
+                                editor_state.cursor.set_position(start);
+                                editor_state.clear_selection();
+                            }
+                        }
+                        let pos = editor_state.cursor.position();
+                        text_buffer.insert(pos, &indent_unit)?;
+                        undo_manager.record(Change::Insert { pos, text: indent_unit.clone() });
+                        editor_state.cursor.column += indent_len;
+                        editor_state.ensure_cursor_visible();
                     }
+                }
+            }
+
+            // Regular character input
+            (KeyCode::Char(c), mods) if !mods.contains(KeyModifiers::CONTROL) => {
+                let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+
+                if editor_state.has_secondary_cursors() {
+                    // Multi-cursor insertion: apply bottom-to-top to avoid invalidating positions
+                    editor_state.clear_selection();
+                    let positions = editor_state.all_positions_bottom_to_top();
+                    for pos in &positions {
+                        text_buffer.insert_char(*pos, c)?;
+                        undo_manager.record(Change::Insert { pos: *pos, text: c.to_string() });
+                    }
+                    // Advance primary cursor
+                    editor_state.cursor.move_horizontal(editor_state.cursor.column + 1);
                     editor_state.ensure_cursor_visible();
-
+                    // Advance secondary cursors
                     for sc in editor_state.secondary_cursors.iter_mut() {
-                        let pos = Position::new(sc.line, sc.column);
-                        if let Ok(Some(ch)) = text_buffer.delete_char(pos) {
-                            undo_manager.record(Change::Delete { pos, text: ch.to_string() });
-                        }
-                    }
-                } else if let Some(selection) = editor_state.selection {
-                    // Single cursor with selection: delete selection
-                    let (start, end) = selection.range();
-                    if let Ok(deleted) = text_buffer.delete_range(start, end) {
-                        undo_manager.record(Change::Delete { pos: start, text: deleted });
-                        editor_state.cursor.set_position(start);
-                        editor_state.clear_selection();
-                        editor_state.ensure_cursor_visible();
+                        sc.move_horizontal(sc.column + 1);
                     }
                 } else {
-                    // Single cursor, no selection: delete char at cursor
+                    // Single cursor: delete selection if present, then insert
+                    if let Some(selection) = editor_state.selection {
+                        let (start, end) = selection.range();
+                        if let Ok(deleted) = text_buffer.delete_range(start, end) {
+                            undo_manager.record(Change::Delete { pos: start, text: deleted });
+                            editor_state.cursor.set_position(start);
+                            editor_state.clear_selection();
+                        }
+                    }
                     let pos = editor_state.cursor.position();
-                    if let Ok(Some(ch)) = text_buffer.delete_char(pos) {
-                        undo_manager.record(Change::Delete { pos, text: ch.to_string() });
+                    text_buffer.insert_char(pos, c)?;
+                    undo_manager.record(Change::Insert { pos, text: c.to_string() });
+                    Movement::move_right(editor_state, text_buffer);
+
+                    // Trigger AI completion debouncing (only if enabled)
+                    if self.ai_completions_enabled {
+                        self.ai_suggestion = None;
+                        self.ai_last_keystroke = Some(Instant::now());
+                        if self.ai_pending_request {
+                            if let Some(manager) = &self.ai_manager {
+                                let _ = manager.cancel_pending();
+                            }
+                            self.ai_pending_request = false;
+                        }
                     }
                 }
             }
 
-            // Enter - Smart indentation
-            (KeyCode::Enter, _) => {
-                let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+            _ => {}
+        }
 
-                // If there's a selection, delete it first
-                if let Some(selection) = editor_state.selection {
-                    let (start, end) = selection.range();
-                    if let Ok(deleted) = text_buffer.delete_range(start, end) {
-                        undo_manager.record(Change::Delete {
-                            pos: start,
-                            text: deleted,
-                        });
-                        editor_state.cursor.set_position(start);
-                        editor_state.clear_selection();
-                    }
-                }
+        // Notify LSP about text changes (if buffer was modified)
+        if let Some(buffer) = self.workspace.active_buffer() {
+            if buffer.text_buffer().is_modified() {
+                self.notify_lsp_did_change();
+            }
+        }
 
-                let pos = editor_state.cursor.position();
+        // Signature help opens when a call's arguments are typed and then
+        // follows the cursor from argument to argument, until Esc. A request
+        // still unanswered is asked again, as its answer will be for where
+        // the cursor was.
+        let typed_call = matches!(key.code, KeyCode::Char('(' | ','))
+            && !key.modifiers.contains(KeyModifiers::CONTROL);
+        if key.code == KeyCode::Esc {
+            self.signature_help = None;
+            self.signature_help_request = None;
+        } else if typed_call || self.signature_help.is_some() || self.signature_help_request.is_some() {
+            self.request_signature_help();
+        }
 
-                // Smart indentation (if enabled)
-                if self.smart_indentation {
-                    // Get current line to calculate indentation
-                    let current_line = text_buffer.get_line(pos.line).unwrap_or_default();
+        Ok(ControlFlow::Continue)
+    }
 
-                    // Calculate base indentation (leading spaces/tabs)
-                    let base_indent = current_line.chars()
-                        .take_while(|c| *c == ' ' || *c == '\t')
-                        .collect::<String>();
+    /// Ask the language server for the signature of the call at the cursor
+    /// (after the edits that led there were sent)
+    fn request_signature_help(&mut self) {
+        let Some(buffer) = self.workspace.active_buffer() else {
+            return;
+        };
+        let (Some(lsp), Some(path), Some(_)) = (&mut self.lsp_manager, buffer.file_path(), buffer.lsp_language()) else {
+            return;
+        };
+        let buffer_id = buffer.id();
+        let position = buffer.editor_state().cursor.position();
+        let lsp_pos = crate::lsp::Position::new(position.line, position.column);
+        if lsp.signature_help(buffer_id.0, path.clone(), lsp_pos).is_ok() {
+            self.signature_help_request = Some((buffer_id, position));
+        }
+    }
 
-                    // Check the part of the line before cursor to see if it ends with ':'
-                    let line_chars: Vec<char> = current_line.chars().collect();
-                    let line_before_cursor = if pos.column <= line_chars.len() {
-                        line_chars.iter().take(pos.column).collect::<String>()
-                    } else {
-                        current_line.clone()
-                    };
-                    let line_before_cursor_trimmed = line_before_cursor.trim_end();
-                    let needs_extra_indent = line_before_cursor_trimmed.ends_with(':');
+    /// Initialize the LSP manager
+    pub fn initialize_lsp(&mut self) -> Result<()> {
+        let (manager, receiver) = LspManager::new();
+        self.lsp_manager = Some(manager);
+        self.lsp_receiver = Some(receiver);
 
-                    // Build the newline + indentation string
-                    let mut indent_str = String::from("\n");
-                    indent_str.push_str(&base_indent);
-                    if needs_extra_indent {
-                        indent_str.push_str("    "); // Add 4 spaces for Python
-                    }
+        // Notify LSP about any currently open file
+        self.notify_lsp_did_open();
 
-                    // Insert newline with indentation
-                    text_buffer.insert(pos, &indent_str)?;
-                    undo_manager.record(Change::Insert {
-                        pos,
-                        text: indent_str.clone(),
-                    });
+        Ok(())
+    }
 
-                    // Move cursor to end of inserted text
-                    let indent_len = base_indent.chars().count() + if needs_extra_indent { 4 } else { 0 };
-                    editor_state.cursor.line += 1;
-                    editor_state.cursor.move_horizontal(indent_len);
-                } else {
-                    // Simple newline without smart indentation
-                    text_buffer.insert(pos, "\n")?;
-                    undo_manager.record(Change::Insert {
-                        pos,
-                        text: String::from("\n"),
-                    });
-                    editor_state.cursor.line += 1;
-                    editor_state.cursor.move_horizontal(0);
+    /// Initialize the AI completion manager
+    pub fn initialize_ai(&mut self) -> Result<()> {
+        use crate::ai::provider::CompletionProvider;
+        use crate::ai::providers::{ClaudeProvider, CopilotProvider, LocalLlmProvider, OpenAiProvider};
+        use std::sync::Arc;
+
+        // Load configuration
+        let config = Config::load()?;
+
+        // Check if AI is enabled
+        if !config.ai.enabled {
+            return Ok(());
+        }
+
+        // Create provider based on configuration
+        let provider: Arc<dyn CompletionProvider> = match config.ai.provider.as_str() {
+            "copilot" => {
+                let api_token = match config.ai.copilot.api_token {
+                    Some(token) if !token.is_empty() => token,
+                    _ => {
+                        logger::log("AI completion disabled: No Copilot API token configured");
+                        return Ok(());
+                    }
+                };
+                Arc::new(CopilotProvider::new(api_token))
+            }
+            "openai" => {
+                let api_key = match config.ai.openai.api_key {
+                    Some(key) if !key.is_empty() => key,
+                    _ => {
+                        logger::log("AI completion disabled: No OpenAI API key configured");
+                        return Ok(());
+                    }
+                };
+                Arc::new(OpenAiProvider::new(
+                    api_key,
+                    config.ai.openai.model,
+                ))
+            }
+            "claude" => {
+                let api_key = match config.ai.claude.api_key {
+                    Some(key) if !key.is_empty() => key,
+                    _ => {
+                        logger::log("AI completion disabled: No Claude API key configured");
+                        return Ok(());
+                    }
+                };
+                Arc::new(ClaudeProvider::new(
+                    api_key,
+                    config.ai.claude.model,
+                ))
+            }
+            "local" => {
+                if config.ai.local.endpoint.is_empty() {
+                    logger::log("AI completion disabled: No local LLM endpoint configured");
+                    return Ok(());
                 }
-                editor_state.ensure_cursor_visible();
+                Arc::new(LocalLlmProvider::new(config.ai.local.endpoint))
+            }
+            _ => {
+                logger::log(&format!(
+                    "AI completion disabled: Unknown provider '{}'",
+                    config.ai.provider
+                ));
+                return Ok(());
             }
+        };
 
-            // Tab - Accept AI suggestion, smart completion, or indentation (but not Ctrl+Tab)
-            (KeyCode::Tab, mods) if !mods.contains(KeyModifiers::CONTROL) && !mods.contains(KeyModifiers::SHIFT) => {
-                // Check if there's an AI suggestion to accept
-                if let Some(suggestion) = self.ai_suggestion.take() {
-                    // Accept the AI suggestion by inserting it at cursor
-                    let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
-                    let pos = editor_state.cursor.position();
+        // Create AI manager
+        let (manager, receiver) = AiManager::new(provider);
+        self.ai_manager = Some(manager);
+        self.ai_receiver = Some(receiver);
 
-                    // Insert the full suggestion (can be multi-line)
-                    text_buffer.insert(pos, &suggestion)?;
-                    undo_manager.record(Change::Insert {
-                        pos,
-                        text: suggestion.clone(),
-                    });
+        logger::log(&format!(
+            "AI completion enabled with provider: {}",
+            config.ai.provider
+        ));
 
-                    // Move cursor to end of inserted text
-                    // Count newlines to update line number
-                    let newline_count = suggestion.matches('\n').count();
-                    if newline_count > 0 {
-                        editor_state.cursor.line += newline_count;
-                        // Get the last line to determine column position
-                        let last_line = suggestion.lines().last().unwrap_or("");
-                        editor_state.cursor.column = last_line.chars().count();
+        Ok(())
+    }
+
+    /// Poll for LSP messages (non-blocking)
+    pub fn poll_lsp_messages(&mut self) -> bool {
+        // Collect responses first to avoid borrow checker issues
+        let mut responses = Vec::new();
+        if let Some(receiver) = &mut self.lsp_receiver {
+            while let Ok(response) = receiver.try_recv() {
+                responses.push(response);
+            }
+        }
+
+        let had_updates = !responses.is_empty();
+
+        // Handle all collected responses
+        for response in responses {
+            self.handle_lsp_response(response);
+        }
+
+        had_updates
+    }
+
+    /// Handle an LSP response
+    fn handle_lsp_response(&mut self, response: LspResponse) {
+        match response {
+            LspResponse::Diagnostics {
+                buffer_id,
+                diagnostics,
+            } => {
+                self.diagnostics_store.update(buffer_id, diagnostics);
+                // The lightbulb is checked again for the new diagnostics
+                self.code_action_checked = None;
+            }
+            LspResponse::GotoDefinition { location } => {
+                // Check if we're jumping within the same file
+                let current_buffer_id = self.layout.active_buffer();
+                let is_same_file = if let Some(buffer_id) = current_buffer_id {
+                    if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
+                        buffer.file_path() == Some(&location.path)
                     } else {
-                        editor_state.cursor.column += suggestion.chars().count();
+                        false
                     }
-                    editor_state.ensure_cursor_visible();
+                } else {
+                    false
+                };
 
-                    self.message = Some("AI suggestion accepted".to_string());
-                    return Ok(ControlFlow::Continue);
-                }
+                if is_same_file {
+                    // Just move cursor in current buffer
+                    if let Some(buffer_id) = current_buffer_id {
+                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                            let editor_state = buffer.editor_state_mut();
+                            editor_state.cursor.set_position(crate::buffer::Position {
+                                line: location.position.line,
+                                column: location.position.column,
+                            });
+                            // Center the view on the cursor after jumping
+                            editor_state.viewport.center_on_line(location.position.line);
+                            self.message = Some(format!("Jumped to line {}:{}",
+                                location.position.line + 1,
+                                location.position.column + 1));
+                        }
+                    }
+                } else {
+                    // Open file and jump to position
+                    match self.workspace.open_file(location.path.clone()) {
+                        Ok(open_result) => {
+                            let buffer_id = open_result.buffer_id();
+                            // Set the buffer in the active pane
+                            let pane = self.layout.active_pane();
+                            self.layout.set_buffer(pane, buffer_id);
 
-                // Determine if we should trigger completion or indent
-                let should_complete = if let Some(path) = buffer.file_path() {
-                    if crate::lsp::Language::from_path(path).is_some() {
-                        // Check the character before the cursor
+                            // Now get the buffer and set cursor position
+                            if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                                let editor_state = buffer.editor_state_mut();
+                                editor_state.cursor.set_position(crate::buffer::Position {
+                                    line: location.position.line,
+                                    column: location.position.column,
+                                });
+                                // Center the view on the cursor after jumping
+                                editor_state.viewport.center_on_line(location.position.line);
+                                self.message = Some(format!("Jumped to {}:{}:{}",
+                                    location.path.display(),
+                                    location.position.line + 1,
+                                    location.position.column + 1));
+                            }
+                        }
+                        Err(e) => {
+                            self.message = Some(format!("Error: {}", e));
+                        }
+                    }
+                }
+            }
+            LspResponse::Completion { mut items } => {
+                if items.is_empty() {
+                    self.message = Some("No completions available".to_string());
+                } else {
+                    // Get the partial word the user has typed
+                    let partial_word = if let Some(buffer) = self.workspace.active_buffer() {
                         let pos = buffer.editor_state().cursor.position();
                         if let Some(line) = buffer.text_buffer().get_line(pos.line) {
                             let chars: Vec<char> = line.chars().collect();
-                            if pos.column > 0 && pos.column <= chars.len() {
-                                let prev_char = chars[pos.column - 1];
-                                // Complete after dot or if we're in the middle of a word
-                                prev_char == '.' || prev_char.is_alphanumeric()
+                            let mut word_start = pos.column;
+                            while word_start > 0 && word_start - 1 < chars.len() {
+                                let ch = chars[word_start - 1];
+                                if ch.is_alphanumeric() {
+                                    word_start -= 1;
+                                } else {
+                                    break;
+                                }
+                            }
+                            if word_start < pos.column && word_start < chars.len() {
+                                chars[word_start..pos.column].iter().collect::<String>().to_lowercase()
                             } else {
-                                false
+                                String::new()
                             }
                         } else {
-                            false
+                            String::new()
                         }
                     } else {
-                        false
-                    }
-                } else {
-                    false
-                };
+                        String::new()
+                    };
 
-                if should_complete && self.lsp_manager.is_some() {
-                    // Trigger completion (same logic as Ctrl+Space)
-                    if let Some(path) = buffer.file_path() {
-                        if let Some(lsp) = &mut self.lsp_manager {
-                            let pos = buffer.editor_state().cursor.position();
-                            let buffer_id = buffer.id().0;
-                            let lsp_pos = crate::lsp::Position::new(pos.line, pos.column);
-                            match lsp.completion(buffer_id, path.clone(), lsp_pos) {
-                                Ok(_) => {
-                                    self.message = Some(format!("Requesting completions at {}:{}...", pos.line, pos.column));
-                                }
-                                Err(e) => {
-                                    self.message = Some(format!("Completion error: {}", e));
-                                }
-                            }
-                        }
+                    // Filter and sort completions
+                    // 1. Filter out dunder methods unless user typed "__"
+                    let show_dunder = partial_word.starts_with("__");
+                    if !show_dunder {
+                        items.retain(|item| !item.label.starts_with("__"));
                     }
-                } else {
-                    // Insert 4 spaces for indentation
-                    let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
 
-                    if editor_state.has_secondary_cursors() {
-                        // Multi-cursor: insert spaces at all cursor positions bottom-to-top
-                        editor_state.clear_selection();
-                        let positions = editor_state.all_positions_bottom_to_top();
-                        for pos in &positions {
-                            text_buffer.insert(*pos, "    ")?;
-                            undo_manager.record(Change::Insert { pos: *pos, text: "    ".to_string() });
+                    // 2. Sort by relevance
+                    items.sort_by(|a, b| {
+                        // Prioritize items that start with the partial word
+                        let a_starts = a.label.to_lowercase().starts_with(&partial_word);
+                        let b_starts = b.label.to_lowercase().starts_with(&partial_word);
+
+                        if a_starts != b_starts {
+                            return b_starts.cmp(&a_starts);
                         }
-                        editor_state.cursor.column += 4;
-                        editor_state.ensure_cursor_visible();
-                        for sc in editor_state.secondary_cursors.iter_mut() {
-                            sc.column += 4;
+
+                        // Then prioritize methods/fields over other types
+                        let a_is_member = matches!(a.kind, Some(crate::lsp::CompletionItemKind::Method) | Some(crate::lsp::CompletionItemKind::Field));
+                        let b_is_member = matches!(b.kind, Some(crate::lsp::CompletionItemKind::Method) | Some(crate::lsp::CompletionItemKind::Field));
+
+                        if a_is_member != b_is_member {
+                            return b_is_member.cmp(&a_is_member);
                         }
+
+                        // Finally sort alphabetically
+                        a.label.cmp(&b.label)
+                    });
+
+                    self.completion_items = items;
+                    self.completion_selected = 0;
+                    self.completion_scroll_offset = 0;
+                    self.mode = AppMode::Completion;
+                    self.message = Some(format!(
+                        "{} completions (↑↓ to navigate, Enter/Tab to select, Esc to cancel)",
+                        self.completion_items.len()
+                    ));
+                }
+            }
+            LspResponse::Hover { contents, markdown } => {
+                let Some(request) = self.hover_request.take() else {
+                    return;
+                };
+                // Drop documentation for a symbol the cursor has left
+                let current = self
+                    .workspace
+                    .active_buffer()
+                    .map(|buffer| (buffer.id(), buffer.editor_state().cursor.position()));
+                if self.mode != AppMode::Normal || current != Some((request.buffer_id, request.position)) {
+                    return;
+                }
+
+                // Unlabeled code blocks are in the buffer's language
+                let buffer_language = self.workspace.active_buffer().and_then(|buffer| buffer.syntax_language());
+                let highlighter = &mut self.highlighter;
+                let lines = crate::lsp::hover_lines(&contents, markdown, |code, name| {
+                    let language = if name.is_empty() {
+                        buffer_language
                     } else {
-                        // Single cursor
-                        if let Some(selection) = editor_state.selection {
-                            let (start, end) = selection.range();
-                            if let Ok(deleted) = text_buffer.delete_range(start, end) {
-                                undo_manager.record(Change::Delete { pos: start, text: deleted });
-                                editor_state.cursor.set_position(start);
-                                editor_state.clear_selection();
-                            }
-                        }
-                        let pos = editor_state.cursor.position();
-                        text_buffer.insert(pos, "    ")?;
-                        undo_manager.record(Change::Insert { pos, text: "    ".to_string() });
-                        editor_state.cursor.column += 4;
-                        editor_state.ensure_cursor_visible();
+                        LanguageRegistry::by_name(name).and_then(|language| language.syntax)
+                    };
+                    language
+                        .and_then(|language| highlighter.highlight_snippet(code, language).ok())
+                        .unwrap_or_default()
+                });
+
+                if lines.is_empty() {
+                    if request.focused {
+                        self.message = Some("No documentation here".to_string());
                     }
+                    return;
                 }
+                self.hover_lines = lines;
+                self.hover_scroll = 0;
+                if request.focused {
+                    self.mode = AppMode::Hover;
+                    self.message = Some("↑↓ PgUp PgDn: Scroll | Esc: Close".to_string());
+                }
+            }
+            LspResponse::References { locations } => {
+                if self.mode == AppMode::Normal {
+                    self.show_references(locations);
+                }
+            }
+            LspResponse::SignatureHelp { signature } => {
+                // Answers for where the cursor no longer is are followed by
+                // a newer one
+                let current = self
+                    .workspace
+                    .active_buffer()
+                    .map(|buffer| (buffer.id(), buffer.editor_state().cursor.position()));
+                let Some((buffer_id, _)) = current.filter(|_| current == self.signature_help_request) else {
+                    return;
+                };
+                // Outside a call there is nothing to ask again until one is typed
+                self.signature_help_request = None;
+                self.signature_help = signature.map(|signature| (buffer_id, signature));
+            }
+            LspResponse::PrepareRename { renameable, placeholder } => {
+                // Only while the cursor is still on the symbol
+                let current = self
+                    .workspace
+                    .active_buffer()
+                    .map(|buffer| (buffer.id(), buffer.editor_state().cursor.position()));
+                if self.mode != AppMode::Normal || current.is_none() || current != self.rename_request {
+                    return;
+                }
+                if !renameable {
+                    self.rename_request = None;
+                    self.message = Some("Nothing to rename here".to_string());
+                    return;
+                }
+                let word = self
+                    .workspace
+                    .active_buffer()
+                    .and_then(|buffer| self.get_word_at_cursor(buffer))
+                    .map(|(word, _, _)| word);
+                self.rename_input = placeholder.or(word).unwrap_or_default();
+                self.mode = AppMode::RenamePrompt;
+                self.message = Some(format!("Rename to: {}", self.rename_input));
+            }
+            LspResponse::Rename { edit } => {
+                if self.rename_request.take().is_none() {
+                    return;
+                }
+                if edit.files.is_empty() {
+                    self.message = Some("Nothing to rename".to_string());
+                    return;
+                }
+                let description = format!("Rename to '{}'", self.rename_input);
+                self.apply_workspace_edit(PendingWorkspaceEdit {
+                    description,
+                    edit,
+                    ..Default::default()
+                });
+            }
+            LspResponse::CodeActions { actions, automatic } => {
+                // Only for the line the cursor is still on; the lightbulb
+                // check and the picker each wait for their own answer
+                let request = if automatic {
+                    self.code_action_bulb_request.take()
+                } else {
+                    self.code_action_request.take()
+                };
+                let Some((buffer_id, line)) = request else {
+                    return;
+                };
+                let current = self
+                    .workspace
+                    .active_buffer()
+                    .map(|buffer| (buffer.id(), buffer.editor_state().cursor.line));
+                if current != Some((buffer_id, line)) {
+                    return;
+                }
+                if automatic {
+                    self.code_action_bulb = (!actions.is_empty()).then_some((buffer_id, line));
+                    return;
+                }
+                if self.mode != AppMode::Normal {
+                    return;
+                }
+                if actions.is_empty() {
+                    self.message = Some("No code actions here".to_string());
+                    return;
+                }
+                self.code_action_selected = actions.iter().position(|action| action.preferred).unwrap_or(0);
+                self.code_action_scroll_offset = self
+                    .code_action_selected
+                    .saturating_sub(crate::lsp::CodeActionPopup::MAX_ROWS - 1);
+                self.code_actions = actions;
+                self.mode = AppMode::CodeActions;
+                self.message = None;
+            }
+            LspResponse::ApplyEdit { language, id, label, edit } => {
+                let description = label.unwrap_or_else(|| "Edit from language server".to_string());
+                self.apply_workspace_edit(PendingWorkspaceEdit {
+                    description,
+                    edit,
+                    reply: Some((language, id)),
+                    command: None,
+                });
+            }
+            LspResponse::Error { message } => {
+                self.message = Some(format!("LSP Error: {}", message));
             }
+        }
+    }
 
-            // Regular character input
-            (KeyCode::Char(c), mods) if !mods.contains(KeyModifiers::CONTROL) => {
-                let (text_buffer, editor_state, undo_manager) = buffer.split_mut();
+    /// Shutdown the LSP manager
+    pub fn shutdown_lsp(&mut self) -> Result<()> {
+        if let Some(manager) = &mut self.lsp_manager {
+            manager.shutdown()?;
+        }
+        Ok(())
+    }
 
-                if editor_state.has_secondary_cursors() {
-                    // Multi-cursor insertion: apply bottom-to-top to avoid invalidating positions
-                    editor_state.clear_selection();
-                    let positions = editor_state.all_positions_bottom_to_top();
-                    for pos in &positions {
-                        text_buffer.insert_char(*pos, c)?;
-                        undo_manager.record(Change::Insert { pos: *pos, text: c.to_string() });
-                    }
-                    // Advance primary cursor
-                    editor_state.cursor.move_horizontal(editor_state.cursor.column + 1);
-                    editor_state.ensure_cursor_visible();
-                    // Advance secondary cursors
-                    for sc in editor_state.secondary_cursors.iter_mut() {
-                        sc.move_horizontal(sc.column + 1);
-                    }
-                } else {
-                    // Single cursor: delete selection if present, then insert
-                    if let Some(selection) = editor_state.selection {
-                        let (start, end) = selection.range();
-                        if let Ok(deleted) = text_buffer.delete_range(start, end) {
-                            undo_manager.record(Change::Delete { pos: start, text: deleted });
-                            editor_state.cursor.set_position(start);
-                            editor_state.clear_selection();
-                        }
-                    }
-                    let pos = editor_state.cursor.position();
-                    text_buffer.insert_char(pos, c)?;
-                    undo_manager.record(Change::Insert { pos, text: c.to_string() });
-                    Movement::move_right(editor_state, text_buffer);
+    /// Poll for AI completion messages (non-blocking)
+    pub fn poll_ai_messages(&mut self) -> bool {
+        // Collect responses first to avoid borrow checker issues
+        let mut responses = Vec::new();
+        if let Some(receiver) = &mut self.ai_receiver {
+            while let Ok(response) = receiver.try_recv() {
+                responses.push(response);
+            }
+        }
+
+        let had_updates = !responses.is_empty();
+
+        // Handle all collected responses
+        for response in responses {
+            self.handle_ai_response(response);
+        }
+
+        had_updates
+    }
+
+    /// Handle an AI completion response
+    fn handle_ai_response(&mut self, response: AiResponse) {
+        match response {
+            AiResponse::Completion {
+                buffer_id,
+                text,
+                provider: _,
+            } => {
+                // Only show suggestion if this is still the active buffer,
+                // unedited since the request
+                let current_revision = self.workspace.get_buffer(buffer_id).map(|b| (buffer_id, b.text_buffer().revision()));
+                if self.ai_request_revision.is_some() && self.ai_request_revision != current_revision {
+                    self.ai_pending_request = false;
+                    return;
+                }
+                if let Some(active_buffer_id) = self.layout.active_buffer() {
+                    if active_buffer_id == buffer_id {
+                        // Get the current line to detect overlap
+                        let cleaned_text = if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
+                            let cursor_pos = buffer.editor_state().cursor.position();
+                            if let Some(current_line) = buffer.text_buffer().get_line(cursor_pos.line) {
+                                // Get text from line start to cursor
+                                let line_chars: Vec<char> = current_line.chars().collect();
+                                let text_before_cursor: String = line_chars
+                                    .iter()
+                                    .take(cursor_pos.column)
+                                    .collect();
 
-                    // Trigger AI completion debouncing (only if enabled)
-                    if self.ai_completions_enabled {
-                        self.ai_suggestion = None;
-                        self.ai_last_keystroke = Some(Instant::now());
-                        if self.ai_pending_request {
-                            if let Some(manager) = &self.ai_manager {
-                                let _ = manager.cancel_pending();
+                                // Strip overlapping prefix from suggestion
+                                Self::strip_overlap(&text_before_cursor, &text)
+                            } else {
+                                text
                             }
-                            self.ai_pending_request = false;
-                        }
+                        } else {
+                            text
+                        };
+
+                        // Store the cleaned suggestion
+                        self.ai_suggestion = Some(cleaned_text);
+                        self.ai_pending_request = false;
                     }
                 }
             }
+            AiResponse::Error(error) => {
+                logger::log(&format!("AI completion error: {}", error));
+                self.ai_pending_request = false;
+            }
+        }
+    }
 
-            _ => {}
+    /// Shutdown the AI manager
+    pub fn shutdown_ai(&mut self) -> Result<()> {
+        if let Some(manager) = &mut self.ai_manager {
+            manager.shutdown()?;
         }
+        Ok(())
+    }
 
-        // Notify LSP about text changes (if buffer was modified)
-        if let Some(buffer) = self.workspace.active_buffer() {
-            if buffer.text_buffer().is_modified() {
-                self.notify_lsp_did_change();
+    /// Strip overlapping prefix from AI suggestion
+    /// If suggestion starts with text already on the line, remove that prefix
+    fn strip_overlap(text_before_cursor: &str, suggestion: &str) -> String {
+        // Try to find the longest suffix of text_before_cursor that matches
+        // a prefix of the suggestion
+        let text_before = text_before_cursor.trim_end();
+
+        // Try progressively shorter suffixes of the current text
+        for start_idx in 0..text_before.len() {
+            let suffix = &text_before[start_idx..];
+            if suggestion.starts_with(suffix) {
+                // Found overlap - strip it
+                return suggestion[suffix.len()..].to_string();
             }
         }
 
-        Ok(ControlFlow::Continue)
+        // No overlap found, return suggestion as-is
+        suggestion.to_string()
     }
 
-    /// Initialize the LSP manager
-    pub fn initialize_lsp(&mut self) -> Result<()> {
-        let (manager, receiver) = LspManager::new();
-        self.lsp_manager = Some(manager);
-        self.lsp_receiver = Some(receiver);
+    /// Trigger an AI completion request for the current cursor position
+    fn trigger_ai_completion(&mut self) -> Result<()> {
+        use crate::ai::provider::CompletionRequest;
+        use crate::ai::AiRequest;
 
-        // Notify LSP about any currently open file
-        self.notify_lsp_did_open();
+        // Check if AI manager is available
+        if self.ai_manager.is_none() {
+            return Ok(());
+        }
+
+        // Get active buffer
+        let buffer_id = match self.layout.active_buffer() {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let buffer = match self.workspace.get_buffer(buffer_id) {
+            Some(b) => b,
+            None => return Ok(()),
+        };
+
+        // No completions in large files: the whole text would be copied for every request
+        if buffer.text_buffer().is_large_file() {
+            return Ok(());
+        }
+
+        // Get file path and language
+        let file_path = buffer.file_path().cloned().unwrap_or_else(|| {
+            std::path::PathBuf::from("untitled")
+        });
+
+        let language = if let Some(path) = buffer.file_path() {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("txt")
+                .to_string()
+        } else {
+            "txt".to_string()
+        };
+
+        // Get cursor position
+        let cursor_pos = buffer.editor_state().cursor.position();
+
+        // Get the last 2000 chars before the cursor and the next 500 after it
+        let (code_before_cursor, code_after_cursor) = buffer.text_buffer().text_around(cursor_pos, 2000, 500)?;
+        // The suggestion only applies to this revision of the text
+        self.ai_request_revision = Some((buffer_id, buffer.text_buffer().revision()));
+
+        // Create completion request
+        let request = CompletionRequest {
+            file_path,
+            language,
+            code_before_cursor,
+            code_after_cursor,
+            cursor_position: cursor_pos,
+        };
+
+        // Send request to AI manager
+        if let Some(manager) = &self.ai_manager {
+            manager.request_completion(request, buffer_id)?;
+        }
 
         Ok(())
     }
 
-    /// Initialize the AI completion manager
-    pub fn initialize_ai(&mut self) -> Result<()> {
-        use crate::ai::provider::CompletionProvider;
-        use crate::ai::providers::{ClaudeProvider, CopilotProvider, LocalLlmProvider, OpenAiProvider};
-        use std::sync::Arc;
-
-        // Load configuration
-        let config = Config::load()?;
+    /// Search the project once typing in the search prompt has paused.
+    /// Returns true if the results were updated.
+    pub fn check_project_search_debounce(&mut self) -> bool {
+        let Some(last_keystroke) = self.project_search_last_keystroke else {
+            return false;
+        };
+        if self.mode != AppMode::ProjectSearch {
+            self.project_search_last_keystroke = None;
+            return false;
+        }
+        if last_keystroke.elapsed() < PROJECT_SEARCH_DELAY {
+            return false;
+        }
+        self.project_search_last_keystroke = None;
+        self.update_project_search_results();
+        true
+    }
 
-        // Check if AI is enabled
-        if !config.ai.enabled {
+    /// Check debounce timer and trigger AI completion if needed
+    pub fn check_ai_debounce(&mut self) -> Result<()> {
+        // Skip if AI completions are disabled
+        if !self.ai_completions_enabled {
             return Ok(());
         }
 
-        // Create provider based on configuration
-        let provider: Arc<dyn CompletionProvider> = match config.ai.provider.as_str() {
-            "copilot" => {
-                let api_token = match config.ai.copilot.api_token {
-                    Some(token) if !token.is_empty() => token,
-                    _ => {
-                        logger::log("AI completion disabled: No Copilot API token configured");
-                        return Ok(());
-                    }
-                };
-                Arc::new(CopilotProvider::new(api_token))
+        if let Some(last_keystroke) = self.ai_last_keystroke {
+            // Check if 150ms has elapsed since last keystroke
+            if last_keystroke.elapsed() >= Duration::from_millis(150) && !self.ai_pending_request {
+                // Trigger completion request
+                self.trigger_ai_completion()?;
+                self.ai_last_keystroke = None;
+                self.ai_pending_request = true;
             }
-            "openai" => {
-                let api_key = match config.ai.openai.api_key {
-                    Some(key) if !key.is_empty() => key,
-                    _ => {
-                        logger::log("AI completion disabled: No OpenAI API key configured");
-                        return Ok(());
+        }
+        Ok(())
+    }
+
+    /// Notify LSP that the active buffer was opened
+    fn notify_lsp_did_open(&mut self) {
+        if let Some(lsp) = &mut self.lsp_manager {
+            if let Some(buffer) = self.workspace.active_buffer() {
+                // Large files are not synced to the language server
+                if buffer.text_buffer().is_large_file() {
+                    return;
+                }
+                if let Some(path) = buffer.file_path() {
+                    if let Some(language) = buffer.lsp_language() {
+                        let content = buffer.text_buffer().to_string();
+                        self.lsp_synced_revisions.insert(buffer.id(), buffer.text_buffer().revision());
+                        let buffer_id = buffer.id().0; // Extract usize from BufferId
+                        let _ = lsp.did_open(buffer_id, path.clone(), content, language);
                     }
-                };
-                Arc::new(OpenAiProvider::new(
-                    api_key,
-                    config.ai.openai.model,
-                ))
+                }
             }
-            "claude" => {
-                let api_key = match config.ai.claude.api_key {
-                    Some(key) if !key.is_empty() => key,
-                    _ => {
-                        logger::log("AI completion disabled: No Claude API key configured");
-                        return Ok(());
+        }
+    }
+
+    /// Notify LSP that the active buffer was changed
+    fn notify_lsp_did_change(&mut self) {
+        if let Some(buffer_id) = self.workspace.active_buffer_id() {
+            self.notify_lsp_buffer_changed(buffer_id);
+        }
+    }
+
+    /// Notify LSP that the active buffer was saved
+    fn notify_lsp_did_save(&mut self) {
+        if let Some(buffer_id) = self.workspace.active_buffer_id() {
+            self.notify_lsp_buffer_saved(buffer_id);
+        }
+    }
+
+    /// Notify LSP that a buffer was saved
+    fn notify_lsp_buffer_saved(&mut self, buffer_id: crate::workspace::BufferId) {
+        if let Some(lsp) = &mut self.lsp_manager {
+            if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
+                // Large files are not synced to the language server
+                if buffer.text_buffer().is_large_file() {
+                    return;
+                }
+                if let Some(path) = buffer.file_path() {
+                    if buffer.lsp_language().is_some() {
+                        let _ = lsp.did_save(buffer_id.0, path.clone());
                     }
-                };
-                Arc::new(ClaudeProvider::new(
-                    api_key,
-                    config.ai.claude.model,
-                ))
-            }
-            "local" => {
-                if config.ai.local.endpoint.is_empty() {
-                    logger::log("AI completion disabled: No local LLM endpoint configured");
-                    return Ok(());
                 }
-                Arc::new(LocalLlmProvider::new(config.ai.local.endpoint))
             }
-            _ => {
-                logger::log(&format!(
-                    "AI completion disabled: Unknown provider '{}'",
-                    config.ai.provider
-                ));
-                return Ok(());
+        }
+    }
+
+    /// Notify LSP that a buffer is no longer open under `path`
+    fn notify_lsp_did_close(&mut self, buffer_id: crate::workspace::BufferId, path: PathBuf) {
+        self.lsp_synced_revisions.remove(&buffer_id);
+        self.diagnostics_store.update(buffer_id.0, Vec::new());
+        if let Some(lsp) = &mut self.lsp_manager {
+            let _ = lsp.did_close(buffer_id.0, path);
+        }
+    }
+
+    /// Check if an error is a permission denied error
+    fn is_permission_denied(&self, error: &anyhow::Error) -> bool {
+        if let Some(io_error) = error.downcast_ref::<std::io::Error>() {
+            return io_error.kind() == std::io::ErrorKind::PermissionDenied;
+        }
+        false
+    }
+
+    /// Get word at cursor position with its boundaries
+    fn get_word_at_cursor(&self, buffer: &crate::workspace::Buffer) -> Option<(String, usize, usize)> {
+        let cursor_pos = buffer.editor_state().cursor.position();
+        let line_text = buffer.text_buffer().get_line(cursor_pos.line)?;
+        let col = cursor_pos.column;
+
+        if line_text.is_empty() || col >= line_text.chars().count() {
+            return None;
+        }
+
+        let chars: Vec<char> = line_text.chars().collect();
+        let cursor_char = chars.get(col)?;
+
+        // Check if we're on a word character (alphanumeric or underscore)
+        if !cursor_char.is_alphanumeric() && *cursor_char != '_' {
+            return None;
+        }
+
+        // Find word start
+        let mut start = col;
+        while start > 0 {
+            let ch = chars[start - 1];
+            if !ch.is_alphanumeric() && ch != '_' {
+                break;
             }
-        };
-
-        // Create AI manager
-        let (manager, receiver) = AiManager::new(provider);
-        self.ai_manager = Some(manager);
-        self.ai_receiver = Some(receiver);
+            start -= 1;
+        }
 
-        logger::log(&format!(
-            "AI completion enabled with provider: {}",
-            config.ai.provider
-        ));
+        // Find word end
+        let mut end = col + 1;
+        while end < chars.len() {
+            let ch = chars[end];
+            if !ch.is_alphanumeric() && ch != '_' {
+                break;
+            }
+            end += 1;
+        }
 
-        Ok(())
+        let word: String = chars[start..end].iter().collect();
+        Some((word, start, end))
     }
 
-    /// Poll for LSP messages (non-blocking)
-    pub fn poll_lsp_messages(&mut self) -> bool {
-        // Collect responses first to avoid borrow checker issues
-        let mut responses = Vec::new();
-        if let Some(receiver) = &mut self.lsp_receiver {
-            while let Ok(response) = receiver.try_recv() {
-                responses.push(response);
-            }
-        }
+    /// Toggle mark at cursor position - marks if not marked, unmarks if already marked
+    fn toggle_word_mark_at_cursor(&mut self) -> Result<()> {
+        let buffer_id = self.layout.active_buffer()
+            .ok_or_else(|| anyhow::anyhow!("No active buffer"))?;
+        let buffer = self.workspace.get_buffer(buffer_id)
+            .ok_or_else(|| anyhow::anyhow!("Buffer not found"))?;
 
-        let had_updates = !responses.is_empty();
+        let file_path = buffer.file_path()
+            .ok_or_else(|| anyhow::anyhow!("Buffer has no file path"))?
+            .clone();
 
-        // Handle all collected responses
-        for response in responses {
-            self.handle_lsp_response(response);
+        let cursor_pos = buffer.editor_state().cursor.position();
+
+        // Check if this position is already marked
+        if let Some(pos) = self.position_marks.iter().position(|mark| {
+            mark.file_path == file_path &&
+            mark.line == cursor_pos.line &&
+            mark.column == cursor_pos.column
+        }) {
+            // Already marked - unmark it
+            self.position_marks.remove(pos);
+            // Adjust current_mark_index if needed
+            if self.current_mark_index >= self.position_marks.len() && !self.position_marks.is_empty() {
+                self.current_mark_index = self.position_marks.len() - 1;
+            }
+            self.message = Some(format!("Unmarked position ({} marks remaining)", self.position_marks.len()));
+        } else {
+            // Not marked - mark it
+            self.position_marks.push(PositionMark {
+                file_path,
+                line: cursor_pos.line,
+                column: cursor_pos.column,
+            });
+            self.message = Some(format!("Marked position ({} marks total)", self.position_marks.len()));
         }
 
-        had_updates
+        Ok(())
     }
 
-    /// Handle an LSP response
-    fn handle_lsp_response(&mut self, response: LspResponse) {
-        match response {
-            LspResponse::Diagnostics {
-                buffer_id,
-                diagnostics,
-            } => {
-                self.diagnostics_store.update(buffer_id, diagnostics);
-            }
-            LspResponse::GotoDefinition { location } => {
-                // Check if we're jumping within the same file
-                let current_buffer_id = self.layout.active_buffer();
-                let is_same_file = if let Some(buffer_id) = current_buffer_id {
-                    if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
-                        buffer.file_path() == Some(&location.path)
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                };
+    /// Mark word at cursor
+    fn mark_word_at_cursor(&mut self) -> Result<()> {
+        let buffer_id = self.layout.active_buffer()
+            .ok_or_else(|| anyhow::anyhow!("No active buffer"))?;
+        let buffer = self.workspace.get_buffer(buffer_id)
+            .ok_or_else(|| anyhow::anyhow!("Buffer not found"))?;
 
-                if is_same_file {
-                    // Just move cursor in current buffer
-                    if let Some(buffer_id) = current_buffer_id {
-                        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                            let editor_state = buffer.editor_state_mut();
-                            editor_state.cursor.set_position(crate::buffer::Position {
-                                line: location.position.line,
-                                column: location.position.column,
-                            });
-                            // Center the view on the cursor after jumping
-                            editor_state.viewport.center_on_line(location.position.line);
-                            self.message = Some(format!("Jumped to line {}:{}",
-                                location.position.line + 1,
-                                location.position.column + 1));
-                        }
-                    }
-                } else {
-                    // Open file and jump to position
-                    match self.workspace.open_file(location.path.clone()) {
-                        Ok(open_result) => {
-                            let buffer_id = open_result.buffer_id();
-                            // Set the buffer in the active pane
-                            let pane = self.layout.active_pane();
-                            self.layout.set_buffer(pane, buffer_id);
+        let file_path = buffer.file_path()
+            .ok_or_else(|| anyhow::anyhow!("Buffer has no file path"))?
+            .clone();
 
-                            // Now get the buffer and set cursor position
-                            if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                                let editor_state = buffer.editor_state_mut();
-                                editor_state.cursor.set_position(crate::buffer::Position {
-                                    line: location.position.line,
-                                    column: location.position.column,
-                                });
-                                // Center the view on the cursor after jumping
-                                editor_state.viewport.center_on_line(location.position.line);
-                                self.message = Some(format!("Jumped to {}:{}:{}",
-                                    location.path.display(),
-                                    location.position.line + 1,
-                                    location.position.column + 1));
-                            }
-                        }
-                        Err(e) => {
-                            self.message = Some(format!("Error: {}", e));
-                        }
-                    }
-                }
-            }
-            LspResponse::Completion { mut items } => {
-                if items.is_empty() {
-                    self.message = Some("No completions available".to_string());
-                } else {
-                    // Get the partial word the user has typed
-                    let partial_word = if let Some(buffer) = self.workspace.active_buffer() {
-                        let pos = buffer.editor_state().cursor.position();
-                        if let Some(line) = buffer.text_buffer().get_line(pos.line) {
-                            let chars: Vec<char> = line.chars().collect();
-                            let mut word_start = pos.column;
-                            while word_start > 0 && word_start - 1 < chars.len() {
-                                let ch = chars[word_start - 1];
-                                if ch.is_alphanumeric() {
-                                    word_start -= 1;
-                                } else {
-                                    break;
-                                }
-                            }
-                            if word_start < pos.column && word_start < chars.len() {
-                                chars[word_start..pos.column].iter().collect::<String>().to_lowercase()
-                            } else {
-                                String::new()
-                            }
-                        } else {
-                            String::new()
-                        }
-                    } else {
-                        String::new()
-                    };
+        let cursor_pos = buffer.editor_state().cursor.position();
 
-                    // Filter and sort completions
-                    // 1. Filter out dunder methods unless user typed "__"
-                    let show_dunder = partial_word.starts_with("__");
-                    if !show_dunder {
-                        items.retain(|item| !item.label.starts_with("__"));
-                    }
+        if let Some((word, start_col, _)) = self.get_word_at_cursor(buffer) {
+            // Check if this word is already marked at this location
+            let already_marked = self.position_marks.iter().any(|mark| {
+                mark.file_path == file_path &&
+                mark.line == cursor_pos.line &&
+                mark.column == start_col
+            });
 
-                    // 2. Sort by relevance
-                    items.sort_by(|a, b| {
-                        // Prioritize items that start with the partial word
-                        let a_starts = a.label.to_lowercase().starts_with(&partial_word);
-                        let b_starts = b.label.to_lowercase().starts_with(&partial_word);
+            if already_marked {
+                self.message = Some(format!("'{}' already marked", word));
+            } else {
+                self.position_marks.push(PositionMark {
+                    file_path,
+                    line: cursor_pos.line,
+                    column: start_col,
+                });
+                self.message = Some(format!("Marked '{}' ({} marks total)", word, self.position_marks.len()));
+            }
+        } else {
+            self.message = Some("No word at cursor".to_string());
+        }
 
-                        if a_starts != b_starts {
-                            return b_starts.cmp(&a_starts);
-                        }
+        Ok(())
+    }
 
-                        // Then prioritize methods/fields over other types
-                        let a_is_member = matches!(a.kind, Some(crate::lsp::CompletionItemKind::Method) | Some(crate::lsp::CompletionItemKind::Field));
-                        let b_is_member = matches!(b.kind, Some(crate::lsp::CompletionItemKind::Method) | Some(crate::lsp::CompletionItemKind::Field));
+    /// Unmark word at cursor
+    fn unmark_word_at_cursor(&mut self) -> Result<()> {
+        let buffer_id = self.layout.active_buffer()
+            .ok_or_else(|| anyhow::anyhow!("No active buffer"))?;
+        let buffer = self.workspace.get_buffer(buffer_id)
+            .ok_or_else(|| anyhow::anyhow!("Buffer not found"))?;
 
-                        if a_is_member != b_is_member {
-                            return b_is_member.cmp(&a_is_member);
-                        }
+        let file_path = buffer.file_path()
+            .ok_or_else(|| anyhow::anyhow!("Buffer has no file path"))?;
 
-                        // Finally sort alphabetically
-                        a.label.cmp(&b.label)
-                    });
+        let cursor_pos = buffer.editor_state().cursor.position();
 
-                    self.completion_items = items;
-                    self.completion_selected = 0;
-                    self.completion_scroll_offset = 0;
-                    self.mode = AppMode::Completion;
-                    self.message = Some(format!(
-                        "{} completions (↑↓ to navigate, Enter/Tab to select, Esc to cancel)",
-                        self.completion_items.len()
-                    ));
+        if let Some((word, start_col, _)) = self.get_word_at_cursor(buffer) {
+            // Find and remove the mark at this location
+            if let Some(pos) = self.position_marks.iter().position(|mark| {
+                mark.file_path == *file_path &&
+                mark.line == cursor_pos.line &&
+                mark.column == start_col
+            }) {
+                self.position_marks.remove(pos);
+                // Adjust current_mark_index if needed
+                if self.current_mark_index >= self.position_marks.len() && !self.position_marks.is_empty() {
+                    self.current_mark_index = self.position_marks.len() - 1;
                 }
+                self.message = Some(format!("Unmarked '{}' ({} marks remaining)", word, self.position_marks.len()));
+            } else {
+                self.message = Some(format!("'{}' is not marked at this location", word));
             }
-            LspResponse::Error { message } => {
-                self.message = Some(format!("LSP Error: {}", message));
-            }
+        } else {
+            self.message = Some("No word at cursor".to_string());
         }
-    }
 
-    /// Shutdown the LSP manager
-    pub fn shutdown_lsp(&mut self) -> Result<()> {
-        if let Some(manager) = &mut self.lsp_manager {
-            manager.shutdown()?;
-        }
         Ok(())
     }
 
-    /// Poll for AI completion messages (non-blocking)
-    pub fn poll_ai_messages(&mut self) -> bool {
-        // Collect responses first to avoid borrow checker issues
-        let mut responses = Vec::new();
-        if let Some(receiver) = &mut self.ai_receiver {
-            while let Ok(response) = receiver.try_recv() {
-                responses.push(response);
-            }
-        }
-
-        let had_updates = !responses.is_empty();
-
-        // Handle all collected responses
-        for response in responses {
-            self.handle_ai_response(response);
+    /// Cycle to next mark
+    fn cycle_to_next_mark(&mut self) -> Result<()> {
+        if self.position_marks.is_empty() {
+            self.message = Some("No marks set".to_string());
+            return Ok(());
         }
 
-        had_updates
-    }
-
-    /// Handle an AI completion response
-    fn handle_ai_response(&mut self, response: AiResponse) {
-        match response {
-            AiResponse::Completion {
-                buffer_id,
-                text,
-                provider: _,
-            } => {
-                // Only show suggestion if this is still the active buffer
-                if let Some(active_buffer_id) = self.layout.active_buffer() {
-                    if active_buffer_id == buffer_id {
-                        // Get the current line to detect overlap
-                        let cleaned_text = if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
-                            let cursor_pos = buffer.editor_state().cursor.position();
-                            if let Some(current_line) = buffer.text_buffer().get_line(cursor_pos.line) {
-                                // Get text from line start to cursor
-                                let line_chars: Vec<char> = current_line.chars().collect();
-                                let text_before_cursor: String = line_chars
-                                    .iter()
-                                    .take(cursor_pos.column)
-                                    .collect();
-
-                                // Strip overlapping prefix from suggestion
-                                Self::strip_overlap(&text_before_cursor, &text)
-                            } else {
-                                text
-                            }
-                        } else {
-                            text
-                        };
+        // Move to next mark
+        self.current_mark_index = (self.current_mark_index + 1) % self.position_marks.len();
+        let mark = &self.position_marks[self.current_mark_index].clone();
 
-                        // Store the cleaned suggestion
-                        self.ai_suggestion = Some(cleaned_text);
-                        self.ai_pending_request = false;
+        // Find the buffer with this file path, or open it
+        let buffer_id = {
+            let mut found_id = None;
+            for id in self.workspace.buffer_ids() {
+                if let Some(buffer) = self.workspace.get_buffer(id) {
+                    if let Some(path) = buffer.file_path() {
+                        if path == &mark.file_path {
+                            found_id = Some(id);
+                            break;
+                        }
                     }
                 }
             }
-            AiResponse::Error(error) => {
-                logger::log(&format!("AI completion error: {}", error));
-                self.ai_pending_request = false;
+
+            if let Some(id) = found_id {
+                id
+            } else {
+                // Open the file
+                let result = self.workspace.open_file(mark.file_path.clone())?;
+                result.buffer_id()
             }
-        }
-    }
+        };
 
-    /// Shutdown the AI manager
-    pub fn shutdown_ai(&mut self) -> Result<()> {
-        if let Some(manager) = &mut self.ai_manager {
-            manager.shutdown()?;
+        // Switch to the buffer
+        let pane = self.layout.active_pane();
+        self.layout.set_buffer(pane, buffer_id);
+
+        // Move cursor to the marked position
+        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+            let editor_state = buffer.editor_state_mut();
+            editor_state.cursor.set_position(Position::new(mark.line, mark.column));
+            editor_state.clear_selection();
+
+            // Center viewport on cursor
+            let (_, term_height) = crossterm::terminal::size()?;
+            let visible_lines = term_height.saturating_sub(4) as usize; // Account for status bars
+            let target_top = mark.line.saturating_sub(visible_lines / 2);
+            editor_state.viewport.top_line = target_top;
         }
+
+        self.message = Some(format!("Mark {}/{} in {}:{}:{}",
+            self.current_mark_index + 1,
+            self.position_marks.len(),
+            mark.file_path.display(),
+            mark.line + 1,
+            mark.column + 1));
+
         Ok(())
     }
 
-    /// Strip overlapping prefix from AI suggestion
-    /// If suggestion starts with text already on the line, remove that prefix
-    fn strip_overlap(text_before_cursor: &str, suggestion: &str) -> String {
-        // Try to find the longest suffix of text_before_cursor that matches
-        // a prefix of the suggestion
-        let text_before = text_before_cursor.trim_end();
+    /// Check if sudo save needs to be executed
+    pub fn needs_sudo_save(&self) -> bool {
+        self.execute_sudo_save_on_render
+    }
 
-        // Try progressively shorter suffixes of the current text
-        for start_idx in 0..text_before.len() {
-            let suffix = &text_before[start_idx..];
-            if suggestion.starts_with(suffix) {
-                // Found overlap - strip it
-                return suggestion[suffix.len()..].to_string();
+    /// Execute sudo save operation
+    pub fn execute_sudo_save(&mut self, terminal: &crate::render::Terminal) -> Result<()> {
+        self.execute_sudo_save_on_render = false;
+
+        let path = self.pending_sudo_save_path.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No pending sudo save path"))?;
+        let content = self.pending_sudo_save_content.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No pending sudo save content"))?;
+
+        // Write in the buffer's own encoding
+        let encoding = self.layout.active_buffer()
+            .and_then(|id| self.workspace.get_buffer(id))
+            .map(|b| b.text_buffer().encoding())
+            .unwrap_or_default();
+        let bytes = match encoding.encode(content) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.message = Some(format!("Save failed: {}", e));
+                self.pending_sudo_save_path = None;
+                self.pending_sudo_save_content = None;
+                return Ok(());
             }
-        }
+        };
 
-        // No overlap found, return suggestion as-is
-        suggestion.to_string()
-    }
+        // Suspend TUI
+        terminal.cleanup()?;
 
-    /// Trigger an AI completion request for the current cursor position
-    fn trigger_ai_completion(&mut self) -> Result<()> {
-        use crate::ai::provider::CompletionRequest;
-        use crate::ai::AiRequest;
+        // Write through sudo into a temp file next to the target, then rename it into place
+        let result = crate::buffer::atomic_write::sudo_atomic_write_command(path)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::inherit())
+            .spawn()
+            .and_then(|mut child| {
+                use std::io::Write;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(&bytes)?;
+                }
+                child.wait()
+            });
 
-        // Check if AI manager is available
-        if self.ai_manager.is_none() {
-            return Ok(());
+        // Resume TUI
+        let _ = crossterm::terminal::enable_raw_mode();
+        let mut stdout = std::io::stdout();
+        let _ = crossterm::execute!(
+            stdout,
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::event::EnableMouseCapture,
+            crossterm::event::EnableBracketedPaste,
+            crossterm::event::EnableFocusChange,
+            crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
+            crossterm::cursor::SetCursorStyle::SteadyBlock,
+            crossterm::cursor::Show
+        );
+
+        // Handle result
+        match result {
+            Ok(status) if status.success() => {
+                if let Some(buffer_id) = self.layout.active_buffer() {
+                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+                        buffer.text_buffer_mut().set_modified(false);
+                        buffer.text_buffer_mut().acknowledge_disk_content(content);
+                    }
+                }
+                self.record_history(path, content);
+                self.message = Some("Saved with sudo".to_string());
+                self.notify_lsp_did_save();
+            }
+            Ok(status) => {
+                self.message = Some(format!("Sudo save failed: exit code {}",
+                    status.code().unwrap_or(-1)));
+            }
+            Err(e) => {
+                self.message = Some(format!("Sudo error: {}", e));
+            }
         }
 
-        // Get active buffer
-        let buffer_id = match self.layout.active_buffer() {
-            Some(id) => id,
-            None => return Ok(()),
-        };
+        // Clear pending state
+        self.pending_sudo_save_path = None;
+        self.pending_sudo_save_content = None;
 
-        let buffer = match self.workspace.get_buffer(buffer_id) {
-            Some(b) => b,
-            None => return Ok(()),
+        Ok(())
+    }
+
+    /// Start or stop following the active buffer's file. While following,
+    /// the buffer is read-only and grows as the file does.
+    fn toggle_follow(&mut self) {
+        let Some(buffer_id) = self.layout.active_buffer() else {
+            return;
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return;
         };
 
-        // Get file path and language
-        let file_path = buffer.file_path().cloned().unwrap_or_else(|| {
-            std::path::PathBuf::from("untitled")
-        });
+        if self.followers.remove(&buffer_id).is_some() {
+            let content = buffer.text_buffer().to_string();
+            buffer.text_buffer_mut().set_read_only(false);
+            buffer.text_buffer_mut().acknowledge_disk_content(&content);
+            self.message = Some(format!("Stopped following {}", buffer.display_name()));
+            return;
+        }
 
-        let language = if let Some(path) = buffer.file_path() {
-            path.extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("txt")
-                .to_string()
-        } else {
-            "txt".to_string()
+        let Some(path) = buffer.file_path().cloned() else {
+            self.message = Some("Buffer has no file path".to_string());
+            return;
         };
+        if buffer.is_modified() {
+            self.message = Some("Buffer has unsaved changes; save or undo them first".to_string());
+            return;
+        }
 
-        // Get cursor position
-        let cursor_pos = buffer.editor_state().cursor.position();
-
-        // Get text before and after cursor
-        let text_buffer = buffer.text_buffer();
-        let full_text = text_buffer.to_string();
-
-        // Calculate byte offset of cursor position
-        let mut byte_offset = 0;
-        for (line_idx, line) in full_text.lines().enumerate() {
-            if line_idx < cursor_pos.line {
-                byte_offset += line.len() + 1; // +1 for newline
-            } else if line_idx == cursor_pos.line {
-                byte_offset += cursor_pos.column.min(line.len());
-                break;
+        match FileFollower::start(path, buffer.text_buffer().encoding()) {
+            Ok((follower, content)) => {
+                if content != buffer.text_buffer().to_string() {
+                    buffer.replace_from_disk(&content);
+                } else {
+                    buffer.move_to_end();
+                }
+                buffer.text_buffer_mut().set_read_only(true);
+                self.message = Some(format!("Following {} (read-only)", buffer.display_name()));
+                self.followers.insert(buffer_id, follower);
+                self.notify_lsp_buffer_changed(buffer_id);
             }
+            Err(e) => self.message = Some(format!("Cannot follow file: {}", e)),
         }
+    }
 
-        // Split text at cursor
-        let (before, after) = full_text.split_at(byte_offset);
-        let code_before_cursor = before.chars().rev().take(2000).collect::<String>()
-            .chars().rev().collect::<String>(); // Take last 2000 chars
-        let code_after_cursor = after.chars().take(500).collect::<String>(); // Take next 500 chars
-
-        // Create completion request
-        let request = CompletionRequest {
-            file_path,
-            language,
-            code_before_cursor,
-            code_after_cursor,
-            cursor_position: cursor_pos,
-        };
+    /// Read what was appended to followed files since the last poll.
+    /// Returns true if any buffer changed.
+    pub fn poll_followed_files(&mut self) -> bool {
+        if self.followers.is_empty() {
+            return false;
+        }
+        // Forget followers of closed buffers
+        self.followers.retain(|id, _| self.workspace.get_buffer(*id).is_some());
 
-        // Send request to AI manager
-        if let Some(manager) = &self.ai_manager {
-            manager.request_completion(request, buffer_id)?;
+        let mut changed = Vec::new();
+        for (&buffer_id, follower) in self.followers.iter_mut() {
+            let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+                continue;
+            };
+            match follower.poll() {
+                Ok(Some(FollowUpdate::Appended(text))) => buffer.append_from_disk(&text),
+                Ok(Some(FollowUpdate::Reset(content))) => {
+                    buffer.replace_from_disk(&content);
+                    self.message = Some(format!("{} was truncated or rotated, reloaded", buffer.display_name()));
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    self.message = Some(format!("Cannot read {}: {}", buffer.display_name(), e));
+                    continue;
+                }
+            }
+            changed.push(buffer_id);
         }
 
-        Ok(())
+        for &buffer_id in &changed {
+            self.notify_lsp_buffer_changed(buffer_id);
+        }
+        // Keep incremental search up to date with the new lines
+        let active_changed = self.layout.active_buffer().is_some_and(|id| changed.contains(&id));
+        if self.mode == AppMode::Search && active_changed {
+            let _ = self.refresh_search_matches();
+        }
+        !changed.is_empty()
     }
 
-    /// Check debounce timer and trigger AI completion if needed
-    pub fn check_ai_debounce(&mut self) -> Result<()> {
-        // Skip if AI completions are disabled
-        if !self.ai_completions_enabled {
-            return Ok(());
+    /// Poll the file watcher and handle buffers whose files changed on disk.
+    /// Returns true if anything was reloaded or a prompt was shown.
+    pub fn poll_file_changes(&mut self) -> bool {
+        // A change may have been queued while another prompt was open
+        if self.mode == AppMode::Normal && !self.pending_disk_changes.is_empty() {
+            self.prompt_next_disk_change();
+            return true;
         }
 
-        if let Some(last_keystroke) = self.ai_last_keystroke {
-            // Check if 150ms has elapsed since last keystroke
-            if last_keystroke.elapsed() >= Duration::from_millis(150) && !self.ai_pending_request {
-                // Trigger completion request
-                self.trigger_ai_completion()?;
-                self.ai_last_keystroke = None;
-                self.ai_pending_request = true;
-            }
+        // Keep the watch list in sync with the open buffers; the watcher only
+        // resolves paths again when a buffer was opened, closed or renamed
+        let paths: Vec<PathBuf> = self
+            .workspace
+            .buffer_ids()
+            .into_iter()
+            .filter_map(|id| self.workspace.get_buffer(id)?.file_path().cloned())
+            .collect();
+        self.file_watcher.sync(paths.iter().map(|p| p.as_path()));
+
+        let changed = self.file_watcher.poll_changes();
+        if changed.is_empty() {
+            return false;
         }
-        Ok(())
+
+        let buffer_ids: Vec<_> = self
+            .workspace
+            .buffer_ids()
+            .into_iter()
+            .filter(|&id| {
+                self.workspace
+                    .get_buffer(id)
+                    .and_then(|b| b.file_path())
+                    .map(|p| changed.contains(&FileWatcher::watch_key(p)))
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        self.check_disk_changes(buffer_ids)
     }
 
-    /// Notify LSP that the active buffer was opened
-    fn notify_lsp_did_open(&mut self) {
-        if let Some(lsp) = &mut self.lsp_manager {
-            if let Some(buffer) = self.workspace.active_buffer() {
-                if let Some(path) = buffer.file_path() {
-                    if let Some(language) = crate::lsp::Language::from_path(path) {
-                        let content = buffer.text_buffer().to_string();
-                        let buffer_id = buffer.id().0; // Extract usize from BufferId
-                        let _ = lsp.did_open(buffer_id, path.clone(), content, language);
-                    }
+    /// Compare the given buffers with their files on disk. Unmodified buffers
+    /// are reloaded silently; modified ones are queued for a keep/take prompt.
+    fn check_disk_changes(&mut self, buffer_ids: Vec<crate::workspace::BufferId>) -> bool {
+        let mut handled = false;
+
+        for buffer_id in buffer_ids {
+            // Followed buffers are kept up to date by poll_followed_files
+            if self.followers.contains_key(&buffer_id) {
+                continue;
+            }
+            let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+                continue;
+            };
+            let Some(content) = buffer.text_buffer().read_disk_change() else {
+                continue;
+            };
+
+            if buffer.is_modified() {
+                if !self.pending_disk_changes.contains(&buffer_id) {
+                    self.pending_disk_changes.push(buffer_id);
                 }
+            } else {
+                buffer.reload_from_disk(&content);
+                self.message = Some(format!("Reloaded {} (changed on disk)", buffer.display_name()));
+                self.notify_lsp_buffer_changed(buffer_id);
             }
+            handled = true;
         }
+
+        self.prompt_next_disk_change();
+        handled
     }
 
-    /// Notify LSP that the active buffer was changed
-    fn notify_lsp_did_change(&mut self) {
-        if let Some(lsp) = &mut self.lsp_manager {
-            if let Some(buffer) = self.workspace.active_buffer() {
-                if let Some(path) = buffer.file_path() {
-                    if crate::lsp::Language::from_path(path).is_some() {
-                        let content = buffer.text_buffer().to_string();
-                        let buffer_id = buffer.id().0; // Extract usize from BufferId
-                        let _ = lsp.did_change(buffer_id, path.clone(), content);
-                    }
-                }
+    /// Ask about the next modified buffer that changed on disk, if we're idle
+    fn prompt_next_disk_change(&mut self) {
+        if self.mode != AppMode::Normal {
+            return;
+        }
+        while let Some(&buffer_id) = self.pending_disk_changes.first() {
+            if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
+                self.message = Some(format!(
+                    "{} changed on disk. (k)eep mine, (t)ake theirs, (d)iff",
+                    buffer.display_name()
+                ));
+                self.mode = AppMode::ConfirmReloadFile;
+                return;
             }
+            // Buffer was closed in the meantime
+            self.pending_disk_changes.remove(0);
         }
     }
 
-    /// Notify LSP that the active buffer was saved
-    fn notify_lsp_did_save(&mut self) {
-        if let Some(lsp) = &mut self.lsp_manager {
-            if let Some(buffer) = self.workspace.active_buffer() {
-                if let Some(path) = buffer.file_path() {
-                    if crate::lsp::Language::from_path(path).is_some() {
-                        let buffer_id = buffer.id().0; // Extract usize from BufferId
-                        let _ = lsp.did_save(buffer_id, path.clone());
-                    }
+    /// Resolve the current external change by keeping or discarding our edits
+    fn resolve_disk_change(&mut self, take_theirs: bool) {
+        if self.pending_disk_changes.is_empty() {
+            return;
+        }
+        let buffer_id = self.pending_disk_changes.remove(0);
+        self.mode = AppMode::Normal;
+        self.diff_session = None;
+
+        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
+            let name = buffer.display_name();
+            match buffer.text_buffer().read_disk_change() {
+                Some(content) if take_theirs => {
+                    buffer.reload_from_disk(&content);
+                    self.message = Some(format!("Reloaded {} from disk", name));
+                    self.notify_lsp_buffer_changed(buffer_id);
+                }
+                Some(content) => {
+                    // Don't ask again until the file changes once more
+                    buffer.text_buffer_mut().acknowledge_disk_content(&content);
+                    self.message = Some(format!("Kept your changes to {}", name));
+                }
+                None => {
+                    self.message = None;
                 }
             }
         }
+
+        self.prompt_next_disk_change();
     }
 
-    /// Check if an error is a permission denied error
-    fn is_permission_denied(&self, error: &anyhow::Error) -> bool {
-        if let Some(io_error) = error.downcast_ref::<std::io::Error>() {
-            return io_error.kind() == std::io::ErrorKind::PermissionDenied;
+    /// Handle key in the "file changed on disk" prompt
+    fn handle_confirm_reload_file_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Char('k') | KeyCode::Char('K') | KeyCode::Esc => {
+                self.resolve_disk_change(false);
+            }
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.resolve_disk_change(false);
+            }
+            KeyCode::Char('t') | KeyCode::Char('T') => {
+                self.resolve_disk_change(true);
+            }
+            KeyCode::Char('d') | KeyCode::Char('D') => {
+                let Some(&buffer_id) = self.pending_disk_changes.first() else {
+                    self.mode = AppMode::Normal;
+                    return Ok(ControlFlow::Continue);
+                };
+                let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+                    return Ok(ControlFlow::Continue);
+                };
+                let Some(path) = buffer.file_path().cloned() else {
+                    return Ok(ControlFlow::Continue);
+                };
+                let Some(disk_content) = buffer.text_buffer().read_disk_change() else {
+                    // The file went back to what we had; nothing to resolve
+                    self.resolve_disk_change(false);
+                    return Ok(ControlFlow::Continue);
+                };
+                let name = buffer.display_name();
+                let view = DiffView::from_contents(path.clone(), path, disk_content, buffer.text_buffer().to_string())
+                    .with_labels(format!("{} (on disk)", name), format!("{} (buffer)", name));
+                self.open_diff_view(view, DiffPurpose::ExternalChange(buffer_id));
+            }
+            _ => {}
         }
-        false
+        Ok(ControlFlow::Continue)
     }
 
-    /// Get word at cursor position with its boundaries
-    fn get_word_at_cursor(&self, buffer: &crate::workspace::Buffer) -> Option<(String, usize, usize)> {
-        let cursor_pos = buffer.editor_state().cursor.position();
-        let line_text = buffer.text_buffer().get_line(cursor_pos.line)?;
-        let col = cursor_pos.column;
+    /// Show a diff full-screen
+    fn open_diff_view(&mut self, view: DiffView, purpose: DiffPurpose) {
+        let highlights = if self.enable_syntax_highlighting {
+            DiffRenderer::highlight(&view, &mut self.highlighter)
+        } else {
+            None
+        };
+        self.diff_session = Some(DiffSession { view, highlights, purpose });
+        self.mode = AppMode::DiffView;
+    }
 
-        if line_text.is_empty() || col >= line_text.chars().count() {
-            return None;
+    /// Key hints shown in the diff view status bar
+    fn diff_view_hints(purpose: DiffPurpose) -> &'static str {
+        match purpose {
+            DiffPurpose::ExternalChange(_) => "m: keep mine | t: take theirs | q: back | ↑↓/jk: scroll | PgUp/PgDn: page",
+            DiffPurpose::SwapRecovery(_) => "r: recover | x: discard swap | q: back | ↑↓/jk: scroll | PgUp/PgDn: page",
+            DiffPurpose::FileHistory(_) => {
+                "n/p: next/prev hunk | h: restore hunk | R: restore all | q: back | ↑↓/jk: scroll"
+            }
         }
+    }
 
-        let chars: Vec<char> = line_text.chars().collect();
-        let cursor_char = chars.get(col)?;
-
-        // Check if we're on a word character (alphanumeric or underscore)
-        if !cursor_char.is_alphanumeric() && *cursor_char != '_' {
-            return None;
-        }
+    /// Handle key in the full-screen diff view
+    fn handle_diff_view_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let Some(session) = &mut self.diff_session else {
+            self.mode = AppMode::Normal;
+            return Ok(ControlFlow::Continue);
+        };
+        let visible_height = Self::diff_visible_height();
 
-        // Find word start
-        let mut start = col;
-        while start > 0 {
-            let ch = chars[start - 1];
-            if !ch.is_alphanumeric() && ch != '_' {
-                break;
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => session.view.scroll_up(1),
+            KeyCode::Down | KeyCode::Char('j') => session.view.scroll_down(1, visible_height),
+            KeyCode::PageUp => session.view.scroll_up(visible_height),
+            KeyCode::PageDown => session.view.scroll_down(visible_height, visible_height),
+            KeyCode::Char('q') | KeyCode::Esc => self.close_diff_view(),
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => self.close_diff_view(),
+            _ => {
+                let purpose = session.purpose;
+                self.handle_diff_action(purpose, key);
             }
-            start -= 1;
         }
+        Ok(ControlFlow::Continue)
+    }
 
-        // Find word end
-        let mut end = col + 1;
-        while end < chars.len() {
-            let ch = chars[end];
-            if !ch.is_alphanumeric() && ch != '_' {
-                break;
+    /// Number of diff rows on screen (header and status bar excluded)
+    fn diff_visible_height() -> usize {
+        let (_, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        height.saturating_sub(2) as usize
+    }
+
+    /// Handle the purpose-specific action keys of the diff view
+    fn handle_diff_action(&mut self, purpose: DiffPurpose, key: KeyEvent) {
+        match (purpose, key.code) {
+            (DiffPurpose::ExternalChange(_), KeyCode::Char('m')) => self.resolve_disk_change(false),
+            (DiffPurpose::ExternalChange(_), KeyCode::Char('t')) => self.resolve_disk_change(true),
+            (DiffPurpose::SwapRecovery(_), KeyCode::Char('r')) => self.resolve_swap_recovery(true),
+            (DiffPurpose::SwapRecovery(_), KeyCode::Char('x')) => self.resolve_swap_recovery(false),
+            (DiffPurpose::FileHistory(_), KeyCode::Char('n')) => {
+                if let Some(session) = &mut self.diff_session {
+                    session.view.select_next_hunk(Self::diff_visible_height());
+                }
             }
-            end += 1;
+            (DiffPurpose::FileHistory(_), KeyCode::Char('p')) => {
+                if let Some(session) = &mut self.diff_session {
+                    session.view.select_previous_hunk(Self::diff_visible_height());
+                }
+            }
+            (DiffPurpose::FileHistory(buffer_id), KeyCode::Char('h')) => self.restore_history_hunk(buffer_id),
+            (DiffPurpose::FileHistory(buffer_id), KeyCode::Char('R')) => self.restore_history_snapshot(buffer_id),
+            _ => {}
         }
+    }
 
-        let word: String = chars[start..end].iter().collect();
-        Some((word, start, end))
+    /// Leave the diff view and go back to what opened it
+    fn close_diff_view(&mut self) {
+        let Some(session) = self.diff_session.take() else {
+            self.mode = AppMode::Normal;
+            return;
+        };
+        self.mode = AppMode::Normal;
+        match session.purpose {
+            DiffPurpose::ExternalChange(_) => self.prompt_next_disk_change(),
+            DiffPurpose::SwapRecovery(_) => self.prompt_next_swap_recovery(),
+            DiffPurpose::FileHistory(_) => self.mode = AppMode::FileHistory,
+        }
     }
 
-    /// Toggle mark at cursor position - marks if not marked, unmarks if already marked
-    fn toggle_word_mark_at_cursor(&mut self) -> Result<()> {
-        let buffer_id = self.layout.active_buffer()
-            .ok_or_else(|| anyhow::anyhow!("No active buffer"))?;
-        let buffer = self.workspace.get_buffer(buffer_id)
-            .ok_or_else(|| anyhow::anyhow!("Buffer not found"))?;
+    /// Handle key in the file history list
+    fn handle_file_history_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Esc => self.close_file_history(),
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => self.close_file_history(),
+            KeyCode::Up if self.file_history_selected > 0 => {
+                self.file_history_selected -= 1;
+                if self.file_history_selected < self.file_history_scroll_offset {
+                    self.file_history_scroll_offset = self.file_history_selected;
+                }
+            }
+            KeyCode::Down if self.file_history_selected + 1 < self.file_history_snapshots.len() => {
+                self.file_history_selected += 1;
+                let visible_count = FileHistoryPanel::MAX_VISIBLE;
+                if self.file_history_selected >= self.file_history_scroll_offset + visible_count {
+                    self.file_history_scroll_offset = self.file_history_selected - visible_count + 1;
+                }
+            }
+            KeyCode::Enter => {
+                let Some(buffer_id) = self.file_history_buffer else {
+                    self.close_file_history();
+                    return Ok(ControlFlow::Continue);
+                };
+                let Some(snapshot) = self.file_history_snapshots.get(self.file_history_selected) else {
+                    return Ok(ControlFlow::Continue);
+                };
+                let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+                    self.close_file_history();
+                    return Ok(ControlFlow::Continue);
+                };
+                let Some(path) = buffer.file_path().cloned() else {
+                    return Ok(ControlFlow::Continue);
+                };
+                match snapshot.read() {
+                    Ok(snapshot_content) => {
+                        let name = buffer.display_name();
+                        let view = DiffView::from_contents(
+                            path.clone(),
+                            path,
+                            snapshot_content,
+                            buffer.text_buffer().to_string(),
+                        )
+                        .with_labels(
+                            format!("{} (saved {})", name, snapshot.age_description()),
+                            format!("{} (buffer)", name),
+                        );
+                        self.open_diff_view(view, DiffPurpose::FileHistory(buffer_id));
+                    }
+                    Err(e) => {
+                        self.message = Some(format!("Failed to read snapshot: {}", e));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
 
-        let file_path = buffer.file_path()
-            .ok_or_else(|| anyhow::anyhow!("Buffer has no file path"))?
-            .clone();
+    /// Open the undo tree browser for the active buffer, on its current state
+    fn open_undo_tree(&mut self) {
+        let Some(buffer_id) = self.layout.active_buffer() else {
+            return;
+        };
+        let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+            return;
+        };
+        let rows = buffer.undo_manager().tree_rows();
+        self.undo_tree_buffer = Some(buffer_id);
+        self.undo_tree_origin = buffer.undo_manager().current_node();
+        self.undo_tree_selected = rows.iter().position(|row| row.is_current).unwrap_or(0);
+        self.undo_tree_scroll_offset = 0;
+        self.scroll_undo_tree_to_selection();
+        self.mode = AppMode::UndoTree;
+        self.message = None;
+    }
 
-        let cursor_pos = buffer.editor_state().cursor.position();
+    /// Handle key in the undo tree browser. Moving the selection previews
+    /// that state in the buffer; Enter keeps it and Esc goes back.
+    fn handle_undo_tree_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let Some(buffer_id) = self.undo_tree_buffer else {
+            self.mode = AppMode::Normal;
+            return Ok(ControlFlow::Continue);
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            self.close_undo_tree();
+            return Ok(ControlFlow::Continue);
+        };
+        let rows = buffer.undo_manager().tree_rows();
+
+        let cancel = key.code == KeyCode::Esc
+            || (key.code == KeyCode::Char('g') && key.modifiers.contains(KeyModifiers::CONTROL));
+        if cancel {
+            buffer.jump_to_undo_state(self.undo_tree_origin)?;
+            self.close_undo_tree();
+            self.message = Some("Undo tree: cancelled".to_string());
+            return Ok(ControlFlow::Continue);
+        }
 
-        // Check if this position is already marked
-        if let Some(pos) = self.position_marks.iter().position(|mark| {
-            mark.file_path == file_path &&
-            mark.line == cursor_pos.line &&
-            mark.column == cursor_pos.column
-        }) {
-            // Already marked - unmark it
-            self.position_marks.remove(pos);
-            // Adjust current_mark_index if needed
-            if self.current_mark_index >= self.position_marks.len() && !self.position_marks.is_empty() {
-                self.current_mark_index = self.position_marks.len() - 1;
+        let page = UndoTreePanel::visible_rows(crossterm::terminal::size().map(|(_, h)| h).unwrap_or(24));
+        let last = rows.len().saturating_sub(1);
+        let selected = match key.code {
+            KeyCode::Up => self.undo_tree_selected.saturating_sub(1),
+            KeyCode::Down => (self.undo_tree_selected + 1).min(last),
+            KeyCode::PageUp => self.undo_tree_selected.saturating_sub(page),
+            KeyCode::PageDown => (self.undo_tree_selected + page).min(last),
+            KeyCode::Home => 0,
+            KeyCode::End => last,
+            KeyCode::Enter => {
+                let seq = rows.get(self.undo_tree_selected).map(|row| row.seq).unwrap_or(0);
+                self.close_undo_tree();
+                self.message = Some(format!("Undo tree: at state {}", seq));
+                return Ok(ControlFlow::Continue);
             }
-            self.message = Some(format!("Unmarked position ({} marks remaining)", self.position_marks.len()));
-        } else {
-            // Not marked - mark it
-            self.position_marks.push(PositionMark {
-                file_path,
-                line: cursor_pos.line,
-                column: cursor_pos.column,
-            });
-            self.message = Some(format!("Marked position ({} marks total)", self.position_marks.len()));
-        }
+            _ => return Ok(ControlFlow::Continue),
+        };
 
-        Ok(())
+        if let Some(row) = rows.get(selected) {
+            buffer.jump_to_undo_state(row.node)?;
+            self.undo_tree_selected = selected;
+            self.scroll_undo_tree_to_selection();
+        }
+        Ok(ControlFlow::Continue)
     }
 
-    /// Mark word at cursor
-    fn mark_word_at_cursor(&mut self) -> Result<()> {
-        let buffer_id = self.layout.active_buffer()
-            .ok_or_else(|| anyhow::anyhow!("No active buffer"))?;
-        let buffer = self.workspace.get_buffer(buffer_id)
-            .ok_or_else(|| anyhow::anyhow!("Buffer not found"))?;
+    /// Scroll the undo tree so the selected row is visible
+    fn scroll_undo_tree_to_selection(&mut self) {
+        let visible_count = UndoTreePanel::visible_rows(crossterm::terminal::size().map(|(_, h)| h).unwrap_or(24));
+        if self.undo_tree_selected < self.undo_tree_scroll_offset {
+            self.undo_tree_scroll_offset = self.undo_tree_selected;
+        } else if self.undo_tree_selected >= self.undo_tree_scroll_offset + visible_count {
+            self.undo_tree_scroll_offset = self.undo_tree_selected + 1 - visible_count;
+        }
+    }
 
-        let file_path = buffer.file_path()
-            .ok_or_else(|| anyhow::anyhow!("Buffer has no file path"))?
-            .clone();
+    fn close_undo_tree(&mut self) {
+        if let Some(buffer_id) = self.undo_tree_buffer.take() {
+            self.notify_lsp_buffer_changed(buffer_id);
+        }
+        self.mode = AppMode::Normal;
+        self.undo_tree_selected = 0;
+        self.undo_tree_scroll_offset = 0;
+    }
 
-        let cursor_pos = buffer.editor_state().cursor.position();
+    /// Load the configured theme, falling back to the default one
+    fn load_theme(name: &str) -> crate::syntax::Theme {
+        crate::syntax::Theme::load(name).unwrap_or_else(|e| {
+            logger::log(&format!("Could not load theme {}: {:#}", name, e));
+            crate::syntax::Theme::default()
+        })
+    }
 
-        if let Some((word, start_col, _)) = self.get_word_at_cursor(buffer) {
-            // Check if this word is already marked at this location
-            let already_marked = self.position_marks.iter().any(|mark| {
-                mark.file_path == file_path &&
-                mark.line == cursor_pos.line &&
-                mark.column == start_col
-            });
+    /// Open the theme picker on the theme in use
+    fn open_theme_picker(&mut self) {
+        let current = self.highlighter.theme().clone();
+        self.theme_picker_names = crate::syntax::Theme::available();
+        self.theme_picker_selected = self.theme_picker_names.iter().position(|name| *name == current.name).unwrap_or(0);
+        self.theme_picker_scroll_offset = 0;
+        self.theme_picker_origin = Some(current);
+        self.scroll_theme_picker_to_selection();
+        self.mode = AppMode::ThemePicker;
+        self.message = None;
+    }
 
-            if already_marked {
-                self.message = Some(format!("'{}' already marked", word));
-            } else {
-                self.position_marks.push(PositionMark {
-                    file_path,
-                    line: cursor_pos.line,
-                    column: start_col,
+    /// Handle key in the theme picker. Moving the selection previews that
+    /// theme; Enter keeps it (and saves it in the config) and Esc goes back.
+    fn handle_theme_picker_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        let cancel = key.code == KeyCode::Esc
+            || (key.code == KeyCode::Char('g') && key.modifiers.contains(KeyModifiers::CONTROL));
+        if cancel {
+            if let Some(origin) = self.theme_picker_origin.take() {
+                self.highlighter.set_theme(origin);
+            }
+            self.close_theme_picker();
+            self.message = Some("Theme: cancelled".to_string());
+            return Ok(ControlFlow::Continue);
+        }
+
+        let page = ThemePickerPanel::visible_rows(crossterm::terminal::size().map(|(_, h)| h).unwrap_or(24));
+        let last = self.theme_picker_names.len().saturating_sub(1);
+        let selected = match key.code {
+            KeyCode::Up => self.theme_picker_selected.saturating_sub(1),
+            KeyCode::Down => (self.theme_picker_selected + 1).min(last),
+            KeyCode::PageUp => self.theme_picker_selected.saturating_sub(page),
+            KeyCode::PageDown => (self.theme_picker_selected + page).min(last),
+            KeyCode::Home => 0,
+            KeyCode::End => last,
+            KeyCode::Enter => {
+                let name = self.highlighter.theme().name.clone();
+                self.close_theme_picker();
+                self.message = Some(match Config::save_theme(&name) {
+                    Ok(()) => format!("Theme: {}", name),
+                    Err(e) => format!("Theme: {} (not saved: {})", name, e),
                 });
-                self.message = Some(format!("Marked '{}' ({} marks total)", word, self.position_marks.len()));
+                return Ok(ControlFlow::Continue);
+            }
+            _ => return Ok(ControlFlow::Continue),
+        };
+
+        self.theme_picker_selected = selected;
+        self.scroll_theme_picker_to_selection();
+        if let Some(name) = self.theme_picker_names.get(selected) {
+            match crate::syntax::Theme::load(name) {
+                Ok(theme) => {
+                    self.highlighter.set_theme(theme);
+                    self.message = None;
+                }
+                Err(e) => self.message = Some(format!("{:#}", e)),
             }
-        } else {
-            self.message = Some("No word at cursor".to_string());
         }
+        Ok(ControlFlow::Continue)
+    }
 
-        Ok(())
+    /// Scroll the theme picker so the selected row is visible
+    fn scroll_theme_picker_to_selection(&mut self) {
+        let visible_count = ThemePickerPanel::visible_rows(crossterm::terminal::size().map(|(_, h)| h).unwrap_or(24));
+        if self.theme_picker_selected < self.theme_picker_scroll_offset {
+            self.theme_picker_scroll_offset = self.theme_picker_selected;
+        } else if self.theme_picker_selected >= self.theme_picker_scroll_offset + visible_count {
+            self.theme_picker_scroll_offset = self.theme_picker_selected + 1 - visible_count;
+        }
     }
 
-    /// Unmark word at cursor
-    fn unmark_word_at_cursor(&mut self) -> Result<()> {
-        let buffer_id = self.layout.active_buffer()
-            .ok_or_else(|| anyhow::anyhow!("No active buffer"))?;
-        let buffer = self.workspace.get_buffer(buffer_id)
-            .ok_or_else(|| anyhow::anyhow!("Buffer not found"))?;
+    fn close_theme_picker(&mut self) {
+        self.mode = AppMode::Normal;
+        self.theme_picker_names.clear();
+        self.theme_picker_origin = None;
+        self.theme_picker_selected = 0;
+        self.theme_picker_scroll_offset = 0;
+    }
 
-        let file_path = buffer.file_path()
-            .ok_or_else(|| anyhow::anyhow!("Buffer has no file path"))?;
+    /// Leave the file history list
+    fn close_file_history(&mut self) {
+        self.mode = AppMode::Normal;
+        self.file_history_buffer = None;
+        self.file_history_snapshots.clear();
+        self.file_history_selected = 0;
+        self.file_history_scroll_offset = 0;
+    }
 
-        let cursor_pos = buffer.editor_state().cursor.position();
+    /// Restore the selected hunk of the history diff into the buffer
+    fn restore_history_hunk(&mut self, buffer_id: crate::workspace::BufferId) {
+        let Some(session) = &self.diff_session else {
+            return;
+        };
+        let Some(hunk) = session.view.current_hunk().cloned() else {
+            self.message = Some("Select a hunk with n/p first".to_string());
+            return;
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return;
+        };
+        // The hunk line numbers are only valid for the content the diff was computed on
+        if buffer.text_buffer().to_string() != session.view.right_content {
+            self.message = Some("Buffer changed since the diff was opened".to_string());
+            return;
+        }
 
-        if let Some((word, start_col, _)) = self.get_word_at_cursor(buffer) {
-            // Find and remove the mark at this location
-            if let Some(pos) = self.position_marks.iter().position(|mark| {
-                mark.file_path == *file_path &&
-                mark.line == cursor_pos.line &&
-                mark.column == start_col
-            }) {
-                self.position_marks.remove(pos);
-                // Adjust current_mark_index if needed
-                if self.current_mark_index >= self.position_marks.len() && !self.position_marks.is_empty() {
-                    self.current_mark_index = self.position_marks.len() - 1;
-                }
-                self.message = Some(format!("Unmarked '{}' ({} marks remaining)", word, self.position_marks.len()));
-            } else {
-                self.message = Some(format!("'{}' is not marked at this location", word));
+        let text = session.view.left_hunk_text(&hunk);
+        if let Err(e) = buffer.replace_lines(hunk.new_lines.clone(), &text) {
+            self.message = Some(format!("Failed to restore hunk: {}", e));
+            return;
+        }
+        let buffer_content = buffer.text_buffer().to_string();
+        self.notify_lsp_buffer_changed(buffer_id);
+
+        // Recompute the diff against the updated buffer, staying near the restored hunk
+        let Some(session) = self.diff_session.take() else {
+            return;
+        };
+        let selected = session.view.selected_hunk.unwrap_or(0);
+        let scroll_offset = session.view.scroll_offset;
+        let view = DiffView::from_contents(
+            session.view.left_path.clone(),
+            session.view.right_path.clone(),
+            session.view.left_content.clone(),
+            buffer_content,
+        )
+        .with_labels(session.view.left_label.clone(), session.view.right_label.clone());
+        let remaining = view.hunks.len();
+        self.open_diff_view(view, session.purpose);
+
+        if let Some(session) = &mut self.diff_session {
+            let visible_height = Self::diff_visible_height();
+            let max_scroll = session.view.left_lines.len().saturating_sub(visible_height);
+            session.view.scroll_offset = scroll_offset.min(max_scroll);
+            if remaining > 0 {
+                // The restored hunk is gone, so the same index is the following hunk
+                session.view.select_hunk(selected.min(remaining - 1), visible_height);
             }
-        } else {
-            self.message = Some("No word at cursor".to_string());
         }
+        self.message = Some(if remaining == 0 {
+            "Hunk restored; buffer now matches the snapshot".to_string()
+        } else {
+            format!("Hunk restored ({} remaining)", remaining)
+        });
+    }
 
-        Ok(())
+    /// Replace the whole buffer with the snapshot shown in the history diff
+    fn restore_history_snapshot(&mut self, buffer_id: crate::workspace::BufferId) {
+        let Some(session) = &self.diff_session else {
+            return;
+        };
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            return;
+        };
+        if let Err(e) = buffer.replace_text(&session.view.left_content) {
+            self.message = Some(format!("Failed to restore snapshot: {}", e));
+            return;
+        }
+        let label = session.view.left_label.clone();
+        self.diff_session = None;
+        self.notify_lsp_buffer_changed(buffer_id);
+        self.close_file_history();
+        self.message = Some(format!("Restored {} (not saved yet)", label));
     }
 
-    /// Cycle to next mark
-    fn cycle_to_next_mark(&mut self) -> Result<()> {
-        if self.position_marks.is_empty() {
-            self.message = Some("No marks set".to_string());
-            return Ok(());
+    /// Snapshot just-saved content into the local history
+    fn record_history(&self, path: &Path, content: &str) {
+        let project_root = self.file_tree.as_ref().map(|t| t.root());
+        if let Err(e) = self.local_history.record(project_root, path, content) {
+            logger::error(&format!("Failed to record history for {:?}: {}", path, e));
         }
+    }
 
-        // Move to next mark
-        self.current_mark_index = (self.current_mark_index + 1) % self.position_marks.len();
-        let mark = &self.position_marks[self.current_mark_index].clone();
+    /// Send the edits made to a (possibly inactive) buffer since it was last
+    /// synced to its language server, or its full content if they are no
+    /// longer all logged
+    fn notify_lsp_buffer_changed(&mut self, buffer_id: crate::workspace::BufferId) {
+        if let Some(lsp) = &mut self.lsp_manager {
+            if let Some(buffer) = self.workspace.get_buffer(buffer_id) {
+                if buffer.text_buffer().is_large_file() {
+                    return;
+                }
+                if let Some(path) = buffer.file_path() {
+                    if buffer.lsp_language().is_some() {
+                        let text_buffer = buffer.text_buffer();
+                        let revision = text_buffer.revision();
+                        let synced = self.lsp_synced_revisions.get(&buffer_id).copied();
+                        if synced == Some(revision) {
+                            return;
+                        }
 
-        // Find the buffer with this file path, or open it
-        let buffer_id = {
-            let mut found_id = None;
-            for id in self.workspace.buffer_ids() {
-                if let Some(buffer) = self.workspace.get_buffer(id) {
-                    if let Some(path) = buffer.file_path() {
-                        if path == &mark.file_path {
-                            found_id = Some(id);
-                            break;
+                        let to_lsp = |pos: Position| crate::lsp::Position::new(pos.line, pos.column);
+                        let changes = synced
+                            .and_then(|synced| text_buffer.edits_since(synced))
+                            .map(|edits| {
+                                edits
+                                    .map(|edit| TextChange {
+                                        range: Some((to_lsp(edit.start), to_lsp(edit.old_end()))),
+                                        text: edit.new_text.clone(),
+                                    })
+                                    .collect()
+                            })
+                            .unwrap_or_else(|| vec![TextChange { range: None, text: text_buffer.to_string() }]);
+                        let _ = lsp.did_change(buffer_id.0, path.clone(), changes);
+                        self.lsp_synced_revisions.insert(buffer_id, revision);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bring everything that follows the text of buffers up to date with
+    /// their latest edits: diagnostics and position marks move with the text,
+    /// a stale AI suggestion is dropped and the language server is synced.
+    /// Returns true if any buffer was edited since the last call.
+    pub fn dispatch_edit_events(&mut self) -> bool {
+        let mut edited = Vec::new();
+        for buffer_id in self.workspace.buffer_ids() {
+            let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+                continue;
+            };
+            let text_buffer = buffer.text_buffer();
+            let revision = text_buffer.revision();
+            // Buffers seen for the first time are followed from their current state
+            let seen = *self.edit_revisions.entry(buffer_id).or_insert(revision);
+            if seen == revision {
+                continue;
+            }
+            self.edit_revisions.insert(buffer_id, revision);
+            edited.push(buffer_id);
+
+            match text_buffer.edits_since(seen) {
+                Some(edits) => {
+                    for edit in edits {
+                        self.diagnostics_store.apply_edit(buffer_id.0, edit);
+                        let marks = self.position_marks.iter_mut().filter(|mark| buffer.file_path() == Some(&mark.file_path));
+                        for mark in marks {
+                            let moved = edit.map_position(Position::new(mark.line, mark.column));
+                            mark.line = moved.line;
+                            mark.column = moved.column;
                         }
                     }
                 }
+                None => {
+                    // Too many edits to follow: the diagnostics no longer match the text
+                    self.diagnostics_store.update(buffer_id.0, Vec::new());
+                }
             }
 
-            if let Some(id) = found_id {
-                id
-            } else {
-                // Open the file
-                let result = self.workspace.open_file(mark.file_path.clone())?;
-                result.buffer_id()
+            // A suggestion is only valid for the text it was made for
+            if self.layout.active_buffer() == Some(buffer_id) {
+                self.ai_suggestion = None;
             }
-        };
+        }
 
-        // Switch to the buffer
-        let pane = self.layout.active_pane();
-        self.layout.set_buffer(pane, buffer_id);
+        for &buffer_id in &edited {
+            self.notify_lsp_buffer_changed(buffer_id);
+        }
 
-        // Move cursor to the marked position
-        if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-            let editor_state = buffer.editor_state_mut();
-            editor_state.cursor.set_position(Position::new(mark.line, mark.column));
-            editor_state.clear_selection();
+        // Forget closed buffers
+        let open_ids = self.workspace.buffer_ids();
+        self.edit_revisions.retain(|id, _| open_ids.contains(id));
+        self.lsp_synced_revisions.retain(|id, _| open_ids.contains(id));
 
-            // Center viewport on cursor
-            let (_, term_height) = crossterm::terminal::size()?;
-            let visible_lines = term_height.saturating_sub(4) as usize; // Account for status bars
-            let target_top = mark.line.saturating_sub(visible_lines / 2);
-            editor_state.viewport.top_line = target_top;
+        !edited.is_empty()
+    }
+
+    /// Maintain swap files: drop those of buffers that were saved or closed,
+    /// write new ones once editing has been idle for a moment, and look for
+    /// leftovers from a crash for newly opened buffers.
+    /// Returns true if a recovery prompt was shown.
+    pub fn check_swap_files(&mut self) -> bool {
+        // Swap files are only needed while a buffer has unsaved changes
+        for path in self.swap_manager.written_paths() {
+            let still_modified = self.workspace.buffer_ids().into_iter().any(|id| {
+                self.workspace
+                    .get_buffer(id)
+                    .map(|b| b.is_modified() && b.file_path() == Some(&path))
+                    .unwrap_or(false)
+            });
+            if !still_modified {
+                self.swap_manager.remove(&path);
+            }
         }
 
-        self.message = Some(format!("Mark {}/{} in {}:{}:{}",
-            self.current_mark_index + 1,
-            self.position_marks.len(),
-            mark.file_path.display(),
-            mark.line + 1,
-            mark.column + 1));
+        if let Some(last_edit) = self.swap_last_edit {
+            if last_edit.elapsed() >= SWAP_IDLE_DELAY {
+                self.swap_last_edit = None;
+                for buffer_id in self.workspace.buffer_ids() {
+                    // Don't overwrite a crash leftover the user hasn't decided on yet,
+                    // or chose to decide on later
+                    if self.pending_swap_recoveries.contains(&buffer_id)
+                        || self.deferred_swap_recoveries.contains(&buffer_id)
+                    {
+                        continue;
+                    }
+                    let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+                        continue;
+                    };
+                    // Large files would be copied and hashed in full on every write
+                    if !buffer.is_modified() || buffer.text_buffer().is_large_file() {
+                        continue;
+                    }
+                    if let Some(path) = buffer.file_path() {
+                        if let Err(e) = self.swap_manager.write(path, &buffer.text_buffer().to_string()) {
+                            logger::error(&format!("Failed to write swap file for {:?}: {}", path, e));
+                        }
+                    }
+                }
+            }
+        }
 
-        Ok(())
+        // A leftover deferred for a buffer that was since closed is offered
+        // again when its file is next opened
+        let open_ids = self.workspace.buffer_ids();
+        self.deferred_swap_recoveries.retain(|id| open_ids.contains(id));
+
+        // Offer recovery for buffers opened since the last check
+        for buffer_id in self.workspace.buffer_ids() {
+            if !self.swap_checked.insert(buffer_id) {
+                continue;
+            }
+            let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+                continue;
+            };
+            let Some(path) = buffer.file_path().cloned() else {
+                continue;
+            };
+            let Some(swap) = self.swap_manager.load(&path) else {
+                continue;
+            };
+            if self.swap_manager.owner_is_running(&swap) {
+                continue;
+            }
+            if swap.content == buffer.text_buffer().to_string() {
+                // Nothing was lost; the swap file is stale
+                self.swap_manager.remove(&path);
+                continue;
+            }
+            self.pending_swap_recoveries.push(buffer_id);
+        }
+
+        if self.mode == AppMode::Normal && !self.pending_swap_recoveries.is_empty() {
+            self.prompt_next_swap_recovery();
+            return self.mode == AppMode::ConfirmRecoverSwap;
+        }
+        false
     }
 
-    /// Check if sudo save needs to be executed
-    pub fn needs_sudo_save(&self) -> bool {
-        self.execute_sudo_save_on_render
+    /// Remove the swap files written by this session (on a clean exit)
+    pub fn discard_swap_files(&mut self) {
+        self.swap_manager.remove_all();
     }
 
-    /// Execute sudo save operation
-    pub fn execute_sudo_save(&mut self, terminal: &crate::render::Terminal) -> Result<()> {
-        self.execute_sudo_save_on_render = false;
+    /// Ask about the next buffer with a recoverable swap file, if we're idle
+    fn prompt_next_swap_recovery(&mut self) {
+        if self.mode != AppMode::Normal {
+            return;
+        }
+        while let Some(&buffer_id) = self.pending_swap_recoveries.first() {
+            let swap = self
+                .workspace
+                .get_buffer(buffer_id)
+                .and_then(|b| b.file_path())
+                .and_then(|path| self.swap_manager.load(path));
+            if let (Some(swap), Some(buffer)) = (swap, self.workspace.get_buffer(buffer_id)) {
+                self.message = Some(format!(
+                    "Unsaved changes to {} found ({}). (r)eview to recover, (x) discard",
+                    buffer.display_name(),
+                    swap.age_description()
+                ));
+                self.mode = AppMode::ConfirmRecoverSwap;
+                return;
+            }
+            // Buffer was closed or the swap file disappeared
+            self.pending_swap_recoveries.remove(0);
+        }
+    }
 
-        let path = self.pending_sudo_save_path.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("No pending sudo save path"))?;
-        let content = self.pending_sudo_save_content.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("No pending sudo save content"))?;
+    /// Recover the current buffer from its swap file, or discard the swap file
+    fn resolve_swap_recovery(&mut self, recover: bool) {
+        if self.pending_swap_recoveries.is_empty() {
+            return;
+        }
+        let buffer_id = self.pending_swap_recoveries.remove(0);
+        self.mode = AppMode::Normal;
+        self.diff_session = None;
+        self.message = None;
 
-        // Suspend TUI
-        terminal.cleanup()?;
+        let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) else {
+            self.prompt_next_swap_recovery();
+            return;
+        };
+        let Some(path) = buffer.file_path().cloned() else {
+            self.prompt_next_swap_recovery();
+            return;
+        };
+        let name = buffer.display_name();
 
-        // Execute: echo "content" | sudo tee filepath > /dev/null
-        let result = std::process::Command::new("sudo")
-            .arg("tee")
-            .arg(path)
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::inherit())
-            .spawn()
-            .and_then(|mut child| {
-                use std::io::Write;
-                if let Some(mut stdin) = child.stdin.take() {
-                    stdin.write_all(content.as_bytes())?;
+        match self.swap_manager.load(&path) {
+            Some(swap) if recover => match buffer.replace_text(&swap.content) {
+                Ok(()) => {
+                    // Take ownership of the swap file so a save cleans it up
+                    let _ = self.swap_manager.write(&path, &swap.content);
+                    self.message = Some(format!("Recovered unsaved changes to {}", name));
+                    self.notify_lsp_buffer_changed(buffer_id);
                 }
-                child.wait()
-            });
+                Err(e) => {
+                    self.message = Some(format!("Recovery failed: {}", e));
+                }
+            },
+            Some(_) => {
+                self.swap_manager.remove(&path);
+                self.message = Some(format!("Discarded swap file for {}", name));
+            }
+            None => {}
+        }
 
-        // Resume TUI
-        let _ = crossterm::terminal::enable_raw_mode();
-        let mut stdout = std::io::stdout();
-        let _ = crossterm::execute!(
-            stdout,
-            crossterm::terminal::EnterAlternateScreen,
-            crossterm::event::EnableMouseCapture,
-            crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
-            crossterm::cursor::SetCursorStyle::SteadyBlock,
-            crossterm::cursor::Show
-        );
+        self.prompt_next_swap_recovery();
+    }
 
-        // Handle result
-        match result {
-            Ok(status) if status.success() => {
-                if let Some(buffer_id) = self.layout.active_buffer() {
-                    if let Some(buffer) = self.workspace.get_buffer_mut(buffer_id) {
-                        buffer.text_buffer_mut().set_modified(false);
-                    }
-                }
-                self.message = Some("Saved with sudo".to_string());
-                self.notify_lsp_did_save();
+    /// Handle key in the swap file recovery prompt
+    fn handle_confirm_recover_swap_mode(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            // Recovering always goes through the diff, where it is confirmed
+            KeyCode::Char('r') | KeyCode::Char('R') | KeyCode::Char('d') | KeyCode::Char('D') => {
+                self.open_swap_recovery_diff();
             }
-            Ok(status) => {
-                self.message = Some(format!("Sudo save failed: exit code {}",
-                    status.code().unwrap_or(-1)));
+            KeyCode::Char('x') | KeyCode::Char('X') => {
+                self.resolve_swap_recovery(false);
             }
-            Err(e) => {
-                self.message = Some(format!("Sudo error: {}", e));
+            KeyCode::Esc => {
+                // Decide later: the swap file stays until the file is opened
+                // again, and this session writes no swap file over it
+                if !self.pending_swap_recoveries.is_empty() {
+                    let buffer_id = self.pending_swap_recoveries.remove(0);
+                    self.deferred_swap_recoveries.insert(buffer_id);
+                }
+                self.mode = AppMode::Normal;
+                self.message = None;
+                self.prompt_next_swap_recovery();
             }
+            _ => {}
         }
+        Ok(ControlFlow::Continue)
+    }
 
-        // Clear pending state
-        self.pending_sudo_save_path = None;
-        self.pending_sudo_save_content = None;
-
-        Ok(())
+    /// Show the buffer against its swap file before recovering it
+    fn open_swap_recovery_diff(&mut self) {
+        let Some(&buffer_id) = self.pending_swap_recoveries.first() else {
+            self.mode = AppMode::Normal;
+            return;
+        };
+        let Some(buffer) = self.workspace.get_buffer(buffer_id) else {
+            return;
+        };
+        let Some(path) = buffer.file_path().cloned() else {
+            return;
+        };
+        let Some(swap) = self.swap_manager.load(&path) else {
+            return;
+        };
+        let name = buffer.display_name();
+        let view = DiffView::from_contents(path.clone(), path, buffer.text_buffer().to_string(), swap.content)
+            .with_labels(format!("{} (buffer)", name), format!("{} (swap file)", name));
+        self.open_diff_view(view, DiffPurpose::SwapRecovery(buffer_id));
     }
 
     /// Save session state for the current project
     pub fn save_session_state(&self) -> Result<()> {
+        // Undo histories are kept for every file, with or without a project
+        self.workspace.save_undo_histories();
+
         // Only save if we have a project directory open
         if let Some(file_tree) = &self.file_tree {
             let project_root = file_tree.root().to_path_buf();
@@ -0,0 +1,220 @@
+use super::stable_hash;
+use crate::buffer::atomic_write;
+use crate::config::HistoryConfig;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// A saved version of a file in the local history
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Unix timestamp (milliseconds) of the save
+    pub timestamp_ms: u64,
+    /// Location of the snapshot content in the history store
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    /// Read the content of the snapshot
+    pub fn read(&self) -> Result<String> {
+        Ok(std::fs::read_to_string(&self.path)?)
+    }
+
+    /// Human-readable age of the snapshot, e.g. "5 min ago"
+    pub fn age_description(&self) -> String {
+        format_age(self.timestamp_ms / 1000)
+    }
+}
+
+/// Format the time elapsed since a unix timestamp (seconds), e.g. "5 min ago"
+pub fn format_age(timestamp_secs: u64) -> String {
+    let age = (unix_now_ms() / 1000).saturating_sub(timestamp_secs);
+    match age {
+        0..=59 => format!("{} s ago", age),
+        60..=3599 => format!("{} min ago", age / 60),
+        3600..=86399 => format!("{} h ago", age / 3600),
+        _ => format!("{} days ago", age / 86400),
+    }
+}
+
+fn unix_now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Per-project store of timestamped file snapshots, taken on every save.
+///
+/// Layout: `~/.cache/scame/history/<project hash>/<file hash>/<timestamp ms>.snap`
+pub struct LocalHistory {
+    history_dir: Option<PathBuf>,
+    config: HistoryConfig,
+}
+
+impl LocalHistory {
+    pub fn new(config: HistoryConfig) -> Self {
+        let history_dir = std::env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".cache/scame/history"));
+        Self::with_dir(history_dir, config)
+    }
+
+    /// Create a history store rooted at `history_dir`
+    pub fn with_dir(history_dir: Option<PathBuf>, config: HistoryConfig) -> Self {
+        Self { history_dir, config }
+    }
+
+    /// Check if snapshots are being recorded
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && self.history_dir.is_some()
+    }
+
+    /// Directory holding the snapshots of one file.
+    /// Files outside a project are grouped by their parent directory.
+    fn file_dir(&self, project_root: Option<&Path>, file_path: &Path) -> Option<PathBuf> {
+        let history_dir = self.history_dir.as_ref()?;
+        let canonical_file = file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf());
+        let project = match project_root {
+            Some(root) => root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            None => canonical_file.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+
+        Some(
+            history_dir
+                .join(format!("{:x}", Self::hash(&project)))
+                .join(format!("{:x}", Self::hash(&canonical_file))),
+        )
+    }
+
+    /// Record a snapshot of `content` as just saved to `file_path`
+    pub fn record(&self, project_root: Option<&Path>, file_path: &Path, content: &str) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let Some(dir) = self.file_dir(project_root, file_path) else {
+            return Ok(());
+        };
+
+        // Saving without changes doesn't need another snapshot
+        let snapshots = self.list_dir(&dir);
+        if let Some(latest) = snapshots.first() {
+            if latest.read().map(|c| c == content).unwrap_or(false) {
+                return Ok(());
+            }
+        }
+
+        std::fs::create_dir_all(&dir)?;
+        let mut timestamp = unix_now_ms();
+        if let Some(latest) = snapshots.first() {
+            // Keep names unique and ordered even for saves within the same millisecond
+            timestamp = timestamp.max(latest.timestamp_ms + 1);
+        }
+        atomic_write(&dir.join(format!("{}.snap", timestamp)), content.as_bytes())?;
+
+        self.prune(&dir);
+        Ok(())
+    }
+
+    /// List the snapshots of a file, newest first
+    pub fn snapshots(&self, project_root: Option<&Path>, file_path: &Path) -> Vec<Snapshot> {
+        match self.file_dir(project_root, file_path) {
+            Some(dir) => self.list_dir(&dir),
+            None => Vec::new(),
+        }
+    }
+
+    fn list_dir(&self, dir: &Path) -> Vec<Snapshot> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        let mut snapshots: Vec<Snapshot> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("snap") {
+                    return None;
+                }
+                let timestamp_ms = path.file_stem()?.to_str()?.parse().ok()?;
+                Some(Snapshot { timestamp_ms, path })
+            })
+            .collect();
+
+        snapshots.sort_by_key(|s| std::cmp::Reverse(s.timestamp_ms));
+        snapshots
+    }
+
+    /// Apply the retention limits: keep at most `max_snapshots` snapshots,
+    /// none older than `max_age_days` (the newest one is always kept)
+    fn prune(&self, dir: &Path) {
+        let max_age_ms = self.config.max_age_days.saturating_mul(24 * 60 * 60 * 1000);
+        let cutoff = unix_now_ms().saturating_sub(max_age_ms);
+
+        for (index, snapshot) in self.list_dir(dir).iter().enumerate() {
+            let too_many = index >= self.config.max_snapshots.max(1);
+            let too_old = index > 0 && snapshot.timestamp_ms < cutoff;
+            if too_many || too_old {
+                let _ = std::fs::remove_file(&snapshot.path);
+            }
+        }
+    }
+
+    fn hash(path: &Path) -> u64 {
+        stable_hash(path.as_os_str().as_encoded_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_snapshots: usize, max_age_days: u64) -> HistoryConfig {
+        HistoryConfig {
+            enabled: true,
+            max_snapshots,
+            max_age_days,
+        }
+    }
+
+    #[test]
+    fn test_record_and_list_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        std::fs::write(&file, "").unwrap();
+        let history = LocalHistory::with_dir(Some(dir.path().join("history")), config(10, 30));
+
+        history.record(Some(dir.path()), &file, "one").unwrap();
+        history.record(Some(dir.path()), &file, "two").unwrap();
+        // Unchanged content doesn't create a new snapshot
+        history.record(Some(dir.path()), &file, "two").unwrap();
+
+        let snapshots = history.snapshots(Some(dir.path()), &file);
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].read().unwrap(), "two");
+        assert_eq!(snapshots[1].read().unwrap(), "one");
+    }
+
+    #[test]
+    fn test_retention_by_count_and_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        std::fs::write(&file, "").unwrap();
+        let history = LocalHistory::with_dir(Some(dir.path().join("history")), config(3, 1));
+
+        // A snapshot from long ago is dropped by age
+        let file_dir = history.file_dir(None, &file).unwrap();
+        std::fs::create_dir_all(&file_dir).unwrap();
+        std::fs::write(file_dir.join("1000.snap"), "ancient").unwrap();
+
+        for i in 0..5 {
+            history.record(None, &file, &format!("version {}", i)).unwrap();
+        }
+
+        let contents: Vec<String> = history
+            .snapshots(None, &file)
+            .iter()
+            .map(|s| s.read().unwrap())
+            .collect();
+        assert_eq!(contents, vec!["version 4", "version 3", "version 2"]);
+    }
+}
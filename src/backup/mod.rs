@@ -1,3 +1,32 @@
+pub mod history;
 pub mod manager;
+pub mod swap;
+pub mod undo_store;
 
+pub use history::{LocalHistory, Snapshot};
 pub use manager::BackupManager;
+pub use swap::SwapManager;
+pub use undo_store::UndoStore;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, whose output may change between
+/// Rust releases, it gives the same hash on every build, so file names derived
+/// from it still match after the editor is rebuilt.
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable_hash() {
+        // Reference values of 64-bit FNV-1a
+        assert_eq!(stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,278 @@
+use super::history::format_age;
+use super::stable_hash;
+use crate::buffer::atomic_write::atomic_write;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+
+/// Unsaved buffer content written to disk for crash recovery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapFile {
+    /// File the buffer belongs to
+    pub file_path: PathBuf,
+    /// Buffer content at the time of writing
+    pub content: String,
+    /// Unix timestamp (seconds) of when the swap file was written
+    pub saved_at: u64,
+    /// Process that wrote the swap file
+    pub pid: u32,
+}
+
+impl SwapFile {
+    /// Human-readable age of the swap file, e.g. "5 min ago"
+    pub fn age_description(&self) -> String {
+        format_age(self.saved_at)
+    }
+}
+
+/// Manages swap files under `~/.cache/scame/swap`, one per modified file.
+///
+/// A process writing swap files holds a lock on `owner_<pid>.lock` next to
+/// them for as long as it runs; the system releases it if the process dies,
+/// so other editors can tell a live owner from a crashed one on any platform.
+pub struct SwapManager {
+    swap_dir: Option<PathBuf>,
+    /// Content hash of the last swap written for each file
+    written: HashMap<PathBuf, u64>,
+    /// Lock file held while this process has swap files
+    owner_lock: Option<File>,
+}
+
+impl SwapManager {
+    pub fn new() -> Self {
+        let swap_dir = std::env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".cache/scame/swap"));
+        Self::with_dir(swap_dir)
+    }
+
+    /// Create a swap manager storing its files in `swap_dir`
+    pub fn with_dir(swap_dir: Option<PathBuf>) -> Self {
+        Self {
+            swap_dir,
+            written: HashMap::new(),
+            owner_lock: None,
+        }
+    }
+
+    /// Lock file held by the process with id `pid` while it has swap files
+    fn owner_lock_path(&self, pid: u32) -> Option<PathBuf> {
+        Some(self.swap_dir.as_ref()?.join(format!("owner_{}.lock", pid)))
+    }
+
+    /// Take this process's owner lock, unless already held
+    fn hold_owner_lock(&mut self) -> Result<()> {
+        if self.owner_lock.is_some() {
+            return Ok(());
+        }
+        let Some(lock_path) = self.owner_lock_path(std::process::id()) else {
+            return Ok(());
+        };
+        let file = File::create(lock_path)?;
+        file.lock()?;
+        self.owner_lock = Some(file);
+        Ok(())
+    }
+
+    /// Check whether the process that wrote a swap file is still running.
+    /// A swap owned by a live editor is not a crash leftover.
+    pub fn owner_is_running(&self, swap: &SwapFile) -> bool {
+        if swap.pid == std::process::id() {
+            return false;
+        }
+        let Some(lock_path) = self.owner_lock_path(swap.pid) else {
+            return false;
+        };
+        let Ok(file) = File::open(&lock_path) else {
+            return false;
+        };
+        match file.try_lock() {
+            Err(TryLockError::WouldBlock) => true,
+            Ok(()) => {
+                // Left behind by a process that died
+                drop(file);
+                let _ = std::fs::remove_file(lock_path);
+                false
+            }
+            Err(TryLockError::Error(_)) => false,
+        }
+    }
+
+    /// Get the swap file path for a file (hash of its canonical path)
+    fn swap_path(&self, file_path: &Path) -> Option<PathBuf> {
+        let swap_dir = self.swap_dir.as_ref()?;
+        let canonical = file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf());
+
+        let hash = stable_hash(canonical.as_os_str().as_encoded_bytes());
+        Some(swap_dir.join(format!("swap_{:x}.json", hash)))
+    }
+
+    /// Write the unsaved content of a file, unless it is unchanged since the last write
+    pub fn write(&mut self, file_path: &Path, content: &str) -> Result<()> {
+        let content_hash = Self::content_hash(content);
+        if self.written.get(file_path) == Some(&content_hash) {
+            return Ok(());
+        }
+        let Some(swap_path) = self.swap_path(file_path) else {
+            return Ok(());
+        };
+        if let Some(dir) = &self.swap_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        self.hold_owner_lock()?;
+
+        let swap = SwapFile {
+            file_path: file_path.to_path_buf(),
+            content: content.to_string(),
+            saved_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            pid: std::process::id(),
+        };
+        atomic_write(&swap_path, serde_json::to_string(&swap)?.as_bytes())?;
+        self.written.insert(file_path.to_path_buf(), content_hash);
+        Ok(())
+    }
+
+    /// Load the swap file for a file, if one exists
+    pub fn load(&self, file_path: &Path) -> Option<SwapFile> {
+        let swap_path = self.swap_path(file_path)?;
+        let json = std::fs::read_to_string(swap_path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Remove the swap file for a file (after a clean save or close)
+    pub fn remove(&mut self, file_path: &Path) {
+        self.written.remove(file_path);
+        if let Some(swap_path) = self.swap_path(file_path) {
+            let _ = std::fs::remove_file(swap_path);
+        }
+    }
+
+    /// Files for which this process currently has a swap file
+    pub fn written_paths(&self) -> Vec<PathBuf> {
+        self.written.keys().cloned().collect()
+    }
+
+    /// Remove every swap file written by this process, and its owner lock
+    pub fn remove_all(&mut self) {
+        for path in self.written_paths() {
+            self.remove(&path);
+        }
+        if self.owner_lock.take().is_some() {
+            if let Some(lock_path) = self.owner_lock_path(std::process::id()) {
+                let _ = std::fs::remove_file(lock_path);
+            }
+        }
+    }
+
+    /// Files inside `project_root` that have a swap file left by a previous run
+    pub fn recoverable_in(&self, project_root: &Path) -> Vec<PathBuf> {
+        let Some(swap_dir) = &self.swap_dir else {
+            return Vec::new();
+        };
+        let Ok(entries) = std::fs::read_dir(swap_dir) else {
+            return Vec::new();
+        };
+        let root = project_root.canonicalize().unwrap_or_else(|_| project_root.to_path_buf());
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+            .filter_map(|json| serde_json::from_str::<SwapFile>(&json).ok())
+            .filter(|swap| !self.owner_is_running(swap))
+            .map(|swap| swap.file_path)
+            .filter(|path| {
+                path.canonicalize()
+                    .map(|canonical| canonical.starts_with(&root))
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+
+    fn content_hash(content: &str) -> u64 {
+        stable_hash(content.as_bytes())
+    }
+}
+
+impl Default for SwapManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_load_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file.txt");
+        std::fs::write(&file, "on disk").unwrap();
+
+        let mut manager = SwapManager::with_dir(Some(dir.path().join("swap")));
+        assert!(manager.load(&file).is_none());
+
+        manager.write(&file, "unsaved edits").unwrap();
+        let swap = manager.load(&file).unwrap();
+        assert_eq!(swap.content, "unsaved edits");
+        assert_eq!(swap.file_path, file);
+
+        manager.remove(&file);
+        assert!(manager.load(&file).is_none());
+        assert!(manager.written_paths().is_empty());
+    }
+
+    #[test]
+    fn test_recoverable_in_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        let file = project.join("main.rs");
+        std::fs::write(&file, "fn main() {}").unwrap();
+        let outside = dir.path().join("outside.rs");
+        std::fs::write(&outside, "").unwrap();
+
+        let swap_dir = dir.path().join("swap");
+        let mut writer = SwapManager::with_dir(Some(swap_dir.clone()));
+        writer.write(&file, "fn main() { todo!() }").unwrap();
+        writer.write(&outside, "x").unwrap();
+
+        // Pretend the swap files were left behind by a crashed process
+        let reader = SwapManager::with_dir(Some(swap_dir));
+        let mut swap = reader.load(&file).unwrap();
+        swap.pid = u32::MAX;
+        std::fs::write(reader.swap_path(&file).unwrap(), serde_json::to_string(&swap).unwrap()).unwrap();
+
+        let recoverable = reader.recoverable_in(&project);
+        assert_eq!(recoverable, vec![file]);
+    }
+
+    #[test]
+    fn test_owner_is_running_while_it_holds_its_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = SwapManager::with_dir(Some(dir.path().to_path_buf()));
+        let swap = SwapFile {
+            file_path: dir.path().join("file.txt"),
+            content: String::new(),
+            saved_at: 0,
+            pid: u32::MAX,
+        };
+        assert!(!manager.owner_is_running(&swap));
+
+        // Another editor holding its lock
+        let lock_path = manager.owner_lock_path(swap.pid).unwrap();
+        let lock = File::create(&lock_path).unwrap();
+        lock.lock().unwrap();
+        assert!(manager.owner_is_running(&swap));
+
+        // Its lock is released when it dies
+        drop(lock);
+        assert!(!manager.owner_is_running(&swap));
+        assert!(!lock_path.exists());
+    }
+}
@@ -0,0 +1,238 @@
+use super::stable_hash;
+use crate::buffer::atomic_write::atomic_write;
+use crate::buffer::UndoManager;
+use crate::config::UndoConfig;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// An undo history as saved on disk
+#[derive(Serialize, Deserialize)]
+struct SavedUndo {
+    /// File the history belongs to (guards against hash collisions)
+    path: PathBuf,
+    /// Hash of the text the history ends in
+    content_hash: u64,
+    history: UndoManager,
+}
+
+/// The file a saved history belongs to, read without the history itself
+#[derive(Deserialize)]
+struct SavedFor {
+    path: PathBuf,
+}
+
+/// Store of undo histories kept across editor restarts. A history is only
+/// restored if the file still has the content it had when the history was
+/// saved; otherwise its changes would no longer line up with the text.
+///
+/// Layout: `~/.cache/scame/undo/<file hash>.json`. The hashes are stable
+/// across builds of the editor (see `stable_hash`).
+pub struct UndoStore {
+    undo_dir: Option<PathBuf>,
+    config: UndoConfig,
+}
+
+impl UndoStore {
+    pub fn new(config: UndoConfig) -> Self {
+        let undo_dir = std::env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".cache/scame/undo"));
+        let store = Self::with_dir(undo_dir, config);
+        if store.is_enabled() {
+            store.prune();
+        }
+        store
+    }
+
+    /// Create an undo store rooted at `undo_dir`
+    pub fn with_dir(undo_dir: Option<PathBuf>, config: UndoConfig) -> Self {
+        Self { undo_dir, config }
+    }
+
+    /// Check if histories are being saved and restored
+    pub fn is_enabled(&self) -> bool {
+        self.config.persist && self.undo_dir.is_some()
+    }
+
+    /// Maximum number of changes kept per buffer
+    pub fn max_changes(&self) -> usize {
+        self.config.max_changes
+    }
+
+    /// Location of the saved history of a file
+    fn history_path(&self, canonical_file: &Path) -> Option<PathBuf> {
+        let undo_dir = self.undo_dir.as_ref()?;
+        let hash = stable_hash(canonical_file.as_os_str().as_encoded_bytes());
+        Some(undo_dir.join(format!("{:x}.json", hash)))
+    }
+
+    /// Delete the histories of files that no longer exist (deleted or
+    /// renamed), and those saved under another name than their file's
+    /// hash, e.g. by an older version of the store
+    fn prune(&self) {
+        let Some(entries) = self.undo_dir.as_ref().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let history_path = entry.path();
+            if history_path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let saved_for = std::fs::read(&history_path)
+                .ok()
+                .and_then(|json| serde_json::from_slice::<SavedFor>(&json).ok());
+            let stale = saved_for.is_none_or(|saved| {
+                !saved.path.exists() || self.history_path(&saved.path).as_ref() != Some(&history_path)
+            });
+            if stale {
+                let _ = std::fs::remove_file(&history_path);
+            }
+        }
+    }
+
+    /// Save the undo history of `file_path`, whose text is now `content`.
+    /// The oldest changes are dropped until the history fits the size limit.
+    pub fn save(&self, file_path: &Path, content: &str, history: &UndoManager) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let canonical_file = file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf());
+        let Some(history_path) = self.history_path(&canonical_file) else {
+            return Ok(());
+        };
+
+        let max_bytes = self.config.max_file_size_kb.saturating_mul(1024) as usize;
+        let mut saved = SavedUndo {
+            path: canonical_file,
+            content_hash: content_hash(content),
+            history: history.clone(),
+        };
+        let mut json = serde_json::to_vec(&saved)?;
+        while json.len() > max_bytes && saved.history.history_len() > 0 {
+            let keep = saved.history.history_len() / 2;
+            saved.history.set_max_history(keep);
+            json = serde_json::to_vec(&saved)?;
+        }
+
+        // Nothing worth restoring: also drop a history saved earlier
+        if saved.history.history_len() == 0 {
+            if history_path.exists() {
+                std::fs::remove_file(&history_path)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(dir) = history_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        atomic_write(&history_path, &json)?;
+        Ok(())
+    }
+
+    /// Load the saved undo history of `file_path` if it was saved for the
+    /// text the file has now (`content`)
+    pub fn load(&self, file_path: &Path, content: &str) -> Option<UndoManager> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let canonical_file = file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf());
+        let json = std::fs::read(self.history_path(&canonical_file)?).ok()?;
+        let saved: SavedUndo = serde_json::from_slice(&json).ok()?;
+
+        if saved.path != canonical_file || saved.content_hash != content_hash(content) {
+            return None;
+        }
+        let mut history = saved.history;
+        if !history.is_consistent() {
+            return None;
+        }
+        history.set_max_history(self.config.max_changes);
+        Some(history)
+    }
+}
+
+fn content_hash(content: &str) -> u64 {
+    stable_hash(content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{Change, Position};
+
+    fn store(dir: &Path, max_file_size_kb: u64) -> UndoStore {
+        let config = UndoConfig {
+            persist: true,
+            max_changes: 100,
+            max_file_size_kb,
+        };
+        UndoStore::with_dir(Some(dir.join("undo")), config)
+    }
+
+    fn history(texts: &[&str]) -> UndoManager {
+        let mut history = UndoManager::new(100);
+        let mut column = 0;
+        for text in texts {
+            history.record(Change::Insert { pos: Position::new(0, column), text: text.to_string() });
+            column += text.len();
+        }
+        history
+    }
+
+    #[test]
+    fn test_restored_only_for_unchanged_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "ab").unwrap();
+        let store = store(dir.path(), 1024);
+
+        store.save(&file, "ab", &history(&["a", "b"])).unwrap();
+
+        let mut restored = store.load(&file, "ab").expect("history should be restored");
+        assert_eq!(restored.history_len(), 2);
+        assert!(matches!(restored.undo(), Some(Change::Delete { text, .. }) if text == "b"));
+
+        // The file was changed by something else since
+        assert!(store.load(&file, "abc").is_none());
+    }
+
+    #[test]
+    fn test_size_limit_drops_oldest_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("big.txt");
+        std::fs::write(&file, "").unwrap();
+        let store = store(dir.path(), 1);
+
+        let chunk = "x".repeat(300);
+        let texts: Vec<&str> = (0..10).map(|_| chunk.as_str()).collect();
+        let content = chunk.repeat(10);
+        store.save(&file, &content, &history(&texts)).unwrap();
+
+        let restored = store.load(&file, &content).expect("part of the history should be kept");
+        assert!(restored.history_len() > 0 && restored.history_len() < 10);
+        assert!(std::fs::metadata(store.history_path(&file.canonicalize().unwrap()).unwrap()).unwrap().len() <= 1024);
+    }
+
+    #[test]
+    fn test_prune_drops_stale_histories() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("kept.txt");
+        let deleted = dir.path().join("deleted.txt");
+        std::fs::write(&kept, "ab").unwrap();
+        std::fs::write(&deleted, "ab").unwrap();
+        let store = store(dir.path(), 1024);
+        store.save(&kept, "ab", &history(&["a", "b"])).unwrap();
+        store.save(&deleted, "ab", &history(&["a", "b"])).unwrap();
+        std::fs::remove_file(&deleted).unwrap();
+
+        // Saved under an old name for a file that still exists
+        let old_name = dir.path().join("undo/0123abcd.json");
+        std::fs::copy(store.history_path(&kept.canonicalize().unwrap()).unwrap(), &old_name).unwrap();
+
+        store.prune();
+        assert!(store.load(&kept, "ab").is_some());
+        assert!(!old_name.exists());
+        assert_eq!(std::fs::read_dir(dir.path().join("undo")).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Maximum number of symlink hops followed when resolving a save target
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Counter used to make temporary file names unique within the process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Resolve the file that a save to `path` should actually replace.
+///
+/// Symlinks are followed (even dangling ones) so that the link itself is left
+/// untouched and the file it points to gets the new content.
+pub fn resolve_save_target(path: &Path) -> PathBuf {
+    let mut current = path.to_path_buf();
+
+    for _ in 0..MAX_SYMLINK_HOPS {
+        let is_symlink = fs::symlink_metadata(&current)
+            .map(|meta| meta.file_type().is_symlink())
+            .unwrap_or(false);
+        if !is_symlink {
+            return current;
+        }
+
+        match fs::read_link(&current) {
+            Ok(target) if target.is_absolute() => current = target,
+            Ok(target) => {
+                let parent = current.parent().unwrap_or_else(|| Path::new("."));
+                current = parent.join(target);
+            }
+            Err(_) => return current,
+        }
+    }
+
+    current
+}
+
+/// Build a unique temporary path in the same directory as `target`
+pub fn temp_path_for(target: &Path) -> PathBuf {
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "untitled".to_string());
+    let counter = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp_name = format!(".{}.scame-{}-{}.tmp", file_name, std::process::id(), counter);
+
+    match target.parent() {
+        Some(parent) => parent.join(temp_name),
+        None => PathBuf::from(temp_name),
+    }
+}
+
+/// Atomically replace the contents of `path`.
+///
+/// The data is written to a temporary file next to the (symlink-resolved)
+/// target, flushed to disk, given the original mode and ownership, and then
+/// renamed over the target. A crash at any point leaves either the old or the
+/// new file in place, never a truncated one.
+pub fn atomic_write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let target = resolve_save_target(path);
+    let original = fs::metadata(&target).ok();
+    let temp_path = temp_path_for(&target);
+
+    let result = write_temp_file(&temp_path, contents, original.as_ref())
+        .and_then(|_| fs::rename(&temp_path, &target));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    // Persist the rename itself; not every platform allows syncing a directory
+    if let Some(parent) = target.parent() {
+        let dir = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+/// Write and fsync the temporary file, copying metadata from the original
+fn write_temp_file(temp_path: &Path, contents: &[u8], original: Option<&fs::Metadata>) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp_path)?;
+
+    file.write_all(contents)?;
+
+    if let Some(meta) = original {
+        copy_ownership(&file, meta);
+        // Set permissions after chown, which may clear setuid/setgid bits
+        file.set_permissions(meta.permissions())?;
+    }
+
+    file.sync_all()
+}
+
+/// Give the temporary file the owner and group of the original, where allowed
+#[cfg(unix)]
+fn copy_ownership(file: &File, meta: &fs::Metadata) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    if fchown(file, Some(meta.uid()), Some(meta.gid())).is_err() {
+        // Unprivileged users can still keep the group if they belong to it
+        let _ = fchown(file, None, Some(meta.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(_file: &File, _meta: &fs::Metadata) {}
+
+/// Build the command used to save `path` through sudo with the same guarantees
+/// as `atomic_write`. The content must be written to the child's stdin.
+///
+/// The script writes to a temporary file next to the resolved target, copies
+/// the original owner and mode when the target exists, syncs it, and renames
+/// it into place. The temporary file is removed if any step fails.
+pub fn sudo_atomic_write_command(path: &Path) -> std::process::Command {
+    let target = resolve_save_target(path);
+    let temp_path = temp_path_for(&target);
+    let (owner, mode) = owner_and_mode(&target);
+
+    let script = r#"set -e
+target="$1"; tmp="$2"; owner="$3"; mode="$4"
+trap 'rm -f "$tmp"' EXIT
+(umask 077 && cat > "$tmp")
+if [ -n "$owner" ]; then chown "$owner" "$tmp"; fi
+chmod "$mode" "$tmp"
+sync "$tmp" 2>/dev/null || sync
+mv -f "$tmp" "$target"
+trap - EXIT"#;
+
+    let mut command = std::process::Command::new("sudo");
+    command
+        .arg("sh")
+        .arg("-c")
+        .arg(script)
+        .arg("sh")
+        .arg(&target)
+        .arg(&temp_path)
+        .arg(owner)
+        .arg(mode);
+    command
+}
+
+/// Owner ("uid:gid") and octal mode to give a sudo-saved file.
+/// New files get no explicit owner and a conventional 644 mode.
+#[cfg(unix)]
+fn owner_and_mode(target: &Path) -> (String, String) {
+    use std::os::unix::fs::MetadataExt;
+
+    match fs::metadata(target) {
+        Ok(meta) => (
+            format!("{}:{}", meta.uid(), meta.gid()),
+            format!("{:o}", meta.mode() & 0o7777),
+        ),
+        Err(_) => (String::new(), "644".to_string()),
+    }
+}
+
+#[cfg(not(unix))]
+fn owner_and_mode(_target: &Path) -> (String, String) {
+    (String::new(), "644".to_string())
+}
@@ -0,0 +1,183 @@
+use crate::buffer::atomic_write;
+use anyhow::Result;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// How much of a file is inspected when deciding whether it is binary
+pub const BINARY_SAMPLE_SIZE: usize = 8192;
+
+/// Check whether the start of a file looks like binary data.
+///
+/// Text files never contain NUL bytes, except UTF-16 text, which is
+/// recognised by its BOM.
+pub fn looks_binary(sample: &[u8]) -> bool {
+    if sample.starts_with(&[0xFF, 0xFE]) || sample.starts_with(&[0xFE, 0xFF]) {
+        return false;
+    }
+    sample.iter().take(BINARY_SAMPLE_SIZE).any(|&b| b == 0)
+}
+
+/// Check whether a file on disk looks like binary data (see `looks_binary`)
+pub fn is_binary_file(path: &Path) -> bool {
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let mut sample = Vec::with_capacity(BINARY_SAMPLE_SIZE);
+    match file.take(BINARY_SAMPLE_SIZE as u64).read_to_end(&mut sample) {
+        Ok(_) => looks_binary(&sample),
+        Err(_) => false,
+    }
+}
+
+/// Turn hex editor search input into the bytes to look for. As text, the
+/// input is searched as typed; as bytes it must be an even number of hex
+/// digits such as "de ad be ef" (whitespace is ignored), or it is None.
+pub fn parse_search_pattern(input: &str, as_text: bool) -> Option<Vec<u8>> {
+    if as_text {
+        return Some(input.as_bytes().to_vec());
+    }
+    parse_hex_pattern(input)
+}
+
+fn parse_hex_pattern(input: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = input
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect())
+}
+
+/// Raw file content for the hex editor. Edits overwrite bytes in place,
+/// so the length never changes.
+pub struct ByteBuffer {
+    bytes: Vec<u8>,
+    file_path: PathBuf,
+    modified: bool,
+}
+
+impl ByteBuffer {
+    /// Load a file's bytes
+    pub fn from_file(path: PathBuf) -> Result<Self> {
+        let bytes = std::fs::read(&path)?;
+        Ok(Self {
+            bytes,
+            file_path: path,
+            modified: false,
+        })
+    }
+
+    /// Get the file path
+    pub fn file_path(&self) -> &PathBuf {
+        &self.file_path
+    }
+
+    /// Check if the bytes were edited since the last save
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Number of bytes
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Check if the file is empty
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Get the byte at an offset
+    pub fn get(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(offset).copied()
+    }
+
+    /// Get the bytes in a range, clamped to the buffer
+    pub fn slice(&self, start: usize, len: usize) -> &[u8] {
+        let start = start.min(self.bytes.len());
+        let end = start.saturating_add(len).min(self.bytes.len());
+        &self.bytes[start..end]
+    }
+
+    /// Overwrite the byte at an offset
+    pub fn set(&mut self, offset: usize, value: u8) -> Result<()> {
+        let len = self.bytes.len();
+        let Some(byte) = self.bytes.get_mut(offset) else {
+            anyhow::bail!("Offset {:#x} is past the end of the file ({} bytes)", offset, len);
+        };
+        if *byte != value {
+            *byte = value;
+            self.modified = true;
+        }
+        Ok(())
+    }
+
+    /// Find `pattern` starting after `from` (or before it, in reverse).
+    /// Wraps around the end of the buffer. Returns the offset of the match.
+    pub fn find(&self, pattern: &[u8], from: usize, reverse: bool) -> Option<usize> {
+        if pattern.is_empty() || pattern.len() > self.bytes.len() {
+            return None;
+        }
+        let last_start = self.bytes.len() - pattern.len();
+        let matches_at = |offset: usize| self.bytes[offset..offset + pattern.len()] == *pattern;
+
+        if reverse {
+            let before = (0..from.min(last_start + 1)).rev();
+            let after = (from.min(last_start + 1)..=last_start).rev();
+            before.chain(after).find(|&offset| matches_at(offset))
+        } else {
+            let after = (from + 1).min(last_start + 1)..=last_start;
+            let before = 0..(from + 1).min(last_start + 1);
+            after.chain(before).find(|&offset| matches_at(offset))
+        }
+    }
+
+    /// Write the bytes back to the file
+    pub fn save(&mut self) -> Result<()> {
+        atomic_write(&self.file_path, &self.bytes)?;
+        self.modified = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_detection_and_search_pattern() {
+        assert!(looks_binary(b"\x7fELF\x02\x01\x01\x00"));
+        assert!(!looks_binary(b"plain text\n"));
+        assert!(!looks_binary(&[0xFF, 0xFE, b'h', 0, b'i', 0]));
+
+        assert_eq!(parse_search_pattern("de ad BE EF", false), Some(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(parse_search_pattern("abc", false), None);
+        assert_eq!(parse_search_pattern("hello", false), None);
+        // Text that happens to be hex is still text
+        assert_eq!(parse_search_pattern("cafe", true).as_deref(), Some(&b"cafe"[..]));
+    }
+
+    #[test]
+    fn test_edit_find_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, b"\x00ab\x00ab\x00").unwrap();
+
+        let mut buffer = ByteBuffer::from_file(path.clone()).unwrap();
+        assert_eq!(buffer.find(b"ab", 0, false), Some(1));
+        assert_eq!(buffer.find(b"ab", 1, false), Some(4));
+        // Wraps around in both directions
+        assert_eq!(buffer.find(b"ab", 4, false), Some(1));
+        assert_eq!(buffer.find(b"ab", 1, true), Some(4));
+
+        buffer.set(0, 0xff).unwrap();
+        assert!(buffer.is_modified());
+        assert!(buffer.set(7, 0).is_err());
+        buffer.save().unwrap();
+        assert!(!buffer.is_modified());
+        assert_eq!(std::fs::read(&path).unwrap(), b"\xffab\x00ab\x00");
+    }
+}
@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Shown in place of control characters, which have no width of their own
+const CONTROL_REPLACEMENT: &str = "\u{FFFD}";
+
+/// A grapheme cluster of a line (what the user sees as one character) and
+/// where it is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grapheme<'a> {
+    /// Column (in chars) of its first char
+    pub column: usize,
+    /// Byte offset of its first char in the line
+    pub byte: usize,
+    /// Screen column it starts at
+    pub display_column: usize,
+    /// Screen columns it takes up: 2 for wide characters, up to the next tab
+    /// stop for tabs, 1 otherwise
+    pub width: usize,
+    pub text: &'a str,
+}
+
+impl Grapheme<'_> {
+    /// Text to print for the grapheme: tabs become spaces and control
+    /// characters a replacement character
+    pub fn display_text(&self) -> Cow<'_, str> {
+        if self.text == "\t" {
+            Cow::Owned(" ".repeat(self.width))
+        } else if self.text.chars().any(char::is_control) {
+            Cow::Borrowed(CONTROL_REPLACEMENT)
+        } else {
+            Cow::Borrowed(self.text)
+        }
+    }
+
+    /// Text to print for the part of the grapheme between screen columns
+    /// `start` and `end`. A wide character cut by an edge shows as spaces.
+    pub fn clipped_text(&self, start: usize, end: usize) -> Cow<'_, str> {
+        let visible = (self.display_column + self.width)
+            .min(end)
+            .saturating_sub(self.display_column.max(start));
+        if visible == self.width {
+            self.display_text()
+        } else {
+            Cow::Owned(" ".repeat(visible))
+        }
+    }
+}
+
+/// The grapheme clusters of a line (without its line ending) with their
+/// screen columns, expanding tabs to stops every `tab_width` columns
+pub fn graphemes(line: &str, tab_width: usize) -> impl Iterator<Item = Grapheme<'_>> {
+    let tab_width = tab_width.max(1);
+    let line = line.trim_end_matches(['\n', '\r']);
+    let mut column = 0;
+    let mut display_column = 0;
+    line.grapheme_indices(true).map(move |(byte, text)| {
+        let width = if text == "\t" {
+            tab_width - display_column % tab_width
+        } else if text.chars().any(char::is_control) {
+            1
+        } else {
+            // Combining marks without a base char still get a cell, so the
+            // cursor can be put on them
+            text.width().clamp(1, 2)
+        };
+        let grapheme = Grapheme { column, byte, display_column, width, text };
+        column += text.chars().count();
+        display_column += width;
+        grapheme
+    })
+}
+
+/// Screen column of char `column` of a line. Columns past the end of the
+/// line are one screen column per char.
+pub fn display_column(line: &str, column: usize, tab_width: usize) -> usize {
+    let mut end = (0, 0);
+    for grapheme in graphemes(line, tab_width) {
+        if grapheme.column >= column {
+            return grapheme.display_column;
+        }
+        end = (grapheme.column + grapheme.text.chars().count(), grapheme.display_column + grapheme.width);
+    }
+    end.1 + column.saturating_sub(end.0)
+}
+
+/// Char column of the grapheme drawn at screen column `display_column` of a
+/// line, or the end of the line if it is shorter
+pub fn column_at_display(line: &str, display_column: usize, tab_width: usize) -> usize {
+    let mut end = 0;
+    for grapheme in graphemes(line, tab_width) {
+        if display_column < grapheme.display_column + grapheme.width {
+            return grapheme.column;
+        }
+        end = grapheme.column + grapheme.text.chars().count();
+    }
+    end
+}
+
+/// Char column where the grapheme cluster ending at `column` starts
+pub fn prev_boundary(line: &str, column: usize) -> usize {
+    graphemes(line, 1)
+        .map(|grapheme| grapheme.column)
+        .take_while(|&start| start < column)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Char column where the grapheme cluster starting at `column` ends
+pub fn next_boundary(line: &str, column: usize) -> usize {
+    graphemes(line, 1)
+        .map(|grapheme| grapheme.column + grapheme.text.chars().count())
+        .find(|&end| end > column)
+        .unwrap_or(column)
+}
+
+/// Screen columns taken up by a whole line
+pub fn line_width(line: &str, tab_width: usize) -> usize {
+    graphemes(line, tab_width).map(|grapheme| grapheme.width).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_columns() {
+        // Tab stops every 4 columns, a wide CJK char, and "e" with a
+        // combining acute accent
+        let line = "a\tb漢e\u{301}x\n";
+        let columns: Vec<(usize, usize)> = graphemes(line, 4).map(|g| (g.column, g.display_column)).collect();
+        assert_eq!(columns, vec![(0, 0), (1, 1), (2, 4), (3, 5), (4, 7), (6, 8)]);
+
+        assert_eq!(display_column(line, 2, 4), 4);
+        assert_eq!(display_column(line, 6, 4), 8);
+        assert_eq!(display_column(line, 7, 4), 9);
+        assert_eq!(display_column(line, 9, 4), 11); // Past the end
+        assert_eq!(display_column("\t\t", 1, 8), 8);
+
+        // Anywhere inside a tab or wide char maps to its start
+        assert_eq!(column_at_display(line, 2, 4), 1);
+        assert_eq!(column_at_display(line, 6, 4), 3);
+        assert_eq!(column_at_display(line, 7, 4), 4);
+        assert_eq!(column_at_display(line, 40, 4), 7);
+
+        assert_eq!(line_width("漢字 ok", 4), 7);
+        assert_eq!(line_width(line, 4), 9);
+    }
+
+    #[test]
+    fn test_grapheme_boundaries() {
+        let line = "e\u{301}👍🏽!";
+        assert_eq!(next_boundary(line, 0), 2);
+        assert_eq!(next_boundary(line, 2), 4);
+        assert_eq!(next_boundary(line, 5), 5);
+        assert_eq!(prev_boundary(line, 5), 4);
+        assert_eq!(prev_boundary(line, 4), 2);
+        assert_eq!(prev_boundary(line, 2), 0);
+        assert_eq!(prev_boundary(line, 0), 0);
+    }
+
+    #[test]
+    fn test_display_text() {
+        let text: Vec<String> = graphemes("\tx\u{7}", 4).map(|g| g.display_text().into_owned()).collect();
+        assert_eq!(text, vec!["    ", "x", CONTROL_REPLACEMENT]);
+
+        // A wide char half scrolled out of view
+        let wide = graphemes("a漢", 4).nth(1).unwrap();
+        assert_eq!(wide.clipped_text(0, 10), "漢");
+        assert_eq!(wide.clipped_text(2, 10), " ");
+        assert_eq!(wide.clipped_text(0, 2), " ");
+    }
+}
@@ -0,0 +1,182 @@
+use super::rope_buffer::Position;
+use ropey::RopeSlice;
+use std::collections::VecDeque;
+
+/// Most edits kept in a buffer's log
+const MAX_LOGGED_EDITS: usize = 1000;
+/// Most text (old and new, in bytes) kept in a buffer's log
+const MAX_LOGGED_BYTES: usize = 4 * 1024 * 1024;
+
+/// One change to the text of a `TextBuffer`: `old_text` at `start` was
+/// replaced by `new_text`. Insertions have an empty `old_text`, deletions an
+/// empty `new_text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditEvent {
+    /// Revision of the buffer after this edit
+    pub revision: u64,
+    /// Byte offset of the start of the edit
+    pub start_byte: usize,
+    /// Line and (character) column of the start of the edit
+    pub start: Position,
+    /// Byte offset of the start of the edit within its line
+    pub start_column_byte: usize,
+    pub old_text: String,
+    pub new_text: String,
+}
+
+impl EditEvent {
+    /// Byte offset of the end of the replaced text, before the edit
+    pub fn old_end_byte(&self) -> usize {
+        self.start_byte + self.old_text.len()
+    }
+
+    /// Byte offset of the end of the new text, after the edit
+    pub fn new_end_byte(&self) -> usize {
+        self.start_byte + self.new_text.len()
+    }
+
+    /// Position of the end of the replaced text, before the edit
+    pub fn old_end(&self) -> Position {
+        Self::end_of(self.start, &self.old_text)
+    }
+
+    /// Position of the end of the new text, after the edit
+    pub fn new_end(&self) -> Position {
+        Self::end_of(self.start, &self.new_text)
+    }
+
+    /// Lines are counted the way the rope counts them, so "\r", "\r\n",
+    /// U+0085, U+2028 and U+2029 all end a line
+    fn end_of(start: Position, text: &str) -> Position {
+        let text = RopeSlice::from(text);
+        let breaks = text.len_lines() - 1;
+        let last_line = text.line(breaks).len_chars();
+        if breaks == 0 {
+            Position::new(start.line, start.column + last_line)
+        } else {
+            Position::new(start.line + breaks, last_line)
+        }
+    }
+
+    /// Where a position in the text before the edit is after it. Positions
+    /// inside the replaced text move to the start of the edit.
+    pub fn map_position(&self, pos: Position) -> Position {
+        let old_end = self.old_end();
+        if (pos.line, pos.column) < (self.start.line, self.start.column) {
+            return pos;
+        }
+        if (pos.line, pos.column) < (old_end.line, old_end.column) {
+            return self.start;
+        }
+
+        let new_end = self.new_end();
+        if pos.line == old_end.line {
+            Position::new(new_end.line, new_end.column + (pos.column - old_end.column))
+        } else {
+            Position::new(pos.line - old_end.line + new_end.line, pos.column)
+        }
+    }
+}
+
+/// The recent edits of a buffer. Consumers (highlighting, LSP sync,
+/// diagnostics, marks...) remember the last revision they have seen and
+/// catch up with `since` instead of rescanning the whole text. The log is
+/// bounded: a consumer that fell too far behind has to start over from the
+/// full text.
+#[derive(Debug, Default)]
+pub struct EditLog {
+    events: VecDeque<EditEvent>,
+    /// Size of the text held by `events`
+    text_bytes: usize,
+}
+
+impl EditLog {
+    /// Add an edit, dropping the oldest ones beyond the limits.
+    /// The newest edit is always kept.
+    pub fn push(&mut self, event: EditEvent) {
+        self.text_bytes += event.old_text.len() + event.new_text.len();
+        self.events.push_back(event);
+
+        while self.events.len() > 1
+            && (self.events.len() > MAX_LOGGED_EDITS || self.text_bytes > MAX_LOGGED_BYTES)
+        {
+            if let Some(old) = self.events.pop_front() {
+                self.text_bytes -= old.old_text.len() + old.new_text.len();
+            }
+        }
+    }
+
+    /// The edits made after `revision`, up to `current` (the buffer's
+    /// revision), oldest first. None if some of them are no longer logged.
+    pub fn since(&self, revision: u64, current: u64) -> Option<impl Iterator<Item = &EditEvent>> {
+        if revision > current {
+            return None;
+        }
+        if revision < current {
+            let oldest = self.events.front()?.revision;
+            if oldest > revision + 1 {
+                return None;
+            }
+        }
+        Some(self.events.iter().filter(move |event| event.revision > revision))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(revision: u64, start: Position, old_text: &str, new_text: &str) -> EditEvent {
+        EditEvent {
+            revision,
+            start_byte: 0,
+            start,
+            start_column_byte: start.column,
+            old_text: old_text.to_string(),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_map_position() {
+        // "ab|cd" -> insert "x\ny" at column 2 of line 3
+        let insert = edit(1, Position::new(3, 2), "", "x\ny");
+        assert_eq!(insert.map_position(Position::new(3, 1)), Position::new(3, 1));
+        assert_eq!(insert.map_position(Position::new(3, 2)), Position::new(4, 1));
+        assert_eq!(insert.map_position(Position::new(3, 4)), Position::new(4, 3));
+        assert_eq!(insert.map_position(Position::new(7, 0)), Position::new(8, 0));
+
+        // Deleting lines 1-2 moves what was inside to the start
+        let delete = edit(2, Position::new(1, 0), "one\ntwo\n", "");
+        assert_eq!(delete.map_position(Position::new(2, 1)), Position::new(1, 0));
+        assert_eq!(delete.map_position(Position::new(3, 5)), Position::new(1, 5));
+    }
+
+    #[test]
+    fn test_end_with_other_line_breaks() {
+        let start = Position::new(2, 3);
+        assert_eq!(edit(1, start, "", "ab").new_end(), Position::new(2, 5));
+        assert_eq!(edit(1, start, "", "a\r\nbc").new_end(), Position::new(3, 2));
+        assert_eq!(edit(1, start, "", "a\rb\rc").new_end(), Position::new(4, 1));
+        assert_eq!(edit(1, start, "", "a\u{2028}b\u{85}").new_end(), Position::new(4, 0));
+    }
+
+    #[test]
+    fn test_log_since() {
+        let mut log = EditLog::default();
+        for revision in 1..=3 {
+            log.push(edit(revision, Position::zero(), "", "x"));
+        }
+
+        let revisions: Vec<u64> = log.since(1, 3).unwrap().map(|e| e.revision).collect();
+        assert_eq!(revisions, vec![2, 3]);
+        assert_eq!(log.since(3, 3).unwrap().count(), 0);
+
+        // Old edits beyond the limit are gone: catching up from there fails
+        for revision in 4..=(MAX_LOGGED_EDITS as u64 + 3) {
+            log.push(edit(revision, Position::zero(), "", "x"));
+        }
+        assert!(log.since(1, MAX_LOGGED_EDITS as u64 + 3).is_none());
+        assert!(log.since(10, MAX_LOGGED_EDITS as u64 + 3).is_some());
+    }
+}
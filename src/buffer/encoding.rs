@@ -0,0 +1,171 @@
+use anyhow::Result;
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Character encoding of a file, plus whether it starts with a byte order mark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEncoding {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+impl FileEncoding {
+    /// Plain UTF-8 without BOM
+    pub const UTF8: FileEncoding = FileEncoding { encoding: UTF_8, bom: false };
+
+    /// Detect the encoding of raw file content and decode it.
+    ///
+    /// A BOM (UTF-8, UTF-16LE, UTF-16BE) wins; otherwise valid UTF-8 is taken
+    /// as UTF-8 and anything else falls back to Windows-1252, a superset of
+    /// Latin-1 that can decode any byte sequence.
+    pub fn detect(bytes: &[u8]) -> (String, FileEncoding) {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            let file_encoding = FileEncoding { encoding, bom: true };
+            return (file_encoding.decode(bytes), file_encoding);
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => (text.to_string(), Self::UTF8),
+            Err(_) => {
+                let file_encoding = FileEncoding { encoding: WINDOWS_1252, bom: false };
+                (file_encoding.decode(bytes), file_encoding)
+            }
+        }
+    }
+
+    /// Decode a line of a file without a BOM the way `detect` decodes a
+    /// whole one: as UTF-8 if valid, else as Windows-1252. Lets big files be
+    /// read line by line (e.g. by project search).
+    pub fn decode_line(bytes: Vec<u8>) -> String {
+        String::from_utf8(bytes).unwrap_or_else(|e| WINDOWS_1252.decode_without_bom_handling(e.as_bytes()).0.into_owned())
+    }
+
+    /// Check whether content starts with a UTF-16 byte order mark, so it
+    /// can't be split into lines at '\n' bytes
+    pub fn has_utf16_bom(bytes: &[u8]) -> bool {
+        matches!(Encoding::for_bom(bytes), Some((encoding, _)) if encoding == UTF_16LE || encoding == UTF_16BE)
+    }
+
+    /// Like `detect`, but content without a BOM is read as `preferred` (the
+    /// EditorConfig charset) if it decodes without errors. UTF-16 is only
+    /// recognised by its BOM, as any even-length file decodes as UTF-16.
+    pub fn detect_preferring(bytes: &[u8], preferred: FileEncoding) -> (String, FileEncoding) {
+        let is_utf16 = preferred.encoding == UTF_16LE || preferred.encoding == UTF_16BE;
+        if Encoding::for_bom(bytes).is_some() || is_utf16 {
+            return Self::detect(bytes);
+        }
+        let (text, had_errors) = preferred.encoding.decode_without_bom_handling(bytes);
+        if had_errors {
+            return Self::detect(bytes);
+        }
+        (text.into_owned(), preferred)
+    }
+
+    /// Look up an encoding by name, e.g. "latin1", "utf-16le" or "shift_jis".
+    /// "utf-8-bom" selects UTF-8 written with a BOM; UTF-16 is always written with one.
+    pub fn for_label(label: &str) -> Option<FileEncoding> {
+        let label = label.trim().to_ascii_lowercase();
+        if matches!(label.as_str(), "utf-8-bom" | "utf8-bom" | "utf-8 bom") {
+            return Some(FileEncoding { encoding: UTF_8, bom: true });
+        }
+        let encoding = Encoding::for_label(label.as_bytes())?;
+        // The "replacement" encoding (labels like iso-2022-kr) can only decode
+        if encoding == encoding_rs::REPLACEMENT {
+            return None;
+        }
+        let bom = encoding == UTF_16LE || encoding == UTF_16BE;
+        Some(FileEncoding { encoding, bom })
+    }
+
+    /// Name shown in the status bar, e.g. "UTF-8", "UTF-8 BOM", "windows-1252"
+    pub fn name(&self) -> String {
+        if self.bom && self.encoding == UTF_8 {
+            "UTF-8 BOM".to_string()
+        } else {
+            self.encoding.name().to_string()
+        }
+    }
+
+    /// Decode raw file content with this encoding, skipping a matching BOM.
+    /// Malformed sequences become U+FFFD.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let bytes = match Encoding::for_bom(bytes) {
+            Some((encoding, bom_len)) if encoding == self.encoding => &bytes[bom_len..],
+            _ => bytes,
+        };
+        self.encoding.decode_without_bom_handling(bytes).0.into_owned()
+    }
+
+    /// Streaming decoder for reading a file in chunks (BOM already skipped).
+    /// Keeps an incomplete multi-byte sequence until the next chunk.
+    pub fn new_decoder(&self) -> Decoder {
+        self.encoding.new_decoder_without_bom_handling()
+    }
+
+    /// Encode text for writing to disk. Fails if a character has no
+    /// representation in this encoding, rather than writing a lossy file.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+
+        // encoding_rs only decodes UTF-16, so encode it by hand
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let little_endian = self.encoding == UTF_16LE;
+            let bom = self.bom.then_some(0xFEFF);
+            for unit in bom.into_iter().chain(text.encode_utf16()) {
+                let unit_bytes = if little_endian { unit.to_le_bytes() } else { unit.to_be_bytes() };
+                bytes.extend_from_slice(&unit_bytes);
+            }
+            return Ok(bytes);
+        }
+
+        if self.bom && self.encoding == UTF_8 {
+            bytes.extend_from_slice(b"\xEF\xBB\xBF");
+        }
+        let (encoded, _, had_unmappable) = self.encoding.encode(text);
+        if had_unmappable {
+            anyhow::bail!("Text contains characters that cannot be encoded as {}", self.name());
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self::UTF8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_round_trip() {
+        // UTF-8 with BOM
+        let (text, encoding) = FileEncoding::detect(b"\xEF\xBB\xBFhello");
+        assert_eq!(text, "hello");
+        assert_eq!(encoding.name(), "UTF-8 BOM");
+        assert_eq!(encoding.encode(&text).unwrap(), b"\xEF\xBB\xBFhello");
+
+        // UTF-16LE with BOM
+        let utf16 = [0xFF, 0xFE, b'h', 0, b'i', 0];
+        let (text, encoding) = FileEncoding::detect(&utf16);
+        assert_eq!(text, "hi");
+        assert_eq!(encoding.name(), "UTF-16LE");
+        assert_eq!(encoding.encode(&text).unwrap(), utf16);
+
+        // Invalid UTF-8 falls back to Windows-1252
+        let (text, encoding) = FileEncoding::detect(b"caf\xE9");
+        assert_eq!(text, "café");
+        assert_eq!(encoding.name(), "windows-1252");
+        assert_eq!(encoding.encode(&text).unwrap(), b"caf\xE9");
+        assert!(encoding.encode("日本").is_err());
+    }
+
+    #[test]
+    fn test_for_label() {
+        assert_eq!(FileEncoding::for_label("latin1").unwrap().name(), "windows-1252");
+        assert_eq!(FileEncoding::for_label("utf-16be").unwrap().encode("a").unwrap(), [0xFE, 0xFF, 0, b'a']);
+        assert_eq!(FileEncoding::for_label("utf-8-bom").unwrap().encode("a").unwrap(), b"\xEF\xBB\xBFa");
+        assert!(FileEncoding::for_label("no-such-encoding").is_none());
+    }
+}
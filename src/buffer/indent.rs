@@ -0,0 +1,151 @@
+/// Lines looked at when detecting a file's indentation
+const DETECTION_LINE_LIMIT: usize = 10_000;
+
+/// How a buffer is indented: with tabs or spaces, and the columns per level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indentation {
+    pub use_tabs: bool,
+    pub width: usize,
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self::spaces(4)
+    }
+}
+
+impl Indentation {
+    /// Indent with `width` spaces per level
+    pub fn spaces(width: usize) -> Self {
+        Self { use_tabs: false, width: width.max(1) }
+    }
+
+    /// Indent with one tab per level, shown `width` columns wide
+    pub fn tabs(width: usize) -> Self {
+        Self { use_tabs: true, width: width.max(1) }
+    }
+
+    /// Text inserted for one level of indentation
+    pub fn unit(&self) -> String {
+        if self.use_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.width)
+        }
+    }
+
+    /// Short description for the status bar, e.g. "Spaces: 2" or "Tabs: 4"
+    pub fn label(&self) -> String {
+        let kind = if self.use_tabs { "Tabs" } else { "Spaces" };
+        format!("{}: {}", kind, self.width)
+    }
+
+    /// Parse user input such as "spaces 2", "2", "tabs" or "tabs 8"
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_ascii_lowercase();
+        let mut words = input.split_whitespace();
+        let first = words.next()?;
+        let width = match words.next() {
+            Some(word) => Some(word.parse::<usize>().ok().filter(|&w| (1..=16).contains(&w))?),
+            None => None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+
+        match first {
+            "tab" | "tabs" => Some(Self::tabs(width.unwrap_or(4))),
+            "space" | "spaces" => Some(Self::spaces(width.unwrap_or(4))),
+            number if width.is_none() => number
+                .parse::<usize>()
+                .ok()
+                .filter(|&w| (1..=16).contains(&w))
+                .map(Self::spaces),
+            _ => None,
+        }
+    }
+
+    /// Infer the indentation of a file from its contents.
+    ///
+    /// Tabs win if more lines start with a tab than with spaces; they are
+    /// shown `tab_width` columns wide. The width of space indentation is the
+    /// most common increase in indentation between consecutive lines.
+    /// Returns None if no line is indented. Only the first lines are read,
+    /// so `lines` may be a lazy iterator over a huge text.
+    pub fn detect<S: AsRef<str>>(lines: impl IntoIterator<Item = S>, tab_width: usize) -> Option<Self> {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        // How often each indentation increase (2..=8 spaces) occurs
+        let mut increases = [0usize; 9];
+        let mut previous_spaces = 0;
+
+        for line in lines.into_iter().take(DETECTION_LINE_LIMIT) {
+            let line = line.as_ref().trim_end_matches(['\n', '\r']);
+            let content = line.trim_start_matches([' ', '\t']);
+            if content.is_empty() {
+                continue;
+            }
+            if line.starts_with('\t') {
+                tab_lines += 1;
+                previous_spaces = 0;
+                continue;
+            }
+
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            // Continuation lines of block comments (" * ...") are aligned, not indented
+            if spaces > 0 && content.starts_with('*') {
+                continue;
+            }
+            if spaces > 0 {
+                space_lines += 1;
+            }
+            if let Some(increase) = spaces.checked_sub(previous_spaces) {
+                if (2..=8).contains(&increase) {
+                    increases[increase] += 1;
+                }
+            }
+            previous_spaces = spaces;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            return None;
+        }
+        if tab_lines > space_lines {
+            return Some(Self::tabs(tab_width));
+        }
+        // Ties go to the larger width: 2-space steps also appear in 4-space files
+        let width = (2..=8)
+            .max_by_key(|&width| increases[width])
+            .filter(|&width| increases[width] > 0)
+            .unwrap_or(4);
+        Some(Self::spaces(width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let tabbed = "fn main() {\n\tlet x = 1;\n\tif x {\n\t\ty();\n\t}\n}\n";
+        assert_eq!(Indentation::detect(tabbed.lines(), 4), Some(Indentation::tabs(4)));
+        assert_eq!(Indentation::detect(tabbed.lines(), 8), Some(Indentation::tabs(8)));
+        assert_eq!(Indentation::detect("a:\n  b:\n    c: 1\n  d: 2\n".lines(), 8), Some(Indentation::spaces(2)));
+        assert_eq!(
+            Indentation::detect("def f():\n    if x:\n        pass\n    /*\n     * comment\n     */\n".lines(), 4),
+            Some(Indentation::spaces(4))
+        );
+        assert_eq!(Indentation::detect("no\nindentation\n".lines(), 4), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Indentation::parse("spaces 2"), Some(Indentation::spaces(2)));
+        assert_eq!(Indentation::parse("Tabs"), Some(Indentation::tabs(4)));
+        assert_eq!(Indentation::parse("tabs 8"), Some(Indentation::tabs(8)));
+        assert_eq!(Indentation::parse("3"), Some(Indentation::spaces(3)));
+        assert_eq!(Indentation::parse("spaces two"), None);
+        assert_eq!(Indentation::parse("0"), None);
+    }
+}
@@ -1,5 +1,16 @@
+pub mod atomic_write;
+pub mod byte_buffer;
+pub mod display;
+pub mod edit_event;
+pub mod encoding;
+pub mod indent;
 pub mod rope_buffer;
 pub mod undo;
 
-pub use rope_buffer::{LineEnding, Position, TextBuffer};
-pub use undo::{Change, UndoManager};
+pub use atomic_write::atomic_write;
+pub use byte_buffer::ByteBuffer;
+pub use edit_event::EditEvent;
+pub use encoding::FileEncoding;
+pub use indent::Indentation;
+pub use rope_buffer::{LineEnding, Position, ReadOnlyError, TextBuffer};
+pub use undo::{Change, CursorSnapshot, UndoManager};
@@ -1,6 +1,20 @@
+use super::atomic_write::atomic_write;
+use super::display;
+use super::edit_event::{EditEvent, EditLog};
+use super::encoding::FileEncoding;
+use super::indent::Indentation;
 use anyhow::Result;
 use ropey::Rope;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Chars around a column looked at to find grapheme cluster boundaries,
+/// more than the longest clusters (emoji sequences) have
+const GRAPHEME_WINDOW: usize = 32;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LineEnding {
@@ -35,7 +49,7 @@ impl LineEnding {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub line: usize,
     pub column: usize,
@@ -51,11 +65,55 @@ impl Position {
     }
 }
 
+/// Size and modification time of a file: a cheap way to tell it changed
+/// without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiskStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl DiskStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Error of an edit refused because the buffer is read-only
+#[derive(Debug, thiserror::Error)]
+#[error("Buffer is read-only")]
+pub struct ReadOnlyError;
+
 pub struct TextBuffer {
     rope: Rope,
     file_path: Option<PathBuf>,
     modified: bool,
     line_ending: LineEnding,
+    /// Encoding (and BOM) the file is read and written with
+    encoding: FileEncoding,
+    /// Hash of the content last read from or written to disk. Not kept in
+    /// large-file mode, where only `disk_stamp` is compared.
+    disk_hash: Option<u64>,
+    /// Size and modification time of the file when it was last read or written
+    disk_stamp: Option<DiskStamp>,
+    /// Large-file mode: no highlighting, LSP sync or whole-text scans
+    large_file: bool,
+    /// Read-only buffers mirror their file (e.g. a followed log) and refuse edits
+    read_only: bool,
+    /// Bumped by every change to the text
+    revision: u64,
+    /// Recent changes, for consumers catching up since a revision
+    edit_log: EditLog,
+    /// Indentation detected by tab width, and the revision it is for:
+    /// detecting it scans the whole text
+    detected_indentation: RefCell<(u64, HashMap<usize, Option<Indentation>>)>,
+    /// Last answer of `is_actually_modified`, with the revision, encoding and
+    /// file stamp it was worked out for: it encodes the whole text
+    actually_modified: Cell<Option<(u64, FileEncoding, DiskStamp, bool)>>,
 }
 
 impl TextBuffer {
@@ -66,30 +124,69 @@ impl TextBuffer {
             file_path: None,
             modified: false,
             line_ending: LineEnding::LF,
+            encoding: FileEncoding::default(),
+            disk_hash: None,
+            disk_stamp: None,
+            large_file: false,
+            read_only: false,
+            revision: 0,
+            edit_log: EditLog::default(),
+            detected_indentation: RefCell::default(),
+            actually_modified: Cell::new(None),
         }
     }
 
-    /// Load a file into the buffer
+    /// Load a file into the buffer, detecting its encoding
     pub fn from_file(path: PathBuf) -> Result<Self> {
-        let content = std::fs::read_to_string(&path)?;
+        let bytes = std::fs::read(&path)?;
+        let (content, encoding) = FileEncoding::detect(&bytes);
+        Ok(Self::from_decoded(path, content, encoding))
+    }
+
+    /// Load a file into the buffer, reading it as `preferred` unless it has
+    /// a BOM or isn't valid in that encoding (see `FileEncoding::detect_preferring`)
+    pub fn from_file_preferring(path: PathBuf, preferred: FileEncoding) -> Result<Self> {
+        let bytes = std::fs::read(&path)?;
+        let (content, encoding) = FileEncoding::detect_preferring(&bytes, preferred);
+        Ok(Self::from_decoded(path, content, encoding))
+    }
+
+    /// The content is only hashed once the buffer is known not to be in
+    /// large-file mode (see `set_large_file`)
+    fn from_decoded(path: PathBuf, content: String, encoding: FileEncoding) -> Self {
         let line_ending = LineEnding::detect(&content);
         let rope = Rope::from_str(&content);
+        let disk_stamp = DiskStamp::of(&path);
 
-        Ok(Self {
+        Self {
             rope,
             file_path: Some(path),
             modified: false,
             line_ending,
-        })
+            encoding,
+            disk_hash: None,
+            disk_stamp,
+            large_file: false,
+            read_only: false,
+            revision: 0,
+            edit_log: EditLog::default(),
+            detected_indentation: RefCell::default(),
+            actually_modified: Cell::new(None),
+        }
     }
 
     /// Save the buffer to its file
+    ///
+    /// The content is written in the buffer's encoding. The write is atomic
+    /// and keeps the file's mode, ownership and symlinks (see `atomic_write`).
     pub fn save(&mut self) -> Result<()> {
         if let Some(path) = &self.file_path {
             let content = self.rope.to_string();
-            match std::fs::write(path, content) {
+            let bytes = self.encoding.encode(&content)?;
+            match atomic_write(path, &bytes) {
                 Ok(_) => {
                     self.modified = false;
+                    self.remember_disk_content(&content);
                     Ok(())
                 }
                 Err(e) => Err(anyhow::Error::new(e))
@@ -102,30 +199,152 @@ impl TextBuffer {
     /// Save the buffer to a specific file
     pub fn save_as(&mut self, path: PathBuf) -> Result<()> {
         let content = self.rope.to_string();
-        std::fs::write(&path, content)?;
+        atomic_write(&path, &self.encoding.encode(&content)?)?;
         self.file_path = Some(path);
         self.modified = false;
+        self.remember_disk_content(&content);
+        Ok(())
+    }
+
+    /// Read the file from disk and return its content if it changed since it
+    /// was last loaded or saved. Returns None if unchanged or unreadable.
+    /// Without a content hash (large-file mode) only the file's size and
+    /// modification time are compared.
+    pub fn read_disk_change(&self) -> Option<String> {
+        let Some(disk_hash) = self.disk_hash else {
+            let stamp = DiskStamp::of(self.file_path.as_ref()?)?;
+            if Some(stamp) == self.disk_stamp {
+                return None;
+            }
+            return self.read_disk_content();
+        };
+        let content = self.read_disk_content()?;
+        if Self::content_hash(&content) == disk_hash {
+            return None;
+        }
+        Some(content)
+    }
+
+    /// Read and decode the file with the buffer's encoding
+    fn read_disk_content(&self) -> Option<String> {
+        let bytes = std::fs::read(self.file_path.as_ref()?).ok()?;
+        Some(self.encoding.decode(&bytes))
+    }
+
+    /// Remember `content` as the current on-disk state without loading it
+    /// (used when keeping local edits, or after a sudo save)
+    pub fn acknowledge_disk_content(&mut self, content: &str) {
+        self.remember_disk_content(content);
+    }
+
+    /// Replace the whole buffer with content read from disk
+    pub fn reload_from(&mut self, content: &str) {
+        let old_text = self.rope.to_string();
+        self.rope = Rope::from_str(content);
+        self.publish_edit(0, Position::zero(), old_text, content.to_string());
+        self.line_ending = LineEnding::detect(content);
+        self.modified = false;
+        self.remember_disk_content(content);
+    }
+
+    /// Re-read the file decoded with `encoding`, which is also used for
+    /// saving from now on. Logged as a full replacement like `reload_from`.
+    pub fn reload_with_encoding(&mut self, encoding: FileEncoding) -> Result<()> {
+        let path = self
+            .file_path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No file path set for buffer"))?;
+        let content = encoding.decode(&std::fs::read(path)?);
+        self.encoding = encoding;
+        self.reload_from(&content);
+        Ok(())
+    }
+
+    /// Record `content` as what is on disk now
+    fn remember_disk_content(&mut self, content: &str) {
+        self.disk_stamp = self.file_path.as_deref().and_then(DiskStamp::of);
+        self.disk_hash = (!self.large_file).then(|| Self::content_hash(content));
+    }
+
+    /// Hash used to tell whether the file on disk changed
+    fn content_hash(content: &str) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Current revision of the text: bumped by every change, so consumers
+    /// can tell whether the text changed without comparing it
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// The changes made after `revision`, oldest first. None if they are no
+    /// longer all logged; the consumer then has to start over from the text.
+    pub fn edits_since(&self, revision: u64) -> Option<impl Iterator<Item = &EditEvent>> {
+        self.edit_log.since(revision, self.revision)
+    }
+
+    /// Bump the revision and log a change that was just made. `start_byte`
+    /// and `start` are where it starts, computed before the change.
+    fn publish_edit(&mut self, start_byte: usize, start: Position, old_text: String, new_text: String) {
+        self.revision += 1;
+        // The text before the edit is unchanged, and so is where its line starts
+        let start_column_byte = start_byte - self.rope.line_to_byte(start.line);
+        self.edit_log.push(EditEvent {
+            revision: self.revision,
+            start_byte,
+            start,
+            start_column_byte,
+            old_text,
+            new_text,
+        });
+    }
+
+    /// Byte offset and position of a character index, for `publish_edit`
+    fn edit_start(&self, char_idx: usize) -> (usize, Position) {
+        let line = self.rope.char_to_line(char_idx);
+        let column = char_idx - self.rope.line_to_char(line);
+        (self.rope.char_to_byte(char_idx), Position::new(line, column))
+    }
+
+    /// Fail if the buffer is read-only: every edit goes through here, so
+    /// read-only mode holds for LSP edits and paste as well as typing
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(ReadOnlyError.into());
+        }
         Ok(())
     }
 
     /// Insert text at a position
     pub fn insert(&mut self, pos: Position, text: &str) -> Result<()> {
+        self.check_writable()?;
         let char_idx = self.pos_to_char(pos)?;
+        let (start_byte, start) = self.edit_start(char_idx);
         self.rope.insert(char_idx, text);
         self.modified = true;
+        self.publish_edit(start_byte, start, String::new(), text.to_string());
         Ok(())
     }
 
     /// Insert a character at a position
     pub fn insert_char(&mut self, pos: Position, ch: char) -> Result<()> {
+        self.check_writable()?;
         let char_idx = self.pos_to_char(pos)?;
+        let (start_byte, start) = self.edit_start(char_idx);
         self.rope.insert_char(char_idx, ch);
         self.modified = true;
+        self.publish_edit(start_byte, start, String::new(), ch.to_string());
         Ok(())
     }
 
     /// Delete a range of text
     pub fn delete_range(&mut self, start: Position, end: Position) -> Result<String> {
+        self.check_writable()?;
         let start_idx = self.pos_to_char(start)?;
         let end_idx = self.pos_to_char(end)?;
 
@@ -134,13 +353,16 @@ impl TextBuffer {
         }
 
         let deleted = self.rope.slice(start_idx..end_idx).to_string();
+        let (start_byte, start) = self.edit_start(start_idx);
         self.rope.remove(start_idx..end_idx);
         self.modified = true;
+        self.publish_edit(start_byte, start, deleted.clone(), String::new());
         Ok(deleted)
     }
 
     /// Delete a single character at position
     pub fn delete_char(&mut self, pos: Position) -> Result<Option<char>> {
+        self.check_writable()?;
         let char_idx = self.pos_to_char(pos)?;
 
         if char_idx >= self.rope.len_chars() {
@@ -148,11 +370,23 @@ impl TextBuffer {
         }
 
         let ch = self.rope.char(char_idx);
+        let (start_byte, start) = self.edit_start(char_idx);
         self.rope.remove(char_idx..char_idx + 1);
         self.modified = true;
+        self.publish_edit(start_byte, start, ch.to_string(), String::new());
         Ok(Some(ch))
     }
 
+    /// Delete the grapheme cluster at position (a char with its combining
+    /// marks, or a whole emoji sequence), or the line break at the end of a line
+    pub fn delete_grapheme(&mut self, pos: Position) -> Result<Option<String>> {
+        let end = self.next_grapheme_column(pos);
+        if end > pos.column {
+            return self.delete_range(pos, Position::new(pos.line, end)).map(Some);
+        }
+        Ok(self.delete_char(pos)?.map(String::from))
+    }
+
     /// Get a line of text
     pub fn get_line(&self, line: usize) -> Option<String> {
         if line >= self.rope.len_lines() {
@@ -169,6 +403,24 @@ impl TextBuffer {
             .collect()
     }
 
+    /// Up to `before` characters of text before `pos`, and up to `after`
+    /// characters from `pos` on, without copying the rest of the buffer
+    pub fn text_around(&self, pos: Position, before: usize, after: usize) -> Result<(String, String)> {
+        let char_idx = self.pos_to_char(pos)?;
+        let start = char_idx.saturating_sub(before);
+        let end = char_idx.saturating_add(after).min(self.rope.len_chars());
+        Ok((
+            self.rope.slice(start..char_idx).to_string(),
+            self.rope.slice(char_idx..end).to_string(),
+        ))
+    }
+
+    /// The underlying rope, for consumers that read the text in chunks
+    /// instead of copying it
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
     /// Get the entire content as a string
     pub fn to_string(&self) -> String {
         self.rope.to_string()
@@ -255,22 +507,41 @@ impl TextBuffer {
             return self.modified;
         };
 
+        // Read-only buffers are never edited, but may lag behind a growing
+        // file; large files are too big to compare on every check
+        if self.read_only || self.large_file {
+            return self.modified;
+        }
+
         // If the file doesn't exist, buffer is modified
-        if !path.exists() {
+        let Some(stamp) = DiskStamp::of(path) else {
             return self.modified;
+        };
+
+        // Nothing to compare again until the text, encoding or file changes
+        let current = (self.revision, self.encoding, stamp);
+        if let Some((.., modified)) = self
+            .actually_modified
+            .get()
+            .filter(|&(revision, encoding, cached_stamp, _)| (revision, encoding, cached_stamp) == current)
+        {
+            return modified;
         }
 
-        // Read file content and compare with buffer
-        match std::fs::read_to_string(path) {
-            Ok(file_content) => {
-                let buffer_content = self.rope.to_string();
-                file_content != buffer_content
-            }
+        // Compare the file with what a save would write, so that an
+        // encoding or BOM change also counts as a modification
+        let modified = match std::fs::read(path) {
+            Ok(file_bytes) => match self.encoding.encode(&self.rope.to_string()) {
+                Ok(buffer_bytes) => file_bytes != buffer_bytes,
+                Err(_) => true,
+            },
             Err(_) => {
                 // If we can't read the file, fall back to the flag
-                self.modified
+                return self.modified;
             }
-        }
+        };
+        self.actually_modified.set(Some((self.revision, self.encoding, stamp, modified)));
+        modified
     }
 
     /// Get the file path
@@ -278,25 +549,145 @@ impl TextBuffer {
         self.file_path.as_ref()
     }
 
-    /// Set the file path
-    pub fn set_file_path(&mut self, path: PathBuf) {
-        self.file_path = Some(path);
-    }
-
     /// Manually set the modified flag (used after sudo save)
     pub fn set_modified(&mut self, modified: bool) {
         self.modified = modified;
     }
 
+    /// Get the encoding the file is read and written with
+    pub fn encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+
+    /// Change the encoding used for the next save (convert on save)
+    pub fn set_encoding(&mut self, encoding: FileEncoding) {
+        if encoding != self.encoding {
+            self.encoding = encoding;
+            self.modified = true;
+        }
+    }
+
+    /// Check if the buffer is in large-file mode
+    pub fn is_large_file(&self) -> bool {
+        self.large_file
+    }
+
+    /// Switch large-file mode on or off. Large files aren't hashed; an
+    /// unmodified buffer leaving large-file mode is hashed from its text.
+    pub fn set_large_file(&mut self, large_file: bool) {
+        self.large_file = large_file;
+        if large_file {
+            self.disk_hash = None;
+        } else if self.disk_hash.is_none() && !self.modified && self.file_path.is_some() {
+            self.disk_hash = Some(Self::content_hash(&self.rope.to_string()));
+        }
+    }
+
+    /// Check if the buffer is read-only
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Make the buffer read-only or editable again
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Append text that was added to the file on disk. Unlike `insert`,
+    /// this doesn't mark the buffer modified.
+    pub fn append_from_disk(&mut self, text: &str) {
+        let char_idx = self.rope.len_chars();
+        let (start_byte, start) = self.edit_start(char_idx);
+        self.rope.insert(char_idx, text);
+        self.publish_edit(start_byte, start, String::new(), text.to_string());
+    }
+
+    /// Check if the content is bigger than `max_bytes` or has a line longer
+    /// than `max_line_chars` (the thresholds for large-file mode)
+    pub fn exceeds_limits(&self, max_bytes: u64, max_line_chars: usize) -> bool {
+        self.rope.len_bytes() as u64 > max_bytes
+            || self.rope.lines().any(|line| line.len_chars() > max_line_chars)
+    }
+
+    /// Get up to `max_chars` characters of a line starting at column
+    /// `start_column`, without the line ending. Only the requested part of the
+    /// line is copied, so this stays cheap on very long lines.
+    pub fn line_window(&self, line: usize, start_column: usize, max_chars: usize) -> String {
+        if line >= self.rope.len_lines() {
+            return String::new();
+        }
+        let line_len = self.line_len(line);
+        let start = start_column.min(line_len);
+        let end = start.saturating_add(max_chars).min(line_len);
+        self.rope.line(line).slice(start..end).to_string()
+    }
+
+    /// Column where the grapheme cluster before `pos` starts, e.g. two chars
+    /// back for "e" followed by a combining accent
+    pub fn prev_grapheme_column(&self, pos: Position) -> usize {
+        let start = pos.column.saturating_sub(GRAPHEME_WINDOW);
+        let window = self.line_window(pos.line, start, pos.column - start);
+        start + display::prev_boundary(&window, pos.column - start)
+    }
+
+    /// Column where the grapheme cluster at `pos` ends (`pos.column` at the
+    /// end of the line)
+    pub fn next_grapheme_column(&self, pos: Position) -> usize {
+        let window = self.line_window(pos.line, pos.column, GRAPHEME_WINDOW);
+        pos.column + display::next_boundary(&window, 0)
+    }
+
+    /// Screen column of a position, with tabs expanded to `tab_width` and
+    /// wide characters taking two columns. In large-file mode every char is
+    /// one column, so long lines are never scanned.
+    pub fn display_column(&self, pos: Position, tab_width: usize) -> usize {
+        if self.large_file {
+            return pos.column;
+        }
+        let line = self.line_window(pos.line, 0, pos.column);
+        display::display_column(&line, pos.column, tab_width)
+    }
+
+    /// Column of the character drawn at screen column `display_column` of a
+    /// line (the inverse of `display_column`)
+    pub fn column_at_display(&self, line: usize, display_column: usize, tab_width: usize) -> usize {
+        if self.large_file {
+            return display_column.min(self.line_len(line));
+        }
+        let text = self.line_window(line, 0, usize::MAX);
+        display::column_at_display(&text, display_column, tab_width)
+    }
+
+    /// Indentation style inferred from the contents, if they have indented
+    /// lines, with tabs shown `tab_width` wide. Not done in large-file mode.
+    pub fn detected_indentation(&self, tab_width: usize) -> Option<Indentation> {
+        if self.large_file {
+            return None;
+        }
+        let mut cache = self.detected_indentation.borrow_mut();
+        let (revision, detected) = &mut *cache;
+        if *revision != self.revision {
+            *revision = self.revision;
+            detected.clear();
+        }
+        *detected
+            .entry(tab_width)
+            .or_insert_with(|| Indentation::detect(self.rope.lines().map(Cow::from), tab_width))
+    }
+
     /// Get the line ending type
     pub fn line_ending(&self) -> LineEnding {
         self.line_ending
     }
 
     /// Clear the buffer
-    pub fn clear(&mut self) {
+    pub fn clear(&mut self) -> Result<()> {
+        self.check_writable()?;
+        let old_text = self.rope.to_string();
         self.rope = Rope::new();
         self.modified = true;
+        self.publish_edit(0, Position::zero(), old_text, String::new());
+        Ok(())
     }
 }
 
@@ -334,6 +725,43 @@ mod tests {
         assert_eq!(buffer.to_string(), " World");
     }
 
+    #[test]
+    fn test_edits_are_published() {
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), "héllo\nworld").unwrap();
+        let seen = buffer.revision();
+        buffer.delete_range(Position::new(1, 0), Position::new(1, 2)).unwrap();
+        buffer.insert_char(Position::new(0, 2), 'x').unwrap();
+
+        let edits: Vec<&EditEvent> = buffer.edits_since(seen).unwrap().collect();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[0].start, Position::new(1, 0));
+        assert_eq!(edits[0].start_byte, 7);
+        assert_eq!(edits[0].old_text, "wo");
+        assert_eq!(edits[1].start_byte, 3);
+        assert_eq!(edits[1].new_text, "x");
+        assert_eq!(edits[1].revision, buffer.revision());
+    }
+
+    #[test]
+    fn test_read_only_refuses_edits() {
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), "log\n").unwrap();
+        buffer.set_read_only(true);
+        let revision = buffer.revision();
+
+        assert!(buffer.insert(Position::zero(), "x").is_err());
+        assert!(buffer.insert_char(Position::zero(), 'x').is_err());
+        assert!(buffer.delete_range(Position::zero(), Position::new(0, 2)).is_err());
+        assert!(buffer.delete_char(Position::zero()).is_err());
+        assert!(buffer.clear().is_err());
+        assert_eq!(buffer.revision(), revision);
+
+        // Text from the file on disk still comes in
+        buffer.append_from_disk("more\n");
+        assert_eq!(buffer.to_string(), "log\nmore\n");
+    }
+
     #[test]
     fn test_multiline() {
         let mut buffer = TextBuffer::new();
@@ -361,4 +789,142 @@ mod tests {
         assert_eq!(LineEnding::detect("Hello\r\nWorld"), LineEnding::CRLF);
         assert_eq!(LineEnding::detect("Hello\rWorld"), LineEnding::CR);
     }
+
+    #[test]
+    fn test_save_replaces_content_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "old content that is longer").unwrap();
+
+        let mut buffer = TextBuffer::from_file(path.clone()).unwrap();
+        buffer.clear().unwrap();
+        buffer.insert(Position::zero(), "new").unwrap();
+        buffer.save().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!buffer.is_modified());
+        let entries = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(entries, 1, "temporary file should not be left behind");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_preserves_mode_and_symlink() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.sh");
+        let link = dir.path().join("link.sh");
+        std::fs::write(&target, "echo old\n").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o750)).unwrap();
+        std::os::unix::fs::symlink("target.sh", &link).unwrap();
+
+        let mut buffer = TextBuffer::from_file(link.clone()).unwrap();
+        buffer.insert(Position::zero(), "#!/bin/sh\n").unwrap();
+        buffer.save().unwrap();
+
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "#!/bin/sh\necho old\n");
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[test]
+    fn test_reload_with_encoding_keeps_revisions_increasing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin1.txt");
+        std::fs::write(&path, b"caf\xe9\n").unwrap();
+
+        let mut buffer = TextBuffer::from_file(path).unwrap();
+        let seen = buffer.revision();
+        let latin1 = FileEncoding::for_label("latin1").unwrap();
+        buffer.reload_with_encoding(latin1).unwrap();
+
+        assert_eq!(buffer.to_string(), "café\n");
+        assert!(buffer.revision() > seen);
+        let edits: Vec<&EditEvent> = buffer.edits_since(seen).unwrap().collect();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "café\n");
+    }
+
+    #[test]
+    fn test_read_disk_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "original").unwrap();
+
+        let mut buffer = TextBuffer::from_file(path.clone()).unwrap();
+        assert!(buffer.read_disk_change().is_none());
+
+        // Our own save must not look like an external change
+        buffer.insert(Position::zero(), "mine ").unwrap();
+        buffer.save().unwrap();
+        assert!(buffer.read_disk_change().is_none());
+
+        std::fs::write(&path, "theirs").unwrap();
+        let content = buffer.read_disk_change().unwrap();
+        assert_eq!(content, "theirs");
+
+        buffer.reload_from(&content);
+        assert_eq!(buffer.to_string(), "theirs");
+        assert!(!buffer.is_modified());
+        assert!(buffer.read_disk_change().is_none());
+    }
+
+    #[test]
+    fn test_large_file_disk_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.log");
+        std::fs::write(&path, "one\n").unwrap();
+
+        let mut buffer = TextBuffer::from_file(path.clone()).unwrap();
+        buffer.set_large_file(true);
+        assert!(buffer.disk_hash.is_none());
+        assert!(buffer.read_disk_change().is_none());
+
+        // Told apart by size and modification time only
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        assert_eq!(buffer.read_disk_change().unwrap(), "one\ntwo\n");
+        buffer.reload_from("one\ntwo\n");
+        assert!(buffer.disk_hash.is_none());
+        assert!(buffer.read_disk_change().is_none());
+        assert!(!buffer.is_actually_modified());
+    }
+
+    #[test]
+    fn test_save_preserves_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin1.txt");
+        std::fs::write(&path, b"caf\xE9\n").unwrap();
+
+        let mut buffer = TextBuffer::from_file(path.clone()).unwrap();
+        assert_eq!(buffer.to_string(), "café\n");
+        assert!(!buffer.is_actually_modified());
+
+        buffer.insert(Position::new(1, 0), "è").unwrap();
+        buffer.save().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"caf\xE9\n\xE8");
+
+        // Converting counts as a modification and takes effect on save
+        buffer.set_encoding(FileEncoding::for_label("utf-8").unwrap());
+        assert!(buffer.is_actually_modified());
+        buffer.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "café\nè");
+    }
+
+    #[test]
+    fn test_large_file_limits_and_line_window() {
+        let mut buffer = TextBuffer::new();
+        let long_line = "x".repeat(100);
+        buffer.insert(Position::zero(), &format!("short\n{}\n", long_line)).unwrap();
+
+        assert!(!buffer.exceeds_limits(1024, 200));
+        assert!(buffer.exceeds_limits(1024, 50));
+        assert!(buffer.exceeds_limits(64, 1000));
+
+        assert_eq!(buffer.line_window(0, 2, 10), "ort");
+        assert_eq!(buffer.line_window(1, 95, 10), "xxxxx");
+        assert_eq!(buffer.line_window(1, 200, 10), "");
+        assert_eq!(buffer.line_window(5, 0, 10), "");
+    }
 }
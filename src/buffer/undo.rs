@@ -1,8 +1,13 @@
 use super::rope_buffer::Position;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Typing within this long of the previous keystroke joins its undo step
+const COALESCE_WINDOW: Duration = Duration::from_secs(1);
 
 /// Represents a single change to the buffer
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Change {
     /// Insert text at a position
     Insert { pos: Position, text: String },
@@ -30,28 +35,198 @@ impl Change {
             }
         }
     }
+
+    /// Short description for the undo tree browser, e.g. `insert "foo"`
+    pub fn describe(&self) -> String {
+        match self {
+            Change::Insert { text, .. } => format!("insert {}", Self::quote(text)),
+            Change::Delete { text, .. } => format!("delete {}", Self::quote(text)),
+            Change::Compound(changes) => match changes.as_slice() {
+                [single] => single.describe(),
+                // Coalesced typing
+                _ if changes.iter().all(|c| matches!(c, Change::Insert { .. })) => {
+                    let text: String = changes
+                        .iter()
+                        .filter_map(|c| match c {
+                            Change::Insert { text, .. } => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect();
+                    format!("insert {}", Self::quote(&text))
+                }
+                _ => format!("{} edits", changes.len()),
+            },
+        }
+    }
+
+    /// First line the change touches
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Change::Insert { pos, .. } | Change::Delete { pos, .. } => Some(pos.line),
+            Change::Compound(changes) => changes.iter().find_map(|c| c.line()),
+        }
+    }
+
+    /// Join two changes into one compound change
+    fn merge(first: Change, second: Change) -> Change {
+        let mut changes = match first {
+            Change::Compound(changes) => changes,
+            change => vec![change],
+        };
+        match second {
+            Change::Compound(more) => changes.extend(more),
+            change => changes.push(change),
+        }
+        Change::Compound(changes)
+    }
+
+    /// Whether the change is plain typing (or deleting) of characters on one
+    /// line, at one or several cursors: the kind of edit coalesced into the
+    /// previous undo step. Returns whether it inserts.
+    fn typing_kind(&self) -> Option<bool> {
+        match self {
+            Change::Insert { text, .. } | Change::Delete { text, .. } if text.chars().count() != 1 || text == "\n" => None,
+            Change::Insert { .. } => Some(true),
+            Change::Delete { .. } => Some(false),
+            Change::Compound(changes) => {
+                let first = changes.first()?.typing_kind()?;
+                changes.iter().all(|c| c.typing_kind() == Some(first)).then_some(first)
+            }
+        }
+    }
+
+    fn quote(text: &str) -> String {
+        const MAX_CHARS: usize = 20;
+        let escaped: String = text.chars().flat_map(|c| c.escape_debug()).collect();
+        if escaped.chars().count() > MAX_CHARS {
+            format!("\"{}…\"", escaped.chars().take(MAX_CHARS).collect::<String>())
+        } else {
+            format!("\"{}\"", escaped)
+        }
+    }
+}
+
+/// Cursors and selection of a buffer, saved around each undo step so undo
+/// and redo put them back where the edit happened
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorSnapshot {
+    pub cursor: Position,
+    pub secondary_cursors: Vec<Position>,
+    /// Anchor and head of the selection
+    pub selection: Option<(Position, Position)>,
+}
+
+/// A group of edits being recorded as one undo step
+#[derive(Clone)]
+struct Transaction {
+    /// Cursors before the first edit
+    before: CursorSnapshot,
+    /// Node the edits go into, once there is one
+    node: Option<usize>,
+    /// When the edit before this transaction was recorded
+    previous_edit: Option<Instant>,
 }
 
-/// Manages undo/redo history for a text buffer
+/// A state in the undo tree: the change that led to it from its parent
+#[derive(Clone, Serialize, Deserialize)]
+struct UndoNode {
+    /// None for the root (the oldest state kept)
+    change: Option<Change>,
+    parent: Option<usize>,
+    /// Children in creation order
+    children: Vec<usize>,
+    /// Child that redo goes to: the one created or visited last
+    redo_child: Option<usize>,
+    /// Creation order, shown in the browser
+    seq: usize,
+    /// Unix time (seconds) of the change
+    timestamp: u64,
+    /// Cursors before and after the change, if it was made in a transaction
+    #[serde(default)]
+    before: Option<CursorSnapshot>,
+    #[serde(default)]
+    after: Option<CursorSnapshot>,
+}
+
+/// One row of the undo tree as shown in the browser
+#[derive(Debug, Clone)]
+pub struct UndoTreeRow {
+    /// Node id, for `UndoManager::jump_to`
+    pub node: usize,
+    /// Branch nesting: older branches are indented under the newer line
+    pub depth: usize,
+    pub seq: usize,
+    pub description: String,
+    pub timestamp: u64,
+    pub is_current: bool,
+}
+
+/// Manages undo/redo history for a text buffer.
+///
+/// History is a tree: a change recorded after undoing starts a new branch
+/// instead of discarding the undone changes, so every earlier state stays
+/// reachable (like Emacs undo-tree). Undo moves to the parent state, redo to
+/// the most recently created or visited child.
+///
+/// Edits made between `begin_transaction` and `end_transaction` (one command,
+/// all cursors of a multi-cursor edit) form a single undo step, and typing
+/// in quick succession is coalesced into the step before it.
+///
+/// The tree can be serialized to keep it across editor restarts; see
+/// `backup::UndoStore`.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct UndoManager {
-    /// History of changes
-    history: Vec<Change>,
-    /// Current position in history
+    /// Nodes of the tree; removed nodes are compacted away when pruning
+    nodes: Vec<UndoNode>,
+    /// The oldest state kept
+    root: usize,
+    /// The state the buffer is in
     current: usize,
-    /// Maximum number of changes to keep
+    /// Sequence number of the next change
+    next_seq: usize,
+    /// Maximum number of changes to keep (not saved: comes from the config)
+    #[serde(skip)]
     max_history: usize,
     /// Whether we're currently in an undo/redo operation
+    #[serde(skip)]
     in_undo_redo: bool,
+    #[serde(skip)]
+    transaction: Option<Transaction>,
+    /// How many `begin_transaction` calls are still waiting for their
+    /// `end_transaction`; nested transactions join the outermost one
+    #[serde(skip)]
+    transaction_depth: usize,
+    /// When the last edit was recorded, for coalescing typing
+    #[serde(skip)]
+    last_edit: Option<Instant>,
 }
 
 impl UndoManager {
     /// Create a new undo manager
     pub fn new(max_history: usize) -> Self {
         Self {
-            history: Vec::new(),
+            nodes: vec![Self::root_node()],
+            root: 0,
             current: 0,
+            next_seq: 1,
             max_history,
             in_undo_redo: false,
+            transaction: None,
+            transaction_depth: 0,
+            last_edit: None,
+        }
+    }
+
+    fn root_node() -> UndoNode {
+        UndoNode {
+            change: None,
+            parent: None,
+            children: Vec::new(),
+            redo_child: None,
+            seq: 0,
+            timestamp: unix_now(),
+            before: None,
+            after: None,
         }
     }
 
@@ -61,44 +236,144 @@ impl UndoManager {
         if self.in_undo_redo {
             return;
         }
+        self.last_edit = Some(Instant::now());
+
+        // Later edits of a transaction join the step of its first edit
+        if let Some(transaction) = &self.transaction {
+            if transaction.node == Some(self.current) {
+                let node = &mut self.nodes[self.current];
+                node.change = node.change.take().map(|first| Change::merge(first, change));
+                return;
+            }
+        }
 
-        // Remove any redo history when making a new change
-        self.history.truncate(self.current);
-
-        // Add the change
-        self.history.push(change);
-        self.current += 1;
+        // Start a new branch from the current state; other branches are kept
+        let id = self.nodes.len();
+        self.nodes.push(UndoNode {
+            change: Some(change),
+            parent: Some(self.current),
+            children: Vec::new(),
+            redo_child: None,
+            seq: self.next_seq,
+            timestamp: unix_now(),
+            before: self.transaction.as_ref().map(|t| t.before.clone()),
+            after: None,
+        });
+        self.next_seq += 1;
+        let parent = &mut self.nodes[self.current];
+        parent.children.push(id);
+        parent.redo_child = Some(id);
+        self.current = id;
 
         // Limit history size
-        if self.history.len() > self.max_history {
-            let remove_count = self.history.len() - self.max_history;
-            self.history.drain(0..remove_count);
-            self.current = self.current.saturating_sub(remove_count);
+        if self.history_len() > self.max_history {
+            self.prune();
+        }
+        // Pruning renumbers the nodes; the new one is still the current one
+        if let Some(transaction) = &mut self.transaction {
+            transaction.node = Some(self.current);
+        }
+    }
+
+    /// Start grouping the edits that follow into one undo step.
+    /// `cursors` is where the cursors are before the edits. Inside another
+    /// transaction, this only nests: the edits join the outer step.
+    pub fn begin_transaction(&mut self, cursors: CursorSnapshot) {
+        self.transaction_depth += 1;
+        if self.transaction_depth > 1 {
+            return;
+        }
+        self.transaction = Some(Transaction {
+            before: cursors,
+            node: None,
+            previous_edit: self.last_edit,
+        });
+    }
+
+    /// Finish the undo step started by `begin_transaction`. `cursors` is
+    /// where the cursors are after the edits.
+    pub fn end_transaction(&mut self, cursors: CursorSnapshot) {
+        self.transaction_depth = self.transaction_depth.saturating_sub(1);
+        if self.transaction_depth > 0 {
+            return;
+        }
+        let Some(transaction) = self.transaction.take() else {
+            return;
+        };
+        let Some(id) = transaction.node.filter(|&id| id == self.current) else {
+            return;
+        };
+        self.nodes[id].after = Some(cursors);
+
+        let recent = transaction.previous_edit.is_some_and(|t| t.elapsed() <= COALESCE_WINDOW);
+        if recent {
+            self.coalesce(id);
         }
     }
 
+    /// Join a typing step into its parent if that is typing of the same kind
+    /// that ended where this one started, and nothing branched off it since
+    fn coalesce(&mut self, id: usize) {
+        let Some(parent) = self.nodes[id].parent else {
+            return;
+        };
+        let node = &self.nodes[id];
+        let previous = &self.nodes[parent];
+        let kind = node.change.as_ref().and_then(Change::typing_kind);
+        let joinable = id + 1 == self.nodes.len()
+            && previous.children == [id]
+            && kind.is_some()
+            && previous.change.as_ref().and_then(Change::typing_kind) == kind
+            && previous.after.is_some()
+            && previous.after == node.before;
+        if !joinable {
+            return;
+        }
+
+        let node = self.nodes.pop().expect("node exists");
+        let previous = &mut self.nodes[parent];
+        previous.change = previous.change.take().zip(node.change).map(|(a, b)| Change::merge(a, b));
+        previous.after = node.after;
+        previous.timestamp = node.timestamp;
+        previous.children.clear();
+        previous.redo_child = None;
+        self.current = parent;
+        self.next_seq -= 1;
+    }
+
     /// Undo the last change and return it
     pub fn undo(&mut self) -> Option<Change> {
-        if self.current == 0 {
-            return None;
-        }
+        let node = &self.nodes[self.current];
+        let (change, parent) = (node.change.as_ref()?.inverse(), node.parent?);
 
         self.in_undo_redo = true;
-        self.current -= 1;
-        let change = self.history[self.current].clone();
-        Some(change.inverse())
+        self.last_edit = None;
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some(change)
     }
 
     /// Redo the next change and return it
     pub fn redo(&mut self) -> Option<Change> {
-        if self.current >= self.history.len() {
-            return None;
-        }
+        let child = self.nodes[self.current].redo_child?;
 
         self.in_undo_redo = true;
-        let change = self.history[self.current].clone();
-        self.current += 1;
-        Some(change)
+        self.last_edit = None;
+        self.current = child;
+        self.nodes[child].change.clone()
+    }
+
+    /// Cursors to restore after an undo: where they were before the change
+    /// that was undone (the one redo would now apply)
+    pub fn cursors_before_redo(&self) -> Option<&CursorSnapshot> {
+        let child = self.nodes[self.current].redo_child?;
+        self.nodes[child].before.as_ref()
+    }
+
+    /// Cursors to restore after a redo or jump: where they were after the
+    /// change that led to the current state
+    pub fn cursors_at_current(&self) -> Option<&CursorSnapshot> {
+        self.nodes[self.current].after.as_ref()
     }
 
     /// Finish an undo/redo operation
@@ -106,32 +381,213 @@ impl UndoManager {
         self.in_undo_redo = false;
     }
 
+    /// Change the maximum number of changes kept, dropping the oldest if needed
+    pub fn set_max_history(&mut self, max_history: usize) {
+        self.max_history = max_history;
+        if self.history_len() > max_history {
+            self.prune();
+        }
+    }
+
+    /// Check the links of a tree read from disk, so a damaged file can't
+    /// send undo out of bounds or into a loop. Parents are always created
+    /// before their children, so a parent id is smaller than its child's.
+    pub fn is_consistent(&self) -> bool {
+        let len = self.nodes.len();
+        self.root < len
+            && self.current < len
+            && self.nodes.iter().enumerate().all(|(id, node)| {
+                let parent_ok = match node.parent {
+                    Some(parent) => parent < id && self.nodes[parent].children.contains(&id),
+                    None => id == self.root && node.change.is_none(),
+                };
+                parent_ok
+                    && node.children.iter().all(|&child| child < len && self.nodes[child].parent == Some(id))
+                    && node.redo_child.is_none_or(|child| node.children.contains(&child))
+            })
+    }
+
     /// Check if undo is available
     pub fn can_undo(&self) -> bool {
-        self.current > 0
+        self.current != self.root
     }
 
     /// Check if redo is available
     pub fn can_redo(&self) -> bool {
-        self.current < self.history.len()
+        self.nodes[self.current].redo_child.is_some()
     }
 
     /// Clear all history
     pub fn clear(&mut self) {
-        self.history.clear();
+        self.nodes = vec![Self::root_node()];
+        self.root = 0;
         self.current = 0;
         self.in_undo_redo = false;
+        self.transaction = None;
+        self.transaction_depth = 0;
+        self.last_edit = None;
     }
 
     /// Get the number of changes in history
     pub fn history_len(&self) -> usize {
-        self.history.len()
+        self.nodes.len() - 1
     }
 
-    /// Get the current position in history
+    /// Get the current position in history: the number of changes between
+    /// the oldest state and the current one
     pub fn current_position(&self) -> usize {
+        self.ancestors(self.current).len() - 1
+    }
+
+    /// Node id of the current state
+    pub fn current_node(&self) -> usize {
         self.current
     }
+
+    /// Move to any state in the tree. Returns the change that turns the
+    /// current text into that state's text (undoing up to the common ancestor,
+    /// then redoing down to the target), or None if there is nothing to do.
+    /// Redo from the states passed on the way down then leads to the target.
+    pub fn jump_to(&mut self, target: usize) -> Option<Change> {
+        if target >= self.nodes.len() || target == self.current {
+            return None;
+        }
+
+        let from_path = self.ancestors(self.current);
+        let to_path = self.ancestors(target);
+        let common = *from_path.iter().find(|node| to_path.contains(node))?;
+
+        let mut steps = Vec::new();
+        for &node in from_path.iter().take_while(|&&node| node != common) {
+            steps.extend(self.nodes[node].change.as_ref().map(|c| c.inverse()));
+        }
+        let down: Vec<usize> = to_path.iter().take_while(|&&node| node != common).copied().collect();
+        for &node in down.iter().rev() {
+            steps.extend(self.nodes[node].change.clone());
+            if let Some(parent) = self.nodes[node].parent {
+                self.nodes[parent].redo_child = Some(node);
+            }
+        }
+
+        self.current = target;
+        self.last_edit = None;
+        Some(Change::Compound(steps))
+    }
+
+    /// The tree flattened into rows, oldest first. The most recent branch
+    /// continues at the same depth; older branches are listed before it,
+    /// one level deeper.
+    pub fn tree_rows(&self) -> Vec<UndoTreeRow> {
+        let mut rows = Vec::with_capacity(self.nodes.len());
+        // Depth-first, without recursion: deep linear histories are common
+        let mut stack = vec![(self.root, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let node = &self.nodes[id];
+            rows.push(UndoTreeRow {
+                node: id,
+                depth,
+                seq: node.seq,
+                description: node
+                    .change
+                    .as_ref()
+                    .map(|change| match change.line() {
+                        Some(line) => format!("{} (line {})", change.describe(), line + 1),
+                        None => change.describe(),
+                    })
+                    .unwrap_or_else(|| "original".to_string()),
+                timestamp: node.timestamp,
+                is_current: id == self.current,
+            });
+
+            // Pushed last, popped first: older branches come out before the newest
+            if let Some((&newest, older)) = node.children.split_last() {
+                stack.push((newest, depth));
+                for &child in older.iter().rev() {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        rows
+    }
+
+    /// A node and its ancestors up to the root, nearest first
+    fn ancestors(&self, mut id: usize) -> Vec<usize> {
+        let mut path = vec![id];
+        while let Some(parent) = self.nodes[id].parent {
+            path.push(parent);
+            id = parent;
+        }
+        path
+    }
+
+    /// Drop the oldest history until at most `max_history` changes are left.
+    /// Branches off the root that don't lead to the current state go first
+    /// (oldest first); otherwise the root's only child becomes the new root.
+    fn prune(&mut self) {
+        let mut removed = vec![false; self.nodes.len()];
+        let mut kept = self.history_len();
+        let current_path = self.ancestors(self.current);
+
+        while kept > self.max_history {
+            let root = self.root;
+            let children: Vec<usize> = self.nodes[root].children.iter().copied().filter(|&c| !removed[c]).collect();
+            let Some(&first) = children.first() else {
+                break;
+            };
+            match children.iter().find(|child| !current_path.contains(child)) {
+                Some(&old_branch) => {
+                    let mut stack = vec![old_branch];
+                    while let Some(id) = stack.pop() {
+                        removed[id] = true;
+                        kept -= 1;
+                        stack.extend(self.nodes[id].children.iter().copied());
+                    }
+                    if self.nodes[root].redo_child == Some(old_branch) {
+                        self.nodes[root].redo_child = None;
+                    }
+                }
+                None => {
+                    removed[root] = true;
+                    kept -= 1;
+                    let new_root = &mut self.nodes[first];
+                    new_root.change = None;
+                    new_root.parent = None;
+                    self.root = first;
+                }
+            }
+        }
+
+        // Compact the node list, renumbering the links
+        let mut new_ids = vec![usize::MAX; self.nodes.len()];
+        let mut next = 0;
+        for (id, &gone) in removed.iter().enumerate() {
+            if !gone {
+                new_ids[id] = next;
+                next += 1;
+            }
+        }
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .enumerate()
+            .filter(|(id, _)| !removed[*id])
+            .map(|(_, mut node)| {
+                node.parent = node.parent.map(|p| new_ids[p]);
+                node.children = node.children.iter().filter(|&&c| !removed[c]).map(|&c| new_ids[c]).collect();
+                node.redo_child = node.redo_child.filter(|&c| !removed[c]).map(|c| new_ids[c]);
+                node
+            })
+            .collect();
+        self.root = new_ids[self.root];
+        self.current = new_ids[self.current];
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 impl Default for UndoManager {
@@ -222,4 +678,161 @@ mod tests {
         });
         assert!(!manager.can_redo());
     }
+
+    #[test]
+    fn test_branches_are_kept_and_reachable() {
+        let insert = |column: usize, text: &str| Change::Insert {
+            pos: Position::new(0, column),
+            text: text.to_string(),
+        };
+        let mut manager = UndoManager::new(100);
+        manager.record(insert(0, "A"));
+        manager.record(insert(1, "B"));
+        let b_node = manager.current_node();
+
+        // Undo B, then type C: a new branch next to B
+        manager.undo();
+        manager.finish_undo_redo();
+        manager.record(insert(1, "C"));
+
+        let rows = manager.tree_rows();
+        let descriptions: Vec<_> = rows.iter().map(|r| (r.depth, r.description.as_str())).collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                (0, "original"),
+                (0, "insert \"A\" (line 1)"),
+                (1, "insert \"B\" (line 1)"),
+                (0, "insert \"C\" (line 1)"),
+            ]
+        );
+
+        // Jumping to B undoes C and redoes B
+        let Some(Change::Compound(steps)) = manager.jump_to(b_node) else {
+            panic!("expected a compound change");
+        };
+        assert!(matches!(&steps[0], Change::Delete { text, .. } if text == "C"));
+        assert!(matches!(&steps[1], Change::Insert { text, .. } if text == "B"));
+        assert_eq!(manager.current_node(), b_node);
+
+        // Undo then redo now follows the B branch
+        manager.undo();
+        manager.finish_undo_redo();
+        assert!(matches!(manager.redo(), Some(Change::Insert { text, .. }) if text == "B"));
+    }
+
+    #[test]
+    fn test_transactions_group_edits_and_keep_cursors() {
+        let cursors = |column: usize| CursorSnapshot {
+            cursor: Position::new(0, column),
+            secondary_cursors: Vec::new(),
+            selection: None,
+        };
+        let insert = |column: usize, text: &str| Change::Insert {
+            pos: Position::new(0, column),
+            text: text.to_string(),
+        };
+        let mut manager = UndoManager::new(100);
+
+        // One command inserting at two cursors is one step
+        manager.begin_transaction(cursors(0));
+        manager.record(insert(4, "x"));
+        manager.record(insert(0, "x"));
+        manager.end_transaction(cursors(1));
+        assert_eq!(manager.history_len(), 1);
+
+        // Typing right after, where the last step left the cursor, joins it
+        manager.begin_transaction(cursors(1));
+        manager.record(insert(1, "y"));
+        manager.end_transaction(cursors(2));
+        assert_eq!(manager.history_len(), 1);
+        assert_eq!(manager.cursors_at_current(), Some(&cursors(2)));
+
+        // Typing somewhere else starts a new step
+        manager.begin_transaction(cursors(5));
+        manager.record(insert(5, "z"));
+        manager.end_transaction(cursors(6));
+        assert_eq!(manager.history_len(), 2);
+
+        // A newline is not coalesced either
+        manager.begin_transaction(cursors(6));
+        manager.record(insert(6, "\n"));
+        manager.end_transaction(cursors(0));
+        assert_eq!(manager.history_len(), 3);
+
+        manager.undo();
+        manager.undo();
+        assert_eq!(manager.cursors_before_redo(), Some(&cursors(5)));
+        let Some(Change::Compound(steps)) = manager.undo() else {
+            panic!("expected the grouped step");
+        };
+        assert_eq!(steps.len(), 3);
+        assert_eq!(manager.cursors_before_redo(), Some(&cursors(0)));
+        manager.finish_undo_redo();
+    }
+
+    #[test]
+    fn test_nested_transactions_join_the_outer_step() {
+        let cursors = |column: usize| CursorSnapshot {
+            cursor: Position::new(0, column),
+            secondary_cursors: Vec::new(),
+            selection: None,
+        };
+        let mut manager = UndoManager::new(100);
+
+        manager.begin_transaction(cursors(0));
+        manager.record(Change::Insert { pos: Position::new(0, 0), text: "a\n".to_string() });
+        manager.begin_transaction(cursors(7));
+        manager.record(Change::Insert { pos: Position::new(1, 0), text: "b\n".to_string() });
+        manager.end_transaction(cursors(8));
+        manager.record(Change::Insert { pos: Position::new(2, 0), text: "c\n".to_string() });
+        manager.end_transaction(cursors(2));
+
+        assert_eq!(manager.history_len(), 1);
+        assert_eq!(manager.cursors_at_current(), Some(&cursors(2)));
+        let Some(Change::Compound(steps)) = manager.undo() else {
+            panic!("expected the grouped step");
+        };
+        assert_eq!(steps.len(), 3);
+        assert_eq!(manager.cursors_before_redo(), Some(&cursors(0)));
+        manager.finish_undo_redo();
+    }
+
+    #[test]
+    fn test_serialized_tree_round_trips() {
+        let mut manager = UndoManager::new(100);
+        manager.record(Change::Insert { pos: Position::new(0, 0), text: "A".to_string() });
+        manager.record(Change::Delete { pos: Position::new(0, 0), text: "A".to_string() });
+        manager.undo();
+        manager.finish_undo_redo();
+
+        let json = serde_json::to_string(&manager).unwrap();
+        let mut restored: UndoManager = serde_json::from_str(&json).unwrap();
+        restored.set_max_history(100);
+        assert!(restored.is_consistent());
+        assert_eq!(restored.current_position(), 1);
+        assert!(matches!(restored.redo(), Some(Change::Delete { text, .. }) if text == "A"));
+
+        // A parent link pointing forward would make a loop
+        let damaged = json.replace("\"parent\":0", "\"parent\":2");
+        let damaged: UndoManager = serde_json::from_str(&damaged).unwrap();
+        assert!(!damaged.is_consistent());
+    }
+
+    #[test]
+    fn test_pruning_drops_old_branches_first() {
+        let mut manager = UndoManager::new(3);
+        manager.record(Change::Insert { pos: Position::new(0, 0), text: "A".to_string() });
+        manager.record(Change::Insert { pos: Position::new(0, 1), text: "B".to_string() });
+        manager.undo();
+        manager.undo();
+        manager.finish_undo_redo();
+        manager.record(Change::Insert { pos: Position::new(0, 0), text: "C".to_string() });
+        manager.record(Change::Insert { pos: Position::new(0, 1), text: "D".to_string() });
+
+        // The A-B branch goes as a whole, C-D stays
+        assert_eq!(manager.history_len(), 2);
+        assert_eq!(manager.current_position(), 2);
+        assert!(matches!(manager.undo(), Some(Change::Delete { text, .. }) if text == "D"));
+    }
 }
@@ -1,6 +1,8 @@
+use crate::buffer::atomic_write;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 /// AI completion configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -138,17 +140,225 @@ impl Default for LocalLlmConfig {
     }
 }
 
+/// Local file history configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// Record a snapshot of every save
+    #[serde(default = "default_history_enabled")]
+    pub enabled: bool,
+
+    /// Maximum number of snapshots kept per file
+    #[serde(default = "default_history_max_snapshots")]
+    pub max_snapshots: usize,
+
+    /// Snapshots older than this many days are removed
+    #[serde(default = "default_history_max_age_days")]
+    pub max_age_days: u64,
+}
+
+fn default_history_enabled() -> bool {
+    true
+}
+
+fn default_history_max_snapshots() -> usize {
+    50
+}
+
+fn default_history_max_age_days() -> u64 {
+    30
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_history_enabled(),
+            max_snapshots: default_history_max_snapshots(),
+            max_age_days: default_history_max_age_days(),
+        }
+    }
+}
+
+/// Large-file mode configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LargeFileConfig {
+    /// Files bigger than this (in megabytes) open in large-file mode
+    #[serde(default = "default_large_file_size_mb")]
+    pub size_threshold_mb: u64,
+
+    /// Files with a line longer than this (in characters) open in large-file mode
+    #[serde(default = "default_large_file_line_length")]
+    pub line_length_threshold: usize,
+}
+
+fn default_large_file_size_mb() -> u64 {
+    20
+}
+
+fn default_large_file_line_length() -> usize {
+    10_000
+}
+
+impl LargeFileConfig {
+    /// Size threshold in bytes
+    pub fn size_threshold_bytes(&self) -> u64 {
+        self.size_threshold_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+impl Default for LargeFileConfig {
+    fn default() -> Self {
+        Self {
+            size_threshold_mb: default_large_file_size_mb(),
+            line_length_threshold: default_large_file_line_length(),
+        }
+    }
+}
+
+/// Language detection configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LanguagesConfig {
+    /// File globs and the language of the files they match, e.g.
+    /// `"*.tmpl" = "html"`; these win over every other way of detecting
+    #[serde(default)]
+    pub mappings: BTreeMap<String, String>,
+}
+
+/// Persistent undo configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoConfig {
+    /// Save the undo history of a file when its buffer closes or the session
+    /// is saved, and restore it when the unchanged file is opened again
+    #[serde(default = "default_undo_persist")]
+    pub persist: bool,
+
+    /// Maximum number of changes kept per buffer
+    #[serde(default = "default_undo_max_changes")]
+    pub max_changes: usize,
+
+    /// Maximum size (in kilobytes) of a saved history; the oldest changes
+    /// are dropped until it fits
+    #[serde(default = "default_undo_max_file_size_kb")]
+    pub max_file_size_kb: u64,
+}
+
+fn default_undo_persist() -> bool {
+    true
+}
+
+fn default_undo_max_changes() -> usize {
+    1000
+}
+
+fn default_undo_max_file_size_kb() -> u64 {
+    1024
+}
+
+impl Default for UndoConfig {
+    fn default() -> Self {
+        Self {
+            persist: default_undo_persist(),
+            max_changes: default_undo_max_changes(),
+            max_file_size_kb: default_undo_max_file_size_kb(),
+        }
+    }
+}
+
+/// Appearance configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiConfig {
+    /// Color theme: "dark", "light" or the name of a file in ~/.scame/themes
+    #[serde(default = "default_theme")]
+    pub theme: String,
+
+    /// Colors the terminal can show: "auto" (detect from COLORTERM, TERM
+    /// and terminfo), "truecolor", "256" or "16"
+    #[serde(default = "default_color_depth")]
+    pub color_depth: String,
+
+    /// Columns between tab stops, for files whose EditorConfig or tab
+    /// indentation doesn't set a width
+    #[serde(default = "default_tab_width")]
+    pub tab_width: usize,
+}
+
+fn default_theme() -> String {
+    "dark".to_string()
+}
+
+fn default_color_depth() -> String {
+    "auto".to_string()
+}
+
+fn default_tab_width() -> usize {
+    4
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            theme: default_theme(),
+            color_depth: default_color_depth(),
+            tab_width: default_tab_width(),
+        }
+    }
+}
+
+/// Language server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LspConfig {
+    /// Milliseconds the cursor must rest on a symbol before its hover
+    /// documentation shows by itself; 0 shows it only on request (F1)
+    #[serde(default = "default_hover_delay_ms")]
+    pub hover_delay_ms: u64,
+}
+
+fn default_hover_delay_ms() -> u64 {
+    0
+}
+
+impl Default for LspConfig {
+    fn default() -> Self {
+        Self {
+            hover_delay_ms: default_hover_delay_ms(),
+        }
+    }
+}
+
 /// Main configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub ai: AiConfig,
+
+    #[serde(default)]
+    pub history: HistoryConfig,
+
+    #[serde(default)]
+    pub large_file: LargeFileConfig,
+
+    #[serde(default)]
+    pub undo: UndoConfig,
+
+    #[serde(default)]
+    pub languages: LanguagesConfig,
+
+    #[serde(default)]
+    pub ui: UiConfig,
+
+    #[serde(default)]
+    pub lsp: LspConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             ai: AiConfig::default(),
+            history: HistoryConfig::default(),
+            large_file: LargeFileConfig::default(),
+            undo: UndoConfig::default(),
+            languages: LanguagesConfig::default(),
+            ui: UiConfig::default(),
+            lsp: LspConfig::default(),
         }
     }
 }
@@ -205,6 +415,30 @@ impl Config {
         Ok(config_dir.join("config.toml"))
     }
 
+    /// Remember the chosen theme in the configuration file. Only `ui.theme`
+    /// is written: the rest of the file stays as the user wrote it (and keys
+    /// taken from the environment stay out of it).
+    pub fn save_theme(name: &str) -> Result<()> {
+        Self::save_theme_to(&Self::config_path()?, name)
+    }
+
+    fn save_theme_to(config_path: &Path, name: &str) -> Result<()> {
+        // Edit the document in place so comments and key order survive
+        let mut document: toml_edit::DocumentMut = match std::fs::read_to_string(config_path) {
+            Ok(content) => content.parse()?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => toml_edit::DocumentMut::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let ui = document
+            .entry("ui")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow::anyhow!("'ui' in {} is not a table", config_path.display()))?;
+        ui.insert("theme", toml_edit::value(name));
+        atomic_write(config_path, document.to_string().as_bytes())?;
+        Ok(())
+    }
+
     /// Save configuration to file
     pub fn save(&self) -> Result<()> {
         let config_path = Self::config_path()?;
@@ -213,3 +447,34 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_theme_keeps_other_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[undo]\npersist = false\n").unwrap();
+
+        Config::save_theme_to(&path, "light").unwrap();
+
+        let config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config.ui.theme, "light");
+        assert!(!config.undo.persist);
+        assert!(config.ai.claude.api_key.is_none());
+    }
+
+    #[test]
+    fn test_save_theme_keeps_comments_and_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "# My settings\n[ui]\nline_numbers = true # keep\ntheme = \"dark\"\n\n[undo]\npersist = false\n";
+        std::fs::write(&path, original).unwrap();
+
+        Config::save_theme_to(&path, "light").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original.replace("\"dark\"", "\"light\""));
+    }
+}
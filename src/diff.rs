@@ -1,6 +1,8 @@
 use anyhow::Result;
+use crate::syntax::Theme;
 use crossterm::style::Color;
 use similar::{ChangeTag, TextDiff};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 /// Represents a line in the diff view with its change type
@@ -21,33 +23,50 @@ pub enum ChangeType {
 }
 
 impl ChangeType {
-    pub fn color(&self) -> Color {
+    /// Text color of a line with this change
+    pub fn color(&self, theme: &Theme) -> Color {
         match self {
-            ChangeType::Unchanged => Color::Reset,
-            ChangeType::Added => Color::Rgb { r: 34, g: 139, b: 34 },    // Green
-            ChangeType::Deleted => Color::Rgb { r: 220, g: 38, b: 38 },  // Red
-            ChangeType::Modified => Color::Rgb { r: 255, g: 191, b: 0 }, // Yellow/Amber
+            ChangeType::Unchanged => theme.ui.foreground,
+            ChangeType::Added => theme.ui.diff_added,
+            ChangeType::Deleted => theme.ui.diff_deleted,
+            ChangeType::Modified => theme.ui.diff_modified,
         }
     }
 
-    pub fn bg_color(&self) -> Color {
+    /// Background color of a line with this change
+    pub fn bg_color(&self, theme: &Theme) -> Color {
         match self {
-            ChangeType::Unchanged => Color::Reset,
-            ChangeType::Added => Color::Rgb { r: 0, g: 64, b: 0 },     // Dark green
-            ChangeType::Deleted => Color::Rgb { r: 64, g: 0, b: 0 },   // Dark red
-            ChangeType::Modified => Color::Rgb { r: 64, g: 48, b: 0 }, // Dark yellow
+            ChangeType::Unchanged => theme.ui.background,
+            ChangeType::Added => theme.ui.diff_added_bg,
+            ChangeType::Deleted => theme.ui.diff_deleted_bg,
+            ChangeType::Modified => theme.ui.diff_modified_bg,
         }
     }
 }
 
+/// A run of consecutive changed lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    /// Diff rows covered by the hunk
+    pub rows: Range<usize>,
+    /// Lines (0-based) of the left content
+    pub old_lines: Range<usize>,
+    /// Lines (0-based) of the right content
+    pub new_lines: Range<usize>,
+}
+
 /// Represents the diff between two files
 pub struct DiffView {
     pub left_path: PathBuf,
     pub right_path: PathBuf,
+    pub left_label: String,
+    pub right_label: String,
     pub left_lines: Vec<DiffLine>,
     pub right_lines: Vec<DiffLine>,
     pub left_content: String,
     pub right_content: String,
+    pub hunks: Vec<DiffHunk>,
+    pub selected_hunk: Option<usize>,
     pub scroll_offset: usize,
 }
 
@@ -57,22 +76,50 @@ impl DiffView {
         let left_content = std::fs::read_to_string(&left_path)?;
         let right_content = std::fs::read_to_string(&right_path)?;
 
-        let (left_lines, right_lines) = Self::compute_diff(&left_content, &right_content);
+        Ok(Self::from_contents(left_path, right_path, left_content, right_content))
+    }
+
+    /// Create a diff view from in-memory contents (e.g. a buffer against disk)
+    pub fn from_contents(left_path: PathBuf, right_path: PathBuf, left_content: String, right_content: String) -> Self {
+        let (left_lines, right_lines, hunks) = Self::compute_diff(&left_content, &right_content);
+        let left_label = Self::file_label(&left_path);
+        let right_label = Self::file_label(&right_path);
 
-        Ok(Self {
+        Self {
             left_path,
             right_path,
+            left_label,
+            right_label,
             left_lines,
             right_lines,
             left_content,
             right_content,
+            hunks,
+            selected_hunk: None,
             scroll_offset: 0,
-        })
+        }
+    }
+
+    /// Override the pane titles shown in the header
+    pub fn with_labels(mut self, left_label: impl Into<String>, right_label: impl Into<String>) -> Self {
+        self.left_label = left_label.into();
+        self.right_label = right_label.into();
+        self
+    }
+
+    fn file_label(path: &Path) -> String {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("(unknown)")
+            .to_string()
     }
 
     /// Get the file extension
     pub fn file_extension(&self) -> Option<&str> {
-        self.left_path.extension().and_then(|e| e.to_str())
+        self.left_path
+            .extension()
+            .or_else(|| self.right_path.extension())
+            .and_then(|e| e.to_str())
     }
 
     /// Check if syntax highlighting is supported for this file type
@@ -80,18 +127,37 @@ impl DiffView {
         matches!(self.file_extension(), Some("py") | Some("pyw") | Some("pyi") | Some("rs"))
     }
 
-    /// Compute the diff between two strings and return paired lines
-    fn compute_diff(left: &str, right: &str) -> (Vec<DiffLine>, Vec<DiffLine>) {
+    /// Compute the diff between two strings and return paired lines and hunks
+    fn compute_diff(left: &str, right: &str) -> (Vec<DiffLine>, Vec<DiffLine>, Vec<DiffHunk>) {
         let diff = TextDiff::from_lines(left, right);
 
         let mut left_lines = Vec::new();
         let mut right_lines = Vec::new();
+        let mut hunks: Vec<DiffHunk> = Vec::new();
         let mut old_line_num = 1;
         let mut new_line_num = 1;
 
         for change in diff.iter_all_changes() {
             let content = change.value().trim_end_matches(&['\n', '\r'][..]).to_string();
 
+            // Group consecutive changed lines into hunks
+            if change.tag() != ChangeTag::Equal {
+                let row = left_lines.len();
+                match hunks.last_mut() {
+                    Some(hunk) if hunk.rows.end == row => hunk.rows.end = row + 1,
+                    _ => hunks.push(DiffHunk {
+                        rows: row..row + 1,
+                        old_lines: old_line_num - 1..old_line_num - 1,
+                        new_lines: new_line_num - 1..new_line_num - 1,
+                    }),
+                }
+                let hunk = hunks.last_mut().expect("hunk was just pushed");
+                match change.tag() {
+                    ChangeTag::Delete => hunk.old_lines.end += 1,
+                    _ => hunk.new_lines.end += 1,
+                }
+            }
+
             match change.tag() {
                 ChangeTag::Equal => {
                     // Unchanged line - appears in both sides
@@ -147,7 +213,59 @@ impl DiffView {
             }
         }
 
-        (left_lines, right_lines)
+        (left_lines, right_lines, hunks)
+    }
+
+    /// Select the next hunk (wrapping around) and scroll it into view
+    pub fn select_next_hunk(&mut self, max_visible: usize) {
+        if self.hunks.is_empty() {
+            return;
+        }
+        let next = match self.selected_hunk {
+            Some(index) => (index + 1) % self.hunks.len(),
+            None => self
+                .hunks
+                .iter()
+                .position(|h| h.rows.start >= self.scroll_offset)
+                .unwrap_or(0),
+        };
+        self.select_hunk(next, max_visible);
+    }
+
+    /// Select the previous hunk (wrapping around) and scroll it into view
+    pub fn select_previous_hunk(&mut self, max_visible: usize) {
+        if self.hunks.is_empty() {
+            return;
+        }
+        let previous = match self.selected_hunk {
+            Some(0) | None => self.hunks.len() - 1,
+            Some(index) => index - 1,
+        };
+        self.select_hunk(previous, max_visible);
+    }
+
+    /// Select a hunk by index, scrolling it into view if needed
+    pub fn select_hunk(&mut self, index: usize, max_visible: usize) {
+        self.selected_hunk = Some(index);
+        let rows = &self.hunks[index].rows;
+        if rows.start < self.scroll_offset || rows.end > self.scroll_offset + max_visible {
+            let max_scroll = self.left_lines.len().saturating_sub(max_visible);
+            self.scroll_offset = rows.start.saturating_sub(2).min(max_scroll);
+        }
+    }
+
+    /// Get the currently selected hunk
+    pub fn current_hunk(&self) -> Option<&DiffHunk> {
+        self.selected_hunk.and_then(|index| self.hunks.get(index))
+    }
+
+    /// Left-side text of a hunk, with line endings as in the left content
+    pub fn left_hunk_text(&self, hunk: &DiffHunk) -> String {
+        self.left_content
+            .split_inclusive('\n')
+            .skip(hunk.old_lines.start)
+            .take(hunk.old_lines.len())
+            .collect()
     }
 
     /// Scroll up by n lines
@@ -169,3 +287,25 @@ impl DiffView {
         (left, right)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hunks_group_consecutive_changes() {
+        let left = "a\nb\nc\nd\ne\n";
+        let right = "a\nB\nc\nd\nx\ny\n";
+        let view = DiffView::from_contents(PathBuf::from("l"), PathBuf::from("r"), left.into(), right.into());
+
+        assert_eq!(view.hunks.len(), 2);
+        assert_eq!(view.hunks[0].old_lines, 1..2);
+        assert_eq!(view.hunks[0].new_lines, 1..2);
+        assert_eq!(view.hunks[1].old_lines, 4..5);
+        assert_eq!(view.hunks[1].new_lines, 4..6);
+        assert_eq!(view.left_hunk_text(&view.hunks[1]), "e\n");
+        for hunk in &view.hunks {
+            assert_eq!(hunk.rows.len(), hunk.old_lines.len() + hunk.new_lines.len());
+        }
+    }
+}
@@ -1,14 +1,15 @@
-use crate::buffer::TextBuffer;
+use crate::buffer::{Position, TextBuffer};
 use crate::editor::EditorState;
 
 /// Cursor movement operations
 pub struct Movement;
 
 impl Movement {
-    /// Move cursor left
+    /// Move cursor left by one grapheme cluster
     pub fn move_left(state: &mut EditorState, buffer: &TextBuffer) {
         if state.cursor.column > 0 {
-            state.cursor.move_horizontal(state.cursor.column - 1);
+            let column = buffer.prev_grapheme_column(state.cursor.position());
+            state.cursor.move_horizontal(column);
         } else if state.cursor.line > 0 {
             // Move to end of previous line
             state.cursor.line -= 1;
@@ -18,11 +19,12 @@ impl Movement {
         state.ensure_cursor_visible();
     }
 
-    /// Move cursor right
+    /// Move cursor right by one grapheme cluster
     pub fn move_right(state: &mut EditorState, buffer: &TextBuffer) {
         let line_len = buffer.line_len(state.cursor.line);
         if state.cursor.column < line_len {
-            state.cursor.move_horizontal(state.cursor.column + 1);
+            let column = buffer.next_grapheme_column(state.cursor.position());
+            state.cursor.move_horizontal(column);
         } else if state.cursor.line + 1 < buffer.len_lines() {
             // Move to start of next line
             state.cursor.line += 1;
@@ -34,9 +36,7 @@ impl Movement {
     /// Move cursor up
     pub fn move_up(state: &mut EditorState, buffer: &TextBuffer) {
         if state.cursor.line > 0 {
-            let new_line = state.cursor.line - 1;
-            let line_len = buffer.line_len(new_line);
-            state.cursor.move_vertical(new_line, line_len);
+            Self::move_vertical(state, buffer, state.cursor.line - 1);
         }
         state.ensure_cursor_visible();
     }
@@ -44,13 +44,31 @@ impl Movement {
     /// Move cursor down
     pub fn move_down(state: &mut EditorState, buffer: &TextBuffer) {
         if state.cursor.line + 1 < buffer.len_lines() {
-            let new_line = state.cursor.line + 1;
-            let line_len = buffer.line_len(new_line);
-            state.cursor.move_vertical(new_line, line_len);
+            Self::move_vertical(state, buffer, state.cursor.line + 1);
         }
         state.ensure_cursor_visible();
     }
 
+    /// Screen column the cursor aims for when moving to another line: where
+    /// it is on screen, plus however far past the end of a shorter line it
+    /// was meant to be
+    pub fn goal_display_column(state: &EditorState, buffer: &TextBuffer) -> usize {
+        let cursor = &state.cursor;
+        let overshoot = cursor.desired_column.saturating_sub(cursor.column);
+        buffer.display_column(cursor.position(), state.tab_width) + overshoot
+    }
+
+    /// Move the cursor to the character of `line` at the goal screen column,
+    /// remembering how far short of it the cursor ended for the next move
+    fn move_vertical(state: &mut EditorState, buffer: &TextBuffer, line: usize) {
+        let goal = Self::goal_display_column(state, buffer);
+        let column = buffer.column_at_display(line, goal, state.tab_width);
+        let reached = buffer.display_column(Position::new(line, column), state.tab_width);
+        state.cursor.line = line;
+        state.cursor.column = column;
+        state.cursor.desired_column = column + goal.saturating_sub(reached);
+    }
+
     /// Move to start of line
     pub fn move_to_line_start(state: &mut EditorState) {
         state.cursor.move_horizontal(0);
@@ -81,8 +99,8 @@ impl Movement {
     /// Jump to a specific line
     pub fn jump_to_line(state: &mut EditorState, buffer: &TextBuffer, line: usize) {
         let line = line.min(buffer.len_lines().saturating_sub(1));
-        let line_len = buffer.line_len(line);
-        let column = state.cursor.desired_column.min(line_len);
+        let goal = Self::goal_display_column(state, buffer);
+        let column = buffer.column_at_display(line, goal, state.tab_width);
         state.cursor.move_to(line, column);
         state.viewport.center_on_line(line);
     }
@@ -91,8 +109,7 @@ impl Movement {
     pub fn page_up(state: &mut EditorState, buffer: &TextBuffer) {
         let page_size = state.viewport.height as usize;
         let new_line = state.cursor.line.saturating_sub(page_size);
-        let line_len = buffer.line_len(new_line);
-        state.cursor.move_vertical(new_line, line_len);
+        Self::move_vertical(state, buffer, new_line);
         state.viewport.scroll_up(page_size);
     }
 
@@ -101,8 +118,7 @@ impl Movement {
         let page_size = state.viewport.height as usize;
         let max_lines = buffer.len_lines();
         let new_line = (state.cursor.line + page_size).min(max_lines.saturating_sub(1));
-        let line_len = buffer.line_len(new_line);
-        state.cursor.move_vertical(new_line, line_len);
+        Self::move_vertical(state, buffer, new_line);
         state.viewport.scroll_down(page_size, max_lines);
     }
 
@@ -313,3 +329,43 @@ impl Movement {
         state.ensure_cursor_visible();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_with(text: &str) -> TextBuffer {
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), text).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_vertical_movement_keeps_screen_column() {
+        // Tab stops every 4 columns; "漢" takes two screen columns
+        let buffer = buffer_with("abcdefgh\n\tx\n漢字ab\nab\nabcdefgh");
+        let mut state = EditorState::new(80, 24);
+        state.cursor.move_to(0, 5);
+
+        Movement::move_down(&mut state, &buffer);
+        assert_eq!((state.cursor.line, state.cursor.column), (1, 2)); // After the "x" at column 5
+        Movement::move_down(&mut state, &buffer);
+        assert_eq!(state.cursor.column, 3); // The "b" at screen column 5
+        Movement::move_down(&mut state, &buffer);
+        assert_eq!(state.cursor.column, 2); // Clamped to the shorter line
+        Movement::move_down(&mut state, &buffer);
+        assert_eq!(state.cursor.column, 5); // Restored
+    }
+
+    #[test]
+    fn test_horizontal_movement_by_grapheme() {
+        let buffer = buffer_with("e\u{301}👍🏽x");
+        let mut state = EditorState::new(80, 24);
+        Movement::move_right(&mut state, &buffer);
+        assert_eq!(state.cursor.column, 2);
+        Movement::move_right(&mut state, &buffer);
+        assert_eq!(state.cursor.column, 4);
+        Movement::move_left(&mut state, &buffer);
+        assert_eq!(state.cursor.column, 2);
+    }
+}
@@ -1,11 +1,12 @@
-use crate::buffer::Position;
+use crate::buffer::{CursorSnapshot, Indentation, Position, TextBuffer};
 
 /// Cursor state in the editor
 #[derive(Debug, Clone, Copy)]
 pub struct Cursor {
     pub line: usize,
     pub column: usize,
-    /// Desired column for vertical movement (preserves column when moving through shorter lines)
+    /// Desired column for vertical movement (preserves column when moving
+    /// through shorter lines). Any excess over `column` is in screen columns.
     pub desired_column: usize,
 }
 
@@ -42,12 +43,6 @@ impl Cursor {
         self.desired_column = column;
     }
 
-    /// Move vertically while preserving desired column
-    pub fn move_vertical(&mut self, line: usize, max_column: usize) {
-        self.line = line;
-        self.column = self.desired_column.min(max_column);
-    }
-
     /// Move horizontally and update desired column
     pub fn move_horizontal(&mut self, column: usize) {
         self.column = column;
@@ -103,7 +98,7 @@ pub struct Viewport {
     pub height: u16,
     /// Top visible line
     pub top_line: usize,
-    /// Left visible column (for horizontal scrolling)
+    /// Left visible screen column (for horizontal scrolling)
     pub left_column: usize,
 }
 
@@ -136,11 +131,9 @@ impl Viewport {
         }
     }
 
-    /// Scroll to make a column visible (horizontal scrolling)
-    pub fn scroll_to_column(&mut self, column: usize, line_number_width: usize) {
-        // Account for line numbers taking up space
-        let visible_width = (self.width as usize).saturating_sub(line_number_width);
-
+    /// Scroll horizontally to make a screen column visible in a text area
+    /// `visible_width` columns wide
+    pub fn scroll_to_column(&mut self, column: usize, visible_width: usize) {
         // Add some margin (keep cursor a bit away from edge)
         let margin = 5;
         let effective_width = visible_width.saturating_sub(margin);
@@ -199,6 +192,12 @@ pub struct EditorState {
     pub selection: Option<Selection>,
     pub viewport: Viewport,
     pub mode: EditorMode,
+    /// Indentation of the buffer, for indent guides and the status bar
+    pub indentation: Indentation,
+    /// Column where the max line length ruler is drawn
+    pub ruler_column: Option<usize>,
+    /// Columns between tab stops
+    pub tab_width: usize,
 }
 
 impl EditorState {
@@ -209,6 +208,9 @@ impl EditorState {
             selection: None,
             viewport: Viewport::new(viewport_width, viewport_height),
             mode: EditorMode::Normal,
+            indentation: Indentation::default(),
+            ruler_column: None,
+            tab_width: 4,
         }
     }
 
@@ -256,17 +258,38 @@ impl EditorState {
         self.selection.is_some() && !self.selection.as_ref().unwrap().is_empty()
     }
 
-    /// Ensure cursor is visible in viewport
-    pub fn ensure_cursor_visible(&mut self) {
-        self.ensure_cursor_visible_with_line_numbers(0);
+    /// Save the cursors and selection, for undo
+    pub fn cursor_snapshot(&self) -> CursorSnapshot {
+        CursorSnapshot {
+            cursor: self.cursor.position(),
+            secondary_cursors: self.secondary_cursors.iter().map(|c| c.position()).collect(),
+            selection: self.selection.map(|s| (s.anchor, s.head)),
+        }
     }
 
-    /// Ensure cursor is visible in viewport with horizontal scrolling support
-    pub fn ensure_cursor_visible_with_line_numbers(&mut self, line_number_width: usize) {
-        // Vertical scrolling
+    /// Put back cursors and selection saved by `cursor_snapshot`
+    pub fn restore_cursor_snapshot(&mut self, snapshot: &CursorSnapshot) {
+        self.cursor.set_position(snapshot.cursor);
+        self.secondary_cursors = snapshot
+            .secondary_cursors
+            .iter()
+            .map(|pos| Cursor::new(pos.line, pos.column))
+            .collect();
+        self.selection = snapshot.selection.map(|(anchor, head)| Selection::new(anchor, head));
+    }
+
+    /// Ensure cursor line is visible in viewport. Horizontal scrolling
+    /// depends on the width of the text area and is done when rendering,
+    /// with `scroll_to_cursor_column`.
+    pub fn ensure_cursor_visible(&mut self) {
         self.viewport.scroll_to_line(self.cursor.line);
-        // Horizontal scrolling
-        self.viewport.scroll_to_column(self.cursor.column, line_number_width);
+    }
+
+    /// Scroll horizontally so the cursor is visible in a text area
+    /// `visible_width` columns wide
+    pub fn scroll_to_cursor_column(&mut self, buffer: &TextBuffer, visible_width: usize) {
+        let column = buffer.display_column(self.cursor.position(), self.tab_width);
+        self.viewport.scroll_to_column(column, visible_width);
     }
 }
 
@@ -283,18 +306,6 @@ mod tests {
         assert_eq!(cursor.desired_column, 10);
     }
 
-    #[test]
-    fn test_cursor_vertical_movement() {
-        let mut cursor = Cursor::new(0, 10);
-        cursor.move_vertical(1, 5); // Move to shorter line
-        assert_eq!(cursor.line, 1);
-        assert_eq!(cursor.column, 5); // Clamped to max
-        assert_eq!(cursor.desired_column, 10); // Preserved
-
-        cursor.move_vertical(2, 20); // Move to longer line
-        assert_eq!(cursor.column, 10); // Restored to desired
-    }
-
     #[test]
     fn test_selection_range() {
         let sel = Selection::new(Position::new(5, 10), Position::new(3, 5));
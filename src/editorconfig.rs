@@ -0,0 +1,403 @@
+use crate::buffer::{FileEncoding, LineEnding};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Indentation used when indent_style/indent_size are not set
+const DEFAULT_INDENT_SIZE: usize = 4;
+
+/// Value of the `indent_style` property
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+/// The `.editorconfig` properties that apply to one file. Unset properties are None.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditorConfig {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub charset: Option<FileEncoding>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+    pub max_line_length: Option<usize>,
+}
+
+impl EditorConfig {
+    /// Resolve the settings for a file from the `.editorconfig` files in its
+    /// directory and the ones above, up to the project root or a file marked
+    /// `root = true`. Closer files override those further up.
+    pub fn resolve(path: &Path, project_root: Option<&Path>) -> Self {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let project_root = project_root.and_then(|root| std::path::absolute(root).ok());
+
+        let mut files = Vec::new();
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if let Ok(content) = std::fs::read_to_string(current.join(".editorconfig")) {
+                let file = ConfigFile::parse(&content);
+                let is_root = file.root;
+                files.push((current.to_path_buf(), file));
+                if is_root {
+                    break;
+                }
+            }
+            if project_root.as_deref() == Some(current) {
+                break;
+            }
+            dir = current.parent();
+        }
+
+        let mut properties = HashMap::new();
+        for (dir, file) in files.iter().rev() {
+            file.apply(dir, &path, &mut properties);
+        }
+        Self::from_properties(&properties)
+    }
+
+    /// Build the settings from raw `key = value` pairs, ignoring invalid values
+    fn from_properties(properties: &HashMap<String, String>) -> Self {
+        let get = |key: &str| properties.get(key).map(|value| value.as_str());
+        let number = |key: &str| get(key).and_then(|value| value.parse::<usize>().ok()).filter(|&n| n > 0);
+        let flag = |key: &str| match get(key) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        };
+
+        let indent_style = match get("indent_style") {
+            Some("tab") => Some(IndentStyle::Tab),
+            Some("space") => Some(IndentStyle::Space),
+            _ => None,
+        };
+        let tab_width = number("tab_width");
+        // indent_size = tab (implied by indent_style = tab) means one tab_width
+        let indent_size = match get("indent_size") {
+            Some("tab") => tab_width,
+            None if indent_style == Some(IndentStyle::Tab) => tab_width,
+            _ => number("indent_size"),
+        };
+        let end_of_line = match get("end_of_line") {
+            Some("lf") => Some(LineEnding::LF),
+            Some("crlf") => Some(LineEnding::CRLF),
+            Some("cr") => Some(LineEnding::CR),
+            _ => None,
+        };
+
+        Self {
+            indent_style,
+            indent_size,
+            tab_width,
+            end_of_line,
+            charset: get("charset").and_then(FileEncoding::for_label),
+            trim_trailing_whitespace: flag("trim_trailing_whitespace"),
+            insert_final_newline: flag("insert_final_newline"),
+            // "off" doesn't parse as a number and leaves it unset
+            max_line_length: number("max_line_length"),
+        }
+    }
+
+    /// Width of one indentation level in columns
+    pub fn indent_width(&self) -> usize {
+        self.indent_size.or(self.tab_width).unwrap_or(DEFAULT_INDENT_SIZE)
+    }
+
+    /// Apply the save-time transforms (trim_trailing_whitespace, end_of_line,
+    /// insert_final_newline) to a buffer's text. Returns None if nothing changes.
+    pub fn transform_for_save(&self, text: &str) -> Option<String> {
+        let trim = self.trim_trailing_whitespace == Some(true);
+        if !trim && self.end_of_line.is_none() && self.insert_final_newline.is_none() {
+            return None;
+        }
+
+        let mut result = String::with_capacity(text.len() + 2);
+        for (content, ending) in split_lines(text) {
+            result.push_str(if trim { content.trim_end_matches([' ', '\t']) } else { content });
+            match self.end_of_line {
+                Some(eol) if !ending.is_empty() => result.push_str(eol.as_str()),
+                _ => result.push_str(ending),
+            }
+        }
+
+        match self.insert_final_newline {
+            Some(true) if !result.is_empty() && !result.ends_with(['\n', '\r']) => {
+                let eol = self.end_of_line.unwrap_or_else(|| LineEnding::detect(text));
+                result.push_str(eol.as_str());
+            }
+            Some(false) => result.truncate(result.trim_end_matches(['\n', '\r']).len()),
+            _ => {}
+        }
+
+        (result != text).then_some(result)
+    }
+}
+
+/// Split text into (content, line ending) pairs, recognising \n, \r\n and \r.
+/// The last line's ending is empty when the text doesn't end with a newline.
+fn split_lines(text: &str) -> Vec<(&str, &str)> {
+    let mut lines = Vec::new();
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let ending_len = match bytes[i] {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => 2,
+            b'\r' | b'\n' => 1,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        lines.push((&text[start..i], &text[i..i + ending_len]));
+        i += ending_len;
+        start = i;
+    }
+    if start < text.len() {
+        lines.push((&text[start..], ""));
+    }
+    lines
+}
+
+/// One parsed `.editorconfig` file
+struct ConfigFile {
+    /// `root = true`: stop looking in parent directories
+    root: bool,
+    /// Sections in file order: glob and its properties
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl ConfigFile {
+    fn parse(content: &str) -> Self {
+        let mut root = false;
+        let mut sections: Vec<(String, Vec<(String, String)>)> = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(glob) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                sections.push((glob.to_string(), Vec::new()));
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim().to_ascii_lowercase();
+            match sections.last_mut() {
+                Some((_, properties)) => properties.push((key, value)),
+                // Only `root` is meaningful before the first section
+                None if key == "root" => root = value == "true",
+                None => {}
+            }
+        }
+
+        Self { root, sections }
+    }
+
+    /// Apply the properties of every section matching `path` (a file below `dir`)
+    fn apply(&self, dir: &Path, path: &Path, properties: &mut HashMap<String, String>) {
+        let Some(relative) = relative_path(dir, path) else {
+            return;
+        };
+        for (glob, section) in &self.sections {
+            if !section_matches(glob, &relative) {
+                continue;
+            }
+            for (key, value) in section {
+                if value == "unset" {
+                    properties.remove(key);
+                } else {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Path of `path` relative to `dir`, with `/` separators
+fn relative_path(dir: &Path, path: &Path) -> Option<String> {
+    let relative: PathBuf = path.strip_prefix(dir).ok()?.to_path_buf();
+    let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+    Some(parts.join("/"))
+}
+
+/// Check a section glob against a path relative to the `.editorconfig` file.
+/// A glob without `/` matches the file name in any subdirectory.
+pub(crate) fn section_matches(glob: &str, relative: &str) -> bool {
+    let pattern = if glob.contains('/') {
+        format!("^{}$", translate_glob(glob.strip_prefix('/').unwrap_or(glob)))
+    } else {
+        format!("^(?:.*/)?{}$", translate_glob(glob))
+    };
+    Regex::new(&pattern).is_ok_and(|re| re.is_match(relative))
+}
+
+/// Translate an EditorConfig glob (`*`, `**`, `?`, `[...]`, `{a,b}`, `{1..3}`)
+/// into regex syntax
+fn translate_glob(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                regex.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 1;
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                regex.push_str(".*");
+                i += 1;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match chars[i + 1..].iter().position(|&c| c == ']') {
+                Some(len) => {
+                    let class: String = chars[i + 1..i + 1 + len].iter().collect();
+                    let (negated, class) = match class.strip_prefix('!') {
+                        Some(rest) => (true, rest.to_string()),
+                        None => (false, class),
+                    };
+                    let class = class.replace('\\', "\\\\").replace('[', "\\[");
+                    regex.push_str(&format!("[{}{}]", if negated { "^" } else { "" }, class));
+                    i += len + 1;
+                }
+                None => regex.push_str("\\["),
+            },
+            '{' => match matching_brace(&chars, i) {
+                Some(end) => {
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    regex.push_str(&translate_braces(&inner));
+                    i = end;
+                }
+                None => regex.push_str("\\{"),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+
+    regex
+}
+
+/// Index of the `}` closing the `{` at `open`, allowing nesting
+fn matching_brace(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &c) in chars.iter().enumerate().skip(open) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Translate the inside of `{...}`: a numeric range or comma-separated alternatives
+fn translate_braces(inner: &str) -> String {
+    if let Some((start, end)) = inner.split_once("..") {
+        if let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) {
+            let (low, high) = (start.min(end), start.max(end));
+            if high - low > 1000 {
+                return "-?[0-9]+".to_string();
+            }
+            return format!("(?:{})", (low..=high).map(|n| n.to_string()).collect::<Vec<_>>().join("|"));
+        }
+    }
+
+    // Split on commas that aren't inside nested braces
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in inner.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    alternatives.push(current);
+
+    if alternatives.len() == 1 {
+        // No comma: braces are literal
+        return format!("\\{{{}\\}}", translate_glob(inner));
+    }
+    let alternatives: Vec<String> = alternatives.iter().map(|alt| translate_glob(alt)).collect();
+    format!("(?:{})", alternatives.join("|"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Indentation;
+
+    #[test]
+    fn test_glob_matching() {
+        assert!(section_matches("*", "main.rs"));
+        assert!(section_matches("*.rs", "src/app/main.rs"));
+        assert!(!section_matches("*.rs", "main.py"));
+        assert!(section_matches("*.{js,ts}", "web/app.ts"));
+        assert!(section_matches("Makefile", "sub/Makefile"));
+        assert!(section_matches("src/*.rs", "src/main.rs"));
+        assert!(!section_matches("src/*.rs", "src/app/main.rs"));
+        assert!(section_matches("/src/**.rs", "src/app/main.rs"));
+        assert!(section_matches("file[0-9].txt", "file7.txt"));
+        assert!(!section_matches("file[!0-9].txt", "file7.txt"));
+        assert!(section_matches("v{1..3}.txt", "v2.txt"));
+        assert!(!section_matches("v{1..3}.txt", "v4.txt"));
+    }
+
+    #[test]
+    fn test_resolve_nested_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::write(
+            root.join(".editorconfig"),
+            "root = true\n\n[*]\nindent_style = space\nindent_size = 4\nend_of_line = lf\n\n[Makefile]\nindent_style = tab\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("lib/.editorconfig"), "[*.rs]\nindent_size = 2\nend_of_line = unset\n").unwrap();
+
+        let config = EditorConfig::resolve(&root.join("lib/main.rs"), Some(root));
+        assert_eq!(config.indent_style, Some(IndentStyle::Space));
+        assert_eq!(config.indent_size, Some(2));
+        assert_eq!(config.end_of_line, None);
+        assert_eq!(Indentation::spaces(config.indent_width()).unit(), "  ");
+
+        let config = EditorConfig::resolve(&root.join("Makefile"), Some(root));
+        assert_eq!(config.indent_style, Some(IndentStyle::Tab));
+        assert_eq!(config.end_of_line, Some(LineEnding::LF));
+    }
+
+    #[test]
+    fn test_transform_for_save() {
+        let config = EditorConfig {
+            trim_trailing_whitespace: Some(true),
+            end_of_line: Some(LineEnding::LF),
+            insert_final_newline: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(config.transform_for_save("a  \r\nb\t\r\nc").as_deref(), Some("a\nb\nc\n"));
+        assert_eq!(config.transform_for_save("clean\n"), None);
+
+        let config = EditorConfig { insert_final_newline: Some(false), ..Default::default() };
+        assert_eq!(config.transform_for_save("x\n\n").as_deref(), Some("x"));
+        assert_eq!(EditorConfig::default().transform_for_save("x  "), None);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::buffer::TextBuffer;
+use crate::config::LanguagesConfig;
+use crate::editorconfig::section_matches;
+use crate::lsp::Language as LspLanguage;
+use crate::syntax::SupportedLanguage;
+use std::path::Path;
+
+/// Lines at the start and end of a file searched for Vim modelines
+const MODELINE_LINES: usize = 5;
+
+/// A language the editor knows: how files in it are recognized, and what
+/// highlighting and the language server do with it
+#[derive(Debug, PartialEq, Eq)]
+pub struct LanguageDef {
+    /// Name used in commands, modelines and the config, e.g. "python"
+    pub name: &'static str,
+    /// Other names it goes by, e.g. "py" or "python3"
+    aliases: &'static [&'static str],
+    extensions: &'static [&'static str],
+    /// Exact file names, e.g. "Makefile"
+    filenames: &'static [&'static str],
+    /// File name globs, e.g. "Dockerfile.*"
+    globs: &'static [&'static str],
+    /// Interpreters named in a shebang line, without version numbers
+    interpreters: &'static [&'static str],
+    /// Grammar used for syntax highlighting
+    pub syntax: Option<SupportedLanguage>,
+    /// Language server
+    pub lsp: Option<LspLanguage>,
+}
+
+impl LanguageDef {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            extensions: &[],
+            filenames: &[],
+            globs: &[],
+            interpreters: &[],
+            syntax: None,
+            lsp: None,
+        }
+    }
+}
+
+/// Every language the editor knows
+static LANGUAGES: &[LanguageDef] = &[
+    LanguageDef {
+        extensions: &["rs"],
+        syntax: Some(SupportedLanguage::Rust),
+        lsp: Some(LspLanguage::Rust),
+        ..LanguageDef::new("rust")
+    },
+    LanguageDef {
+        aliases: &["py", "python3"],
+        extensions: &["py", "pyw", "pyi"],
+        filenames: &["SConstruct", "SConscript"],
+        interpreters: &["python"],
+        syntax: Some(SupportedLanguage::Python),
+        lsp: Some(LspLanguage::Python),
+        ..LanguageDef::new("python")
+    },
+    LanguageDef {
+        aliases: &["c", "c++", "cc"],
+        extensions: &["cpp", "cxx", "cc", "c++", "hpp", "hxx", "hh", "h++", "c", "h"],
+        syntax: Some(SupportedLanguage::Cpp),
+        lsp: Some(LspLanguage::Cpp),
+        ..LanguageDef::new("cpp")
+    },
+    LanguageDef {
+        aliases: &["js", "node", "ecmascript"],
+        extensions: &["js", "jsx", "mjs", "cjs"],
+        interpreters: &["node", "nodejs"],
+        syntax: Some(SupportedLanguage::JavaScript),
+        ..LanguageDef::new("javascript")
+    },
+    LanguageDef {
+        extensions: &["json"],
+        filenames: &[".prettierrc", ".babelrc", ".eslintrc"],
+        syntax: Some(SupportedLanguage::Json),
+        ..LanguageDef::new("json")
+    },
+    LanguageDef {
+        aliases: &["md"],
+        extensions: &["md", "markdown"],
+        syntax: Some(SupportedLanguage::Markdown),
+        ..LanguageDef::new("markdown")
+    },
+    LanguageDef {
+        aliases: &["htm"],
+        extensions: &["html", "htm"],
+        syntax: Some(SupportedLanguage::Html),
+        ..LanguageDef::new("html")
+    },
+    LanguageDef {
+        extensions: &["css"],
+        syntax: Some(SupportedLanguage::Css),
+        ..LanguageDef::new("css")
+    },
+    LanguageDef {
+        extensions: &["xml", "svg", "xhtml"],
+        syntax: Some(SupportedLanguage::Xml),
+        ..LanguageDef::new("xml")
+    },
+    LanguageDef {
+        aliases: &["make"],
+        extensions: &["mk", "mak"],
+        filenames: &["Makefile", "makefile", "GNUmakefile"],
+        globs: &["Makefile.*"],
+        interpreters: &["make"],
+        ..LanguageDef::new("makefile")
+    },
+    LanguageDef {
+        aliases: &["docker"],
+        extensions: &["dockerfile"],
+        filenames: &["Dockerfile", "Containerfile"],
+        globs: &["Dockerfile.*", "Containerfile.*"],
+        ..LanguageDef::new("dockerfile")
+    },
+    LanguageDef {
+        aliases: &["sh", "bash", "zsh"],
+        extensions: &["sh", "bash", "zsh"],
+        filenames: &[".bashrc", ".bash_profile", ".zshrc", ".profile"],
+        interpreters: &["sh", "bash", "zsh", "dash", "ksh"],
+        ..LanguageDef::new("shell")
+    },
+    LanguageDef {
+        extensions: &["toml"],
+        filenames: &["Cargo.lock"],
+        ..LanguageDef::new("toml")
+    },
+    LanguageDef {
+        aliases: &["yml"],
+        extensions: &["yaml", "yml"],
+        ..LanguageDef::new("yaml")
+    },
+];
+
+/// The one place file languages are decided, for highlighting and the
+/// language server alike. In order of precedence a file's language comes
+/// from the user's mappings in the config, a Vim or Emacs modeline, its file
+/// name, its extension, and finally its shebang line.
+#[derive(Debug, Default)]
+pub struct LanguageRegistry {
+    /// File globs and the languages the user assigned them
+    mappings: Vec<(String, &'static LanguageDef)>,
+}
+
+impl LanguageRegistry {
+    /// Create a registry with the user's mappings. Mappings to unknown
+    /// languages are ignored.
+    pub fn new(config: &LanguagesConfig) -> Self {
+        let mappings = config
+            .mappings
+            .iter()
+            .filter_map(|(glob, name)| Some((glob.clone(), Self::by_name(name)?)))
+            .collect();
+        Self { mappings }
+    }
+
+    /// All known languages
+    pub fn all() -> &'static [LanguageDef] {
+        LANGUAGES
+    }
+
+    /// Find a language by name, alias or extension (case-insensitive)
+    pub fn by_name(name: &str) -> Option<&'static LanguageDef> {
+        let name = name.trim().to_ascii_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|lang| lang.name == name || lang.aliases.contains(&name.as_str()))
+            .or_else(|| LANGUAGES.iter().find(|lang| lang.extensions.contains(&name.as_str())))
+    }
+
+    /// Language of a file judging by its path alone
+    pub fn for_path(&self, path: &Path) -> Option<&'static LanguageDef> {
+        self.mapped(path).or_else(|| Self::by_file_name(path))
+    }
+
+    /// Language of a buffer, from its path and its first and last lines
+    pub fn detect(&self, path: Option<&Path>, text: &TextBuffer) -> Option<&'static LanguageDef> {
+        let first_lines: Vec<String> = (0..MODELINE_LINES.min(text.len_lines()))
+            .filter_map(|line| text.get_line(line))
+            .collect();
+        let last_lines: Vec<String> = (text.len_lines().saturating_sub(MODELINE_LINES)..text.len_lines())
+            .filter_map(|line| text.get_line(line))
+            .collect();
+
+        path.and_then(|path| self.mapped(path))
+            .or_else(|| modeline_language(&first_lines, &last_lines))
+            .or_else(|| path.and_then(Self::by_file_name))
+            .or_else(|| first_lines.first().and_then(|line| shebang_language(line)))
+    }
+
+    /// Language the user's config assigns to a path
+    fn mapped(&self, path: &Path) -> Option<&'static LanguageDef> {
+        let path = path.to_string_lossy();
+        let path = path.trim_start_matches('/');
+        self.mappings
+            .iter()
+            .find(|(glob, _)| section_matches(glob.trim_start_matches('/'), path))
+            .map(|(_, lang)| *lang)
+    }
+
+    /// Language of a file name: exact names and globs first, then the extension
+    fn by_file_name(path: &Path) -> Option<&'static LanguageDef> {
+        let file_name = path.file_name()?.to_str()?;
+        LANGUAGES
+            .iter()
+            .find(|lang| {
+                lang.filenames.contains(&file_name) || lang.globs.iter().any(|glob| section_matches(glob, file_name))
+            })
+            .or_else(|| {
+                let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+                LANGUAGES.iter().find(|lang| lang.extensions.contains(&ext.as_str()))
+            })
+    }
+}
+
+/// Language named by a shebang line such as `#!/usr/bin/env python3`
+fn shebang_language(line: &str) -> Option<&'static LanguageDef> {
+    let command = line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // Skip options (`env -S`) and variable assignments (`env FOO=1 python`)
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    // "python3.12" runs python
+    let interpreter = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    LANGUAGES.iter().find(|lang| lang.interpreters.contains(&interpreter))
+}
+
+/// Language named by an Emacs modeline (`-*- mode: python -*-` on the first
+/// line, or the second after a shebang) or a Vim modeline (`vim: set ft=python:`
+/// in the first or last lines)
+fn modeline_language(first_lines: &[String], last_lines: &[String]) -> Option<&'static LanguageDef> {
+    let emacs_lines = if first_lines.first().is_some_and(|line| line.starts_with("#!")) { 2 } else { 1 };
+    first_lines
+        .iter()
+        .take(emacs_lines)
+        .find_map(|line| emacs_mode(line))
+        .or_else(|| first_lines.iter().chain(last_lines).find_map(|line| vim_filetype(line)))
+        .and_then(LanguageRegistry::by_name)
+}
+
+/// The mode of an Emacs `-*- ... -*-` line
+fn emacs_mode(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+    if !variables.contains(':') {
+        // `-*- python -*-`
+        return Some(variables.trim());
+    }
+    variables.split(';').find_map(|variable| {
+        let (name, value) = variable.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("mode").then(|| value.trim())
+    })
+}
+
+/// The filetype of a Vim modeline (`vim: set ft=python:` or `vim: ft=python`)
+fn vim_filetype(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let index = line.find(marker)?;
+        // The marker must start the line or follow whitespace
+        let at_word_start = line[..index].chars().next_back().is_none_or(char::is_whitespace);
+        at_word_start.then_some(index + marker.len())
+    })?;
+    line[start..]
+        .split([' ', '\t', ':'])
+        .find_map(|option| option.strip_prefix("ft=").or_else(|| option.strip_prefix("filetype=")))
+        .filter(|filetype| !filetype.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Position;
+    use std::collections::BTreeMap;
+
+    fn detect(registry: &LanguageRegistry, path: Option<&str>, text: &str) -> Option<&'static str> {
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), text).unwrap();
+        registry.detect(path.map(Path::new), &buffer).map(|lang| lang.name)
+    }
+
+    #[test]
+    fn test_detect() {
+        let registry = LanguageRegistry::default();
+        assert_eq!(detect(&registry, Some("src/main.rs"), ""), Some("rust"));
+        assert_eq!(detect(&registry, Some("stubs/os.pyi"), ""), Some("python"));
+        assert_eq!(detect(&registry, Some("/work/Makefile"), ""), Some("makefile"));
+        assert_eq!(detect(&registry, Some("Dockerfile.dev"), ""), Some("dockerfile"));
+        assert_eq!(detect(&registry, Some("bin/tool"), "#!/usr/bin/env python3\nprint()\n"), Some("python"));
+        assert_eq!(detect(&registry, Some("run"), "#!/bin/bash -e\n"), Some("shell"));
+        assert_eq!(detect(&registry, Some("notes"), "plain text\n"), None);
+
+        // Modelines win over the extension
+        assert_eq!(detect(&registry, Some("conf.txt"), "# -*- mode: python; coding: utf-8 -*-\n"), Some("python"));
+        assert_eq!(detect(&registry, Some("x.js"), "/* -*- c++ -*- */\n"), Some("cpp"));
+        assert_eq!(detect(&registry, None, "a\nb\n# vim: set ft=sh ts=4:\n"), Some("shell"));
+        assert_eq!(detect(&registry, None, "myvim:ft=rust is not a modeline\n"), None);
+    }
+
+    #[test]
+    fn test_user_mappings() {
+        let mut mappings = BTreeMap::new();
+        mappings.insert("*.tmpl".to_string(), "html".to_string());
+        mappings.insert("**/config/*.conf".to_string(), "toml".to_string());
+        mappings.insert("*.x".to_string(), "no-such-language".to_string());
+        let registry = LanguageRegistry::new(&LanguagesConfig { mappings });
+
+        assert_eq!(detect(&registry, Some("/srv/page.tmpl"), ""), Some("html"));
+        assert_eq!(detect(&registry, Some("/srv/app/config/app.conf"), "# vim: ft=yaml\n"), Some("toml"));
+        assert_eq!(detect(&registry, Some("a.x"), ""), None);
+        assert_eq!(LanguageRegistry::by_name("Py").map(|lang| lang.name), Some("python"));
+    }
+}
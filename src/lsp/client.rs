@@ -1,8 +1,9 @@
 use crate::lsp::config::Language;
 use crate::lsp::protocol::{
-    BufferId, Diagnostic, DiagnosticSeverity,
-    LspRequest, LspResponse, Position,
+    BufferId, CodeAction, Diagnostic, DiagnosticSeverity, FileEdit, LocationRange, LspCommand, LspRequest,
+    LspResponse, Position, Signature, TextChange, TextEdit, WorkspaceEdit,
 };
+use crate::lsp::workspace_edit::utf16_to_char;
 use anyhow::Result;
 
 // Debug logging helper
@@ -19,16 +20,23 @@ macro_rules! lsp_debug {
     };
 }
 use lsp_types::{
-    notification::{DidChangeTextDocument, DidOpenTextDocument, DidSaveTextDocument, Notification},
-    request::{Initialize, Request},
-    DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, Notification,
+    },
+    request::{
+        ApplyWorkspaceEdit, CodeActionRequest, ExecuteCommand, HoverRequest, Initialize, PrepareRenameRequest,
+        References, Rename, Request, SignatureHelpRequest,
+    },
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
     DidSaveTextDocumentParams, InitializeParams,
     TextDocumentContentChangeEvent, TextDocumentIdentifier,
     TextDocumentItem, Url, VersionedTextDocumentIdentifier,
 };
+use ropey::Rope;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::{Arc, Mutex};
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, ChildStdin, ChildStdout, Command};
 use tokio::sync::mpsc;
@@ -41,6 +49,28 @@ struct LspClient {
     next_request_id: i64,
     language: Language,
     buffer_id: BufferId,
+    /// Whether the server accepts ranged changes (otherwise it gets full text)
+    incremental_sync: bool,
+    /// Whether the server answers prepareRename (otherwise any position is
+    /// offered for renaming)
+    prepare_rename: bool,
+    /// Our copy of each open document, as the server sees it: ranged changes
+    /// are converted to UTF-16 columns against it, and full syncs are sent from it
+    documents: HashMap<PathBuf, Rope>,
+    /// Requests still waiting for their response, by request id, so
+    /// responses are parsed as the right type
+    pending_requests: PendingRequests,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<i64, PendingRequest>>>;
+
+/// A request still waiting for its response
+#[derive(Debug, Clone, Copy)]
+struct PendingRequest {
+    method: &'static str,
+    /// Asked for by the editor itself rather than the user (code actions
+    /// for the lightbulb), so the answer is told apart from the user's
+    automatic: bool,
 }
 
 impl LspClient {
@@ -151,6 +181,10 @@ impl LspClient {
             next_request_id: 1,
             language,
             buffer_id,
+            incremental_sync: false,
+            prepare_rename: false,
+            documents: HashMap::new(),
+            pending_requests: Arc::new(Mutex::new(HashMap::new())),
         };
 
         // Send initialize request and wait for response
@@ -158,8 +192,9 @@ impl LspClient {
 
         // Now spawn task to read responses, taking ownership of stdout_reader
         if let Some(stdout_reader) = client.stdout_reader.take() {
+            let pending_requests = client.pending_requests.clone();
             tokio::spawn(async move {
-                Self::read_responses(stdout_reader, response_tx, buffer_id).await;
+                Self::read_responses(stdout_reader, response_tx, language, buffer_id, pending_requests).await;
             });
         }
 
@@ -203,6 +238,64 @@ impl LspClient {
                     }),
                     ..Default::default()
                 }),
+                hover: Some(lsp_types::HoverClientCapabilities {
+                    dynamic_registration: Some(false),
+                    content_format: Some(vec![lsp_types::MarkupKind::Markdown, lsp_types::MarkupKind::PlainText]),
+                }),
+                references: Some(lsp_types::ReferenceClientCapabilities {
+                    dynamic_registration: Some(false),
+                }),
+                signature_help: Some(lsp_types::SignatureHelpClientCapabilities {
+                    dynamic_registration: Some(false),
+                    signature_information: Some(lsp_types::SignatureInformationSettings {
+                        documentation_format: Some(vec![lsp_types::MarkupKind::PlainText]),
+                        parameter_information: Some(lsp_types::ParameterInformationSettings {
+                            label_offset_support: Some(true),
+                        }),
+                        active_parameter_support: Some(true),
+                    }),
+                    context_support: Some(false),
+                }),
+                rename: Some(lsp_types::RenameClientCapabilities {
+                    dynamic_registration: Some(false),
+                    prepare_support: Some(true),
+                    prepare_support_default_behavior: Some(lsp_types::PrepareSupportDefaultBehavior::IDENTIFIER),
+                    honors_change_annotations: Some(false),
+                }),
+                code_action: Some(lsp_types::CodeActionClientCapabilities {
+                    dynamic_registration: Some(false),
+                    code_action_literal_support: Some(lsp_types::CodeActionLiteralSupport {
+                        code_action_kind: lsp_types::CodeActionKindLiteralSupport {
+                            value_set: [
+                                lsp_types::CodeActionKind::EMPTY,
+                                lsp_types::CodeActionKind::QUICKFIX,
+                                lsp_types::CodeActionKind::REFACTOR,
+                                lsp_types::CodeActionKind::REFACTOR_EXTRACT,
+                                lsp_types::CodeActionKind::REFACTOR_INLINE,
+                                lsp_types::CodeActionKind::REFACTOR_REWRITE,
+                                lsp_types::CodeActionKind::SOURCE,
+                                lsp_types::CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                            ]
+                            .iter()
+                            .map(|kind| kind.as_str().to_string())
+                            .collect(),
+                        },
+                    }),
+                    is_preferred_support: Some(true),
+                    disabled_support: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            workspace: Some(lsp_types::WorkspaceClientCapabilities {
+                apply_edit: Some(true),
+                workspace_edit: Some(lsp_types::WorkspaceEditClientCapabilities {
+                    document_changes: Some(true),
+                    ..Default::default()
+                }),
+                execute_command: Some(lsp_types::DynamicRegistrationClientCapabilities {
+                    dynamic_registration: Some(false),
+                }),
                 ..Default::default()
             }),
             ..Default::default()
@@ -249,6 +342,15 @@ impl LspClient {
                         if let Some(error) = value.get("error") {
                             return Err(anyhow::anyhow!("LSP initialization failed: {:?}", error));
                         }
+                        // textDocumentSync is either a kind or options with a `change` kind
+                        let sync = value.pointer("/result/capabilities/textDocumentSync");
+                        let change_kind = sync
+                            .and_then(|sync| sync.as_i64().or_else(|| sync.get("change")?.as_i64()));
+                        self.incremental_sync = change_kind == Some(2);
+                        self.prepare_rename = value
+                            .pointer("/result/capabilities/renameProvider/prepareProvider")
+                            .and_then(|prepare| prepare.as_bool())
+                            .unwrap_or(false);
                     }
                 }
                 Err(e) => {
@@ -257,6 +359,10 @@ impl LspClient {
                 }
             }
         }
+        // The initialize response was read here, not by the reader task
+        if let Ok(mut pending) = self.pending_requests.lock() {
+            pending.clear();
+        }
 
         // Send initialized notification (required by LSP spec)
         let initialized_notification = serde_json::json!({
@@ -275,7 +381,9 @@ impl LspClient {
     async fn read_responses(
         mut reader: BufReader<ChildStdout>,
         response_tx: mpsc::UnboundedSender<LspResponse>,
+        language: Language,
         buffer_id: BufferId,
+        pending_requests: PendingRequests,
     ) {
         let mut content_length = 0;
 
@@ -293,7 +401,7 @@ impl LspClient {
                         let mut body = vec![0u8; content_length];
                         if reader.read_exact(&mut body).await.is_ok() {
                             if let Ok(text) = String::from_utf8(body) {
-                                Self::handle_message(&text, &response_tx, buffer_id);
+                                Self::handle_message(&text, &response_tx, language, buffer_id, &pending_requests);
                             }
                         }
                         content_length = 0;
@@ -308,7 +416,9 @@ impl LspClient {
     fn handle_message(
         message: &str,
         response_tx: &mpsc::UnboundedSender<LspResponse>,
+        language: Language,
         buffer_id: BufferId,
+        pending_requests: &PendingRequests,
     ) {
         lsp_debug!("[LSP DEBUG] Received message: {}", message);
 
@@ -329,11 +439,30 @@ impl LspClient {
                             });
                         }
                     }
+                } else if method == ApplyWorkspaceEdit::METHOD {
+                    // A request of the server: the editor applies the edit
+                    // and answers with an `ApplyEditResult`
+                    let params = value
+                        .get("params")
+                        .and_then(|params| serde_json::from_value::<lsp_types::ApplyWorkspaceEditParams>(params.clone()).ok());
+                    if let (Some(id), Some(params)) = (value.get("id"), params) {
+                        let _ = response_tx.send(LspResponse::ApplyEdit {
+                            language: language.language_id().to_string(),
+                            id: id.clone(),
+                            label: params.label,
+                            edit: Self::convert_workspace_edit(params.edit),
+                        });
+                    }
                 }
             }
             // Check if it's a response (has "id" field)
             else if value.get("id").is_some() {
                 lsp_debug!("[LSP DEBUG] This is a response message with id: {:?}", value.get("id"));
+                let pending = value
+                    .get("id")
+                    .and_then(|id| id.as_i64())
+                    .and_then(|id| pending_requests.lock().ok()?.remove(&id));
+                let method = pending.map(|pending| pending.method);
 
                 // Check for error response
                 if let Some(error) = value.get("error") {
@@ -356,6 +485,73 @@ impl LspClient {
                     }
                     lsp_debug!("[LSP DEBUG] Result field: {:?}", result);
 
+                    // Responses whose type can't be told from their shape
+                    if method == Some(HoverRequest::METHOD) {
+                        Self::handle_hover(result, response_tx);
+                        return;
+                    }
+                    if method == Some(References::METHOD) {
+                        let locations = serde_json::from_value::<Option<Vec<lsp_types::Location>>>(result.clone())
+                            .ok()
+                            .flatten()
+                            .unwrap_or_default();
+                        let locations = locations
+                            .into_iter()
+                            .filter_map(|location| {
+                                Some(LocationRange {
+                                    path: location.uri.to_file_path().ok()?,
+                                    start: Position::new(location.range.start.line as usize, location.range.start.character as usize),
+                                    end: Position::new(location.range.end.line as usize, location.range.end.character as usize),
+                                })
+                            })
+                            .collect();
+                        let _ = response_tx.send(LspResponse::References { locations });
+                        return;
+                    }
+                    if method == Some(SignatureHelpRequest::METHOD) {
+                        let help = serde_json::from_value::<Option<lsp_types::SignatureHelp>>(result.clone()).ok().flatten();
+                        let signature = help.and_then(Self::convert_signature_help);
+                        let _ = response_tx.send(LspResponse::SignatureHelp { signature });
+                        return;
+                    }
+                    if method == Some(PrepareRenameRequest::METHOD) {
+                        let prepare = serde_json::from_value::<Option<lsp_types::PrepareRenameResponse>>(result.clone())
+                            .ok()
+                            .flatten();
+                        let (renameable, placeholder) = match prepare {
+                            None => (false, None),
+                            Some(lsp_types::PrepareRenameResponse::RangeWithPlaceholder { placeholder, .. }) => {
+                                (true, Some(placeholder))
+                            }
+                            Some(_) => (true, None),
+                        };
+                        let _ = response_tx.send(LspResponse::PrepareRename { renameable, placeholder });
+                        return;
+                    }
+                    if method == Some(Rename::METHOD) {
+                        let edit = serde_json::from_value::<Option<lsp_types::WorkspaceEdit>>(result.clone())
+                            .ok()
+                            .flatten()
+                            .map(Self::convert_workspace_edit)
+                            .unwrap_or_default();
+                        let _ = response_tx.send(LspResponse::Rename { edit });
+                        return;
+                    }
+                    if method == Some(CodeActionRequest::METHOD) {
+                        let actions = serde_json::from_value::<Option<lsp_types::CodeActionResponse>>(result.clone())
+                            .ok()
+                            .flatten()
+                            .unwrap_or_default();
+                        let actions = actions.into_iter().map(Self::convert_code_action).collect();
+                        let automatic = pending.is_some_and(|pending| pending.automatic);
+                        let _ = response_tx.send(LspResponse::CodeActions { actions, automatic });
+                        return;
+                    }
+                    if method == Some(ExecuteCommand::METHOD) {
+                        // Commands do their work through requests of their own
+                        return;
+                    }
+
                     // Check if result is null
                     if result.is_null() {
                         lsp_debug!("[LSP DEBUG] Result is null");
@@ -446,6 +642,154 @@ impl LspClient {
         }
     }
 
+    /// Send the documentation of a hover response (`null` if there is none)
+    fn handle_hover(result: &serde_json::Value, response_tx: &mpsc::UnboundedSender<LspResponse>) {
+        let hover = serde_json::from_value::<Option<lsp_types::Hover>>(result.clone()).ok().flatten();
+        let (contents, markdown) = match hover.map(|hover| hover.contents) {
+            None => (String::new(), false),
+            Some(lsp_types::HoverContents::Markup(markup)) => {
+                (markup.value, markup.kind == lsp_types::MarkupKind::Markdown)
+            }
+            Some(lsp_types::HoverContents::Scalar(marked)) => (Self::marked_string_markdown(marked), true),
+            Some(lsp_types::HoverContents::Array(marked)) => {
+                let parts: Vec<String> = marked.into_iter().map(Self::marked_string_markdown).collect();
+                (parts.join("\n\n"), true)
+            }
+        };
+        let _ = response_tx.send(LspResponse::Hover { contents, markdown });
+    }
+
+    /// The active signature of a signature help response, with the range of
+    /// its active parameter
+    fn convert_signature_help(help: lsp_types::SignatureHelp) -> Option<Signature> {
+        let index = help.active_signature.unwrap_or(0) as usize;
+        let signature = help.signatures.get(index).or(help.signatures.first())?;
+        let parameter = signature
+            .active_parameter
+            .or(help.active_parameter)
+            .and_then(|index| signature.parameters.as_ref()?.get(index as usize));
+
+        let active_parameter = parameter.and_then(|parameter| match &parameter.label {
+            lsp_types::ParameterLabel::Simple(name) => {
+                let start = signature.label.find(name.as_str())?;
+                let start = signature.label[..start].chars().count();
+                Some((start, start + name.chars().count()))
+            }
+            // Offsets are in UTF-16 code units; out-of-order ones are ignored
+            lsp_types::ParameterLabel::LabelOffsets([start, end]) if start <= end => {
+                let char_offset = |offset: u32| utf16_to_char(&signature.label, offset as usize);
+                Some((char_offset(*start), char_offset(*end)))
+            }
+            lsp_types::ParameterLabel::LabelOffsets(_) => None,
+        });
+
+        Some(Signature {
+            label: signature.label.clone(),
+            active_parameter,
+        })
+    }
+
+    /// Convert a workspace edit to our internal format, one step per text
+    /// document edit in the order the server sent them
+    fn convert_workspace_edit(edit: lsp_types::WorkspaceEdit) -> WorkspaceEdit {
+        let mut result = WorkspaceEdit::default();
+        let mut add_edits = |uri: &Url, version: Option<i32>, edits: Vec<lsp_types::TextEdit>| {
+            let Ok(path) = uri.to_file_path() else {
+                return;
+            };
+            let edits = edits
+                .into_iter()
+                .map(|edit| TextEdit {
+                    range: (
+                        Position::new(edit.range.start.line as usize, edit.range.start.character as usize),
+                        Position::new(edit.range.end.line as usize, edit.range.end.character as usize),
+                    ),
+                    new_text: edit.new_text,
+                })
+                .collect();
+            result.files.push(FileEdit { path, version, edits });
+        };
+        // Each text document edit is a step of its own: a later one for the
+        // same file refers to the text the earlier ones leave
+        let mut add_document_edit = |edit: lsp_types::TextDocumentEdit| {
+            let edits = edit
+                .edits
+                .into_iter()
+                .map(|edit| match edit {
+                    lsp_types::OneOf::Left(edit) => edit,
+                    lsp_types::OneOf::Right(annotated) => annotated.text_edit,
+                })
+                .collect();
+            add_edits(&edit.text_document.uri, edit.text_document.version, edits);
+        };
+
+        // Servers send either `documentChanges` or `changes`
+        let mut skipped_operations = 0;
+        match edit.document_changes {
+            Some(lsp_types::DocumentChanges::Edits(edits)) => {
+                for edit in edits {
+                    add_document_edit(edit);
+                }
+            }
+            Some(lsp_types::DocumentChanges::Operations(operations)) => {
+                for operation in operations {
+                    match operation {
+                        lsp_types::DocumentChangeOperation::Edit(edit) => add_document_edit(edit),
+                        lsp_types::DocumentChangeOperation::Op(_) => skipped_operations += 1,
+                    }
+                }
+            }
+            None => {
+                // Sorted by path, as the map has no order
+                let mut changes: Vec<_> = edit.changes.unwrap_or_default().into_iter().collect();
+                changes.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+                for (uri, edits) in changes {
+                    add_edits(&uri, None, edits);
+                }
+            }
+        }
+        result.skipped_operations = skipped_operations;
+        result
+    }
+
+    /// Convert a code action, or a bare command offered as one, to our
+    /// internal format
+    fn convert_code_action(action: lsp_types::CodeActionOrCommand) -> CodeAction {
+        let convert_command = |command: lsp_types::Command| LspCommand {
+            title: command.title,
+            command: command.command,
+            arguments: command.arguments,
+        };
+        match action {
+            lsp_types::CodeActionOrCommand::Command(command) => CodeAction {
+                title: command.title.clone(),
+                kind: None,
+                preferred: false,
+                disabled: None,
+                edit: None,
+                command: Some(convert_command(command)),
+            },
+            lsp_types::CodeActionOrCommand::CodeAction(action) => CodeAction {
+                title: action.title,
+                kind: action.kind.map(|kind| kind.as_str().to_string()),
+                preferred: action.is_preferred.unwrap_or(false),
+                disabled: action.disabled.map(|disabled| disabled.reason),
+                edit: action.edit.map(Self::convert_workspace_edit),
+                command: action.command.map(convert_command),
+            },
+        }
+    }
+
+    /// Markdown for a (deprecated) marked string: code is put in a fenced block
+    fn marked_string_markdown(marked: lsp_types::MarkedString) -> String {
+        match marked {
+            lsp_types::MarkedString::String(text) => text,
+            lsp_types::MarkedString::LanguageString(code) => {
+                format!("```{}\n{}\n```", code.language, code.value)
+            }
+        }
+    }
+
     /// Convert LSP diagnostics to our internal format
     fn convert_diagnostics(lsp_diagnostics: Vec<lsp_types::Diagnostic>) -> Vec<Diagnostic> {
         lsp_diagnostics
@@ -464,7 +808,8 @@ impl LspClient {
                     Some(lsp_types::DiagnosticSeverity::HINT) => DiagnosticSeverity::Hint,
                     _ => DiagnosticSeverity::Information,
                 },
-                message: d.message,
+                message: d.message.clone(),
+                lsp: d,
             })
             .collect()
     }
@@ -496,8 +841,16 @@ impl LspClient {
 
     /// Send a request to the language server
     async fn send_request<R: Request>(&mut self, params: R::Params) -> Result<()> {
+        self.send_request_marked::<R>(params, false).await
+    }
+
+    /// Send a request, marked as `automatic` if the editor made it on its own
+    async fn send_request_marked<R: Request>(&mut self, params: R::Params, automatic: bool) -> Result<()> {
         let id = self.next_request_id;
         self.next_request_id += 1;
+        if let Ok(mut pending) = self.pending_requests.lock() {
+            pending.insert(id, PendingRequest { method: R::METHOD, automatic });
+        }
 
         let request = serde_json::json!({
             "jsonrpc": "2.0",
@@ -548,6 +901,7 @@ impl LspClient {
         };
 
         lsp_debug!("[LSP DEBUG] didOpen for {:?} (absolute: {:?})", path, abs_path);
+        self.documents.insert(path.clone(), Rope::from_str(&content));
 
         let uri = Url::from_file_path(&abs_path)
             .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
@@ -566,8 +920,9 @@ impl LspClient {
         self.send_notification::<DidOpenTextDocument>(params).await
     }
 
-    /// Handle didChange notification
-    async fn did_change(&mut self, path: PathBuf, content: String, version: i32) -> Result<()> {
+    /// Handle didChange notification: update our copy of the document and
+    /// send the changes, as ranges if the server supports it
+    async fn did_change(&mut self, path: PathBuf, changes: Vec<TextChange>, version: i32) -> Result<()> {
         // Convert to absolute path
         let abs_path = if path.is_absolute() {
             path.clone()
@@ -583,16 +938,85 @@ impl LspClient {
                 uri,
                 version,
             },
-            content_changes: vec![TextDocumentContentChangeEvent {
-                range: None,
-                range_length: None,
-                text: content,
-            }],
+            content_changes: self.apply_changes(&path, changes),
         };
 
         self.send_notification::<DidChangeTextDocument>(params).await
     }
 
+    /// Apply changes to our copy of a document and return them in LSP form
+    fn apply_changes(&mut self, path: &Path, changes: Vec<TextChange>) -> Vec<TextDocumentContentChangeEvent> {
+        let document = self.documents.entry(path.to_path_buf()).or_default();
+        let mut content_changes = Vec::new();
+
+        for change in changes {
+            match change.range {
+                Some((start, end)) => {
+                    let start_idx = Self::char_index(document, start);
+                    let end_idx = Self::char_index(document, end).max(start_idx);
+                    let range = lsp_types::Range {
+                        start: Self::utf16_position(document, start_idx),
+                        end: Self::utf16_position(document, end_idx),
+                    };
+                    document.remove(start_idx..end_idx);
+                    document.insert(start_idx, &change.text);
+                    content_changes.push(TextDocumentContentChangeEvent {
+                        range: Some(range),
+                        range_length: None,
+                        text: change.text,
+                    });
+                }
+                None => {
+                    *document = Rope::from_str(&change.text);
+                    content_changes.push(TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: change.text,
+                    });
+                }
+            }
+        }
+
+        if !self.incremental_sync {
+            content_changes = vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: document.to_string(),
+            }];
+        }
+        content_changes
+    }
+
+    /// Character index of a line/column position, clamped to the document
+    fn char_index(document: &Rope, pos: Position) -> usize {
+        let line = pos.line.min(document.len_lines().saturating_sub(1));
+        let line_start = document.line_to_char(line);
+        let line_len = document.line(line).len_chars();
+        line_start + pos.column.min(line_len)
+    }
+
+    /// LSP position (UTF-16 column) of a position in an open document
+    fn lsp_position(&self, path: &Path, position: Position) -> lsp_types::Position {
+        match self.documents.get(path) {
+            Some(document) => Self::utf16_position(document, Self::char_index(document, position)),
+            None => lsp_types::Position {
+                line: position.line as u32,
+                character: position.column as u32,
+            },
+        }
+    }
+
+    /// LSP position (UTF-16 columns) of a character index
+    fn utf16_position(document: &Rope, char_idx: usize) -> lsp_types::Position {
+        let line = document.char_to_line(char_idx);
+        let line_start = document.line_to_char(line);
+        let character: usize = document.slice(line_start..char_idx).chars().map(char::len_utf16).sum();
+        lsp_types::Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
     /// Handle didSave notification
     async fn did_save(&mut self, path: PathBuf) -> Result<()> {
         // Convert to absolute path
@@ -613,6 +1037,26 @@ impl LspClient {
         self.send_notification::<DidSaveTextDocument>(params).await
     }
 
+    /// Handle didClose notification: forget our copy of the document
+    async fn did_close(&mut self, path: PathBuf) -> Result<()> {
+        // Convert to absolute path
+        let abs_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            std::env::current_dir()?.join(&path)
+        };
+        self.documents.remove(&path);
+
+        let uri = Url::from_file_path(&abs_path)
+            .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
+
+        let params = DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri },
+        };
+
+        self.send_notification::<DidCloseTextDocument>(params).await
+    }
+
     /// Handle goto definition request
     async fn goto_definition(&mut self, path: PathBuf, position: Position) -> Result<()> {
         // Convert to absolute path
@@ -675,6 +1119,193 @@ impl LspClient {
 
         self.send_request::<lsp_types::request::Completion>(params).await
     }
+
+    /// Request documentation of the symbol at a given position
+    async fn hover(&mut self, path: PathBuf, position: Position) -> Result<()> {
+        // Convert to absolute path
+        let abs_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            std::env::current_dir()?.join(&path)
+        };
+
+        let uri = Url::from_file_path(&abs_path)
+            .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
+
+        let params = lsp_types::HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: self.lsp_position(&path, position),
+            },
+            work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+        };
+
+        self.send_request::<HoverRequest>(params).await
+    }
+
+    /// Request every reference to the symbol at a given position, its
+    /// declaration included
+    async fn references(&mut self, path: PathBuf, position: Position) -> Result<()> {
+        // Convert to absolute path
+        let abs_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            std::env::current_dir()?.join(&path)
+        };
+
+        let uri = Url::from_file_path(&abs_path)
+            .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
+
+        let params = lsp_types::ReferenceParams {
+            text_document_position: lsp_types::TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: self.lsp_position(&path, position),
+            },
+            work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+            partial_result_params: lsp_types::PartialResultParams::default(),
+            context: lsp_types::ReferenceContext {
+                include_declaration: true,
+            },
+        };
+
+        self.send_request::<References>(params).await
+    }
+
+    /// Request the signature of the call at a given position
+    async fn signature_help(&mut self, path: PathBuf, position: Position) -> Result<()> {
+        // Convert to absolute path
+        let abs_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            std::env::current_dir()?.join(&path)
+        };
+
+        let uri = Url::from_file_path(&abs_path)
+            .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
+
+        let params = lsp_types::SignatureHelpParams {
+            context: None,
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: self.lsp_position(&path, position),
+            },
+            work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+        };
+
+        self.send_request::<SignatureHelpRequest>(params).await
+    }
+
+    /// Check whether the symbol at a given position can be renamed
+    async fn prepare_rename(&mut self, path: PathBuf, position: Position) -> Result<()> {
+        // Convert to absolute path
+        let abs_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            std::env::current_dir()?.join(&path)
+        };
+
+        let uri = Url::from_file_path(&abs_path)
+            .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
+
+        let params = lsp_types::TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: self.lsp_position(&path, position),
+        };
+
+        self.send_request::<PrepareRenameRequest>(params).await
+    }
+
+    /// Request the edits that rename the symbol at a given position
+    async fn rename(&mut self, path: PathBuf, position: Position, new_name: String) -> Result<()> {
+        // Convert to absolute path
+        let abs_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            std::env::current_dir()?.join(&path)
+        };
+
+        let uri = Url::from_file_path(&abs_path)
+            .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
+
+        let params = lsp_types::RenameParams {
+            text_document_position: lsp_types::TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: self.lsp_position(&path, position),
+            },
+            new_name,
+            work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+        };
+
+        self.send_request::<Rename>(params).await
+    }
+
+    /// Request the code actions for a range with the diagnostics overlapping
+    /// it. They are sent back as the server published them, with their code,
+    /// source, data and original range, so the server can recognise them.
+    /// `automatic` requests are the editor's own (for the lightbulb).
+    async fn code_actions(
+        &mut self,
+        path: PathBuf,
+        range: (Position, Position),
+        diagnostics: Vec<Diagnostic>,
+        automatic: bool,
+    ) -> Result<()> {
+        // Convert to absolute path
+        let abs_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            std::env::current_dir()?.join(&path)
+        };
+
+        let uri = Url::from_file_path(&abs_path)
+            .map_err(|_| anyhow::anyhow!("Invalid file path: {:?}", abs_path))?;
+
+        let diagnostics = diagnostics.into_iter().map(|diagnostic| diagnostic.lsp).collect();
+
+        let params = lsp_types::CodeActionParams {
+            text_document: TextDocumentIdentifier { uri },
+            range: lsp_types::Range::new(self.lsp_position(&path, range.0), self.lsp_position(&path, range.1)),
+            context: lsp_types::CodeActionContext {
+                diagnostics,
+                only: None,
+                trigger_kind: Some(if automatic {
+                    lsp_types::CodeActionTriggerKind::AUTOMATIC
+                } else {
+                    lsp_types::CodeActionTriggerKind::INVOKED
+                }),
+            },
+            work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+            partial_result_params: lsp_types::PartialResultParams::default(),
+        };
+
+        self.send_request_marked::<CodeActionRequest>(params, automatic).await
+    }
+
+    /// Run a command of the language server
+    async fn execute_command(&mut self, command: LspCommand) -> Result<()> {
+        let params = lsp_types::ExecuteCommandParams {
+            command: command.command,
+            arguments: command.arguments.unwrap_or_default(),
+            work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+        };
+
+        self.send_request::<ExecuteCommand>(params).await
+    }
+
+    /// Answer a `workspace/applyEdit` request of the server
+    async fn apply_edit_result(&mut self, id: serde_json::Value, applied: bool) -> Result<()> {
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": lsp_types::ApplyWorkspaceEditResponse {
+                applied,
+                failure_reason: None,
+                failed_change: None,
+            },
+        });
+
+        self.send_message(&response.to_string()).await
+    }
 }
 
 /// Main LSP task handler
@@ -683,6 +1314,8 @@ pub async fn lsp_task_handler(
     response_tx: mpsc::UnboundedSender<LspResponse>,
 ) {
     let mut clients: HashMap<String, LspClient> = HashMap::new();
+    // Client (language id) each open document was sent to
+    let mut documents: HashMap<PathBuf, String> = HashMap::new();
 
     while let Some(request) = request_rx.recv().await {
         match request {
@@ -692,9 +1325,10 @@ pub async fn lsp_task_handler(
                 content,
                 language,
             } => {
-                // Detect language from path
-                if let Some(lang) = Language::from_path(&path) {
+                // The editor decided the document's language
+                if let Some(lang) = Language::from_id(&language) {
                     let key = lang.language_id().to_string();
+                    documents.insert(path.clone(), key.clone());
 
                     // Create client if doesn't exist
                     if !clients.contains_key(&key) {
@@ -718,33 +1352,37 @@ pub async fn lsp_task_handler(
             }
             LspRequest::DidChange {
                 path,
-                content,
+                changes,
                 version,
                 ..
             } => {
-                if let Some(lang) = Language::from_path(&path) {
-                    let key = lang.language_id().to_string();
-                    if let Some(client) = clients.get_mut(&key) {
-                        let _ = client.did_change(path, content, version).await;
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        let _ = client.did_change(path, changes, version).await;
                     }
                 }
             }
             LspRequest::DidSave { path, .. } => {
-                if let Some(lang) = Language::from_path(&path) {
-                    let key = lang.language_id().to_string();
-                    if let Some(client) = clients.get_mut(&key) {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
                         let _ = client.did_save(path).await;
                     }
                 }
             }
+            LspRequest::DidClose { path, .. } => {
+                if let Some(key) = documents.remove(&path) {
+                    if let Some(client) = clients.get_mut(&key) {
+                        let _ = client.did_close(path).await;
+                    }
+                }
+            }
             LspRequest::GotoDefinition {
                 path,
                 position,
                 ..
             } => {
-                if let Some(lang) = Language::from_path(&path) {
-                    let key = lang.language_id().to_string();
-                    if let Some(client) = clients.get_mut(&key) {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
                         let _ = client.goto_definition(path, position).await;
                     }
                 }
@@ -755,11 +1393,10 @@ pub async fn lsp_task_handler(
                 position,
             } => {
                 lsp_debug!("[TASK HANDLER DEBUG] Received completion request for buffer {} at {:?} line:{} col:{}", buffer_id, path, position.line, position.column);
-                if let Some(lang) = Language::from_path(&path) {
-                    let key = lang.language_id().to_string();
+                if let Some(key) = documents.get(&path) {
                     lsp_debug!("[TASK HANDLER DEBUG] Looking for client with key: {}", key);
                     lsp_debug!("[TASK HANDLER DEBUG] Available clients: {:?}", clients.keys().collect::<Vec<_>>());
-                    if let Some(client) = clients.get_mut(&key) {
+                    if let Some(client) = clients.get_mut(key) {
                         lsp_debug!("[TASK HANDLER DEBUG] Found client, calling completion...");
                         let result = client.completion(path, position).await;
                         lsp_debug!("[TASK HANDLER DEBUG] Completion call result: {:?}", result);
@@ -768,9 +1405,218 @@ pub async fn lsp_task_handler(
                     }
                 }
             }
+            LspRequest::Hover { path, position, .. } => {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        let _ = client.hover(path, position).await;
+                    }
+                }
+            }
+            LspRequest::SignatureHelp { path, position, .. } => {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        let _ = client.signature_help(path, position).await;
+                    }
+                }
+            }
+            LspRequest::References { path, position, .. } => {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        let _ = client.references(path, position).await;
+                    }
+                }
+            }
+            LspRequest::PrepareRename { path, position, .. } => {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        if client.prepare_rename {
+                            let _ = client.prepare_rename(path, position).await;
+                        } else {
+                            // Leave it to the rename request to find a symbol
+                            let _ = response_tx.send(LspResponse::PrepareRename {
+                                renameable: true,
+                                placeholder: None,
+                            });
+                        }
+                    }
+                }
+            }
+            LspRequest::Rename {
+                path,
+                position,
+                new_name,
+                ..
+            } => {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        let _ = client.rename(path, position, new_name).await;
+                    }
+                }
+            }
+            LspRequest::CodeActions {
+                path,
+                range,
+                diagnostics,
+                automatic,
+                ..
+            } => {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        let _ = client.code_actions(path, range, diagnostics, automatic).await;
+                    }
+                }
+            }
+            LspRequest::ExecuteCommand { path, command, .. } => {
+                if let Some(key) = documents.get(&path) {
+                    if let Some(client) = clients.get_mut(key) {
+                        let _ = client.execute_command(command).await;
+                    }
+                }
+            }
+            LspRequest::ApplyEditResult { language, id, applied } => {
+                if let Some(client) = clients.get_mut(&language) {
+                    let _ = client.apply_edit_result(id, applied).await;
+                }
+            }
             LspRequest::Shutdown => {
                 break;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_diagnostics_keeps_published_diagnostic() {
+        let published: lsp_types::Diagnostic = serde_json::from_value(serde_json::json!({
+            "range": {"start": {"line": 2, "character": 4}, "end": {"line": 2, "character": 9}},
+            "severity": 2,
+            "code": "unused_variables",
+            "source": "rustc",
+            "message": "unused variable: `total`",
+            "data": {"fix": 1}
+        }))
+        .unwrap();
+        let diagnostics = LspClient::convert_diagnostics(vec![published.clone()]);
+        assert_eq!(diagnostics[0].range, (Position::new(2, 4), Position::new(2, 9)));
+        assert_eq!(diagnostics[0].severity, DiagnosticSeverity::Warning);
+        assert_eq!(diagnostics[0].lsp, published);
+    }
+
+    #[test]
+    fn test_convert_signature_help() {
+        let help: lsp_types::SignatureHelp = serde_json::from_value(serde_json::json!({
+            "signatures": [{
+                "label": "fn greet(name: &str, émoji: char)",
+                "parameters": [{"label": "name: &str"}, {"label": [21, 32]}]
+            }],
+            "activeParameter": 1
+        }))
+        .unwrap();
+        let signature = LspClient::convert_signature_help(help).unwrap();
+        assert_eq!(signature.active_parameter, Some((21, 32)));
+        let label: String = signature.label.chars().skip(21).take(11).collect();
+        assert_eq!(label, "émoji: char");
+
+        let help: lsp_types::SignatureHelp = serde_json::from_value(serde_json::json!({
+            "signatures": [{"label": "f(a, b)", "parameters": [{"label": "a"}, {"label": "b"}]}],
+            "activeSignature": 0,
+            "activeParameter": 1
+        }))
+        .unwrap();
+        // A parameter name is found in the label
+        assert_eq!(LspClient::convert_signature_help(help).unwrap().active_parameter, Some((5, 6)));
+
+        let help: lsp_types::SignatureHelp = serde_json::from_value(serde_json::json!({
+            "signatures": [{"label": "f(a, b)", "parameters": [{"label": [5, 2]}]}],
+            "activeParameter": 0
+        }))
+        .unwrap();
+        // Reversed offsets highlight nothing
+        assert_eq!(LspClient::convert_signature_help(help).unwrap().active_parameter, None);
+
+        let empty: lsp_types::SignatureHelp = serde_json::from_value(serde_json::json!({"signatures": []})).unwrap();
+        assert!(LspClient::convert_signature_help(empty).is_none());
+    }
+
+    #[test]
+    fn test_handle_code_actions_and_apply_edit() {
+        let (response_tx, mut response_rx) = mpsc::unbounded_channel();
+        let pending_requests: PendingRequests = Arc::new(Mutex::new(HashMap::from([
+            (7, PendingRequest { method: CodeActionRequest::METHOD, automatic: false }),
+            (8, PendingRequest { method: CodeActionRequest::METHOD, automatic: true }),
+        ])));
+        let handle = |message: serde_json::Value| {
+            LspClient::handle_message(&message.to_string(), &response_tx, Language::Rust, 0, &pending_requests)
+        };
+
+        handle(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "result": [
+                {"title": "Run cargo fmt", "command": "fmt"},
+                {"title": "Remove unused import", "kind": "quickfix", "isPreferred": true, "edit": {"changes": {}}}
+            ]
+        }));
+        let Some(LspResponse::CodeActions { actions, automatic: false }) = response_rx.try_recv().ok() else {
+            panic!("expected code actions");
+        };
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].command.as_ref().map(|c| c.command.as_str()), Some("fmt"));
+        assert!(actions[0].edit.is_none());
+        assert_eq!(actions[1].kind.as_deref(), Some("quickfix"));
+        assert!(actions[1].preferred && actions[1].edit.is_some());
+
+        // The lightbulb's own request is told apart from the user's
+        handle(serde_json::json!({"jsonrpc": "2.0", "id": 8, "result": []}));
+        assert!(matches!(response_rx.try_recv(), Ok(LspResponse::CodeActions { automatic: true, .. })));
+
+        // A request of the server keeps its id for the answer
+        handle(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": "apply-1",
+            "method": "workspace/applyEdit",
+            "params": {"label": "Fix", "edit": {"changes": {"file:///src/a.rs": []}}}
+        }));
+        let Some(LspResponse::ApplyEdit { language, id, label, edit }) = response_rx.try_recv().ok() else {
+            panic!("expected an edit to apply");
+        };
+        assert_eq!((language.as_str(), label.as_deref()), ("rust", Some("Fix")));
+        assert_eq!(id, serde_json::json!("apply-1"));
+        assert_eq!(edit.files.len(), 1);
+    }
+
+    #[test]
+    fn test_convert_workspace_edit() {
+        let range = |line: u32| serde_json::json!({"start": {"line": line, "character": 4}, "end": {"line": line, "character": 7}});
+        let edit: lsp_types::WorkspaceEdit = serde_json::from_value(serde_json::json!({
+            "documentChanges": [
+                {"textDocument": {"uri": "file:///src/a.rs", "version": 3}, "edits": [{"range": range(0), "newText": "bar"}]},
+                {"kind": "create", "uri": "file:///src/new.rs"},
+                {"textDocument": {"uri": "file:///src/b.rs", "version": null}, "edits": [{"range": range(2), "newText": "bar"}]},
+                {"textDocument": {"uri": "file:///src/a.rs", "version": 3}, "edits": [{"range": range(5), "newText": "bar"}]}
+            ]
+        }))
+        .unwrap();
+        let edit = LspClient::convert_workspace_edit(edit);
+        assert_eq!(edit.skipped_operations, 1);
+        // Edits of the same file stay separate steps, in the server's order
+        let files: Vec<(&str, Option<i32>, usize)> =
+            edit.files.iter().map(|f| (f.path.to_str().unwrap(), f.version, f.edits.len())).collect();
+        assert_eq!(files, vec![("/src/a.rs", Some(3), 1), ("/src/b.rs", None, 1), ("/src/a.rs", Some(3), 1)]);
+        assert_eq!(edit.files[1].edits[0].range, (Position::new(2, 4), Position::new(2, 7)));
+        assert_eq!(edit.files[2].edits[0].range, (Position::new(5, 4), Position::new(5, 7)));
+
+        let edit: lsp_types::WorkspaceEdit = serde_json::from_value(serde_json::json!({
+            "changes": {"file:///src/c.rs": [{"range": range(1), "newText": "baz"}]}
+        }))
+        .unwrap();
+        let edit = LspClient::convert_workspace_edit(edit);
+        assert_eq!(edit.files[0].path, PathBuf::from("/src/c.rs"));
+        assert_eq!(edit.files[0].version, None);
+        assert_eq!(edit.files[0].edits[0].new_text, "baz");
+    }
+}
@@ -1,7 +1,8 @@
-use crate::lsp::protocol::{CompletionItem, CompletionItemKind};
+use crate::lsp::protocol::{CodeAction, CompletionItem, CompletionItemKind, Signature};
 use crate::render::terminal::Terminal;
 use anyhow::Result;
-use crossterm::style::Color;
+use crate::syntax::Theme;
+use crossterm::style::Attribute;
 
 /// Completion popup UI
 pub struct CompletionPopup;
@@ -14,6 +15,7 @@ impl CompletionPopup {
         selected: usize,
         scroll_offset: usize,
         cursor_screen_pos: (u16, u16),
+        theme: &Theme,
     ) -> Result<()> {
         if items.is_empty() {
             return Ok(());
@@ -45,11 +47,11 @@ impl CompletionPopup {
 
             // Set background for selected item
             if item_index == selected {
-                terminal.set_bg(Color::DarkBlue)?;
-                terminal.set_fg(Color::White)?;
+                terminal.set_bg(theme.ui.popup_selected_bg)?;
+                terminal.set_fg(theme.ui.popup_selected_fg)?;
             } else {
-                terminal.set_bg(Color::DarkGrey)?;
-                terminal.set_fg(Color::White)?;
+                terminal.set_bg(theme.ui.popup_bg)?;
+                terminal.set_fg(theme.ui.popup_fg)?;
             }
 
             // Icon based on kind
@@ -96,3 +98,128 @@ impl CompletionPopup {
         }
     }
 }
+
+/// Signature help popup UI: the signature of the call being typed
+pub struct SignatureHelpPopup;
+
+impl SignatureHelpPopup {
+    /// Render the signature on one line above the cursor (below it on the
+    /// first line), with the active parameter emphasized
+    pub fn render(
+        terminal: &Terminal,
+        signature: &Signature,
+        cursor_screen_pos: (u16, u16),
+        theme: &Theme,
+    ) -> Result<()> {
+        let (term_width, _) = terminal.size();
+        let (cursor_x, cursor_y) = cursor_screen_pos;
+        let mut chars: Vec<char> = signature.label.chars().collect();
+        let (active_start, active_end) = signature.active_parameter.unwrap_or((0, 0));
+
+        // Cut long signatures around the active parameter
+        let max_width = (term_width as usize).saturating_sub(2).max(1);
+        let start = if chars.len() > max_width {
+            active_start.saturating_sub(max_width / 3).min(chars.len() - max_width)
+        } else {
+            0
+        };
+        let end = (start + max_width).min(chars.len());
+        if start > 0 {
+            chars[start] = '…';
+        }
+        if end < chars.len() {
+            chars[end - 1] = '…';
+        }
+
+        // The tab bar and path bar take the first two rows
+        let popup_y = if cursor_y > 2 { cursor_y - 1 } else { cursor_y + 1 };
+        let popup_width = (end - start + 2) as u16;
+        let popup_x = cursor_x.min(term_width.saturating_sub(popup_width));
+        terminal.move_cursor(popup_x, popup_y)?;
+
+        let active = active_start.clamp(start, end)..active_end.clamp(start, end);
+        let text = |range: std::ops::Range<usize>| chars[range].iter().collect::<String>();
+        terminal.set_bg(theme.ui.popup_bg)?;
+        terminal.set_fg(theme.ui.popup_fg)?;
+        terminal.print(&format!(" {}", text(start..active.start)))?;
+        terminal.set_bg(theme.ui.popup_selected_bg)?;
+        terminal.set_fg(theme.ui.popup_selected_fg)?;
+        terminal.set_attribute(Attribute::Bold)?;
+        terminal.print(&text(active.clone()))?;
+        terminal.set_attribute(Attribute::NormalIntensity)?;
+        terminal.set_bg(theme.ui.popup_bg)?;
+        terminal.set_fg(theme.ui.popup_fg)?;
+        terminal.print(&format!("{} ", text(active.end..end)))?;
+        terminal.reset_color()?;
+
+        Ok(())
+    }
+}
+
+/// Code action picker UI: the actions offered at the cursor
+pub struct CodeActionPopup;
+
+impl CodeActionPopup {
+    /// Most actions shown at once
+    pub const MAX_ROWS: usize = 10;
+
+    /// Render the actions below the cursor (above it near the bottom). The
+    /// preferred fix is starred and disabled actions are dimmed.
+    pub fn render(
+        terminal: &Terminal,
+        actions: &[CodeAction],
+        selected: usize,
+        scroll_offset: usize,
+        cursor_screen_pos: (u16, u16),
+        theme: &Theme,
+    ) -> Result<()> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+
+        let (term_width, term_height) = terminal.size();
+        let (cursor_x, cursor_y) = cursor_screen_pos;
+
+        let rows = Self::MAX_ROWS.min(actions.len());
+        let popup_height = rows as u16;
+        let longest = actions.iter().map(|action| action.title.chars().count()).max().unwrap_or(0);
+        let popup_width = ((longest + 4) as u16).clamp(20, 60).min(term_width.saturating_sub(2));
+
+        let popup_y = if cursor_y + popup_height + 1 < term_height {
+            cursor_y + 1
+        } else {
+            cursor_y.saturating_sub(popup_height)
+        };
+        let popup_x = cursor_x.min(term_width.saturating_sub(popup_width));
+
+        for (i, action) in actions.iter().enumerate().skip(scroll_offset).take(rows) {
+            terminal.move_cursor(popup_x, popup_y + (i - scroll_offset) as u16)?;
+            if i == selected {
+                terminal.set_bg(theme.ui.popup_selected_bg)?;
+                terminal.set_fg(theme.ui.popup_selected_fg)?;
+            } else {
+                terminal.set_bg(theme.ui.popup_bg)?;
+                terminal.set_fg(theme.ui.popup_fg)?;
+            }
+            if action.disabled.is_some() {
+                terminal.set_attribute(Attribute::Dim)?;
+            }
+
+            // Cut long titles and pad to popup width
+            let marker = if action.preferred { '★' } else { ' ' };
+            let max_title = (popup_width as usize).saturating_sub(3);
+            let mut title: String = action.title.chars().take(max_title).collect();
+            if action.title.chars().count() > max_title {
+                title.pop();
+                title.push('…');
+            }
+            let padding = max_title - title.chars().count();
+            terminal.print(&format!("{} {}{} ", marker, title, " ".repeat(padding)))?;
+
+            terminal.set_attribute(Attribute::NormalIntensity)?;
+            terminal.reset_color()?;
+        }
+
+        Ok(())
+    }
+}
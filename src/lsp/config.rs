@@ -1,5 +1,3 @@
-use std::path::Path;
-
 /// Supported programming languages for LSP
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Language {
@@ -9,16 +7,14 @@ pub enum Language {
 }
 
 impl Language {
-    /// Detect language from file extension
-    pub fn from_path(path: &Path) -> Option<Self> {
-        path.extension()
-            .and_then(|ext| ext.to_str())
-            .and_then(|ext| match ext {
-                "rs" => Some(Language::Rust),
-                "py" | "pyw" | "pyi" => Some(Language::Python),
-                "cpp" | "cxx" | "cc" | "c++" | "hpp" | "hxx" | "hh" | "h++" | "c" | "h" => Some(Language::Cpp),
-                _ => None,
-            })
+    /// Language of an LSP language identifier (see `language_id`)
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "rust" => Some(Language::Rust),
+            "python" => Some(Language::Python),
+            "cpp" => Some(Language::Cpp),
+            _ => None,
+        }
     }
 
     /// Get the language identifier for LSP
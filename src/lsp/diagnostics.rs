@@ -1,4 +1,5 @@
-use crate::lsp::protocol::{BufferId, Diagnostic};
+use crate::buffer::EditEvent;
+use crate::lsp::protocol::{BufferId, Diagnostic, Position};
 use std::collections::HashMap;
 
 /// Store for diagnostics from the language server
@@ -23,6 +24,22 @@ impl DiagnosticsStore {
         }
     }
 
+    /// Move the diagnostics of a buffer along with an edit, so they stay on
+    /// their code until the server sends new ones
+    pub fn apply_edit(&mut self, buffer_id: BufferId, edit: &EditEvent) {
+        let Some(diagnostics) = self.diagnostics.get_mut(&buffer_id) else {
+            return;
+        };
+        let map = |pos: Position| {
+            let mapped = edit.map_position(crate::buffer::Position::new(pos.line, pos.column));
+            Position::new(mapped.line, mapped.column)
+        };
+        for diagnostic in diagnostics.iter_mut() {
+            let (start, end) = diagnostic.range;
+            diagnostic.range = (map(start), map(end));
+        }
+    }
+
     /// Get diagnostics for a buffer
     pub fn get(&self, buffer_id: BufferId) -> Option<&[Diagnostic]> {
         self.diagnostics.get(&buffer_id).map(|v| v.as_slice())
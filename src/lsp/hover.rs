@@ -0,0 +1,546 @@
+use crate::render::terminal::Terminal;
+use crate::syntax::{HighlightSpan, Theme, TokenType};
+use anyhow::Result;
+use crossterm::style::Attribute;
+use unicode_width::UnicodeWidthStr;
+
+/// Widest the popup gets, in columns of text
+const MAX_WIDTH: usize = 80;
+
+/// Most lines shown at once; longer documentation scrolls
+const MAX_HEIGHT: usize = 15;
+
+/// How a piece of hover text is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoverStyle {
+    Text,
+    Bold,
+    Italic,
+    /// Inline code
+    Code,
+    Heading,
+    /// A horizontal rule, drawn across the popup
+    Rule,
+    /// Code of a fenced block, colored like a buffer
+    Syntax(Option<TokenType>),
+}
+
+/// A run of hover text in one style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoverSpan {
+    pub text: String,
+    pub style: HoverStyle,
+}
+
+/// A line of hover documentation, before wrapping
+pub type HoverLine = Vec<HoverSpan>;
+
+/// Turn hover documentation into styled lines. Markdown gets basic styling
+/// and its fenced code blocks are colored with `highlight(code, language)`;
+/// plain text is shown as it is.
+pub fn hover_lines(
+    contents: &str,
+    markdown: bool,
+    mut highlight: impl FnMut(&str, &str) -> Vec<HighlightSpan>,
+) -> Vec<HoverLine> {
+    let mut lines: Vec<HoverLine> = Vec::new();
+    if !markdown {
+        for line in contents.lines() {
+            push_span(&mut lines, line.replace('\t', "    "), HoverStyle::Text);
+        }
+        return trim_blank_lines(lines);
+    }
+
+    // Consecutive text lines are joined into a paragraph, wrapped later
+    let mut paragraph: Option<String> = None;
+    // Marker, language and code of the fenced block being read
+    let mut fence: Option<(&str, String, String)> = None;
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if let Some((marker, language, code)) = &mut fence {
+            if trimmed.starts_with(*marker) {
+                lines.extend(code_lines(code, language, &mut highlight));
+                fence = None;
+            } else {
+                code.push_str(line);
+                code.push('\n');
+            }
+            continue;
+        }
+
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|marker| trimmed.starts_with(marker)) {
+            flush_paragraph(&mut lines, &mut paragraph);
+            let info = trimmed.trim_start_matches(&marker[..1]).trim();
+            let language = info.split(|c: char| c == ',' || c.is_whitespace()).next().unwrap_or_default();
+            fence = Some((marker, language.to_string(), String::new()));
+        } else if trimmed.is_empty() {
+            flush_paragraph(&mut lines, &mut paragraph);
+            if lines.last().is_some_and(|line| !line.is_empty()) {
+                lines.push(Vec::new());
+            }
+        } else if is_rule(trimmed) {
+            flush_paragraph(&mut lines, &mut paragraph);
+            lines.push(vec![HoverSpan { text: String::new(), style: HoverStyle::Rule }]);
+        } else if let Some(title) = heading(trimmed) {
+            flush_paragraph(&mut lines, &mut paragraph);
+            let text: String = inline_spans(title).into_iter().map(|span| span.text).collect();
+            lines.push(vec![HoverSpan { text, style: HoverStyle::Heading }]);
+        } else if let Some(item) = list_item(trimmed) {
+            flush_paragraph(&mut lines, &mut paragraph);
+            paragraph = Some(item);
+        } else {
+            match &mut paragraph {
+                Some(text) => {
+                    text.push(' ');
+                    text.push_str(trimmed);
+                }
+                None => paragraph = Some(trimmed.to_string()),
+            }
+            // Two trailing spaces or a backslash end the line
+            if line.ends_with("  ") || line.ends_with('\\') {
+                if let Some(text) = &mut paragraph {
+                    text.truncate(text.trim_end_matches('\\').len());
+                }
+                flush_paragraph(&mut lines, &mut paragraph);
+            }
+        }
+    }
+    flush_paragraph(&mut lines, &mut paragraph);
+    if let Some((_, language, code)) = fence {
+        lines.extend(code_lines(&code, &language, &mut highlight));
+    }
+    trim_blank_lines(lines)
+}
+
+/// Add a line with a single span
+fn push_span(lines: &mut Vec<HoverLine>, text: String, style: HoverStyle) {
+    if text.is_empty() {
+        lines.push(Vec::new());
+    } else {
+        lines.push(vec![HoverSpan { text, style }]);
+    }
+}
+
+/// Add the paragraph being read, if any, as a line
+fn flush_paragraph(lines: &mut Vec<HoverLine>, paragraph: &mut Option<String>) {
+    if let Some(text) = paragraph.take() {
+        lines.push(inline_spans(&text));
+    }
+}
+
+/// Drop the blank lines at the start and end
+fn trim_blank_lines(mut lines: Vec<HoverLine>) -> Vec<HoverLine> {
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let first = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    lines.split_off(first)
+}
+
+/// Whether a line is a horizontal rule: three or more `-`, `*` or `_`
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|&marker| chars.iter().all(|&c| c == marker))
+}
+
+/// Title of an ATX heading (`# Title`)
+fn heading(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let rest = &line[level..];
+    (rest.is_empty() || rest.starts_with(' ')).then(|| rest.trim().trim_end_matches('#').trim_end())
+}
+
+/// A list item, with a bullet in place of `-`, `*` or `+`
+fn list_item(line: &str) -> Option<String> {
+    if let Some(rest) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")).or_else(|| line.strip_prefix("+ ")) {
+        return Some(format!("• {}", rest.trim_start()));
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = &line[digits..];
+    (digits > 0 && (rest.starts_with(". ") || rest.starts_with(") "))).then(|| line.to_string())
+}
+
+/// Spans of a paragraph: `code`, **bold** and *italic* text, with links
+/// shown as their text and escapes resolved
+fn inline_spans(text: &str) -> HoverLine {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans: HoverLine = Vec::new();
+    let push = |spans: &mut HoverLine, text: &str, style: HoverStyle| match spans.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(text),
+        _ if text.is_empty() => {}
+        _ => spans.push(HoverSpan { text: text.to_string(), style }),
+    };
+    let (mut bold, mut italic) = (false, false);
+    let mut i = 0;
+
+    while i < chars.len() {
+        let style = if bold {
+            HoverStyle::Bold
+        } else if italic {
+            HoverStyle::Italic
+        } else {
+            HoverStyle::Text
+        };
+        let c = chars[i];
+        let prev = i.checked_sub(1).map(|j| chars[j]);
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                push(&mut spans, &chars[i + 1].to_string(), style);
+                i += 2;
+            }
+            '`' => {
+                // The code ends at a run of as many backticks
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let fence: String = "`".repeat(run);
+                let rest: String = chars[i + run..].iter().collect();
+                match rest.find(&fence) {
+                    Some(end) => {
+                        push(&mut spans, rest[..end].trim(), HoverStyle::Code);
+                        i += run + rest[..end].chars().count() + run;
+                    }
+                    None => {
+                        push(&mut spans, &fence, style);
+                        i += run;
+                    }
+                }
+            }
+            '*' | '_' => {
+                let double = chars.get(i + 1) == Some(&c);
+                let len = if double { 2 } else { 1 };
+                let next = chars.get(i + len).copied();
+                let opens = next.is_some_and(|next| !next.is_whitespace());
+                let closes = prev.is_some_and(|prev| !prev.is_whitespace());
+                // Underscores inside words (snake_case) are not emphasis
+                let intraword = c == '_'
+                    && prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric);
+                let emphasis = if double { &mut bold } else { &mut italic };
+                if !intraword && ((*emphasis && closes) || (!*emphasis && opens)) {
+                    *emphasis = !*emphasis;
+                } else {
+                    push(&mut spans, &chars[i..i + len].iter().collect::<String>(), style);
+                }
+                i += len;
+            }
+            '[' => {
+                // [text](url) shows as its text
+                let rest: String = chars[i + 1..].iter().collect();
+                let link = rest.find("](").and_then(|close| {
+                    let end = rest[close + 2..].find(')')?;
+                    Some((close, close + 2 + end + 1))
+                });
+                match link {
+                    Some((close, end)) => {
+                        push(&mut spans, &rest[..close], style);
+                        i += 1 + rest[..end].chars().count();
+                    }
+                    None => {
+                        push(&mut spans, "[", style);
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                push(&mut spans, &c.to_string(), style);
+                i += 1;
+            }
+        }
+    }
+    spans
+}
+
+/// Lines of a fenced code block, colored by token type
+fn code_lines(
+    code: &str,
+    language: &str,
+    highlight: &mut impl FnMut(&str, &str) -> Vec<HighlightSpan>,
+) -> Vec<HoverLine> {
+    let highlights = highlight(code, language);
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    for line in code.lines() {
+        let mut spans: HoverLine = Vec::new();
+        for (offset, c) in line.char_indices() {
+            let byte = line_start + offset;
+            // Spans are sorted by start, so the innermost one comes last
+            let token = highlights
+                .iter()
+                .rev()
+                .find(|span| span.start_byte <= byte && byte < span.end_byte)
+                .map(|span| span.token_type);
+            let style = HoverStyle::Syntax(token);
+            let text = if c == '\t' { "    ".to_string() } else { c.to_string() };
+            match spans.last_mut() {
+                Some(last) if last.style == style => last.text.push_str(&text),
+                _ => spans.push(HoverSpan { text, style }),
+            }
+        }
+        lines.push(spans);
+        line_start += line.len() + 1;
+    }
+    lines
+}
+
+/// Screen columns taken up by a line
+fn line_width(line: &HoverLine) -> usize {
+    line.iter().map(|span| span.text.width()).sum()
+}
+
+/// Wrap a line to `width` columns, breaking at spaces where possible
+fn wrap(line: &HoverLine, width: usize) -> Vec<HoverLine> {
+    if line_width(line) <= width {
+        return vec![line.clone()];
+    }
+    let mut rows: Vec<HoverLine> = vec![Vec::new()];
+    let mut row_width = 0;
+
+    for span in line {
+        // Words and the whitespace between them
+        let mut words: Vec<&str> = Vec::new();
+        let mut start = 0;
+        for (i, c) in span.text.char_indices().skip(1) {
+            let prev = span.text[..i].chars().next_back().unwrap_or(c);
+            if prev.is_whitespace() != c.is_whitespace() {
+                words.push(&span.text[start..i]);
+                start = i;
+            }
+        }
+        words.push(&span.text[start..]);
+
+        for word in words {
+            let is_space = word.starts_with(char::is_whitespace);
+            if row_width > 0 && row_width + word.width() > width {
+                rows.push(Vec::new());
+                row_width = 0;
+            }
+            // Wrapped rows don't start with the space they were broken at
+            if is_space && row_width == 0 && rows.len() > 1 {
+                continue;
+            }
+            // Words too long for a row are broken anywhere
+            let mut piece = String::new();
+            for c in word.chars() {
+                let c_width = c.to_string().width();
+                if row_width + piece.width() + c_width > width && row_width + piece.width() > 0 {
+                    push_text(rows.last_mut(), std::mem::take(&mut piece), span.style);
+                    rows.push(Vec::new());
+                    row_width = 0;
+                }
+                piece.push(c);
+            }
+            row_width += piece.width();
+            push_text(rows.last_mut(), piece, span.style);
+        }
+    }
+    rows
+}
+
+/// Add text to a row, extending its last span if it has the same style
+fn push_text(row: Option<&mut HoverLine>, text: String, style: HoverStyle) {
+    let Some(row) = row else { return };
+    if text.is_empty() {
+        return;
+    }
+    match row.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(&text),
+        _ => row.push(HoverSpan { text, style }),
+    }
+}
+
+/// Where the popup goes and what it shows
+struct Layout {
+    x: u16,
+    y: u16,
+    /// Columns of text, inside a column of padding on each side
+    width: usize,
+    rows: Vec<HoverLine>,
+    height: usize,
+}
+
+impl Layout {
+    /// Place the popup below the cursor, or above it if there is more room
+    /// there
+    fn new(lines: &[HoverLine], cursor_screen_pos: (u16, u16), term_size: (u16, u16)) -> Self {
+        let (term_width, term_height) = term_size;
+        let (cursor_x, cursor_y) = cursor_screen_pos;
+        let max_width = MAX_WIDTH.min((term_width as usize).saturating_sub(4)).max(1);
+        let width = lines.iter().map(line_width).max().unwrap_or(0).clamp(1, max_width);
+        let rows: Vec<HoverLine> = lines.iter().flat_map(|line| wrap(line, width)).collect();
+
+        // Tab bar and path bar above, status bar below
+        let room_below = (term_height as usize).saturating_sub(cursor_y as usize + 2);
+        let room_above = (cursor_y as usize).saturating_sub(2);
+        let wanted = rows.len().min(MAX_HEIGHT);
+        let (height, y) = if room_below >= wanted || room_below >= room_above {
+            (wanted.min(room_below), cursor_y + 1)
+        } else {
+            let height = wanted.min(room_above);
+            (height, cursor_y - height as u16)
+        };
+
+        let x = cursor_x.min(term_width.saturating_sub(width as u16 + 2));
+        Self { x, y, width, rows, height }
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.rows.len().saturating_sub(self.height)
+    }
+}
+
+/// Hover documentation popup UI
+pub struct HoverPopup;
+
+impl HoverPopup {
+    /// How far the documentation can be scrolled, in lines
+    pub fn max_scroll(lines: &[HoverLine], cursor_screen_pos: (u16, u16), term_size: (u16, u16)) -> usize {
+        Layout::new(lines, cursor_screen_pos, term_size).max_scroll()
+    }
+
+    /// Number of lines shown at once, for scrolling by pages
+    pub fn visible_rows(lines: &[HoverLine], cursor_screen_pos: (u16, u16), term_size: (u16, u16)) -> usize {
+        Layout::new(lines, cursor_screen_pos, term_size).height
+    }
+
+    /// Render the hover popup next to the cursor, scrolled down `scroll` lines
+    pub fn render(
+        terminal: &Terminal,
+        lines: &[HoverLine],
+        scroll: usize,
+        cursor_screen_pos: (u16, u16),
+        theme: &Theme,
+    ) -> Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let layout = Layout::new(lines, cursor_screen_pos, terminal.size());
+        let scroll = scroll.min(layout.max_scroll());
+        let empty = Vec::new();
+
+        for i in 0..layout.height {
+            terminal.move_cursor(layout.x, layout.y + i as u16)?;
+            terminal.set_bg(theme.ui.popup_bg)?;
+            terminal.set_fg(theme.ui.popup_fg)?;
+            terminal.print(" ")?;
+
+            let row = layout.rows.get(scroll + i).unwrap_or(&empty);
+            let mut row_width = 0;
+            for span in row {
+                let text = if span.style == HoverStyle::Rule {
+                    "─".repeat(layout.width)
+                } else {
+                    span.text.clone()
+                };
+                let color = match span.style {
+                    HoverStyle::Text | HoverStyle::Bold | HoverStyle::Italic | HoverStyle::Syntax(None) => {
+                        theme.ui.popup_fg
+                    }
+                    HoverStyle::Code => theme.color_for(TokenType::Type),
+                    HoverStyle::Heading => theme.color_for(TokenType::Keyword),
+                    HoverStyle::Rule => theme.ui.panel_muted,
+                    HoverStyle::Syntax(Some(token_type)) => theme.color_for(token_type),
+                };
+                let attribute = match span.style {
+                    HoverStyle::Bold | HoverStyle::Heading => Some((Attribute::Bold, Attribute::NormalIntensity)),
+                    HoverStyle::Italic => Some((Attribute::Italic, Attribute::NoItalic)),
+                    _ => None,
+                };
+                terminal.set_fg(color)?;
+                if let Some((on, _)) = attribute {
+                    terminal.set_attribute(on)?;
+                }
+                terminal.print(&text)?;
+                if let Some((_, off)) = attribute {
+                    terminal.set_attribute(off)?;
+                }
+                row_width += text.width();
+            }
+
+            // Arrows in the right margin show there is more to scroll to
+            terminal.set_fg(theme.ui.popup_fg)?;
+            let more = if i == 0 && scroll > 0 {
+                "↑"
+            } else if i + 1 == layout.height && scroll < layout.max_scroll() {
+                "↓"
+            } else {
+                " "
+            };
+            terminal.print(&format!("{}{}", " ".repeat(layout.width.saturating_sub(row_width)), more))?;
+            terminal.reset_color()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(line: &HoverLine) -> Vec<(&str, HoverStyle)> {
+        line.iter().map(|span| (span.text.as_str(), span.style)).collect()
+    }
+
+    #[test]
+    fn test_markdown_lines() {
+        let markdown = "# Title\n\nSome **bold** and *italic* text\nwith `code`, a [link](http://x) and snake_case.\n\n---\n- item\n\n```rust\nfn f() {}\n```";
+        let mut languages = Vec::new();
+        let lines = hover_lines(markdown, true, |code, language| {
+            languages.push(language.to_string());
+            vec![HighlightSpan { start_byte: 0, end_byte: 2, token_type: TokenType::Keyword }]
+                .into_iter()
+                .filter(|_| code.starts_with("fn"))
+                .collect()
+        });
+        assert_eq!(languages, vec!["rust"]);
+
+        assert_eq!(texts(&lines[0]), vec![("Title", HoverStyle::Heading)]);
+        assert!(lines[1].is_empty());
+        assert_eq!(
+            texts(&lines[2]),
+            vec![
+                ("Some ", HoverStyle::Text),
+                ("bold", HoverStyle::Bold),
+                (" and ", HoverStyle::Text),
+                ("italic", HoverStyle::Italic),
+                (" text with ", HoverStyle::Text),
+                ("code", HoverStyle::Code),
+                (", a link and snake_case.", HoverStyle::Text),
+            ]
+        );
+        assert_eq!(lines[4][0].style, HoverStyle::Rule);
+        assert_eq!(texts(&lines[5]), vec![("• item", HoverStyle::Text)]);
+        assert_eq!(
+            texts(&lines[7]),
+            vec![("fn", HoverStyle::Syntax(Some(TokenType::Keyword))), (" f() {}", HoverStyle::Syntax(None))]
+        );
+        assert_eq!(lines.len(), 8);
+
+        // Plain text is not interpreted
+        let plain = hover_lines("a *b*\n", false, |_, _| Vec::new());
+        assert_eq!(texts(&plain[0]), vec![("a *b*", HoverStyle::Text)]);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let line = vec![
+            HoverSpan { text: "one two ".to_string(), style: HoverStyle::Text },
+            HoverSpan { text: "three".to_string(), style: HoverStyle::Bold },
+            HoverSpan { text: " abcdefghij".to_string(), style: HoverStyle::Text },
+        ];
+        let rows = wrap(&line, 8);
+        let rows: Vec<Vec<(&str, HoverStyle)>> = rows.iter().map(texts).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![("one two ", HoverStyle::Text)],
+                vec![("three", HoverStyle::Bold), (" ", HoverStyle::Text)],
+                vec![("abcdefgh", HoverStyle::Text)],
+                vec![("ij", HoverStyle::Text)],
+            ]
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use crate::lsp::config::Language;
-use crate::lsp::protocol::{BufferId, LspRequest, LspResponse, Position};
+use crate::lsp::protocol::{BufferId, Diagnostic, LspCommand, LspRequest, LspResponse, Position, TextChange};
 use anyhow::Result;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::sync::mpsc;
 
 /// Manages LSP communication between main thread and background task
@@ -54,12 +54,13 @@ impl LspManager {
         })
     }
 
-    /// Notify LSP that a document was changed
+    /// Notify LSP that a document was changed, with the edits since the
+    /// last notification (applied in order)
     pub fn did_change(
         &mut self,
         buffer_id: BufferId,
         path: PathBuf,
-        content: String,
+        changes: Vec<TextChange>,
     ) -> Result<()> {
         // Get version first before sending request
         let version = {
@@ -73,7 +74,7 @@ impl LspManager {
         self.send_request(LspRequest::DidChange {
             buffer_id,
             path,
-            content,
+            changes,
             version,
         })
     }
@@ -83,6 +84,17 @@ impl LspManager {
         self.send_request(LspRequest::DidSave { buffer_id, path })
     }
 
+    /// Version of a document as last sent to the language server
+    pub fn document_version(&self, path: &Path) -> Option<i32> {
+        self.document_versions.get(path).copied()
+    }
+
+    /// Notify LSP that a document was closed
+    pub fn did_close(&mut self, buffer_id: BufferId, path: PathBuf) -> Result<()> {
+        self.document_versions.remove(&path);
+        self.send_request(LspRequest::DidClose { buffer_id, path })
+    }
+
     /// Request to jump to definition at a position
     pub fn goto_definition(
         &mut self,
@@ -128,6 +140,86 @@ impl LspManager {
         result
     }
 
+    /// Request documentation of the symbol at a position
+    pub fn hover(&mut self, buffer_id: BufferId, path: PathBuf, position: Position) -> Result<()> {
+        self.send_request(LspRequest::Hover {
+            buffer_id,
+            path,
+            position,
+        })
+    }
+
+    /// Request the signature of the call a position is in
+    pub fn signature_help(&mut self, buffer_id: BufferId, path: PathBuf, position: Position) -> Result<()> {
+        self.send_request(LspRequest::SignatureHelp {
+            buffer_id,
+            path,
+            position,
+        })
+    }
+
+    /// Request every reference to the symbol at a position
+    pub fn references(&mut self, buffer_id: BufferId, path: PathBuf, position: Position) -> Result<()> {
+        self.send_request(LspRequest::References {
+            buffer_id,
+            path,
+            position,
+        })
+    }
+
+    /// Check whether the symbol at a position can be renamed
+    pub fn prepare_rename(&mut self, buffer_id: BufferId, path: PathBuf, position: Position) -> Result<()> {
+        self.send_request(LspRequest::PrepareRename {
+            buffer_id,
+            path,
+            position,
+        })
+    }
+
+    /// Request the edits that rename the symbol at a position to `new_name`
+    pub fn rename(&mut self, buffer_id: BufferId, path: PathBuf, position: Position, new_name: String) -> Result<()> {
+        self.send_request(LspRequest::Rename {
+            buffer_id,
+            path,
+            position,
+            new_name,
+        })
+    }
+
+    /// Request the code actions for a range, with the diagnostics
+    /// overlapping it. `automatic` requests are the editor's own (e.g. to
+    /// light the bulb); their answers say so.
+    pub fn code_actions(
+        &mut self,
+        buffer_id: BufferId,
+        path: PathBuf,
+        range: (Position, Position),
+        diagnostics: Vec<Diagnostic>,
+        automatic: bool,
+    ) -> Result<()> {
+        self.send_request(LspRequest::CodeActions {
+            buffer_id,
+            path,
+            range,
+            diagnostics,
+            automatic,
+        })
+    }
+
+    /// Run a command of the language server handling a document
+    pub fn execute_command(&mut self, buffer_id: BufferId, path: PathBuf, command: LspCommand) -> Result<()> {
+        self.send_request(LspRequest::ExecuteCommand {
+            buffer_id,
+            path,
+            command,
+        })
+    }
+
+    /// Tell a language server whether the edit it asked for was applied
+    pub fn apply_edit_result(&mut self, language: String, id: serde_json::Value, applied: bool) -> Result<()> {
+        self.send_request(LspRequest::ApplyEditResult { language, id, applied })
+    }
+
     /// Shutdown the LSP client
     pub fn shutdown(&mut self) -> Result<()> {
         self.send_request(LspRequest::Shutdown)
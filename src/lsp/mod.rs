@@ -2,16 +2,21 @@ mod client;
 mod completion;
 mod config;
 mod diagnostics;
+mod hover;
 mod manager;
 mod navigation;
 mod protocol;
+mod workspace_edit;
 
-pub use completion::CompletionPopup;
+pub use completion::{CodeActionPopup, CompletionPopup, SignatureHelpPopup};
 pub use config::Language;
 pub use diagnostics::DiagnosticsStore;
+pub use hover::{hover_lines, HoverLine, HoverPopup};
 pub use manager::LspManager;
 pub use navigation::NavigationHistory;
 pub use protocol::{
-    BufferId, CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, Location,
-    LspRequest, LspResponse, Position,
-};
\ No newline at end of file
+    BufferId, CodeAction, CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, FileEdit, Location,
+    LocationRange, LspCommand, LspRequest, LspResponse, Position, Signature, TextChange, TextEdit,
+    WorkspaceEdit,
+};
+pub use workspace_edit::{char_edits, utf16_to_char, CharEdit};
\ No newline at end of file
@@ -16,6 +16,14 @@ impl Position {
     }
 }
 
+/// A change to an open document: `text` replaces `range` (start and end,
+/// with columns in characters), or the whole document if there is no range
+#[derive(Debug, Clone)]
+pub struct TextChange {
+    pub range: Option<(Position, Position)>,
+    pub text: String,
+}
+
 /// Requests sent from main thread to LSP background task
 #[derive(Debug)]
 pub enum LspRequest {
@@ -30,7 +38,7 @@ pub enum LspRequest {
     DidChange {
         buffer_id: BufferId,
         path: PathBuf,
-        content: String,
+        changes: Vec<TextChange>,
         version: i32,
     },
     /// Notify LSP that a document was saved
@@ -38,6 +46,11 @@ pub enum LspRequest {
         buffer_id: BufferId,
         path: PathBuf,
     },
+    /// Notify LSP that a document was closed
+    DidClose {
+        buffer_id: BufferId,
+        path: PathBuf,
+    },
     /// Request to jump to definition
     GotoDefinition {
         buffer_id: BufferId,
@@ -50,6 +63,59 @@ pub enum LspRequest {
         path: PathBuf,
         position: Position,
     },
+    /// Request documentation of the symbol at a position
+    Hover {
+        buffer_id: BufferId,
+        path: PathBuf,
+        position: Position,
+    },
+    /// Request the signature of the call the position is in
+    SignatureHelp {
+        buffer_id: BufferId,
+        path: PathBuf,
+        position: Position,
+    },
+    /// Request every reference to the symbol at a position
+    References {
+        buffer_id: BufferId,
+        path: PathBuf,
+        position: Position,
+    },
+    /// Check whether the symbol at a position can be renamed
+    PrepareRename {
+        buffer_id: BufferId,
+        path: PathBuf,
+        position: Position,
+    },
+    /// Request the edits that rename the symbol at a position
+    Rename {
+        buffer_id: BufferId,
+        path: PathBuf,
+        position: Position,
+        new_name: String,
+    },
+    /// Request the code actions for a range (columns in characters), with
+    /// the diagnostics overlapping it
+    CodeActions {
+        buffer_id: BufferId,
+        path: PathBuf,
+        range: (Position, Position),
+        diagnostics: Vec<Diagnostic>,
+        /// Asked for by the editor (the lightbulb), not by the user
+        automatic: bool,
+    },
+    /// Run a command of the language server that handles a document
+    ExecuteCommand {
+        buffer_id: BufferId,
+        path: PathBuf,
+        command: LspCommand,
+    },
+    /// Answer an `ApplyEdit` request of the language server for `language`
+    ApplyEditResult {
+        language: String,
+        id: serde_json::Value,
+        applied: bool,
+    },
     /// Shutdown the LSP client
     Shutdown,
 }
@@ -70,6 +136,45 @@ pub enum LspResponse {
     Completion {
         items: Vec<CompletionItem>,
     },
+    /// Result of hover request: documentation of the symbol, empty if
+    /// there is none
+    Hover {
+        contents: String,
+        /// Whether `contents` is Markdown rather than plain text
+        markdown: bool,
+    },
+    /// Result of signature help request: `None` outside of a call
+    SignatureHelp {
+        signature: Option<Signature>,
+    },
+    /// Result of references request
+    References {
+        locations: Vec<LocationRange>,
+    },
+    /// Result of prepare rename request
+    PrepareRename {
+        /// Whether there is a symbol to rename at the position
+        renameable: bool,
+        /// Name the server suggests editing, if it sent one
+        placeholder: Option<String>,
+    },
+    /// Result of rename request: empty if there is nothing to rename
+    Rename {
+        edit: WorkspaceEdit,
+    },
+    /// Result of code action request, `automatic` if the editor asked on its own
+    CodeActions {
+        actions: Vec<CodeAction>,
+        automatic: bool,
+    },
+    /// The language server for `language` asks for an edit to be applied;
+    /// it waits for an `ApplyEditResult` with the same `id`
+    ApplyEdit {
+        language: String,
+        id: serde_json::Value,
+        label: Option<String>,
+        edit: WorkspaceEdit,
+    },
     /// Error occurred in LSP
     Error {
         message: String,
@@ -79,9 +184,13 @@ pub enum LspResponse {
 /// A diagnostic (error, warning, info) from the language server
 #[derive(Debug, Clone)]
 pub struct Diagnostic {
+    /// Moved along with local edits until the server publishes again
     pub range: (Position, Position),
     pub severity: DiagnosticSeverity,
     pub message: String,
+    /// The diagnostic exactly as the server published it, with its code,
+    /// source and data, to send back when asking for code actions
+    pub lsp: lsp_types::Diagnostic,
 }
 
 /// Severity of a diagnostic
@@ -100,6 +209,74 @@ pub struct Location {
     pub position: Position,
 }
 
+/// Signature of the function being called, from signature help
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub label: String,
+    /// Char range in `label` of the parameter the cursor is on
+    pub active_parameter: Option<(usize, usize)>,
+}
+
+/// A span of a source file, such as a reference to a symbol. Columns are
+/// in UTF-16 code units, as the server sent them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocationRange {
+    pub path: PathBuf,
+    pub start: Position,
+    pub end: Position,
+}
+
+/// Edits to make across files, as from a rename
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkspaceEdit {
+    pub files: Vec<FileEdit>,
+    /// File creations, renames and deletions, which are not applied
+    pub skipped_operations: usize,
+}
+
+/// One step of a workspace edit: edits to one file, all relative to its
+/// text before the step. A workspace edit can have several steps for the
+/// same file, made one after the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEdit {
+    pub path: PathBuf,
+    /// Version of the document the edits were computed for, if the server
+    /// gave one
+    pub version: Option<i32>,
+    pub edits: Vec<TextEdit>,
+}
+
+/// `new_text` replaces `range` (start and end) of a file. Columns are in
+/// UTF-16 code units, as the server sent them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: (Position, Position),
+    pub new_text: String,
+}
+
+/// A code action or quick fix offered by the language server. Its edit is
+/// applied before its command runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeAction {
+    pub title: String,
+    /// Kind such as "quickfix" or "refactor.extract"
+    pub kind: Option<String>,
+    /// Whether it is the preferred fix for its diagnostics
+    pub preferred: bool,
+    /// Why it can't be applied right now, if it can't
+    pub disabled: Option<String>,
+    pub edit: Option<WorkspaceEdit>,
+    pub command: Option<LspCommand>,
+}
+
+/// A command implemented by the language server
+#[derive(Debug, Clone, PartialEq)]
+pub struct LspCommand {
+    pub title: String,
+    pub command: String,
+    pub arguments: Option<Vec<serde_json::Value>>,
+}
+
 /// A completion item from the language server
 #[derive(Debug, Clone)]
 pub struct CompletionItem {
@@ -0,0 +1,120 @@
+use crate::lsp::protocol::{Position, TextEdit};
+use ropey::Rope;
+
+/// A text edit as a char range of the document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharEdit {
+    pub start: usize,
+    pub end: usize,
+    pub new_text: String,
+}
+
+/// Char column of UTF-16 column `column` of a line, clamped to its end
+pub fn utf16_to_char(line: &str, column: usize) -> usize {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let mut units = 0;
+    for (index, ch) in line.chars().enumerate() {
+        if units >= column {
+            return index;
+        }
+        units += ch.len_utf16();
+    }
+    line.chars().count()
+}
+
+/// Char index in `document` of a position with a UTF-16 column
+fn char_index(document: &Rope, pos: Position) -> usize {
+    if pos.line >= document.len_lines() {
+        return document.len_chars();
+    }
+    let line = document.line(pos.line).to_string();
+    document.line_to_char(pos.line) + utf16_to_char(&line, pos.column)
+}
+
+/// Convert the edits of one document to char ranges, ordered so that each
+/// range is where the edit goes once the ones before it are applied. All
+/// ranges refer to the document before the edits, as the server sends
+/// them; inserts at the same position keep the server's order.
+pub fn char_edits(document: &Rope, edits: &[TextEdit]) -> Vec<CharEdit> {
+    let mut edits: Vec<CharEdit> = edits
+        .iter()
+        .map(|edit| {
+            let start = char_index(document, edit.range.0);
+            CharEdit {
+                start,
+                end: char_index(document, edit.range.1).max(start),
+                new_text: edit.new_text.clone(),
+            }
+        })
+        .collect();
+    edits.sort_by_key(|edit| edit.start);
+
+    // Shift each edit by what the earlier ones added or removed, and never
+    // before the text the previous one inserted
+    let mut shift = 0isize;
+    let mut floor = 0;
+    for edit in &mut edits {
+        let new_len = edit.new_text.chars().count();
+        let old_len = edit.end - edit.start;
+        let start = ((edit.start as isize + shift) as usize).max(floor);
+        let end = ((edit.end as isize + shift) as usize).max(start);
+        shift += new_len as isize - old_len as isize;
+        floor = start + new_len;
+        edit.start = start;
+        edit.end = end;
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(start: (usize, usize), end: (usize, usize), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: (Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    fn apply(text: &str, edits: &[TextEdit]) -> String {
+        let mut document = Rope::from_str(text);
+        for edit in char_edits(&document.clone(), edits) {
+            document.remove(edit.start..edit.end);
+            document.insert(edit.start, &edit.new_text);
+        }
+        document.to_string()
+    }
+
+    #[test]
+    fn test_utf16_to_char() {
+        // The emoji takes two UTF-16 code units but is one char
+        assert_eq!(utf16_to_char("a😀b", 3), 2);
+        assert_eq!(utf16_to_char("héllo", 2), 2);
+        assert_eq!(utf16_to_char("ab\n", 10), 2);
+        // A column inside a surrogate pair moves past the char
+        assert_eq!(utf16_to_char("😀b", 1), 1);
+        assert_eq!(utf16_to_char("", 0), 0);
+    }
+
+    #[test]
+    fn test_char_edits() {
+        // Renaming `x`, with the edits out of order and after an emoji
+        let text = "let 😀x = 1;\nx + x\n";
+        let edits = [edit((1, 4), (1, 5), "total"), edit((0, 6), (0, 7), "total"), edit((1, 0), (1, 1), "total")];
+        assert_eq!(apply(text, &edits), "let 😀total = 1;\ntotal + total\n");
+
+        // Inserts at the same position keep their order, also after a
+        // replacement starting there
+        let edits = [edit((0, 0), (0, 1), "b"), edit((0, 0), (0, 0), "c"), edit((0, 0), (0, 0), "d")];
+        assert_eq!(apply("a", &edits), "bcd");
+
+        // A position past the end of the document is its end
+        assert_eq!(apply("a\n", &[edit((5, 0), (5, 0), "z")]), "a\nz");
+
+        // Steps of a workspace edit for the same file each refer to the
+        // text the previous one left
+        let first = apply("fn f() {}\n", &[edit((0, 3), (0, 4), "run")]);
+        assert_eq!(apply(&first, &[edit((0, 7), (0, 7), "x: u8")]), "fn run(x: u8) {}\n");
+    }
+}
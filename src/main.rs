@@ -3,6 +3,8 @@ mod app;
 mod buffer;
 mod diff;
 mod editor;
+mod editorconfig;
+mod language;
 mod logger;
 mod render;
 mod session;
@@ -19,9 +21,9 @@ mod workspace;
 
 use app::{poll_event, App, ControlFlow};
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
-use crossterm::style::Color;
 use diff::DiffView;
-use render::Terminal;
+use render::diff_view::{DiffHighlights, DiffRenderer};
+use render::{ColorDepth, Terminal};
 use std::env;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -84,7 +86,8 @@ fn main() -> anyhow::Result<()> {
     app.initialize_ai()?;
 
     // Initialize terminal
-    let mut terminal = Terminal::new()?;
+    let color_depth = ColorDepth::from_setting(&config::Config::load().unwrap_or_default().ui.color_depth);
+    let mut terminal = Terminal::new(color_depth)?;
 
     // Initial render
     app.render(&terminal)?;
@@ -100,8 +103,36 @@ fn main() -> anyhow::Result<()> {
         // Check AI debounce timer and trigger completion if needed
         app.check_ai_debounce()?;
 
-        // Only render if LSP or AI had updates
-        if had_lsp_updates || had_ai_updates {
+        // Search the project once typing in its prompt has paused
+        let had_search_results = app.check_project_search_debounce();
+
+        // Ask for hover documentation once the cursor has rested on a symbol
+        app.check_hover_idle();
+
+        // Look for code actions when the cursor moves onto a line with diagnostics
+        app.check_code_actions();
+
+        // Reload or prompt for files changed on disk
+        let had_file_changes = app.poll_file_changes();
+
+        // Append to buffers following their growing file
+        let had_follow_updates = app.poll_followed_files();
+
+        // Write swap files when idle and offer crash recovery
+        let had_swap_prompt = app.check_swap_files();
+
+        // Let diagnostics, marks, AI and the language server follow the edits
+        let had_edits = app.dispatch_edit_events();
+
+        // Only render if LSP, AI, project search, the file watcher, followed files, swap recovery or edits had updates
+        if had_lsp_updates
+            || had_ai_updates
+            || had_search_results
+            || had_file_changes
+            || had_follow_updates
+            || had_swap_prompt
+            || had_edits
+        {
             app.render(&terminal)?;
         }
 
@@ -118,6 +149,7 @@ fn main() -> anyhow::Result<()> {
                     if app.needs_sudo_save() {
                         app.execute_sudo_save(&terminal)?;
                     }
+                    app.dispatch_edit_events();
 
                     // Render after handling input
                     app.render(&terminal)?;
@@ -132,6 +164,9 @@ fn main() -> anyhow::Result<()> {
         eprintln!("Warning: Failed to save session state: {}", e);
     }
 
+    // Unsaved changes were either saved or deliberately discarded
+    app.discard_swap_files();
+
     // Shutdown LSP
     app.shutdown_lsp()?;
 
@@ -147,45 +182,18 @@ fn main() -> anyhow::Result<()> {
 
 /// Run the diff viewer mode
 fn run_diff_mode(left_path: PathBuf, right_path: PathBuf) -> anyhow::Result<()> {
-    use crate::syntax::{Highlighter, SupportedLanguage};
+    use crate::syntax::Highlighter;
 
     // Load the diff
     let mut diff_view = DiffView::new(left_path, right_path)?;
 
-    // Initialize syntax highlighter if supported
-    let mut highlighter = Highlighter::new();
-    let highlight_data = if diff_view.supports_syntax_highlighting() {
-        let lang = SupportedLanguage::from_path(&diff_view.left_path);
-        if let Some(language) = lang {
-            highlighter.set_language(&language.language()).ok();
-            let left_query = language.query().ok();
-            let right_query = language.query().ok();
-            let left_capture_names = language.capture_names().ok();
-            let right_capture_names = language.capture_names().ok();
-
-            // Get highlight spans for both files
-            let left_spans = if let (Some(query), Some(capture_names)) = (&left_query, &left_capture_names) {
-                highlighter.highlight(&diff_view.left_content, "left", query, capture_names).ok()
-            } else {
-                None
-            };
-
-            let right_spans = if let (Some(query), Some(capture_names)) = (&right_query, &right_capture_names) {
-                highlighter.highlight(&diff_view.right_content, "right", query, capture_names).ok()
-            } else {
-                None
-            };
-
-            Some((left_spans, right_spans))
-        } else {
-            None
-        }
-    } else {
-        None
-    };
+    // Initialize syntax highlighter if supported, with the configured theme
+    let ui_config = config::Config::load().unwrap_or_default().ui;
+    let mut highlighter = Highlighter::with_theme(syntax::Theme::load(&ui_config.theme).unwrap_or_default());
+    let highlight_data = DiffRenderer::highlight(&diff_view, &mut highlighter);
 
     // Initialize terminal
-    let terminal = Terminal::new()?;
+    let terminal = Terminal::new(ColorDepth::from_setting(&ui_config.color_depth))?;
 
     // Initial render
     render_diff(&terminal, &diff_view, &highlighter, &highlight_data)?;
@@ -220,10 +228,9 @@ fn handle_diff_key(
     diff_view: &mut DiffView,
     key: KeyEvent,
     highlighter: &syntax::Highlighter,
-    highlight_data: &Option<(Option<Vec<syntax::HighlightSpan>>, Option<Vec<syntax::HighlightSpan>>)>,
+    highlight_data: &DiffHighlights,
 ) -> anyhow::Result<bool> {
-    let (_, term_height) = terminal.size();
-    let visible_height = term_height.saturating_sub(3) as usize; // Leave space for header/footer
+    let visible_height = DiffRenderer::visible_height(terminal);
 
     match (key.code, key.modifiers) {
         // Quit
@@ -268,191 +275,13 @@ fn render_diff(
     terminal: &Terminal,
     diff_view: &DiffView,
     highlighter: &syntax::Highlighter,
-    highlight_data: &Option<(Option<Vec<syntax::HighlightSpan>>, Option<Vec<syntax::HighlightSpan>>)>,
+    highlight_data: &DiffHighlights,
 ) -> anyhow::Result<()> {
-    use crossterm::execute;
-    use crossterm::terminal::{Clear, ClearType};
-    use std::io::stdout;
-
-    let (term_width, term_height) = terminal.size();
-
-    // Clear screen
-    execute!(stdout(), Clear(ClearType::All))?;
-
-    // Calculate pane widths (50/50 split with a divider)
-    let pane_width = (term_width / 2).saturating_sub(1);
-
-    // Render header (file names)
-    terminal.move_cursor(0, 0)?;
-    terminal.set_bg(Color::DarkGrey)?;
-    terminal.set_fg(Color::White)?;
-
-    // Left file name
-    let left_name = diff_view
-        .left_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("(unknown)");
-    let left_header = format!("{:width$}", left_name, width = pane_width as usize);
-    terminal.print(&left_header)?;
-
-    // Divider
-    terminal.print("│")?;
-
-    // Right file name
-    let right_name = diff_view
-        .right_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("(unknown)");
-    let right_header = format!("{:width$}", right_name, width = pane_width as usize);
-    terminal.print(&right_header)?;
-    terminal.reset_color()?;
-
-    // Calculate visible content area
-    let content_height = term_height.saturating_sub(2) as usize; // Header + status bar
-    let (left_lines, right_lines) = diff_view.visible_lines(content_height);
-
-    // Extract highlight spans if available
-    let (left_spans, right_spans) = match highlight_data {
-        Some((left, right)) => (left.as_deref(), right.as_deref()),
-        None => (None, None),
-    };
-
-    // Render diff lines
-    for (idx, (left_line, right_line)) in left_lines.iter().zip(right_lines.iter()).enumerate() {
-        let screen_row = idx as u16 + 1;
-
-        // Render left pane
-        terminal.move_cursor(0, screen_row)?;
-        render_diff_line(terminal, left_line, pane_width, left_spans, highlighter.theme())?;
-
-        // Render divider
-        terminal.move_cursor(pane_width, screen_row)?;
-        terminal.set_fg(Color::DarkGrey)?;
-        terminal.print("│")?;
-        terminal.reset_color()?;
-
-        // Render right pane
-        terminal.move_cursor(pane_width + 1, screen_row)?;
-        render_diff_line(terminal, right_line, pane_width, right_spans, highlighter.theme())?;
-    }
-
-    // Render status bar
-    let status_row = term_height - 1;
-    terminal.move_cursor(0, status_row)?;
-    terminal.set_bg(Color::DarkGrey)?;
-    terminal.set_fg(Color::White)?;
-    let status = format!(
-        " Line {}/{} | q: quit | ↑↓/jk: scroll | PgUp/PgDn: page ",
-        diff_view.scroll_offset + 1,
-        diff_view.left_lines.len()
-    );
-    terminal.print(&format!("{:width$}", status, width = term_width as usize))?;
-    terminal.reset_color()?;
-
-    terminal.flush()?;
-
-    Ok(())
-}
-
-/// Render a single diff line with syntax highlighting
-fn render_diff_line(
-    terminal: &Terminal,
-    line: &diff::DiffLine,
-    width: u16,
-    highlight_spans: Option<&[syntax::HighlightSpan]>,
-    theme: &syntax::Theme,
-) -> anyhow::Result<()> {
-    use diff::ChangeType;
-
-    // Set background color based on change type
-    let bg_color = line.change_type.bg_color();
-    terminal.set_bg(bg_color)?;
-
-    // Render line number (without syntax highlighting)
-    if let Some(line_num) = line.old_line_num.or(line.new_line_num) {
-        terminal.set_fg(Color::DarkGrey)?;
-        terminal.print(&format!("{:>4} ", line_num))?;
-    } else {
-        terminal.print("     ")?; // Empty lines
-    }
-
-    // Render content with syntax highlighting if available
-    let content_width = (width as usize).saturating_sub(5); // 5 for line number + space
-
-    if line.content.is_empty() {
-        // Empty line - just fill with background
-        for _ in 0..content_width {
-            terminal.print(" ")?;
-        }
-    } else if let Some(spans) = highlight_spans {
-        // Render with syntax highlighting
-        // We need to find which spans correspond to this line
-        // For simplicity, we'll render character by character and check spans
-        let displayed_content = if line.content.len() > content_width {
-            &line.content[..content_width]
-        } else {
-            &line.content
-        };
-
-        let chars: Vec<char> = displayed_content.chars().collect();
-        let mut byte_offset = 0;
-
-        for ch in &chars {
-            // Find if this character is within a syntax span
-            let syntax_color = spans
-                .iter()
-                .find(|span| byte_offset >= span.start_byte && byte_offset < span.end_byte)
-                .map(|span| theme.color_for(span.token_type));
-
-            // Apply syntax color or default color
-            if let Some(color) = syntax_color {
-                terminal.set_fg(color)?;
-            } else {
-                // Default text color for diff type
-                terminal.set_fg(match line.change_type {
-                    ChangeType::Unchanged => Color::White,
-                    ChangeType::Added => Color::Rgb { r: 144, g: 238, b: 144 }, // Light green
-                    ChangeType::Deleted => Color::Rgb { r: 255, g: 160, b: 122 }, // Light red
-                    ChangeType::Modified => Color::Yellow,
-                })?;
-            }
-
-            terminal.print(&ch.to_string())?;
-            byte_offset += ch.len_utf8();
-        }
-
-        // Fill remaining space
-        let padding = content_width.saturating_sub(displayed_content.len());
-        for _ in 0..padding {
-            terminal.print(" ")?;
-        }
-    } else {
-        // No syntax highlighting - use plain colors
-        let displayed_content = if line.content.len() > content_width {
-            &line.content[..content_width]
-        } else {
-            &line.content
-        };
-
-        terminal.set_fg(match line.change_type {
-            ChangeType::Unchanged => Color::White,
-            ChangeType::Added => Color::Rgb { r: 144, g: 238, b: 144 }, // Light green
-            ChangeType::Deleted => Color::Rgb { r: 255, g: 160, b: 122 }, // Light red
-            ChangeType::Modified => Color::Yellow,
-        })?;
-
-        terminal.print(displayed_content)?;
-
-        // Fill remaining space
-        let padding = content_width.saturating_sub(line.content.len());
-        for _ in 0..padding {
-            terminal.print(" ")?;
-        }
-    }
-
-    terminal.reset_color()?;
-
-    Ok(())
+    DiffRenderer::render(
+        terminal,
+        diff_view,
+        highlighter.theme(),
+        highlight_data,
+        "q: quit | ↑↓/jk: scroll | PgUp/PgDn: page",
+    )
 }
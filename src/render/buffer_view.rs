@@ -1,4 +1,4 @@
-use crate::buffer::{Position, TextBuffer};
+use crate::buffer::{display, Position, TextBuffer};
 use crate::editor::EditorState;
 use crate::lsp::{Diagnostic, DiagnosticSeverity};
 use crate::render::terminal::Terminal;
@@ -9,9 +9,24 @@ use std::path::Path;
 
 pub struct BufferView;
 
+/// Width of the gutter column holding the diagnostic marker or the code
+/// action lightbulb, which is two columns wide
+const MARKER_COLUMN_WIDTH: u16 = 2;
+
+/// How a batch of text on a line is colored
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineStyle {
+    Mark,
+    Selection,
+    SearchMatch,
+    Guide,
+    Syntax(Option<Color>),
+}
+
 impl BufferView {
     /// Render the text buffer to the terminal
     /// word_marks_positions: list of (line, start_column, word_length) for marked words in current file
+    /// code_action_line: line to put a lightbulb on, as code actions are available there
     pub fn render(
         terminal: &Terminal,
         buffer: &TextBuffer,
@@ -26,13 +41,11 @@ impl BufferView {
         show_indent_guides: bool,
         word_marks_positions: &[(usize, usize, usize)],
         search_matches: &[(Position, Position)],
+        code_action_line: Option<usize>,
     ) -> Result<()> {
         let (term_width, term_height) = terminal.size();
-        let line_number_width = if show_line_numbers {
-            Self::calculate_line_number_width(buffer) + 2 // +1 for diagnostic marker space, +1 for trailing space
-        } else {
-            0
-        };
+        let line_number_width = Self::gutter_width(buffer, show_line_numbers);
+        let text_width = term_width.saturating_sub(line_number_width) as usize;
 
         // Tab bar and path bar take 2 lines at top, status bar takes 1 line at bottom
         let tab_bar_height = 1;
@@ -46,6 +59,7 @@ impl BufferView {
 
         // Check if we should show indentation guides (enabled AND Python files only)
         let show_indent_guides = show_indent_guides
+            && !buffer.is_large_file()
             && file_path
                 .and_then(|p| p.extension())
                 .and_then(|e| e.to_str())
@@ -77,7 +91,7 @@ impl BufferView {
             if buffer_line >= buffer.len_lines() && !is_ghost_line {
                 // Empty line beyond buffer (and not an AI ghost line)
                 terminal.reset_color()?;
-                terminal.set_fg(Color::DarkGrey)?;
+                terminal.set_fg(theme.ui.line_number)?;
                 terminal.print("~")?;
                 terminal.reset_color()?;
                 continue;
@@ -94,32 +108,30 @@ impl BufferView {
                 None
             };
 
-            // Render line number with diagnostic indicator
+            // Render the marker column: the lightbulb where code actions
+            // are available, else the diagnostic indicator
+            if code_action_line == Some(buffer_line) && !is_ghost_line {
+                terminal.print("💡")?;
+            } else if let Some(diag) = line_diagnostic {
+                let color = match diag.severity {
+                    DiagnosticSeverity::Error => theme.ui.diagnostic_error,
+                    DiagnosticSeverity::Warning => theme.ui.diagnostic_warning,
+                    DiagnosticSeverity::Information => theme.ui.diagnostic_info,
+                    DiagnosticSeverity::Hint => theme.ui.diagnostic_hint,
+                };
+                terminal.set_fg(color)?;
+                terminal.print("● ")?;
+                terminal.reset_color()?;
+            } else {
+                terminal.print(&" ".repeat(MARKER_COLUMN_WIDTH as usize))?;
+            }
+
+            // Render line number
             if show_line_numbers {
-                // Show diagnostic marker if present
-                if let Some(diag) = line_diagnostic {
-                    let (marker, color) = match diag.severity {
-                        DiagnosticSeverity::Error => ("●", Color::Red),
-                        DiagnosticSeverity::Warning => ("●", Color::Yellow),
-                        DiagnosticSeverity::Information => ("●", Color::Blue),
-                        DiagnosticSeverity::Hint => ("●", Color::Cyan),
-                    };
-                    terminal.set_fg(color)?;
-                    terminal.print(marker)?;
-                    terminal.reset_color()?;
-                    terminal.set_fg(Color::DarkGrey)?;
-                    let line_num = format!("{:>width$}", buffer_line + 1, width = (line_number_width - 2) as usize);
-                    terminal.print(&line_num)?;
-                    terminal.print(" ")?;
-                    terminal.reset_color()?;
-                } else {
-                    // No diagnostic - print space for marker, then line number
-                    terminal.print(" ")?; // Space where marker would be
-                    terminal.set_fg(Color::DarkGrey)?;
-                    let line_num = format!("{:>width$} ", buffer_line + 1, width = (line_number_width - 2) as usize);
-                    terminal.print(&line_num)?;
-                    terminal.reset_color()?;
-                }
+                let width = (line_number_width - MARKER_COLUMN_WIDTH - 1) as usize;
+                terminal.set_fg(theme.ui.line_number)?;
+                terminal.print(&format!("{:>width$} ", buffer_line + 1))?;
+                terminal.reset_color()?;
             }
 
             // Render ghost line (AI suggestion beyond buffer)
@@ -128,7 +140,7 @@ impl BufferView {
                 let suggestion_line_idx = buffer_line - state.cursor.line;
                 if let Some(ref lines) = ai_suggestion_lines {
                     if suggestion_line_idx < lines.len() {
-                        terminal.set_fg(Color::DarkGrey)?;
+                        terminal.set_fg(theme.ui.ghost_text)?;
                         terminal.print(lines[suggestion_line_idx])?;
                         terminal.reset_color()?;
                     }
@@ -136,6 +148,12 @@ impl BufferView {
                 continue;
             }
 
+            // Large files: only copy and draw the visible part of the line
+            if buffer.is_large_file() {
+                Self::render_line_window(terminal, buffer, buffer_line, state, text_width, search_matches, theme)?;
+                continue;
+            }
+
             // Get the line text
             if let Some(line) = buffer.get_line(buffer_line) {
                 // Remove the trailing newline for display
@@ -155,7 +173,21 @@ impl BufferView {
                 let indent_level = indent_levels.get(screen_row as usize).copied().unwrap_or(0);
 
                 // Render the line with selection highlighting if applicable
-                Self::render_line(terminal, line, buffer_line, state, line_number_width, buffer, highlight_spans, theme, ai_line_to_show, show_ai_on_cursor_line, show_indent_guides, indent_level, word_marks_positions, search_matches)?;
+                Self::render_line(terminal, line, buffer_line, state, text_width, buffer, highlight_spans, theme, ai_line_to_show, show_ai_on_cursor_line, show_indent_guides, indent_level, word_marks_positions, search_matches)?;
+
+                // Max line length ruler, in the empty space after shorter lines
+                if let Some(ruler) = state.ruler_column.filter(|_| ai_line_to_show.is_none()) {
+                    let left_column = state.viewport.left_column;
+                    if display::line_width(line, state.tab_width) <= ruler && ruler >= left_column {
+                        let x = line_number_width as usize + ruler - left_column;
+                        if x < term_width as usize {
+                            terminal.move_cursor(x as u16, screen_row + top_bars_height)?;
+                            terminal.set_fg(theme.ui.ruler)?;
+                            terminal.print("│")?;
+                            terminal.reset_color()?;
+                        }
+                    }
+                }
             }
         }
 
@@ -169,11 +201,7 @@ impl BufferView {
         show_line_numbers: bool,
         buffer: &TextBuffer,
     ) -> Result<()> {
-        let line_number_width = if show_line_numbers {
-            Self::calculate_line_number_width(buffer) + 2 // +1 for diagnostic marker space, +1 for trailing space
-        } else {
-            0
-        };
+        let line_number_width = Self::gutter_width(buffer, show_line_numbers);
 
         // Render secondary cursors as inverted blocks
         if !state.secondary_cursors.is_empty() {
@@ -190,25 +218,86 @@ impl BufferView {
                 if sc_screen_line >= content_height as usize {
                     continue;
                 }
-                let sc_screen_col = sc.column.saturating_sub(state.viewport.left_column) as u16 + line_number_width;
+                let sc_display_col = buffer.display_column(sc.position(), state.tab_width);
+                let sc_screen_col = sc_display_col.saturating_sub(state.viewport.left_column) as u16 + line_number_width;
                 let sc_screen_y = sc_screen_line as u16 + top_bars_height;
 
-                let ch = buffer.get_line(sc.line)
-                    .as_deref()
-                    .and_then(|line| line.trim_end_matches(&['\n', '\r'][..]).chars().nth(sc.column))
-                    .unwrap_or(' ');
+                // The grapheme under the cursor; blank for tabs, control
+                // characters and the end of the line
+                let end = buffer.next_grapheme_column(sc.position());
+                let mut text = buffer.line_window(sc.line, sc.column, end - sc.column);
+                if text.is_empty() || text.chars().any(char::is_control) {
+                    text = " ".to_string();
+                }
 
                 terminal.move_cursor(sc_screen_col, sc_screen_y)?;
                 queue!(stdout,
                     SetAttribute(Attribute::Reverse),
-                    Print(ch),
+                    Print(text),
                     SetAttribute(Attribute::Reset)
                 )?;
             }
             stdout.flush()?;
         }
 
-        Self::render_cursor(terminal, state, line_number_width)?;
+        Self::render_cursor(terminal, state, buffer, line_number_width)?;
+        Ok(())
+    }
+
+    /// Render the visible columns of a line without syntax highlighting,
+    /// reading only that part of the line from the buffer (large-file mode)
+    pub fn render_line_window(
+        terminal: &Terminal,
+        buffer: &TextBuffer,
+        line_num: usize,
+        state: &EditorState,
+        width: usize,
+        search_matches: &[(Position, Position)],
+        theme: &Theme,
+    ) -> Result<()> {
+        let left_column = state.viewport.left_column;
+        let window = buffer.line_window(line_num, left_column, width);
+
+        // Column range of a (possibly multi-line) span on this line
+        let columns_on_line = |start: Position, end: Position| -> Option<(usize, usize)> {
+            if line_num < start.line || line_num > end.line {
+                return None;
+            }
+            let start_col = if line_num == start.line { start.column } else { 0 };
+            let end_col = if line_num == end.line { end.column } else { usize::MAX };
+            Some((start_col, end_col))
+        };
+        let selection_range = state.selection.as_ref().and_then(|selection| {
+            let (start, end) = selection.range();
+            columns_on_line(start, end)
+        });
+        let search_ranges: Vec<(usize, usize)> = search_matches
+            .iter()
+            .filter_map(|(start, end)| columns_on_line(*start, *end))
+            .collect();
+
+        for (offset, ch) in window.chars().enumerate() {
+            let col_idx = left_column + offset;
+            let in_range = |(start, end): (usize, usize)| col_idx >= start && col_idx < end;
+
+            if selection_range.map(in_range).unwrap_or(false) {
+                terminal.set_bg(theme.ui.selection_bg)?;
+                terminal.set_fg(theme.ui.selection_fg)?;
+            } else if search_ranges.iter().copied().any(in_range) {
+                terminal.set_bg(theme.ui.search_match_bg)?;
+                terminal.set_fg(theme.ui.search_match_fg)?;
+            }
+            // One column per char here, so tabs and control characters
+            // are drawn as a single cell
+            let ch = match ch {
+                '\t' => ' ',
+                ch if ch.is_control() => '\u{FFFD}',
+                ch => ch,
+            };
+            terminal.print(&ch.to_string())?;
+            terminal.reset_color()?;
+        }
+
         Ok(())
     }
 
@@ -217,7 +306,7 @@ impl BufferView {
         line: &str,
         line_num: usize,
         state: &EditorState,
-        _line_number_width: u16,
+        width: usize,
         buffer: &TextBuffer,
         highlight_spans: Option<&[HighlightSpan]>,
         theme: &Theme,
@@ -276,20 +365,9 @@ impl BufferView {
             search_ranges.iter().any(|(s, e)| col >= *s && col < *e)
         };
 
-        // Selection colors - use bright cyan background with black text for maximum contrast
-        let selection_bg = Color::Rgb { r: 100, g: 180, b: 255 }; // Bright blue
-        let selection_fg = Color::Black;
-
-        // Mark colors - use magenta background with white text
-        let mark_bg = Color::Rgb { r: 255, g: 100, b: 255 }; // Magenta
-        let mark_fg = Color::White;
-
-        // Search match colors - amber background
-        let search_match_bg = Color::Rgb { r: 180, g: 120, b: 0 };
-        let search_match_fg = Color::Black;
-
-        // Guide color for indentation guides
-        let guide_color = Color::Rgb { r: 60, g: 60, b: 60 };
+        // Guide color for indentation guides, one guide per indent level
+        let guide_color = theme.ui.indent_guide;
+        let indent_width = state.indentation.width.max(1);
 
         // Handle empty lines with indentation guides
         if show_indent_guides && indent_level > 0 && line.trim().is_empty() {
@@ -297,9 +375,7 @@ impl BufferView {
             for level in 0..indent_level {
                 if level > 0 {
                     // Print spaces between guides
-                    for _ in 0..3 {
-                        terminal.print(" ")?;
-                    }
+                    terminal.print(&" ".repeat(indent_width - 1))?;
                 }
                 terminal.set_fg(guide_color)?;
                 terminal.print("│")?;
@@ -308,284 +384,77 @@ impl BufferView {
             return Ok(());
         }
 
-        // If no syntax highlighting, use simple rendering
-        if highlight_spans.is_none() {
-            let chars: Vec<char> = line.chars().collect();
-
-            for (col_idx, &ch) in chars.iter().enumerate() {
-                // Check if this is a guide position
-                let is_guide_pos = show_indent_guides
-                    && ch == ' '
-                    && col_idx < indent_level * 4
-                    && col_idx % 4 == 0;
-
-                // Check if marked (priority over selection)
-                if is_marked(col_idx) {
-                    terminal.set_bg(mark_bg)?;
-                    terminal.set_fg(mark_fg)?;
-                    if is_guide_pos {
-                        terminal.print("│")?;
-                    } else {
-                        terminal.print(&ch.to_string())?;
-                    }
-                    terminal.reset_color()?;
-                } else if let Some((start_col, end_col)) = selection_range {
-                    if col_idx >= start_col && col_idx < end_col {
-                        terminal.set_bg(selection_bg)?;
-                        terminal.set_fg(selection_fg)?;
-                        if is_guide_pos {
-                            terminal.print("│")?;
-                        } else {
-                            terminal.print(&ch.to_string())?;
-                        }
-                        terminal.reset_color()?;
-                    } else if is_search_match(col_idx) {
-                        terminal.set_bg(search_match_bg)?;
-                        terminal.set_fg(search_match_fg)?;
-                        terminal.print(&ch.to_string())?;
-                        terminal.reset_color()?;
-                    } else {
-                        if is_guide_pos {
-                            terminal.set_fg(guide_color)?;
-                            terminal.print("│")?;
-                            terminal.reset_color()?;
-                        } else {
-                            terminal.print(&ch.to_string())?;
-                        }
-                    }
-                } else if is_search_match(col_idx) {
-                    terminal.set_bg(search_match_bg)?;
-                    terminal.set_fg(search_match_fg)?;
-                    terminal.print(&ch.to_string())?;
-                    terminal.reset_color()?;
-                } else {
-                    if is_guide_pos {
-                        terminal.set_fg(guide_color)?;
-                        terminal.print("│")?;
-                        terminal.reset_color()?;
-                    } else {
-                        terminal.print(&ch.to_string())?;
-                    }
-                }
-            }
-            return Ok(());
-        }
-
-        // Render with syntax highlighting
-        // This should always be Some() due to the check above, but be defensive
-        let spans = match highlight_spans {
-            Some(s) => s,
-            None => {
-                // Fallback to simple rendering if highlighting somehow failed
-                terminal.print(line)?;
-                return Ok(());
-            }
-        };
-
         // Filter spans that overlap with this line
-        let line_spans: Vec<_> = spans
+        let line_spans: Vec<_> = highlight_spans
+            .unwrap_or_default()
             .iter()
             .filter(|span| span.start_byte < line_end_byte && span.end_byte > line_start_byte)
             .collect();
+        let syntax_color = |byte: usize| {
+            line_spans
+                .iter()
+                .find(|span| byte >= span.start_byte && byte < span.end_byte)
+                .map(|span| theme.color_for(span.token_type))
+        };
 
-        // Simple batching: group consecutive selected/non-selected characters
-        let chars: Vec<char> = line.chars().collect();
-
-        if chars.is_empty() {
-            terminal.reset_color()?;
-            return Ok(());
-        }
-
-        // Apply horizontal scrolling offset
+        // Screen columns of the line that are visible (horizontal scrolling)
         let left_column = state.viewport.left_column;
+        let right_column = left_column + width;
 
-        // Skip characters before the left_column (horizontal scroll offset)
-        let mut char_idx = 0;
-        for i in 0..left_column.min(chars.len()) {
-            char_idx += chars[i].len_utf8();
-        }
-        let mut col_idx = left_column.min(chars.len());
-
-        while col_idx < chars.len() {
-            let absolute_byte = line_start_byte + char_idx;
+        // Batch consecutive graphemes with the same style into one print
+        let mut batch = String::new();
+        let mut batch_style = None;
+        for grapheme in display::graphemes(line, state.tab_width) {
+            if grapheme.display_column + grapheme.width <= left_column {
+                continue;
+            }
+            if grapheme.display_column >= right_column {
+                break;
+            }
+            let col_idx = grapheme.column;
 
-            // Check if current character is marked (priority over selection)
-            let is_marked_char = is_marked(col_idx);
+            // Check if this is an indentation guide position
+            let is_guide_pos = show_indent_guides
+                && grapheme.text == " "
+                && grapheme.display_column < indent_level * indent_width
+                && grapheme.display_column.is_multiple_of(indent_width);
 
-            // Check if current character is selected
+            // Marks take priority over the selection, which takes priority
+            // over search matches
             let is_selected = selection_range
                 .map(|(start, end)| col_idx >= start && col_idx < end)
                 .unwrap_or(false);
-
-            // Check if current character is a (non-current) search match
-            let is_search_match_char = !is_selected && is_search_match(col_idx);
-
-            if is_marked_char {
-                // Find the end of the marked region
-                let mut end_col = col_idx;
-                while end_col < chars.len() && is_marked(end_col) {
-                    end_col += 1;
-                }
-
-                // Render the entire marked region at once
-                let marked_text: String = chars[col_idx..end_col].iter().collect();
-                terminal.set_bg(mark_bg)?;
-                terminal.set_fg(mark_fg)?;
-                terminal.print(&marked_text)?;
-                terminal.reset_color()?;
-
-                // Advance
-                for i in col_idx..end_col {
-                    char_idx += chars[i].len_utf8();
-                }
-                col_idx = end_col;
+            let style = if is_marked(col_idx) {
+                LineStyle::Mark
             } else if is_selected {
-                // Find the end of the selected region
-                let mut end_col = col_idx;
-                while end_col < chars.len() {
-                    let is_still_selected = selection_range
-                        .map(|(start, end)| end_col >= start && end_col < end)
-                        .unwrap_or(false);
-                    // Don't extend into marked regions
-                    if !is_still_selected || is_marked(end_col) {
-                        break;
-                    }
-                    end_col += 1;
-                }
-
-                // Render the entire selected region at once
-                let selected_text: String = chars[col_idx..end_col].iter().collect();
-                terminal.set_bg(selection_bg)?;
-                terminal.set_fg(selection_fg)?;
-                terminal.print(&selected_text)?;
-                terminal.reset_color()?;
-
-                // Advance
-                for i in col_idx..end_col {
-                    char_idx += chars[i].len_utf8();
-                }
-                col_idx = end_col;
-            } else if is_search_match_char {
-                // Search match (not currently selected) — batch consecutive matched chars
-                let mut end_col = col_idx;
-                while end_col < chars.len() && is_search_match(end_col) && !is_marked(end_col) {
-                    let still_selected = selection_range
-                        .map(|(s, e)| end_col >= s && end_col < e)
-                        .unwrap_or(false);
-                    if still_selected { break; }
-                    end_col += 1;
-                }
-                let matched_text: String = chars[col_idx..end_col].iter().collect();
-                terminal.set_bg(search_match_bg)?;
-                terminal.set_fg(search_match_fg)?;
-                terminal.print(&matched_text)?;
-                terminal.reset_color()?;
-                for i in col_idx..end_col {
-                    char_idx += chars[i].len_utf8();
-                }
-                col_idx = end_col;
+                LineStyle::Selection
+            } else if is_search_match(col_idx) {
+                LineStyle::SearchMatch
+            } else if is_guide_pos {
+                LineStyle::Guide
             } else {
-                // Non-selected character - batch consecutive same-color characters
-                let ch = chars[col_idx];
-
-                // Check if this is an indentation guide position
-                let is_guide_pos = show_indent_guides
-                    && ch == ' '
-                    && col_idx < indent_level * 4
-                    && col_idx % 4 == 0;
-
-                if is_guide_pos {
-                    // Render indentation guide
-                    terminal.set_fg(guide_color)?;
-                    terminal.print("│")?;
-                    terminal.reset_color()?;
-                    char_idx += ch.len_utf8();
-                    col_idx += 1;
-                } else {
-                    // Batch consecutive characters with the same syntax color
-                    let absolute_byte_start = line_start_byte + char_idx;
-
-                    // Get color for the current character
-                    let batch_color = line_spans
-                        .iter()
-                        .find(|span| absolute_byte_start >= span.start_byte && absolute_byte_start < span.end_byte)
-                        .map(|span| theme.color_for(span.token_type));
-
-                    // Find the end of consecutive characters with the same color/styling
-                    let mut batch_end = col_idx + 1;
-                    let mut temp_byte_offset = char_idx + ch.len_utf8();
-
-                    while batch_end < chars.len() {
-                        let next_ch = chars[batch_end];
-
-                        // Check if next position is marked
-                        if is_marked(batch_end) {
-                            break;
-                        }
-
-                        // Check if next position is selected
-                        if let Some((start, end)) = selection_range {
-                            if batch_end >= start && batch_end < end {
-                                break;
-                            }
-                        }
-
-                        // Check if next position is a search match
-                        if is_search_match(batch_end) {
-                            break;
-                        }
-
-                        // Check if next position is an indent guide
-                        let next_is_guide = show_indent_guides
-                            && next_ch == ' '
-                            && batch_end < indent_level * 4
-                            && batch_end % 4 == 0;
-                        if next_is_guide {
-                            break;
-                        }
-
-                        // Check if next character has a different color
-                        let next_absolute_byte = line_start_byte + temp_byte_offset;
-                        let next_color = line_spans
-                            .iter()
-                            .find(|span| next_absolute_byte >= span.start_byte && next_absolute_byte < span.end_byte)
-                            .map(|span| theme.color_for(span.token_type));
-
-                        if next_color != batch_color {
-                            break;
-                        }
-
-                        temp_byte_offset += next_ch.len_utf8();
-                        batch_end += 1;
-                    }
-
-                    // Collect and print the entire batch
-                    let batch_text: String = chars[col_idx..batch_end].iter().collect();
-
-                    if let Some(color) = batch_color {
-                        terminal.set_fg(color)?;
-                        terminal.print(&batch_text)?;
-                        terminal.reset_color()?;
-                    } else {
-                        terminal.print(&batch_text)?;
-                    }
-
-                    // Update byte tracking for all characters in the batch
-                    for i in col_idx..batch_end {
-                        char_idx += chars[i].len_utf8();
-                    }
+                LineStyle::Syntax(syntax_color(line_start_byte + grapheme.byte))
+            };
 
-                    col_idx = batch_end;
-                }
+            if batch_style != Some(style) {
+                Self::print_batch(terminal, &batch, batch_style, theme)?;
+                batch.clear();
+                batch_style = Some(style);
+            }
+            if style == LineStyle::Guide {
+                batch.push('│');
+            } else {
+                batch.push_str(&grapheme.clipped_text(left_column, right_column));
             }
         }
+        Self::print_batch(terminal, &batch, batch_style, theme)?;
 
         // Render AI suggestion as ghost text (gray text after cursor)
         // This only happens on the cursor line, and only if cursor is at the end
         if let Some(suggestion_line) = ai_line_to_show {
             let cursor_col = state.cursor.column;
             if show_ai_on_cursor_line && cursor_col >= line.chars().count() {
-                terminal.set_fg(Color::DarkGrey)?;
+                terminal.set_fg(theme.ui.ghost_text)?;
                 terminal.print(suggestion_line)?;
                 terminal.reset_color()?;
             }
@@ -595,9 +464,32 @@ impl BufferView {
         Ok(())
     }
 
+    /// Print a batch of text of a line in its style
+    fn print_batch(terminal: &Terminal, text: &str, style: Option<LineStyle>, theme: &Theme) -> Result<()> {
+        let Some(style) = style.filter(|_| !text.is_empty()) else {
+            return Ok(());
+        };
+        let (bg, fg) = match style {
+            LineStyle::Mark => (Some(theme.ui.mark_bg), Some(theme.ui.mark_fg)),
+            LineStyle::Selection => (Some(theme.ui.selection_bg), Some(theme.ui.selection_fg)),
+            LineStyle::SearchMatch => (Some(theme.ui.search_match_bg), Some(theme.ui.search_match_fg)),
+            LineStyle::Guide => (None, Some(theme.ui.indent_guide)),
+            LineStyle::Syntax(color) => (None, color),
+        };
+        if let Some(bg) = bg {
+            terminal.set_bg(bg)?;
+        }
+        if let Some(fg) = fg {
+            terminal.set_fg(fg)?;
+        }
+        terminal.print(text)?;
+        terminal.reset_color()
+    }
+
     fn render_cursor(
         terminal: &Terminal,
         state: &EditorState,
+        buffer: &TextBuffer,
         line_number_width: u16,
     ) -> Result<()> {
         let (_, term_height) = terminal.size();
@@ -605,7 +497,8 @@ impl BufferView {
         // Calculate screen position of cursor
         let screen_line = state.cursor.line.saturating_sub(state.viewport.top_line);
         // Apply horizontal scrolling offset
-        let screen_col = state.cursor.column.saturating_sub(state.viewport.left_column) as u16 + line_number_width;
+        let display_col = buffer.display_column(state.cursor.position(), state.tab_width);
+        let screen_col = display_col.saturating_sub(state.viewport.left_column) as u16 + line_number_width;
 
         // Account for tab bar and path bar at top (2 lines offset)
         let tab_bar_height = 1;
@@ -623,6 +516,16 @@ impl BufferView {
         Ok(())
     }
 
+    /// Width of the gutter left of the text: the marker column, then the
+    /// line numbers and a space after them, if shown
+    pub fn gutter_width(buffer: &TextBuffer, show_line_numbers: bool) -> u16 {
+        if show_line_numbers {
+            MARKER_COLUMN_WIDTH + Self::calculate_line_number_width(buffer) + 1
+        } else {
+            MARKER_COLUMN_WIDTH
+        }
+    }
+
     fn calculate_line_number_width(buffer: &TextBuffer) -> u16 {
         let line_count = buffer.len_lines();
         let digits = if line_count == 0 {
@@ -633,10 +536,19 @@ impl BufferView {
         digits.max(3) // Minimum width of 3
     }
 
-    /// Calculate indentation level (number of 4-space indents)
-    fn calculate_indent_level(line: &str) -> usize {
-        let leading_spaces = line.chars().take_while(|c| *c == ' ').count();
-        leading_spaces / 4 // Python uses 4 spaces per indent level
+    /// Calculate indentation level (number of `indent_width`-column indents).
+    /// A tab counts as one level, advancing to the next multiple of `indent_width`.
+    fn calculate_indent_level(line: &str, indent_width: usize) -> usize {
+        let indent_width = indent_width.max(1);
+        let mut columns = 0;
+        for ch in line.chars() {
+            match ch {
+                ' ' => columns += 1,
+                '\t' => columns = (columns / indent_width + 1) * indent_width,
+                _ => break,
+            }
+        }
+        columns / indent_width
     }
 
     /// Calculate indentation levels for all lines in the viewport
@@ -668,7 +580,7 @@ impl BufferView {
                         if let Some(next_line) = buffer.get_line(look_ahead) {
                             let next_line = next_line.trim_end_matches(&['\n', '\r'][..]);
                             if !next_line.trim().is_empty() {
-                                next_indent = Self::calculate_indent_level(next_line);
+                                next_indent = Self::calculate_indent_level(next_line, state.indentation.width);
                                 break;
                             }
                         }
@@ -676,7 +588,7 @@ impl BufferView {
                     levels.push(next_indent);
                 } else {
                     // Non-empty line - calculate its indentation
-                    levels.push(Self::calculate_indent_level(line));
+                    levels.push(Self::calculate_indent_level(line, state.indentation.width));
                 }
             } else {
                 levels.push(0);
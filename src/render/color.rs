@@ -0,0 +1,251 @@
+use crossterm::style::Color;
+use std::path::PathBuf;
+
+/// How many colors the terminal can show. Theme colors are 24-bit; on
+/// terminals with fewer colors they are mapped to the nearest palette entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+/// Index of `max_colors` among the numeric terminfo capabilities
+const TERMINFO_MAX_COLORS: usize = 13;
+
+/// The 16 basic colors, with the RGB values xterm uses for them
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel levels of the 6x6x6 color cube of the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    /// Color depth for the `ui.color_depth` setting: "truecolor", "256" or
+    /// "16" force it, anything else ("auto") detects it
+    pub fn from_setting(setting: &str) -> Self {
+        match setting.to_lowercase().as_str() {
+            "truecolor" | "24bit" => Self::TrueColor,
+            "256" => Self::Ansi256,
+            "16" | "8" => Self::Ansi16,
+            _ => Self::detect(),
+        }
+    }
+
+    /// Detect the color depth from COLORTERM, TERM and the terminfo entry
+    /// of TERM
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+        let terminfo_colors = term.as_deref().and_then(terminfo_max_colors);
+        Self::detect_from(colorterm.as_deref(), term.as_deref(), terminfo_colors)
+    }
+
+    /// Detect the color depth from the values of COLORTERM and TERM and the
+    /// `max_colors` capability of TERM's terminfo entry
+    pub fn detect_from(colorterm: Option<&str>, term: Option<&str>, terminfo_colors: Option<i32>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            return Self::TrueColor;
+        }
+        let term = term.unwrap_or_default();
+        if term.ends_with("-direct") || term.contains("truecolor") || term.contains("24bit") {
+            return Self::TrueColor;
+        }
+        match terminfo_colors {
+            Some(colors) if colors >= 1 << 24 => Self::TrueColor,
+            Some(colors) if colors >= 256 => Self::Ansi256,
+            Some(_) => Self::Ansi16,
+            None if term.contains("256color") => Self::Ansi256,
+            None => Self::Ansi16,
+        }
+    }
+
+    /// The color to send to the terminal for `color`
+    pub fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (Self::TrueColor, _) => color,
+            (Self::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(rgb_to_ansi256(r, g, b)),
+            (Self::Ansi16, Color::Rgb { r, g, b }) => rgb_to_ansi16(r, g, b),
+            (Self::Ansi16, Color::AnsiValue(value)) => {
+                let (r, g, b) = ansi256_to_rgb(value);
+                rgb_to_ansi16(r, g, b)
+            }
+            _ => color,
+        }
+    }
+}
+
+/// Squared distance between two colors, weighted for how much the eye
+/// notices differences in each channel
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let dr = r1.abs_diff(r2) as u32;
+    let dg = g1.abs_diff(g2) as u32;
+    let db = b1.abs_diff(b2) as u32;
+    3 * dr * dr + 4 * dg * dg + 2 * db * db
+}
+
+/// Nearest entry of the 256-color palette (color cube or gray ramp)
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |channel: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(channel))
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    // Gray ramp: 232..=255 are the grays 8, 18, ..., 238
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + 10 * gray_index;
+
+    if distance((gray_level, gray_level, gray_level), (r, g, b)) < distance(cube, (r, g, b)) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+/// RGB value of an entry of the 256-color palette
+fn ansi256_to_rgb(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..=15 => ANSI16[value as usize].1,
+        16..=231 => {
+            let index = (value - 16) as usize;
+            (CUBE_LEVELS[index / 36], CUBE_LEVELS[index / 6 % 6], CUBE_LEVELS[index % 6])
+        }
+        _ => {
+            let level = 8 + 10 * (value - 232);
+            (level, level, level)
+        }
+    }
+}
+
+/// Nearest of the 16 basic colors
+fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> Color {
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::White)
+}
+
+/// The `max_colors` capability of a terminal's terminfo entry
+fn terminfo_max_colors(term: &str) -> Option<i32> {
+    let first = term.chars().next()?;
+    terminfo_dirs().into_iter().find_map(|dir| {
+        // Entries are in a directory named after their first letter, or
+        // its hex code on some systems
+        [first.to_string(), format!("{:x}", first as u32)]
+            .iter()
+            .find_map(|subdir| std::fs::read(dir.join(subdir).join(term)).ok())
+            .and_then(|data| parse_terminfo_max_colors(&data))
+    })
+}
+
+/// Directories searched for terminfo entries, in the order ncurses uses
+fn terminfo_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(dir) = std::env::var("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Ok(home) = std::env::var("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Ok(list) = std::env::var("TERMINFO_DIRS") {
+        dirs.extend(list.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from));
+    }
+    dirs.extend(["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"].map(PathBuf::from));
+    dirs
+}
+
+/// Read `max_colors` from a compiled terminfo entry (legacy 16-bit or
+/// extended 32-bit number format)
+fn parse_terminfo_max_colors(data: &[u8]) -> Option<i32> {
+    let header = |i: usize| -> Option<usize> {
+        let bytes = data.get(2 * i..2 * i + 2)?;
+        Some(i16::from_le_bytes([bytes[0], bytes[1]]).max(0) as usize)
+    };
+    let number_size = match header(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names_size, bool_count, number_count) = (header(1)?, header(2)?, header(3)?);
+    if number_count <= TERMINFO_MAX_COLORS {
+        return None;
+    }
+
+    // Numbers start on an even offset after the names and booleans
+    let mut offset = 12 + names_size + bool_count;
+    offset += offset % 2;
+    let start = offset + TERMINFO_MAX_COLORS * number_size;
+    let bytes = data.get(start..start + number_size)?;
+    let value = if number_size == 2 {
+        i16::from_le_bytes([bytes[0], bytes[1]]) as i32
+    } else {
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    (value >= 0).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let detect = ColorDepth::detect_from;
+        assert_eq!(detect(Some("truecolor"), Some("xterm-256color"), Some(256)), ColorDepth::TrueColor);
+        assert_eq!(detect(None, Some("tmux-256color"), Some(256)), ColorDepth::Ansi256);
+        assert_eq!(detect(None, Some("screen-256color"), None), ColorDepth::Ansi256);
+        assert_eq!(detect(None, Some("linux"), Some(8)), ColorDepth::Ansi16);
+        assert_eq!(detect(None, Some("xterm-direct"), None), ColorDepth::TrueColor);
+        assert_eq!(detect(None, None, None), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn test_convert() {
+        let red = Color::Rgb { r: 250, g: 5, b: 5 };
+        assert_eq!(ColorDepth::TrueColor.convert(red), red);
+        assert_eq!(ColorDepth::Ansi256.convert(red), Color::AnsiValue(196));
+        assert_eq!(ColorDepth::Ansi16.convert(red), Color::Red);
+
+        // Grays use the gray ramp rather than the cube
+        assert_eq!(ColorDepth::Ansi256.convert(Color::Rgb { r: 60, g: 60, b: 60 }), Color::AnsiValue(237));
+        assert_eq!(ColorDepth::Ansi16.convert(Color::AnsiValue(237)), Color::Black);
+
+        // Named colors are already in every palette
+        assert_eq!(ColorDepth::Ansi16.convert(Color::DarkGrey), Color::DarkGrey);
+        assert_eq!(ColorDepth::Ansi256.convert(Color::Reset), Color::Reset);
+    }
+
+    #[test]
+    fn test_parse_terminfo() {
+        // Legacy format: header, names "x\0", no booleans, 14 numbers
+        let mut data: Vec<u8> = [0o432i16, 2, 0, 14, 0, 0].iter().flat_map(|n| n.to_le_bytes()).collect();
+        data.extend(b"x\0");
+        for i in 0..14i16 {
+            data.extend(if i == 13 { 256i16 } else { -1 }.to_le_bytes());
+        }
+        assert_eq!(parse_terminfo_max_colors(&data), Some(256));
+        assert_eq!(parse_terminfo_max_colors(&data[..20]), None);
+        assert_eq!(parse_terminfo_max_colors(b"not terminfo"), None);
+    }
+}
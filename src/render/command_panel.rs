@@ -1,7 +1,7 @@
 use crate::app::Command;
 use crate::render::terminal::Terminal;
+use crate::syntax::Theme;
 use anyhow::Result;
-use crossterm::style::Color;
 
 /// Render the command panel UI (Ctrl+Shift+P)
 pub struct CommandPanel;
@@ -14,6 +14,7 @@ impl CommandPanel {
         commands: &[Command],
         selected: usize,
         scroll_offset: usize,
+        theme: &Theme,
     ) -> Result<()> {
         let (term_width, term_height) = terminal.size();
 
@@ -29,8 +30,8 @@ impl CommandPanel {
 
         // Draw header
         terminal.move_cursor(x, y)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::White)?;
+        terminal.set_bg(theme.ui.panel_header_bg)?;
+        terminal.set_fg(theme.ui.panel_header_fg)?;
         terminal.print(&format!(" Command Palette: {} ", pattern))?;
         terminal.print(&" ".repeat((width as usize).saturating_sub(pattern.len() + 19)))?;
         terminal.reset_color()?;
@@ -42,11 +43,11 @@ impl CommandPanel {
             terminal.move_cursor(x, row)?;
 
             if command_index == selected {
-                terminal.set_bg(Color::Blue)?;
-                terminal.set_fg(Color::White)?;
+                terminal.set_bg(theme.ui.panel_selected_bg)?;
+                terminal.set_fg(theme.ui.panel_selected_fg)?;
             } else {
-                terminal.set_bg(Color::Black)?;
-                terminal.set_fg(Color::White)?;
+                terminal.set_bg(theme.ui.panel_bg)?;
+                terminal.set_fg(theme.ui.panel_fg)?;
             }
 
             // Format: "Command Name    Keybinding"
@@ -71,8 +72,8 @@ impl CommandPanel {
 
         // Draw footer with help text
         terminal.move_cursor(x, y + height - 1)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::Grey)?;
+        terminal.set_bg(theme.ui.panel_footer_bg)?;
+        terminal.set_fg(theme.ui.panel_footer_fg)?;
         let footer = format!(" {} commands | ↑↓: Navigate | Enter: Execute | Esc: Cancel ", commands.len());
         let footer_display = if footer.len() > width as usize {
             format!(" {} commands ", commands.len())
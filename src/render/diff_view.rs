@@ -0,0 +1,229 @@
+use crate::diff::{DiffLine, DiffView};
+use crate::language::LanguageRegistry;
+use crate::render::terminal::Terminal;
+use crate::syntax::{HighlightSpan, Highlighter, Theme};
+use anyhow::Result;
+
+/// Highlight spans for the left and right side of a diff, if supported
+pub type DiffHighlights = Option<(Option<Vec<HighlightSpan>>, Option<Vec<HighlightSpan>>)>;
+
+/// Side-by-side diff renderer, shared by `--diff` mode and the in-editor diff
+pub struct DiffRenderer;
+
+impl DiffRenderer {
+    /// Compute syntax highlighting for both sides of the diff
+    pub fn highlight(diff_view: &DiffView, highlighter: &mut Highlighter) -> DiffHighlights {
+        if !diff_view.supports_syntax_highlighting() {
+            return None;
+        }
+        let language = LanguageRegistry::default().for_path(&diff_view.left_path)?.syntax?;
+
+        highlighter.set_language(&language.language()).ok();
+        let query = language.query().ok();
+        let capture_names = language.capture_names().ok();
+
+        // Get highlight spans for both files
+        let (left_spans, right_spans) = if let (Some(query), Some(capture_names)) = (&query, &capture_names) {
+            (
+                highlighter.highlight(&diff_view.left_content, query, capture_names).ok(),
+                highlighter.highlight(&diff_view.right_content, query, capture_names).ok(),
+            )
+        } else {
+            (None, None)
+        };
+
+        Some((left_spans, right_spans))
+    }
+
+    /// Number of diff rows that fit on screen (header and status bar excluded)
+    pub fn visible_height(terminal: &Terminal) -> usize {
+        let (_, term_height) = terminal.size();
+        term_height.saturating_sub(2) as usize
+    }
+
+    /// Render the diff view full-screen, with `hints` listing the available keys
+    pub fn render(
+        terminal: &Terminal,
+        diff_view: &DiffView,
+        theme: &Theme,
+        highlights: &DiffHighlights,
+        hints: &str,
+    ) -> Result<()> {
+        let (term_width, term_height) = terminal.size();
+
+        // Clear screen
+        terminal.set_base_colors(theme.ui.foreground, theme.ui.background);
+        terminal.clear()?;
+
+        // Calculate pane widths (50/50 split with a divider)
+        let pane_width = (term_width / 2).saturating_sub(1);
+
+        // Render header (file names)
+        terminal.move_cursor(0, 0)?;
+        terminal.set_bg(theme.ui.status_bg)?;
+        terminal.set_fg(theme.ui.status_fg)?;
+
+        let left_header = format!("{:width$}", diff_view.left_label, width = pane_width as usize);
+        terminal.print(&left_header)?;
+
+        // Divider
+        terminal.print("│")?;
+
+        let right_header = format!("{:width$}", diff_view.right_label, width = pane_width as usize);
+        terminal.print(&right_header)?;
+        terminal.reset_color()?;
+
+        // Calculate visible content area
+        let content_height = Self::visible_height(terminal);
+        let (left_lines, right_lines) = diff_view.visible_lines(content_height);
+
+        // Extract highlight spans if available
+        let (left_spans, right_spans) = match highlights {
+            Some((left, right)) => (left.as_deref(), right.as_deref()),
+            None => (None, None),
+        };
+
+        // Rows of the selected hunk get a highlighted divider
+        let selected_rows = diff_view.current_hunk().map(|hunk| hunk.rows.clone());
+
+        // Render diff lines
+        for (idx, (left_line, right_line)) in left_lines.iter().zip(right_lines.iter()).enumerate() {
+            let screen_row = idx as u16 + 1;
+            let in_selected_hunk = selected_rows
+                .as_ref()
+                .map(|rows| rows.contains(&(diff_view.scroll_offset + idx)))
+                .unwrap_or(false);
+
+            // Render left pane
+            terminal.move_cursor(0, screen_row)?;
+            Self::render_line(terminal, left_line, pane_width, left_spans, theme)?;
+
+            // Render divider
+            terminal.move_cursor(pane_width, screen_row)?;
+            if in_selected_hunk {
+                terminal.set_fg(theme.ui.diff_hunk)?;
+                terminal.print("┃")?;
+            } else {
+                terminal.set_fg(theme.ui.separator)?;
+                terminal.print("│")?;
+            }
+            terminal.reset_color()?;
+
+            // Render right pane
+            terminal.move_cursor(pane_width + 1, screen_row)?;
+            Self::render_line(terminal, right_line, pane_width, right_spans, theme)?;
+        }
+
+        // Render status bar
+        let status_row = term_height - 1;
+        terminal.move_cursor(0, status_row)?;
+        terminal.set_bg(theme.ui.status_bg)?;
+        terminal.set_fg(theme.ui.status_fg)?;
+        let hunk_info = match diff_view.selected_hunk {
+            Some(index) => format!("Hunk {}/{} | ", index + 1, diff_view.hunks.len()),
+            None => String::new(),
+        };
+        let status = format!(
+            " Line {}/{} | {}{} ",
+            diff_view.scroll_offset + 1,
+            diff_view.left_lines.len(),
+            hunk_info,
+            hints
+        );
+        terminal.print(&format!("{:width$}", status, width = term_width as usize))?;
+        terminal.reset_color()?;
+
+        terminal.flush()?;
+
+        Ok(())
+    }
+
+    /// Render a single diff line with syntax highlighting
+    fn render_line(
+        terminal: &Terminal,
+        line: &DiffLine,
+        width: u16,
+        highlight_spans: Option<&[HighlightSpan]>,
+        theme: &Theme,
+    ) -> Result<()> {
+        // Set background color based on change type
+        let bg_color = line.change_type.bg_color(theme);
+        terminal.set_bg(bg_color)?;
+
+        // Render line number (without syntax highlighting)
+        if let Some(line_num) = line.old_line_num.or(line.new_line_num) {
+            terminal.set_fg(theme.ui.line_number)?;
+            terminal.print(&format!("{:>4} ", line_num))?;
+        } else {
+            terminal.print("     ")?; // Empty lines
+        }
+
+        // Render content with syntax highlighting if available
+        let content_width = (width as usize).saturating_sub(5); // 5 for line number + space
+
+        if line.content.is_empty() {
+            // Empty line - just fill with background
+            for _ in 0..content_width {
+                terminal.print(" ")?;
+            }
+        } else if let Some(spans) = highlight_spans {
+            // Render with syntax highlighting
+            // We need to find which spans correspond to this line
+            // For simplicity, we'll render character by character and check spans
+            let displayed_content = if line.content.len() > content_width {
+                &line.content[..content_width]
+            } else {
+                &line.content
+            };
+
+            let chars: Vec<char> = displayed_content.chars().collect();
+            let mut byte_offset = 0;
+
+            for ch in &chars {
+                // Find if this character is within a syntax span
+                let syntax_color = spans
+                    .iter()
+                    .find(|span| byte_offset >= span.start_byte && byte_offset < span.end_byte)
+                    .map(|span| theme.color_for(span.token_type));
+
+                // Apply syntax color or default color
+                if let Some(color) = syntax_color {
+                    terminal.set_fg(color)?;
+                } else {
+                    // Default text color for diff type
+                    terminal.set_fg(line.change_type.color(theme))?;
+                }
+
+                terminal.print(&ch.to_string())?;
+                byte_offset += ch.len_utf8();
+            }
+
+            // Fill remaining space
+            let padding = content_width.saturating_sub(displayed_content.len());
+            for _ in 0..padding {
+                terminal.print(" ")?;
+            }
+        } else {
+            // No syntax highlighting - use plain colors
+            let displayed_content = if line.content.len() > content_width {
+                &line.content[..content_width]
+            } else {
+                &line.content
+            };
+
+            terminal.set_fg(line.change_type.color(theme))?;
+
+            terminal.print(displayed_content)?;
+
+            // Fill remaining space
+            let padding = content_width.saturating_sub(line.content.len());
+            for _ in 0..padding {
+                terminal.print(" ")?;
+            }
+        }
+
+        terminal.reset_color()?;
+
+        Ok(())
+    }
+}
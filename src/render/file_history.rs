@@ -0,0 +1,82 @@
+use crate::backup::Snapshot;
+use crate::render::terminal::Terminal;
+use crate::syntax::Theme;
+use anyhow::Result;
+
+/// Render the local history panel (File History command)
+pub struct FileHistoryPanel;
+
+impl FileHistoryPanel {
+    /// Maximum number of snapshots shown at once
+    pub const MAX_VISIBLE: usize = 18;
+
+    /// Render the file history overlay
+    pub fn render(
+        terminal: &Terminal,
+        file_name: &str,
+        snapshots: &[Snapshot],
+        selected: usize,
+        scroll_offset: usize,
+        theme: &Theme,
+    ) -> Result<()> {
+        let (term_width, term_height) = terminal.size();
+
+        // Calculate dimensions (centered, 80% width, max 20 lines)
+        let width = (term_width as f32 * 0.8) as u16;
+        let height = (Self::MAX_VISIBLE as u16 + 2).min(snapshots.len() as u16 + 2);
+        let x = (term_width - width) / 2;
+        let y = (term_height - height) / 2;
+        let visible_count = (height - 2) as usize;
+
+        // Draw header
+        terminal.move_cursor(x, y)?;
+        terminal.set_bg(theme.ui.panel_header_bg)?;
+        terminal.set_fg(theme.ui.panel_header_fg)?;
+        let header = format!(" File History: {} ", file_name);
+        terminal.print(&header)?;
+        terminal.print(&" ".repeat((width as usize).saturating_sub(header.chars().count())))?;
+        terminal.reset_color()?;
+
+        // Draw snapshots with scrolling
+        for (i, snapshot) in snapshots.iter().skip(scroll_offset).take(visible_count).enumerate() {
+            let row = y + 1 + i as u16;
+            let snapshot_index = scroll_offset + i;
+            terminal.move_cursor(x, row)?;
+
+            if snapshot_index == selected {
+                terminal.set_bg(theme.ui.panel_selected_bg)?;
+                terminal.set_fg(theme.ui.panel_selected_fg)?;
+            } else {
+                terminal.set_bg(theme.ui.panel_bg)?;
+                terminal.set_fg(theme.ui.panel_fg)?;
+            }
+
+            // Format: "Saved 5 min ago            1234 bytes"
+            let age = format!("Saved {}", snapshot.age_description());
+            let size = std::fs::metadata(&snapshot.path)
+                .map(|m| format!("{} bytes", m.len()))
+                .unwrap_or_default();
+            let padding = (width as usize).saturating_sub(age.len() + size.len() + 2);
+
+            terminal.print(&format!(" {}{}{} ", age, " ".repeat(padding), size))?;
+            terminal.reset_color()?;
+        }
+
+        // Draw footer with help text
+        terminal.move_cursor(x, y + height - 1)?;
+        terminal.set_bg(theme.ui.panel_footer_bg)?;
+        terminal.set_fg(theme.ui.panel_footer_fg)?;
+        let footer = format!(" {} snapshots | ↑↓: Navigate | Enter: Diff | Esc: Cancel ", snapshots.len());
+        let footer_display = if footer.chars().count() > width as usize {
+            format!(" {} snapshots ", snapshots.len())
+        } else {
+            footer
+        };
+        terminal.print(&footer_display)?;
+        terminal.print(&" ".repeat((width as usize).saturating_sub(footer_display.chars().count())))?;
+        terminal.reset_color()?;
+
+        terminal.flush()?;
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use crate::render::terminal::Terminal;
+use crate::syntax::Theme;
 use crate::search::FileSearchResult;
 use anyhow::Result;
-use crossterm::style::Color;
 
 /// Render the file picker UI (Ctrl+P)
 pub struct FilePicker;
@@ -14,6 +14,7 @@ impl FilePicker {
         results: &[FileSearchResult],
         selected: usize,
         scroll_offset: usize,
+        theme: &Theme,
     ) -> Result<()> {
         let (term_width, term_height) = terminal.size();
 
@@ -29,8 +30,8 @@ impl FilePicker {
 
         // Draw border
         terminal.move_cursor(x, y)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::White)?;
+        terminal.set_bg(theme.ui.panel_header_bg)?;
+        terminal.set_fg(theme.ui.panel_header_fg)?;
         terminal.print(&format!(" Find File: {} ", pattern))?;
         terminal.print(&" ".repeat((width as usize).saturating_sub(pattern.len() + 13)))?;
         terminal.reset_color()?;
@@ -42,11 +43,11 @@ impl FilePicker {
             terminal.move_cursor(x, row)?;
 
             if result_index == selected {
-                terminal.set_bg(Color::Blue)?;
-                terminal.set_fg(Color::White)?;
+                terminal.set_bg(theme.ui.panel_selected_bg)?;
+                terminal.set_fg(theme.ui.panel_selected_fg)?;
             } else {
-                terminal.set_bg(Color::Black)?;
-                terminal.set_fg(Color::White)?;
+                terminal.set_bg(theme.ui.panel_bg)?;
+                terminal.set_fg(theme.ui.panel_fg)?;
             }
 
             // Truncate display path if too long
@@ -63,8 +64,8 @@ impl FilePicker {
 
         // Draw status line
         terminal.move_cursor(x, y + height - 1)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::Grey)?;
+        terminal.set_bg(theme.ui.panel_footer_bg)?;
+        terminal.set_fg(theme.ui.panel_footer_fg)?;
         let status = format!(" {} matches ", results.len());
         terminal.print(&status)?;
         terminal.print(&" ".repeat((width as usize).saturating_sub(status.len())))?;
@@ -0,0 +1,129 @@
+use crate::render::terminal::Terminal;
+use crate::syntax::Theme;
+use crate::workspace::{HexBuffer, PaneRect};
+use anyhow::Result;
+use crossterm::style::Color;
+
+/// Render a hex buffer: offset column, hex bytes and ASCII gutter
+pub struct HexView;
+
+impl HexView {
+    /// Width of the offset column, including the gap after it
+    const OFFSET_WIDTH: u16 = 10;
+    /// Screen column (relative to the pane) where the ASCII gutter starts
+    const ASCII_START: u16 = Self::OFFSET_WIDTH + 3 * HexBuffer::BYTES_PER_ROW as u16 + 2;
+
+    /// Render the visible rows of a hex buffer into a pane.
+    /// `search_match` is the (offset, length) of the current search match.
+    pub fn render(
+        terminal: &Terminal,
+        hex: &HexBuffer,
+        rect: &PaneRect,
+        search_match: Option<(usize, usize)>,
+        theme: &Theme,
+    ) -> Result<()> {
+        let data = hex.data();
+        let ui = &theme.ui;
+
+        for screen_row in 0..rect.height {
+            let row = hex.top_row() + screen_row as usize;
+            let row_start = row * HexBuffer::BYTES_PER_ROW;
+            let y = rect.y + screen_row;
+
+            terminal.move_cursor(rect.x, y)?;
+            terminal.print(&" ".repeat(rect.width as usize))?;
+            // An empty file still shows its first (empty) row
+            if row_start >= data.len() && !(row == 0 && data.is_empty()) {
+                continue;
+            }
+
+            let mut line = RowPrinter { terminal, x: rect.x, end: rect.x + rect.width, y };
+            line.print(&format!("{:08x}  ", row_start), Some(ui.line_number), None)?;
+
+            let bytes = data.slice(row_start, HexBuffer::BYTES_PER_ROW);
+            let style = |offset: usize, byte: u8, is_edit_column: bool| {
+                let in_match = search_match.is_some_and(|(start, len)| offset >= start && offset < start + len);
+                if offset == hex.cursor() && !is_edit_column {
+                    (Some(ui.selection_fg), Some(ui.selection_bg))
+                } else if in_match {
+                    (Some(ui.search_match_fg), Some(ui.search_match_bg))
+                } else if byte == 0 {
+                    (Some(ui.line_number), None)
+                } else {
+                    (None, None)
+                }
+            };
+
+            for i in 0..HexBuffer::BYTES_PER_ROW {
+                let gap = if i == 7 { "  " } else { " " };
+                match bytes.get(i) {
+                    Some(&byte) => {
+                        let (fg, bg) = style(row_start + i, byte, !hex.ascii_column());
+                        line.print(&format!("{:02x}", byte), fg, bg)?;
+                        line.print(gap, None, None)?;
+                    }
+                    None => line.print(&format!("  {}", gap), None, None)?,
+                }
+            }
+
+            line.print("|", Some(ui.line_number), None)?;
+            for (i, &byte) in bytes.iter().enumerate() {
+                let (fg, bg) = style(row_start + i, byte, hex.ascii_column());
+                let ch = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+                line.print(&ch.to_string(), fg, bg)?;
+            }
+            line.print("|", Some(ui.line_number), None)?;
+        }
+
+        terminal.flush()?;
+        Ok(())
+    }
+
+    /// Screen position of the edit cursor, if it is inside the pane
+    pub fn cursor_position(hex: &HexBuffer, rect: &PaneRect) -> Option<(u16, u16)> {
+        let row = hex.cursor() / HexBuffer::BYTES_PER_ROW;
+        let column = hex.cursor() % HexBuffer::BYTES_PER_ROW;
+        let screen_row = row.checked_sub(hex.top_row())?;
+        if screen_row >= rect.height as usize {
+            return None;
+        }
+
+        let x = if hex.ascii_column() {
+            Self::ASCII_START + column as u16
+        } else {
+            let gap = if column >= 8 { 1 } else { 0 };
+            Self::OFFSET_WIDTH + 3 * column as u16 + gap + hex.low_nibble() as u16
+        };
+        (x < rect.width).then_some((rect.x + x, rect.y + screen_row as u16))
+    }
+}
+
+/// Prints the segments of one row, clipped to the pane width
+struct RowPrinter<'a> {
+    terminal: &'a Terminal,
+    x: u16,
+    end: u16,
+    y: u16,
+}
+
+impl RowPrinter<'_> {
+    fn print(&mut self, text: &str, fg: Option<Color>, bg: Option<Color>) -> Result<()> {
+        let available = self.end.saturating_sub(self.x) as usize;
+        if available == 0 {
+            return Ok(());
+        }
+        let text: String = text.chars().take(available).collect();
+
+        self.terminal.move_cursor(self.x, self.y)?;
+        if let Some(fg) = fg {
+            self.terminal.set_fg(fg)?;
+        }
+        if let Some(bg) = bg {
+            self.terminal.set_bg(bg)?;
+        }
+        self.terminal.print(&text)?;
+        self.terminal.reset_color()?;
+        self.x += text.chars().count() as u16;
+        Ok(())
+    }
+}
@@ -1,16 +1,28 @@
 pub mod buffer_view;
+pub mod color;
 pub mod command_panel;
+pub mod diff_view;
+pub mod file_history;
 pub mod file_picker;
+pub mod hex_view;
 pub mod project_search;
 pub mod statusbar;
 pub mod tab_bar;
 pub mod terminal;
+pub mod theme_picker;
 pub mod ui_components;
+pub mod undo_tree;
 
 pub use buffer_view::BufferView;
+pub use color::ColorDepth;
 pub use command_panel::CommandPanel;
+pub use diff_view::{DiffHighlights, DiffRenderer};
+pub use file_history::FileHistoryPanel;
 pub use file_picker::FilePicker;
+pub use hex_view::HexView;
 pub use project_search::ProjectSearch;
 pub use statusbar::StatusBar;
 pub use tab_bar::TabBar;
 pub use terminal::Terminal;
+pub use theme_picker::ThemePickerPanel;
+pub use undo_tree::UndoTreePanel;
@@ -1,19 +1,21 @@
 use crate::app::ProjectSearchResult;
 use crate::render::terminal::Terminal;
+use crate::syntax::Theme;
 use anyhow::Result;
-use crossterm::style::Color;
 
-/// Render the project search UI (Ctrl+X Ctrl+F)
+/// Render the project search UI (Ctrl+X Ctrl+F), also used to list the
+/// references to a symbol
 pub struct ProjectSearch;
 
 impl ProjectSearch {
-    /// Render the project search overlay
+    /// Render the project search overlay, with `title` in its header
     pub fn render(
         terminal: &Terminal,
-        pattern: &str,
+        title: &str,
         results: &[ProjectSearchResult],
         selected: usize,
         scroll_offset: usize,
+        theme: &Theme,
     ) -> Result<()> {
         let (term_width, term_height) = terminal.size();
 
@@ -25,10 +27,11 @@ impl ProjectSearch {
 
         // Draw header
         terminal.move_cursor(x, y)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::White)?;
-        terminal.print(&format!(" Project Search: {} ", pattern))?;
-        terminal.print(&" ".repeat((width as usize).saturating_sub(pattern.len() + 18)))?;
+        terminal.set_bg(theme.ui.panel_header_bg)?;
+        terminal.set_fg(theme.ui.panel_header_fg)?;
+        let header = format!(" {} ", title);
+        terminal.print(&header)?;
+        terminal.print(&" ".repeat((width as usize).saturating_sub(header.chars().count())))?;
         terminal.reset_color()?;
 
         // Use the provided scroll_offset (managed by App state for symmetric scrolling)
@@ -44,8 +47,11 @@ impl ProjectSearch {
             let row = y + 1 + i as u16;
             terminal.move_cursor(x, row)?;
 
-            let bg_color = if result_index == selected { Color::Blue } else { Color::Black };
-            let fg_color = if result_index == selected { Color::White } else { Color::White };
+            let (bg_color, fg_color) = if result_index == selected {
+                (theme.ui.panel_selected_bg, theme.ui.panel_selected_fg)
+            } else {
+                (theme.ui.panel_bg, theme.ui.panel_fg)
+            };
 
             terminal.set_bg(bg_color)?;
             terminal.set_fg(fg_color)?;
@@ -92,7 +98,7 @@ impl ProjectSearch {
 
                     // Print match (highlighted in yellow, safe slicing)
                     if adjusted_match_start < visible_content.len() {
-                        terminal.set_fg(Color::Yellow)?;
+                        terminal.set_fg(theme.ui.panel_match)?;
                         if let Some(matched) = visible_content.get(adjusted_match_start..adjusted_match_end) {
                             terminal.print(matched)?;
                         }
@@ -122,7 +128,7 @@ impl ProjectSearch {
 
                 // Print match (highlighted in yellow)
                 if match_start < content.len() && match_end <= content.len() {
-                    terminal.set_fg(Color::Yellow)?;
+                    terminal.set_fg(theme.ui.panel_match)?;
                     if let Some(matched) = content.get(match_start..match_end) {
                         terminal.print(matched)?;
                     }
@@ -151,15 +157,15 @@ impl ProjectSearch {
         for i in results_shown..visible_lines {
             let row = y + 1 + i as u16;
             terminal.move_cursor(x, row)?;
-            terminal.set_bg(Color::Black)?;
+            terminal.set_bg(theme.ui.panel_bg)?;
             terminal.print(&" ".repeat(width as usize))?;
             terminal.reset_color()?;
         }
 
         // Draw footer with help text and position indicator
         terminal.move_cursor(x, y + height - 1)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::Grey)?;
+        terminal.set_bg(theme.ui.panel_footer_bg)?;
+        terminal.set_fg(theme.ui.panel_footer_fg)?;
 
         let position_info = if !results.is_empty() {
             format!("{}/{}", selected + 1, results.len())
@@ -2,8 +2,9 @@ use crate::buffer::TextBuffer;
 use crate::editor::EditorState;
 use crate::lsp::Diagnostic;
 use crate::render::terminal::Terminal;
+use crate::workspace::HexBuffer;
 use anyhow::Result;
-use crossterm::style::Color;
+use crate::syntax::Theme;
 
 pub struct StatusBar;
 
@@ -15,13 +16,14 @@ impl StatusBar {
         state: &EditorState,
         message: Option<&str>,
         diagnostics: Option<&[Diagnostic]>,
+        theme: &Theme,
     ) -> Result<()> {
         let (term_width, term_height) = terminal.size();
         let status_y = term_height.saturating_sub(1);
 
         terminal.move_cursor(0, status_y)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::White)?;
+        terminal.set_bg(theme.ui.status_bg)?;
+        terminal.set_fg(theme.ui.status_fg)?;
 
         // If there's a message, show it prominently
         if let Some(msg) = message {
@@ -34,7 +36,7 @@ impl StatusBar {
                 display_msg.truncate(term_width as usize);
             }
 
-            terminal.set_fg(Color::Yellow)?;
+            terminal.set_fg(theme.ui.status_message)?;
             terminal.print(&display_msg)?;
             terminal.reset_color()?;
         } else {
@@ -54,6 +56,9 @@ impl StatusBar {
             if buffer.is_modified() {
                 status.push_str(" [+]");
             }
+            if buffer.is_read_only() {
+                status.push_str(" [RO]");
+            }
 
             // Cursor position
             let position_info = format!(
@@ -65,6 +70,13 @@ impl StatusBar {
             // Line count
             let line_info = format!("{} lines ", buffer.len_lines());
 
+            // Indentation inserted by Tab
+            let indent_info = format!("{} ", state.indentation.label());
+
+            // File encoding, and whether large-file mode is on
+            let large_file_info = if buffer.is_large_file() { "LARGE " } else { "" };
+            let encoding_info = format!("{}{} ", large_file_info, buffer.encoding().name());
+
             // Diagnostics count
             let diag_info = if let Some(diags) = diagnostics {
                 let errors = diags.iter().filter(|d| matches!(d.severity, crate::lsp::DiagnosticSeverity::Error)).count();
@@ -79,8 +91,8 @@ impl StatusBar {
             };
 
             // Calculate spacing
-            let right_side = format!("{}{}{}", position_info, line_info, diag_info);
-            let spaces_needed = term_width as usize - status.len() - right_side.len();
+            let right_side = format!("{}{}{}{}{}", position_info, line_info, indent_info, encoding_info, diag_info);
+            let spaces_needed = (term_width as usize).saturating_sub(status.len() + right_side.len());
 
             status.push_str(&" ".repeat(spaces_needed));
             status.push_str(&right_side);
@@ -100,4 +112,43 @@ impl StatusBar {
         terminal.flush()?;
         Ok(())
     }
+
+    /// Render the status bar for a hex buffer
+    pub fn render_hex(terminal: &Terminal, hex: &HexBuffer, message: Option<&str>, theme: &Theme) -> Result<()> {
+        let (term_width, term_height) = terminal.size();
+        let width = term_width as usize;
+
+        terminal.move_cursor(0, term_height.saturating_sub(1))?;
+        terminal.set_bg(theme.ui.status_bg)?;
+        terminal.set_fg(theme.ui.status_fg)?;
+
+        let status = if let Some(msg) = message {
+            terminal.set_fg(theme.ui.status_message)?;
+            msg.to_string()
+        } else {
+            let mut left = hex.display_name();
+            if hex.is_modified() {
+                left.push_str(" [+]");
+            }
+            let column = if hex.ascii_column() { "ASCII" } else { "HEX" };
+            let value = hex.data().get(hex.cursor()).map(|b| format!(" = {}", b)).unwrap_or_default();
+            let right = format!(
+                " {:#x}{} | {} bytes | {} ",
+                hex.cursor(),
+                value,
+                hex.data().len(),
+                column
+            );
+            let spaces = width.saturating_sub(left.len() + right.len());
+            format!("{}{}{}", left, " ".repeat(spaces), right)
+        };
+
+        let status: String = status.chars().take(width).collect();
+        let padding = width.saturating_sub(status.chars().count());
+        terminal.print(&status)?;
+        terminal.print(&" ".repeat(padding))?;
+        terminal.reset_color()?;
+        terminal.flush()?;
+        Ok(())
+    }
 }
@@ -1,5 +1,5 @@
 use crate::render::terminal::Terminal;
-use crossterm::style::Color;
+use crate::syntax::Theme;
 use crate::workspace::BufferId;
 use anyhow::Result;
 
@@ -11,10 +11,11 @@ impl TabBar {
         terminal: &Terminal,
         buffers: &[(BufferId, String, bool)], // (id, name, modified)
         active_buffer: BufferId,
+        theme: &Theme,
     ) -> Result<()> {
         terminal.move_cursor(0, 0)?;
-        terminal.set_bg(Color::DarkGrey)?;
-        terminal.set_fg(Color::White)?;
+        terminal.set_bg(theme.ui.tab_bar_bg)?;
+        terminal.set_fg(theme.ui.tab_inactive_fg)?;
 
         let (term_width, _) = terminal.size();
         let mut x_pos = 0;
@@ -22,11 +23,11 @@ impl TabBar {
         for (id, name, modified) in buffers {
             // Highlight active tab
             if *id == active_buffer {
-                terminal.set_bg(Color::Blue)?;
-                terminal.set_fg(Color::White)?;
+                terminal.set_bg(theme.ui.tab_active_bg)?;
+                terminal.set_fg(theme.ui.tab_active_fg)?;
             } else {
-                terminal.set_bg(Color::DarkGrey)?;
-                terminal.set_fg(Color::Grey)?;
+                terminal.set_bg(theme.ui.tab_inactive_bg)?;
+                terminal.set_fg(theme.ui.tab_inactive_fg)?;
             }
 
             let modified_marker = if *modified { "*" } else { "" };
@@ -43,6 +44,7 @@ impl TabBar {
 
         // Fill remaining space
         if x_pos < term_width {
+            terminal.set_bg(theme.ui.tab_bar_bg)?;
             terminal.move_cursor(x_pos, 0)?;
             terminal.print(&" ".repeat((term_width - x_pos) as usize))?;
         }
@@ -1,21 +1,30 @@
+use crate::render::color::ColorDepth;
 use anyhow::Result;
 use crossterm::{
     cursor::{self, SetCursorStyle},
-    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
+    event::{
+        DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, EnableMouseCapture,
+    },
     execute, queue,
-    style::{self, Color, Print, SetBackgroundColor, SetForegroundColor},
+    style::{self, Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
     terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use std::cell::Cell;
 use std::io::{self, Write};
 
 pub struct Terminal {
     width: u16,
     height: u16,
+    /// Foreground and background that `reset_color` goes back to
+    base_colors: Cell<(Color, Color)>,
+    /// Colors are converted to what the terminal can show
+    color_depth: ColorDepth,
 }
 
 impl Terminal {
     /// Initialize the terminal
-    pub fn new() -> Result<Self> {
+    pub fn new(color_depth: ColorDepth) -> Result<Self> {
         terminal::enable_raw_mode()?;
         let mut stdout = io::stdout();
         execute!(
@@ -23,6 +32,7 @@ impl Terminal {
             EnterAlternateScreen,
             EnableMouseCapture,
             EnableBracketedPaste,
+            EnableFocusChange,
             Clear(ClearType::All),
             SetCursorStyle::SteadyBlock,
             cursor::Show
@@ -30,7 +40,12 @@ impl Terminal {
 
         let (width, height) = terminal::size()?;
 
-        Ok(Self { width, height })
+        Ok(Self {
+            width,
+            height,
+            base_colors: Cell::new((Color::Reset, Color::Reset)),
+            color_depth,
+        })
     }
 
     /// Get terminal dimensions
@@ -48,6 +63,7 @@ impl Terminal {
 
     /// Clear the entire screen
     pub fn clear(&self) -> Result<()> {
+        self.reset_color()?;
         let mut stdout = io::stdout();
         execute!(stdout, Clear(ClearType::All))?;
         Ok(())
@@ -84,21 +100,42 @@ impl Terminal {
     /// Set foreground color
     pub fn set_fg(&self, color: Color) -> Result<()> {
         let mut stdout = io::stdout();
-        queue!(stdout, SetForegroundColor(color))?;
+        queue!(stdout, SetForegroundColor(self.color_depth.convert(color)))?;
         Ok(())
     }
 
     /// Set background color
     pub fn set_bg(&self, color: Color) -> Result<()> {
         let mut stdout = io::stdout();
-        queue!(stdout, SetBackgroundColor(color))?;
+        queue!(stdout, SetBackgroundColor(self.color_depth.convert(color)))?;
         Ok(())
     }
 
-    /// Reset colors to default
+    /// Turn on a text attribute such as bold. It stays on until turned off
+    /// by its opposite (e.g. `NormalIntensity` for bold).
+    pub fn set_attribute(&self, attribute: Attribute) -> Result<()> {
+        let mut stdout = io::stdout();
+        queue!(stdout, SetAttribute(attribute))?;
+        Ok(())
+    }
+
+    /// Set the colors of the editing area, which `reset_color` goes back to
+    /// (`Color::Reset` keeps the terminal's own)
+    pub fn set_base_colors(&self, fg: Color, bg: Color) {
+        self.base_colors.set((fg, bg));
+    }
+
+    /// Reset colors to the base colors
     pub fn reset_color(&self) -> Result<()> {
         let mut stdout = io::stdout();
         queue!(stdout, style::ResetColor)?;
+        let (fg, bg) = self.base_colors.get();
+        if fg != Color::Reset {
+            queue!(stdout, SetForegroundColor(self.color_depth.convert(fg)))?;
+        }
+        if bg != Color::Reset {
+            queue!(stdout, SetBackgroundColor(self.color_depth.convert(bg)))?;
+        }
         Ok(())
     }
 
@@ -127,7 +164,9 @@ impl Terminal {
         let mut stdout = io::stdout();
         execute!(
             stdout,
+            style::ResetColor,
             cursor::Show,
+            DisableFocusChange,
             DisableBracketedPaste,
             DisableMouseCapture,
             LeaveAlternateScreen
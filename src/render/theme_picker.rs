@@ -0,0 +1,76 @@
+use crate::render::terminal::Terminal;
+use crate::syntax::Theme;
+use anyhow::Result;
+
+/// Render the theme list, docked on the right so the previewed theme can be
+/// seen on the buffer next to it
+pub struct ThemePickerPanel;
+
+impl ThemePickerPanel {
+    /// Number of theme rows that fit on a terminal of the given height
+    pub fn visible_rows(term_height: u16) -> usize {
+        // Tab bar and path bar above, status bar below, plus header and footer
+        term_height.saturating_sub(5).max(1) as usize
+    }
+
+    /// Render the theme picker panel. `theme` is the theme being previewed
+    /// and `current` the name of the theme in use before the picker opened.
+    pub fn render(
+        terminal: &Terminal,
+        names: &[String],
+        current: &str,
+        selected: usize,
+        scroll_offset: usize,
+        theme: &Theme,
+    ) -> Result<()> {
+        let (term_width, term_height) = terminal.size();
+        let width = (term_width / 3).clamp(30.min(term_width), 40);
+        let x = term_width - width;
+        let y = 2;
+        let visible_count = Self::visible_rows(term_height).min(names.len().max(1));
+
+        // Draw header
+        terminal.move_cursor(x, y)?;
+        terminal.set_bg(theme.ui.panel_header_bg)?;
+        terminal.set_fg(theme.ui.panel_header_fg)?;
+        Self::print_padded(terminal, " Select Theme ", width)?;
+        terminal.reset_color()?;
+
+        // Draw the theme names with scrolling
+        for i in 0..visible_count {
+            terminal.move_cursor(x, y + 1 + i as u16)?;
+            let index = scroll_offset + i;
+            let (bg, fg) = if index == selected {
+                (theme.ui.panel_selected_bg, theme.ui.panel_selected_fg)
+            } else {
+                (theme.ui.panel_bg, theme.ui.panel_fg)
+            };
+            terminal.set_bg(bg)?;
+            terminal.set_fg(fg)?;
+            let text = match names.get(index) {
+                Some(name) if name == current => format!(" {} (current)", name),
+                Some(name) => format!(" {}", name),
+                None => String::new(),
+            };
+            Self::print_padded(terminal, &text, width)?;
+            terminal.reset_color()?;
+        }
+
+        // Draw footer with help text
+        terminal.move_cursor(x, y + 1 + visible_count as u16)?;
+        terminal.set_bg(theme.ui.panel_footer_bg)?;
+        terminal.set_fg(theme.ui.panel_footer_fg)?;
+        Self::print_padded(terminal, " ↑↓: Preview | Enter: Use | Esc: Cancel ", width)?;
+        terminal.reset_color()?;
+
+        terminal.flush()?;
+        Ok(())
+    }
+
+    /// Print text cut or padded to exactly `width` columns
+    fn print_padded(terminal: &Terminal, text: &str, width: u16) -> Result<()> {
+        let text: String = text.chars().take(width as usize).collect();
+        let padding = (width as usize).saturating_sub(text.chars().count());
+        terminal.print(&format!("{}{}", text, " ".repeat(padding)))
+    }
+}
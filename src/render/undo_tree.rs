@@ -0,0 +1,94 @@
+use crate::backup::history::format_age;
+use crate::buffer::undo::UndoTreeRow;
+use crate::render::terminal::Terminal;
+use crate::syntax::Theme;
+use anyhow::Result;
+
+/// Render the undo tree browser, docked on the right so the buffer
+/// (showing the selected state) stays visible next to it
+pub struct UndoTreePanel;
+
+impl UndoTreePanel {
+    /// Number of tree rows that fit on a terminal of the given height
+    pub fn visible_rows(term_height: u16) -> usize {
+        // Tab bar and path bar above, status bar below, plus header and footer
+        term_height.saturating_sub(5).max(1) as usize
+    }
+
+    /// Render the undo tree panel
+    pub fn render(
+        terminal: &Terminal,
+        file_name: &str,
+        rows: &[UndoTreeRow],
+        selected: usize,
+        scroll_offset: usize,
+        theme: &Theme,
+    ) -> Result<()> {
+        let (term_width, term_height) = terminal.size();
+        let width = (term_width / 2).clamp(30.min(term_width), 60);
+        let x = term_width - width;
+        let y = 2;
+        let visible_count = Self::visible_rows(term_height);
+
+        // Draw header
+        terminal.move_cursor(x, y)?;
+        terminal.set_bg(theme.ui.panel_header_bg)?;
+        terminal.set_fg(theme.ui.panel_header_fg)?;
+        Self::print_padded(terminal, &format!(" Undo Tree: {} ", file_name), width)?;
+        terminal.reset_color()?;
+
+        // Draw the tree rows with scrolling
+        for i in 0..visible_count {
+            terminal.move_cursor(x, y + 1 + i as u16)?;
+            let row_index = scroll_offset + i;
+            let Some(row) = rows.get(row_index) else {
+                terminal.set_bg(theme.ui.panel_bg)?;
+                Self::print_padded(terminal, "", width)?;
+                terminal.reset_color()?;
+                continue;
+            };
+
+            let (bg, fg) = if row_index == selected {
+                (theme.ui.panel_selected_bg, theme.ui.panel_selected_fg)
+            } else {
+                (theme.ui.panel_bg, theme.ui.panel_fg)
+            };
+            terminal.set_bg(bg)?;
+
+            // Format: "│ ● 12 insert "foo" (line 3)          2 min ago"
+            terminal.set_fg(theme.ui.panel_muted)?;
+            let branches = "│ ".repeat(row.depth);
+            terminal.print(&format!(" {}", branches))?;
+            terminal.set_fg(if row.is_current { theme.ui.undo_current } else { theme.ui.panel_muted })?;
+            terminal.print(if row.is_current { "●" } else { "○" })?;
+            terminal.set_fg(fg)?;
+
+            let age = format_age(row.timestamp);
+            let used = 2 + branches.chars().count();
+            let available = (width as usize).saturating_sub(used + age.len() + 2);
+            let text: String = format!("{:>3} {}", row.seq, row.description).chars().take(available).collect();
+            let padding = available - text.chars().count();
+            terminal.print(&format!("{}{}", text, " ".repeat(padding)))?;
+            terminal.set_fg(theme.ui.panel_muted)?;
+            terminal.print(&format!(" {} ", age))?;
+            terminal.reset_color()?;
+        }
+
+        // Draw footer with help text
+        terminal.move_cursor(x, y + 1 + visible_count as u16)?;
+        terminal.set_bg(theme.ui.panel_footer_bg)?;
+        terminal.set_fg(theme.ui.panel_footer_fg)?;
+        Self::print_padded(terminal, " ↑↓: Preview | Enter: Jump here | Esc: Cancel ", width)?;
+        terminal.reset_color()?;
+
+        terminal.flush()?;
+        Ok(())
+    }
+
+    /// Print text cut or padded to exactly `width` columns
+    fn print_padded(terminal: &Terminal, text: &str, width: u16) -> Result<()> {
+        let text: String = text.chars().take(width as usize).collect();
+        let padding = (width as usize).saturating_sub(text.chars().count());
+        terminal.print(&format!("{}{}", text, " ".repeat(padding)))
+    }
+}
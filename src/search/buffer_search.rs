@@ -0,0 +1,96 @@
+use crate::buffer::{Position, TextBuffer};
+use regex::Regex;
+
+/// Find the next match of `regex` from `from`, one line at a time.
+///
+/// Used for large files, where the incremental search can't afford to copy
+/// the whole buffer and collect every match. Matches don't span lines.
+/// Forward search returns the first match starting at or after `from`;
+/// reverse search the last match ending at or before it. Both wrap around.
+pub fn find_in_lines(buffer: &TextBuffer, regex: &Regex, from: Position, reverse: bool) -> Option<(Position, Position)> {
+    let line_count = buffer.len_lines();
+    if line_count == 0 {
+        return None;
+    }
+
+    // The cursor line is visited twice: first the part on the search side of
+    // the cursor, and again after wrapping around for the other part
+    for step in 0..=line_count {
+        let line_idx = if reverse {
+            (from.line + line_count - step % line_count) % line_count
+        } else {
+            (from.line + step) % line_count
+        };
+        let Some(line) = buffer.get_line(line_idx) else {
+            continue;
+        };
+        let line = line.trim_end_matches(&['\n', '\r'][..]);
+
+        let cursor_byte = if line_idx == from.line {
+            line.char_indices().nth(from.column).map(|(b, _)| b).unwrap_or(line.len())
+        } else {
+            0
+        };
+        let first_pass = step == 0;
+        let wrapped = step == line_count;
+
+        let mut candidates = regex.find_iter(line).filter(|m| {
+            if m.start() == m.end() {
+                return false;
+            }
+            match (reverse, first_pass, wrapped) {
+                (false, true, _) => m.start() >= cursor_byte,
+                (false, _, true) => m.start() < cursor_byte,
+                (true, true, _) => m.end() <= cursor_byte,
+                (true, _, true) => m.end() > cursor_byte,
+                _ => true,
+            }
+        });
+        let found = if reverse { candidates.last() } else { candidates.next() };
+
+        if let Some(m) = found {
+            let start_column = line[..m.start()].chars().count();
+            let end_column = start_column + line[m.start()..m.end()].chars().count();
+            return Some((Position::new(line_idx, start_column), Position::new(line_idx, end_column)));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> TextBuffer {
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), text).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_forward_and_wrap() {
+        let buffer = buffer("foo bar\nbaz foo\nfoo");
+        let re = Regex::new("foo").unwrap();
+
+        let found = find_in_lines(&buffer, &re, Position::new(0, 3), false);
+        assert_eq!(found, Some((Position::new(1, 4), Position::new(1, 7))));
+
+        // Past the last match: wraps to the first one
+        let found = find_in_lines(&buffer, &re, Position::new(2, 3), false);
+        assert_eq!(found, Some((Position::new(0, 0), Position::new(0, 3))));
+    }
+
+    #[test]
+    fn test_reverse_and_wrap() {
+        let buffer = buffer("foo bar\nbaz foo\nfoo");
+        let re = Regex::new("foo").unwrap();
+
+        let found = find_in_lines(&buffer, &re, Position::new(1, 6), true);
+        assert_eq!(found, Some((Position::new(0, 0), Position::new(0, 3))));
+
+        // Before the first match: wraps to the last one
+        let found = find_in_lines(&buffer, &re, Position::new(0, 0), true);
+        assert_eq!(found, Some((Position::new(2, 0), Position::new(2, 3))));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod buffer_search;
 pub mod file_search;
 
+pub use buffer_search::find_in_lines;
 pub use file_search::{FileSearch, FileSearchResult};
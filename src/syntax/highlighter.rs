@@ -1,7 +1,17 @@
+use super::languages::SupportedLanguage;
 use super::theme::{Theme, TokenType};
-use anyhow::Result;
-use std::collections::HashMap;
-use tree_sitter::{Language, Parser, Query, QueryCursor, Tree};
+use crate::buffer::{EditEvent, TextBuffer};
+use crate::language::LanguageRegistry;
+use crate::workspace::BufferId;
+use anyhow::{anyhow, Result};
+use ropey::RopeSlice;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, QueryPredicateArg, Tree};
+
+/// How deep injections may nest (e.g. CSS in HTML in Markdown)
+const MAX_INJECTION_DEPTH: usize = 3;
 
 /// A highlighted span with token type
 #[derive(Debug, Clone)]
@@ -11,17 +21,40 @@ pub struct HighlightSpan {
     pub token_type: TokenType,
 }
 
-/// Cache entry for parsed syntax tree
+/// Syntax tree of a buffer, kept in step with its edits
 struct CacheEntry {
     tree: Tree,
-    text_hash: u64,
+    /// Revision of the text the tree was parsed from
+    revision: u64,
+    /// Spans of the lines highlighted so far. A span is listed on every line
+    /// it covers.
+    line_spans: BTreeMap<usize, Vec<HighlightSpan>>,
+}
+
+/// Compiled highlighting query of a language
+struct LanguageQuery {
+    query: Query,
+    capture_names: HashMap<usize, TokenType>,
+}
+
+/// A region of text written in another language than its surroundings,
+/// such as a fenced code block in Markdown or a `<script>` in HTML
+struct Injection {
+    language: SupportedLanguage,
+    range: Range<usize>,
 }
 
 /// Syntax highlighter using tree-sitter
 pub struct Highlighter {
     parser: Parser,
+    /// Parser for injected regions, so `parser` keeps the buffer's language
+    injection_parser: Parser,
     theme: Theme,
-    cache: HashMap<String, CacheEntry>,
+    /// Syntax trees by buffer, not by path: a file closed and opened again
+    /// is a new buffer whose revisions start over
+    cache: HashMap<BufferId, CacheEntry>,
+    queries: HashMap<SupportedLanguage, Arc<LanguageQuery>>,
+    injection_queries: HashMap<SupportedLanguage, Option<Arc<Query>>>,
 }
 
 impl Highlighter {
@@ -29,57 +62,307 @@ impl Highlighter {
     pub fn new() -> Self {
         Self {
             parser: Parser::new(),
+            injection_parser: Parser::new(),
             theme: Theme::default(),
             cache: HashMap::new(),
+            queries: HashMap::new(),
+            injection_queries: HashMap::new(),
         }
     }
 
+    /// Create a highlighter that colors with `theme`
+    pub fn with_theme(theme: Theme) -> Self {
+        Self { theme, ..Self::new() }
+    }
+
     /// Set the language for parsing
     pub fn set_language(&mut self, language: &Language) -> Result<()> {
         self.parser.set_language(language)?;
         Ok(())
     }
 
-    /// Parse text and return syntax tree
-    fn parse(&mut self, text: &str, file_id: &str) -> Result<Option<Tree>> {
-        // Simple hash for cache invalidation
-        let text_hash = Self::simple_hash(text);
+    /// The highlighting query of a language, compiled on first use
+    fn language_query(&mut self, language: SupportedLanguage) -> Result<Arc<LanguageQuery>> {
+        if let Some(query) = self.queries.get(&language) {
+            return Ok(query.clone());
+        }
+        let query = Arc::new(LanguageQuery {
+            query: language.query()?,
+            capture_names: language.capture_names()?,
+        });
+        self.queries.insert(language, query.clone());
+        Ok(query)
+    }
+
+    /// The injection query of a language, if it can embed other languages
+    fn injection_query(&mut self, language: SupportedLanguage) -> Result<Option<Arc<Query>>> {
+        if let Some(query) = self.injection_queries.get(&language) {
+            return Ok(query.clone());
+        }
+        let query = language.injection_query()?.map(Arc::new);
+        self.injection_queries.insert(language, query.clone());
+        Ok(query)
+    }
+
+    /// Bring the syntax tree of a buffer up to date. The edits made since the
+    /// last parse are applied to the old tree so tree-sitter only reparses
+    /// what they touched; the spans of lines they may have changed are dropped.
+    fn sync_tree(&mut self, buffer: &TextBuffer, buffer_id: BufferId) -> Result<()> {
+        let language = self.parser.language().ok_or_else(|| anyhow!("No language set"))?;
+        let revision = buffer.revision();
+        let rope = buffer.rope();
+        let mut read = |byte: usize, _: Point| read_chunk(rope.slice(..), byte);
+
+        // A tree of another language (the buffer's language was changed) is of no use
+        if self.cache.get(&buffer_id).is_some_and(|entry| *entry.tree.language() != language) {
+            self.cache.remove(&buffer_id);
+        }
 
-        // Check cache
-        if let Some(entry) = self.cache.get(file_id) {
-            if entry.text_hash == text_hash {
-                return Ok(Some(entry.tree.clone()));
+        match self.cache.get_mut(&buffer_id) {
+            Some(entry) if entry.revision == revision => {}
+            Some(entry) => {
+                // Line and byte of the first edit, or None if the edits are no longer logged
+                let first_edit = buffer.edits_since(entry.revision).map(|edits| {
+                    edits.fold((usize::MAX, usize::MAX), |(first_line, first_byte), edit| {
+                        entry.tree.edit(&input_edit(edit));
+                        (first_line.min(edit.start.line), first_byte.min(edit.start_byte))
+                    })
+                });
+                let old_tree = first_edit.is_some().then_some(&entry.tree);
+                let tree = self.parser.parse_with(&mut read, old_tree).ok_or_else(|| anyhow!("Parsing failed"))?;
+
+                // Lines after the first edit may have moved; lines before it only
+                // change if tree-sitter says their syntax did, or if one of their
+                // spans reaches into the edited text
+                match first_edit {
+                    Some((first_line, first_byte)) => {
+                        let first_changed_line = entry
+                            .tree
+                            .changed_ranges(&tree)
+                            .map(|range| rope.byte_to_line(range.start_byte.min(rope.len_bytes())))
+                            .fold(first_line, usize::min);
+                        entry.line_spans.split_off(&first_changed_line);
+                        entry.line_spans.retain(|_, spans| spans.iter().all(|span| span.end_byte < first_byte));
+                    }
+                    None => entry.line_spans.clear(),
+                }
+                entry.tree = tree;
+                entry.revision = revision;
+            }
+            None => {
+                let tree = self.parser.parse_with(&mut read, None).ok_or_else(|| anyhow!("Parsing failed"))?;
+                self.cache.insert(
+                    buffer_id,
+                    CacheEntry {
+                        tree,
+                        revision,
+                        line_spans: BTreeMap::new(),
+                    },
+                );
             }
         }
 
-        // Parse
-        let tree = self.parser.parse(text, None);
-
-        // Cache the result
-        if let Some(tree) = &tree {
-            self.cache.insert(
-                file_id.to_string(),
-                CacheEntry {
-                    tree: tree.clone(),
-                    text_hash,
-                },
-            );
+        Ok(())
+    }
+
+    /// Highlight the lines `lines` (usually the visible ones) of a buffer.
+    /// Only edited text is reparsed, the query only runs over lines not
+    /// highlighted before, so the cost does not grow with the file.
+    pub fn highlight_lines(
+        &mut self,
+        buffer: &TextBuffer,
+        buffer_id: BufferId,
+        language: SupportedLanguage,
+        lines: Range<usize>,
+    ) -> Result<Vec<HighlightSpan>> {
+        self.parser.set_language(&language.language())?;
+        let rope = buffer.rope();
+        let lines = lines.start..lines.end.min(rope.len_lines());
+        self.sync_tree(buffer, buffer_id)?;
+        let mut entry = self.cache.remove(&buffer_id).ok_or_else(|| anyhow!("Missing syntax tree"))?;
+        let result = self.highlight_cached_lines(&mut entry, buffer, language, lines);
+        self.cache.insert(buffer_id, entry);
+        result
+    }
+
+    /// Highlight lines of a buffer whose tree is up to date, querying only the
+    /// lines without cached spans
+    fn highlight_cached_lines(
+        &mut self,
+        entry: &mut CacheEntry,
+        buffer: &TextBuffer,
+        language: SupportedLanguage,
+        lines: Range<usize>,
+    ) -> Result<Vec<HighlightSpan>> {
+        let rope = buffer.rope();
+
+        // Highlight the lines without cached spans in one query over their bytes
+        let missing_first = lines.clone().find(|line| !entry.line_spans.contains_key(line));
+        let missing_last = lines.clone().rev().find(|line| !entry.line_spans.contains_key(line));
+        if let (Some(first), Some(last)) = (missing_first, missing_last) {
+            for line in first..=last {
+                entry.line_spans.insert(line, Vec::new());
+            }
+
+            let byte_range = rope.line_to_byte(first)..rope.line_to_byte(last + 1);
+            for span in self.query_spans(language, &entry.tree, rope.slice(..), byte_range, 0)? {
+                let start_line = rope.byte_to_line(span.start_byte).max(first);
+                let end_line = rope.byte_to_line(span.end_byte.saturating_sub(1).max(span.start_byte)).min(last);
+                for line in start_line..=end_line {
+                    if let Some(spans) = entry.line_spans.get_mut(&line) {
+                        spans.push(span.clone());
+                    }
+                }
+            }
         }
 
-        Ok(tree)
+        // Each span once: on the first requested line it covers
+        let mut spans: Vec<HighlightSpan> = entry
+            .line_spans
+            .range(lines.clone())
+            .flat_map(|(&line, spans)| {
+                let line_start = rope.line_to_byte(line);
+                spans
+                    .iter()
+                    .filter(move |span| line == lines.start || span.start_byte >= line_start)
+                    .cloned()
+            })
+            .collect();
+
+        // Sort spans by start position
+        spans.sort_by_key(|s| s.start_byte);
+
+        Ok(spans)
     }
 
-    /// Highlight text and return spans
+    /// Spans of `tree` (parsed from `text`) within `byte_range`. Regions in
+    /// another language get that language's spans instead of their own.
+    fn query_spans(
+        &mut self,
+        language: SupportedLanguage,
+        tree: &Tree,
+        text: RopeSlice,
+        byte_range: Range<usize>,
+        depth: usize,
+    ) -> Result<Vec<HighlightSpan>> {
+        let language_query = self.language_query(language)?;
+        let mut spans = Vec::new();
+
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(byte_range.clone());
+        let text_of = |node: Node| text.byte_slice(node.byte_range()).chunks().map(str::as_bytes);
+        for m in cursor.matches(&language_query.query, tree.root_node(), text_of) {
+            for capture in m.captures {
+                let capture_index = capture.index as usize;
+                if let Some(token_type) = language_query.capture_names.get(&capture_index) {
+                    spans.push(HighlightSpan {
+                        start_byte: capture.node.start_byte(),
+                        end_byte: capture.node.end_byte(),
+                        token_type: *token_type,
+                    });
+                }
+            }
+        }
+
+        if depth < MAX_INJECTION_DEPTH {
+            for injection in self.injections(language, tree, text, byte_range.clone())? {
+                spans = cut_out(spans, &injection.range);
+
+                // Only the part of the region that was asked for
+                let offset = injection.range.start;
+                let local_range = byte_range.start.saturating_sub(offset)
+                    ..byte_range.end.min(injection.range.end).saturating_sub(offset);
+                if local_range.is_empty() {
+                    continue;
+                }
+
+                let region = text.byte_slice(injection.range.clone());
+                self.injection_parser.set_language(&injection.language.language())?;
+                let Some(injected_tree) = self.injection_parser.parse_with(&mut |byte, _| read_chunk(region, byte), None) else {
+                    continue;
+                };
+                let injected = self.query_spans(injection.language, &injected_tree, region, local_range, depth + 1)?;
+                spans.extend(injected.into_iter().map(|span| HighlightSpan {
+                    start_byte: span.start_byte + offset,
+                    end_byte: span.end_byte + offset,
+                    token_type: span.token_type,
+                }));
+            }
+        }
+
+        Ok(spans)
+    }
+
+    /// Regions of `tree` overlapping `byte_range` that are in another language
+    fn injections(
+        &mut self,
+        language: SupportedLanguage,
+        tree: &Tree,
+        text: RopeSlice,
+        byte_range: Range<usize>,
+    ) -> Result<Vec<Injection>> {
+        let Some(query) = self.injection_query(language)? else {
+            return Ok(Vec::new());
+        };
+        let content_index = query.capture_index_for_name("injection.content");
+        let language_index = query.capture_index_for_name("injection.language");
+
+        let mut injections = Vec::new();
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(byte_range);
+        let text_of = |node: Node| text.byte_slice(node.byte_range()).chunks().map(str::as_bytes);
+        for m in cursor.matches(&query, tree.root_node(), text_of) {
+            let Some(content) = m.captures.iter().find(|c| Some(c.index) == content_index) else {
+                continue;
+            };
+            let range = content.node.byte_range();
+            if range.is_empty() {
+                continue;
+            }
+
+            // The language is either named in the text (a fence's info string)
+            // or fixed by the pattern (`#set! injection.language`)
+            let named = m
+                .captures
+                .iter()
+                .find(|c| Some(c.index) == language_index)
+                .map(|c| text.byte_slice(c.node.byte_range()).to_string());
+            let fixed = query
+                .property_settings(m.pattern_index)
+                .iter()
+                .find(|property| &*property.key == "injection.language")
+                .and_then(|property| property.value.as_deref().map(str::to_string));
+            let Some(injected) = named
+                .or(fixed)
+                .and_then(|name| LanguageRegistry::by_name(&name)?.syntax) else {
+                continue;
+            };
+
+            // `#is-json?` only accepts text holding a JSON object or array
+            let wants_json = query.general_predicates(m.pattern_index).iter().any(|predicate| {
+                &*predicate.operator == "is-json?"
+                    && predicate.args.iter().any(|arg| matches!(arg, QueryPredicateArg::Capture(index) if Some(*index) == content_index))
+            });
+            if wants_json && !is_json_document(&text.byte_slice(range.clone()).to_string()) {
+                continue;
+            }
+
+            injections.push(Injection { language: injected, range });
+        }
+
+        Ok(injections)
+    }
+
+    /// Highlight a whole text at once, without caching (e.g. a side of a diff)
     pub fn highlight(
         &mut self,
         text: &str,
-        file_id: &str,
         query: &Query,
         query_capture_names: &HashMap<usize, TokenType>,
     ) -> Result<Vec<HighlightSpan>> {
         let mut spans = Vec::new();
 
-        let Some(tree) = self.parse(text, file_id)? else {
+        let Some(tree) = self.parser.parse(text, None) else {
             return Ok(spans);
         };
 
@@ -106,18 +389,22 @@ impl Highlighter {
         Ok(spans)
     }
 
+    /// Highlight a snippet of code in a given language, without caching
+    /// (e.g. a code block of hover documentation)
+    pub fn highlight_snippet(&mut self, text: &str, language: SupportedLanguage) -> Result<Vec<HighlightSpan>> {
+        let query = self.language_query(language)?;
+        self.parser.set_language(&language.language())?;
+        self.highlight(text, &query.query, &query.capture_names)
+    }
+
     /// Get the theme
     pub fn theme(&self) -> &Theme {
         &self.theme
     }
 
-    /// Simple hash function for cache invalidation
-    fn simple_hash(text: &str) -> u64 {
-        let mut hash: u64 = 5381;
-        for byte in text.bytes() {
-            hash = hash.wrapping_mul(33).wrapping_add(byte as u64);
-        }
-        hash
+    /// Replace the theme
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
     }
 
     /// Clear cache
@@ -127,11 +414,9 @@ impl Highlighter {
         self.parser.reset();
     }
 
-    /// Clear cache for specific file
-    pub fn clear_file_cache(&mut self, file_id: &str) {
-        self.cache.remove(file_id);
-        // Reset parser to clear any internal state
-        self.parser.reset();
+    /// Drop the syntax trees of buffers that are no longer open
+    pub fn retain_buffers(&mut self, open: &[BufferId]) {
+        self.cache.retain(|id, _| open.contains(id));
     }
 }
 
@@ -140,3 +425,138 @@ impl Default for Highlighter {
         Self::new()
     }
 }
+
+/// The text of a rope from `byte` to the end of its chunk, for tree-sitter
+fn read_chunk<'a>(text: RopeSlice<'a>, byte: usize) -> &'a [u8] {
+    if byte >= text.len_bytes() {
+        return &[];
+    }
+    let (chunk, chunk_start, _, _) = text.chunk_at_byte(byte);
+    &chunk.as_bytes()[byte - chunk_start..]
+}
+
+/// Spans with the bytes of `hole` removed, splitting those around it
+fn cut_out(spans: Vec<HighlightSpan>, hole: &Range<usize>) -> Vec<HighlightSpan> {
+    let mut result = Vec::with_capacity(spans.len());
+    for span in spans {
+        if span.end_byte <= hole.start || span.start_byte >= hole.end {
+            result.push(span);
+            continue;
+        }
+        if span.start_byte < hole.start {
+            result.push(HighlightSpan { end_byte: hole.start, ..span.clone() });
+        }
+        if span.end_byte > hole.end {
+            result.push(HighlightSpan { start_byte: hole.end, ..span });
+        }
+    }
+    result
+}
+
+/// Check if a string holds a JSON object or array
+fn is_json_document(text: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(text).is_ok_and(|value| value.is_object() || value.is_array())
+}
+
+/// A buffer edit as tree-sitter expects it
+fn input_edit(edit: &EditEvent) -> InputEdit {
+    let start_position = Point::new(edit.start.line, edit.start_column_byte);
+    InputEdit {
+        start_byte: edit.start_byte,
+        old_end_byte: edit.old_end_byte(),
+        new_end_byte: edit.new_end_byte(),
+        start_position,
+        old_end_position: end_point(start_position, &edit.old_text),
+        new_end_position: end_point(start_position, &edit.new_text),
+    }
+}
+
+/// Row and byte column where `text` ends if it starts at `start`
+fn end_point(start: Point, text: &str) -> Point {
+    match text.rfind('\n') {
+        Some(last_newline) => Point::new(start.row + text.matches('\n').count(), text.len() - last_newline - 1),
+        None => Point::new(start.row, start.column + text.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Position;
+
+    /// Check that highlighting a buffer incrementally gives the same spans as
+    /// highlighting its whole text from scratch
+    fn assert_matches_full_parse(highlighter: &mut Highlighter, buffer: &TextBuffer) {
+        let lang = SupportedLanguage::Rust;
+        let incremental = highlighter.highlight_lines(buffer, BufferId(0), lang, 0..buffer.len_lines()).unwrap();
+        let query = lang.query().unwrap();
+        let capture_names = lang.capture_names().unwrap();
+        let full = highlighter.highlight(&buffer.to_string(), &query, &capture_names).unwrap();
+
+        let ranges = |spans: &[HighlightSpan]| spans.iter().map(|s| (s.start_byte, s.end_byte)).collect::<Vec<_>>();
+        assert_eq!(ranges(&incremental), ranges(&full));
+    }
+
+    #[test]
+    fn test_incremental_highlighting_matches_full_parse() {
+        let mut highlighter = Highlighter::new();
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), "fn main() {\n    let x = 1;\n}\n").unwrap();
+        assert_matches_full_parse(&mut highlighter, &buffer);
+
+        // Opening a block comment changes the highlighting of the lines after it
+        buffer.insert(Position::new(1, 4), "/* ").unwrap();
+        buffer.insert(Position::new(0, 0), "// é\n").unwrap();
+        assert_matches_full_parse(&mut highlighter, &buffer);
+
+        buffer.delete_range(Position::new(2, 4), Position::new(2, 7)).unwrap();
+        assert_matches_full_parse(&mut highlighter, &buffer);
+
+        // A whole-buffer replacement (reload, reopen with another encoding)
+        buffer.reload_from("struct S;\n");
+        assert_matches_full_parse(&mut highlighter, &buffer);
+    }
+
+    #[test]
+    fn test_highlight_lines_only_covers_requested_lines() {
+        let mut highlighter = Highlighter::new();
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), &"fn f() {}\n".repeat(100)).unwrap();
+        let spans = highlighter.highlight_lines(&buffer, BufferId(0), SupportedLanguage::Rust, 50..52).unwrap();
+        assert!(!spans.is_empty());
+        assert!(spans.iter().all(|s| s.start_byte >= buffer.line_to_byte(50) && s.end_byte <= buffer.line_to_byte(52)));
+    }
+
+    /// Token types of the spans covering `needle` in `text`
+    fn tokens_at(spans: &[HighlightSpan], text: &str, needle: &str) -> Vec<TokenType> {
+        let start = text.find(needle).unwrap();
+        spans
+            .iter()
+            .filter(|s| s.start_byte <= start && s.end_byte >= start + needle.len())
+            .map(|s| s.token_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_injected_languages_are_highlighted() {
+        let mut highlighter = Highlighter::new();
+        let cases = [
+            (SupportedLanguage::Markdown, "# Title\n\n```rust\nfn main() {}\n```\n", "fn", BufferId(1)),
+            (SupportedLanguage::Html, "<p>hi</p>\n<script>\nfunction go() {}\n</script>\n", "function", BufferId(2)),
+            (SupportedLanguage::Python, "data = '{\"key\": 1}'\n", "\"key\"", BufferId(3)),
+        ];
+        for (lang, text, needle, buffer_id) in cases {
+            let mut buffer = TextBuffer::new();
+            buffer.insert(Position::zero(), text).unwrap();
+            let spans = highlighter.highlight_lines(&buffer, buffer_id, lang, 0..buffer.len_lines()).unwrap();
+            let tokens = tokens_at(&spans, text, needle);
+            assert!(!tokens.is_empty() && tokens.iter().all(|&t| t != TokenType::String || needle.starts_with('"')), "{:?}: {:?}", lang, tokens);
+        }
+
+        // Strings are only highlighted as JSON if they hold JSON
+        let mut buffer = TextBuffer::new();
+        buffer.insert(Position::zero(), "s = '{not json}'\n").unwrap();
+        let spans = highlighter.highlight_lines(&buffer, BufferId(4), SupportedLanguage::Python, 0..1).unwrap();
+        assert_eq!(tokens_at(&spans, "s = '{not json}'\n", "not"), vec![TokenType::String]);
+    }
+}
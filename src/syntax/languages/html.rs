@@ -34,6 +34,21 @@ pub fn query() -> Result<Query, tree_sitter::QueryError> {
     Query::new(&language(), query_source)
 }
 
+/// Get the HTML injection query: regions highlighted as another language
+pub fn injection_query() -> Result<Query, tree_sitter::QueryError> {
+    let query_source = r#"
+; Scripts and style sheets
+(script_element
+  (raw_text) @injection.content
+  (#set! injection.language "javascript"))
+(style_element
+  (raw_text) @injection.content
+  (#set! injection.language "css"))
+    "#;
+
+    Query::new(&language(), query_source)
+}
+
 /// Get capture name to token type mapping
 pub fn capture_names() -> Result<HashMap<usize, TokenType>, tree_sitter::QueryError> {
     let query = query()?;
@@ -38,7 +38,7 @@ pub fn query() -> Result<Query, tree_sitter::QueryError> {
 "catch" @keyword
 "throw" @keyword
 "new" @keyword
-"this" @keyword
+(this) @keyword
 
 ; Constants
 (true) @constant
@@ -61,6 +61,19 @@ pub fn query() -> Result<Query, tree_sitter::QueryError> {
     Query::new(&language(), query_source)
 }
 
+/// Get the JavaScript injection query: regions highlighted as another language
+pub fn injection_query() -> Result<Query, tree_sitter::QueryError> {
+    let query_source = r#"
+; Strings holding JSON
+(string
+  (string_fragment) @injection.content
+  (#is-json? @injection.content)
+  (#set! injection.language "json"))
+    "#;
+
+    Query::new(&language(), query_source)
+}
+
 /// Get capture name to token type mapping
 pub fn capture_names() -> Result<HashMap<usize, TokenType>, tree_sitter::QueryError> {
     let query = query()?;
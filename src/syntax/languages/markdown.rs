@@ -43,6 +43,19 @@ pub fn query() -> Result<Query, tree_sitter::QueryError> {
     Query::new(&language(), query_source)
 }
 
+/// Get the Markdown injection query: regions highlighted as another language
+pub fn injection_query() -> Result<Query, tree_sitter::QueryError> {
+    let query_source = r#"
+; Fenced code blocks, in the language of their info string
+(fenced_code_block
+  (info_string
+    (language) @injection.language)
+  (code_fence_content) @injection.content)
+    "#;
+
+    Query::new(&language(), query_source)
+}
+
 /// Get capture name to token type mapping
 pub fn capture_names() -> Result<HashMap<usize, TokenType>, tree_sitter::QueryError> {
     let query = query()?;
@@ -10,11 +10,10 @@ pub mod cpp;
 
 use crate::syntax::theme::TokenType;
 use std::collections::HashMap;
-use std::path::Path;
 use tree_sitter::{Language, Query};
 
 /// Supported languages
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SupportedLanguage {
     Python,
     Rust,
@@ -28,24 +27,6 @@ pub enum SupportedLanguage {
 }
 
 impl SupportedLanguage {
-    /// Detect language from file extension
-    pub fn from_path(path: &Path) -> Option<Self> {
-        path.extension()
-            .and_then(|ext| ext.to_str())
-            .and_then(|ext| match ext {
-                "py" | "pyw" | "pyi" => Some(Self::Python),
-                "rs" => Some(Self::Rust),
-                "json" => Some(Self::Json),
-                "md" | "markdown" => Some(Self::Markdown),
-                "html" | "htm" => Some(Self::Html),
-                "css" => Some(Self::Css),
-                "js" | "jsx" | "mjs" | "cjs" => Some(Self::JavaScript),
-                "xml" | "svg" | "xhtml" => Some(Self::Xml),
-                "cpp" | "cxx" | "cc" | "c++" | "hpp" | "hxx" | "hh" | "h++" | "c" | "h" => Some(Self::Cpp),
-                _ => None,
-            })
-    }
-
     /// Get the tree-sitter language
     pub fn language(&self) -> Language {
         match self {
@@ -76,6 +57,19 @@ impl SupportedLanguage {
         }
     }
 
+    /// Get the query finding regions written in other languages, for
+    /// languages that embed them
+    pub fn injection_query(&self) -> Result<Option<Query>, tree_sitter::QueryError> {
+        match self {
+            Self::Python => python::injection_query().map(Some),
+            Self::Rust => rust::injection_query().map(Some),
+            Self::Markdown => markdown::injection_query().map(Some),
+            Self::Html => html::injection_query().map(Some),
+            Self::JavaScript => javascript::injection_query().map(Some),
+            Self::Json | Self::Css | Self::Xml | Self::Cpp => Ok(None),
+        }
+    }
+
     /// Get capture name to token type mapping
     pub fn capture_names(&self) -> Result<HashMap<usize, TokenType>, tree_sitter::QueryError> {
         match self {
@@ -91,3 +85,17 @@ impl SupportedLanguage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_compile() {
+        use SupportedLanguage::*;
+        for lang in [Python, Rust, Json, Markdown, Html, Css, JavaScript, Xml, Cpp] {
+            assert!(lang.query().is_ok(), "{:?} highlighting query", lang);
+            assert!(lang.injection_query().is_ok(), "{:?} injection query", lang);
+        }
+    }
+}
@@ -134,6 +134,19 @@ pub fn query() -> Result<Query, tree_sitter::QueryError> {
     Query::new(&language(), query_source)
 }
 
+/// Get the Python injection query: regions highlighted as another language
+pub fn injection_query() -> Result<Query, tree_sitter::QueryError> {
+    let query_source = r#"
+; Strings holding JSON
+(string
+  (string_content) @injection.content
+  (#is-json? @injection.content)
+  (#set! injection.language "json"))
+    "#;
+
+    Query::new(&language(), query_source)
+}
+
 /// Get capture name to token type mapping
 pub fn capture_names() -> Result<HashMap<usize, TokenType>, tree_sitter::QueryError> {
     let query = query()?;
@@ -1,4 +1,8 @@
+use anyhow::{anyhow, bail, Context, Result};
 use crossterm::style::Color;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 /// Token types that can be highlighted
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -18,9 +22,14 @@ pub enum TokenType {
     Error,
 }
 
-/// A color theme for syntax highlighting
+/// Themes that ship with the editor
+pub const BUILTIN_THEMES: &[&str] = &["dark", "light"];
+
+/// A color theme for syntax highlighting and the rest of the UI
 #[derive(Debug, Clone)]
 pub struct Theme {
+    /// Name the theme is selected by
+    pub name: String,
     pub keyword: Color,
     pub function: Color,
     pub type_: Color,
@@ -35,12 +44,91 @@ pub struct Theme {
     pub punctuation: Color,
     pub error: Color,
     pub default: Color,
+    pub ui: UiColors,
+}
+
+/// Colors of everything that is not syntax: bars, panels, popups, gutter,
+/// selections and diffs. `Color::Reset` means the terminal's own color.
+#[derive(Debug, Clone)]
+pub struct UiColors {
+    /// Text and background of the editing area
+    pub foreground: Color,
+    pub background: Color,
+    pub line_number: Color,
+    pub indent_guide: Color,
+    pub ruler: Color,
+    pub selection_bg: Color,
+    pub selection_fg: Color,
+    pub search_match_bg: Color,
+    pub search_match_fg: Color,
+    pub mark_bg: Color,
+    pub mark_fg: Color,
+    /// AI suggestions shown after the cursor
+    pub ghost_text: Color,
+    /// Text of the pane that does not have focus
+    pub inactive_text: Color,
+    pub separator: Color,
+    pub diagnostic_error: Color,
+    pub diagnostic_warning: Color,
+    pub diagnostic_info: Color,
+    pub diagnostic_hint: Color,
+    /// Status bar, and the path bar under the tabs
+    pub status_bg: Color,
+    pub status_fg: Color,
+    pub status_message: Color,
+    pub tab_bar_bg: Color,
+    pub tab_active_bg: Color,
+    pub tab_active_fg: Color,
+    pub tab_inactive_bg: Color,
+    pub tab_inactive_fg: Color,
+    /// Pickers and panels (files, commands, search, history, undo tree...)
+    pub panel_header_bg: Color,
+    pub panel_header_fg: Color,
+    pub panel_bg: Color,
+    pub panel_fg: Color,
+    pub panel_selected_bg: Color,
+    pub panel_selected_fg: Color,
+    pub panel_footer_bg: Color,
+    pub panel_footer_fg: Color,
+    pub panel_match: Color,
+    pub panel_muted: Color,
+    pub undo_current: Color,
+    /// Completion popup
+    pub popup_bg: Color,
+    pub popup_fg: Color,
+    pub popup_selected_bg: Color,
+    pub popup_selected_fg: Color,
+    /// Diff view
+    pub diff_added: Color,
+    pub diff_deleted: Color,
+    pub diff_modified: Color,
+    pub diff_added_bg: Color,
+    pub diff_deleted_bg: Color,
+    pub diff_modified_bg: Color,
+    pub diff_hunk: Color,
+}
+
+/// A theme file: `[syntax]` and `[ui]` tables of colors, applied on top of
+/// the built-in theme named by `base`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    base: Option<String>,
+    #[serde(default)]
+    syntax: BTreeMap<String, String>,
+    #[serde(default)]
+    ui: BTreeMap<String, String>,
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
 }
 
 impl Theme {
     /// Create a default dark theme (similar to VS Code Dark+)
     pub fn default_dark() -> Self {
         Self {
+            name: "dark".to_string(),
             keyword: Color::Rgb { r: 197, g: 134, b: 192 },      // Purple
             function: Color::Rgb { r: 220, g: 220, b: 170 },     // Yellow
             type_: Color::Rgb { r: 78, g: 201, b: 176 },         // Teal/green (for classes and imports)
@@ -55,7 +143,225 @@ impl Theme {
             punctuation: Color::Rgb { r: 212, g: 212, b: 212 },  // Light gray
             error: Color::Rgb { r: 244, g: 71, b: 71 },          // Red
             default: Color::Rgb { r: 212, g: 212, b: 212 },      // Light gray
+            ui: UiColors {
+                foreground: Color::Reset,
+                background: Color::Reset,
+                line_number: Color::DarkGrey,
+                indent_guide: rgb(60, 60, 60),
+                ruler: rgb(60, 60, 60),
+                selection_bg: rgb(100, 180, 255),
+                selection_fg: Color::Black,
+                search_match_bg: rgb(180, 120, 0),
+                search_match_fg: Color::Black,
+                mark_bg: rgb(255, 100, 255),
+                mark_fg: Color::White,
+                ghost_text: Color::DarkGrey,
+                inactive_text: Color::DarkGrey,
+                separator: Color::DarkGrey,
+                diagnostic_error: Color::Red,
+                diagnostic_warning: Color::Yellow,
+                diagnostic_info: Color::Blue,
+                diagnostic_hint: Color::Cyan,
+                status_bg: Color::DarkGrey,
+                status_fg: Color::White,
+                status_message: Color::Yellow,
+                tab_bar_bg: Color::DarkGrey,
+                tab_active_bg: Color::Blue,
+                tab_active_fg: Color::White,
+                tab_inactive_bg: Color::DarkGrey,
+                tab_inactive_fg: Color::Grey,
+                panel_header_bg: Color::DarkGrey,
+                panel_header_fg: Color::White,
+                panel_bg: Color::Black,
+                panel_fg: Color::White,
+                panel_selected_bg: Color::Blue,
+                panel_selected_fg: Color::White,
+                panel_footer_bg: Color::DarkGrey,
+                panel_footer_fg: Color::Grey,
+                panel_match: Color::Yellow,
+                panel_muted: Color::DarkGrey,
+                undo_current: Color::Green,
+                popup_bg: Color::DarkGrey,
+                popup_fg: Color::White,
+                popup_selected_bg: Color::DarkBlue,
+                popup_selected_fg: Color::White,
+                diff_added: rgb(144, 238, 144),
+                diff_deleted: rgb(255, 160, 122),
+                diff_modified: Color::Yellow,
+                diff_added_bg: rgb(0, 64, 0),
+                diff_deleted_bg: rgb(64, 0, 0),
+                diff_modified_bg: rgb(64, 48, 0),
+                diff_hunk: Color::Yellow,
+            },
+        }
+    }
+
+    /// Create a light theme (similar to VS Code Light+)
+    pub fn default_light() -> Self {
+        Self {
+            name: "light".to_string(),
+            keyword: rgb(175, 0, 219),
+            function: rgb(121, 94, 38),
+            type_: rgb(38, 127, 153),
+            string: rgb(163, 21, 21),
+            number: rgb(9, 134, 88),
+            comment: rgb(0, 128, 0),
+            operator: rgb(56, 56, 56),
+            variable: rgb(0, 16, 128),
+            constant: rgb(0, 112, 193),
+            parameter: rgb(0, 16, 128),
+            property: rgb(0, 16, 128),
+            punctuation: rgb(56, 56, 56),
+            error: rgb(205, 49, 49),
+            default: rgb(30, 30, 30),
+            ui: UiColors {
+                foreground: rgb(30, 30, 30),
+                background: rgb(255, 255, 255),
+                line_number: rgb(150, 150, 150),
+                indent_guide: rgb(220, 220, 220),
+                ruler: rgb(220, 220, 220),
+                selection_bg: rgb(173, 214, 255),
+                selection_fg: rgb(0, 0, 0),
+                search_match_bg: rgb(255, 200, 100),
+                search_match_fg: rgb(0, 0, 0),
+                mark_bg: rgb(255, 150, 255),
+                mark_fg: rgb(0, 0, 0),
+                ghost_text: rgb(160, 160, 160),
+                inactive_text: rgb(150, 150, 150),
+                separator: rgb(200, 200, 200),
+                diagnostic_error: rgb(205, 49, 49),
+                diagnostic_warning: rgb(191, 136, 3),
+                diagnostic_info: rgb(26, 133, 255),
+                diagnostic_hint: rgb(0, 150, 150),
+                status_bg: rgb(0, 122, 204),
+                status_fg: rgb(255, 255, 255),
+                status_message: rgb(255, 235, 150),
+                tab_bar_bg: rgb(236, 236, 236),
+                tab_active_bg: rgb(255, 255, 255),
+                tab_active_fg: rgb(30, 30, 30),
+                tab_inactive_bg: rgb(236, 236, 236),
+                tab_inactive_fg: rgb(110, 110, 110),
+                panel_header_bg: rgb(221, 221, 221),
+                panel_header_fg: rgb(30, 30, 30),
+                panel_bg: rgb(243, 243, 243),
+                panel_fg: rgb(30, 30, 30),
+                panel_selected_bg: rgb(0, 96, 192),
+                panel_selected_fg: rgb(255, 255, 255),
+                panel_footer_bg: rgb(221, 221, 221),
+                panel_footer_fg: rgb(90, 90, 90),
+                panel_match: rgb(200, 100, 0),
+                panel_muted: rgb(140, 140, 140),
+                undo_current: rgb(0, 140, 0),
+                popup_bg: rgb(243, 243, 243),
+                popup_fg: rgb(30, 30, 30),
+                popup_selected_bg: rgb(0, 96, 192),
+                popup_selected_fg: rgb(255, 255, 255),
+                diff_added: rgb(0, 110, 0),
+                diff_deleted: rgb(170, 0, 0),
+                diff_modified: rgb(140, 100, 0),
+                diff_added_bg: rgb(220, 255, 220),
+                diff_deleted_bg: rgb(255, 220, 220),
+                diff_modified_bg: rgb(255, 245, 200),
+                diff_hunk: rgb(200, 130, 0),
+            },
+        }
+    }
+
+    /// A built-in theme by name
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::default_dark()),
+            "light" => Some(Self::default_light()),
+            _ => None,
+        }
+    }
+
+    /// Directory user themes are loaded from (`~/.scame/themes`)
+    pub fn themes_dir() -> Option<PathBuf> {
+        std::env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".scame/themes"))
+    }
+
+    /// Load a theme by name: `<name>.toml` in the user's themes directory,
+    /// or else a built-in theme
+    pub fn load(name: &str) -> Result<Self> {
+        Self::load_from(Self::themes_dir().as_deref(), name)
+    }
+
+    /// Load a theme by name, looking for theme files in `themes_dir`
+    pub fn load_from(themes_dir: Option<&Path>, name: &str) -> Result<Self> {
+        if let Some(dir) = themes_dir {
+            let path = dir.join(format!("{}.toml", name));
+            if path.is_file() {
+                let content = std::fs::read_to_string(&path)?;
+                return Self::from_toml(name, &content)
+                    .with_context(|| format!("Invalid theme file {}", path.display()));
+            }
+        }
+        Self::builtin(name).ok_or_else(|| anyhow!("Unknown theme: {}", name))
+    }
+
+    /// Names of all the themes that can be loaded, sorted
+    pub fn available() -> Vec<String> {
+        Self::available_in(Self::themes_dir().as_deref())
+    }
+
+    /// Names of the built-in themes and the theme files in `themes_dir`
+    pub fn available_in(themes_dir: Option<&Path>) -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_THEMES.iter().map(|name| name.to_string()).collect();
+        if let Some(entries) = themes_dir.and_then(|dir| std::fs::read_dir(dir).ok()) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "toml") {
+                    if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                        names.push(stem.to_string());
+                    }
+                }
+            }
         }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Parse a theme file. Colors not set in the file come from its `base`
+    /// theme (dark by default).
+    pub fn from_toml(name: &str, content: &str) -> Result<Self> {
+        let file: ThemeFile = toml::from_str(content)?;
+        let base = file.base.as_deref().unwrap_or("dark");
+        let mut theme = Self::builtin(base).ok_or_else(|| anyhow!("Unknown base theme: {}", base))?;
+        theme.name = name.to_string();
+
+        for (key, value) in &file.syntax {
+            let color = parse_color(value).with_context(|| format!("syntax.{}", key))?;
+            *theme.syntax_color_mut(key).ok_or_else(|| anyhow!("Unknown syntax color: {}", key))? = color;
+        }
+        for (key, value) in &file.ui {
+            let color = parse_color(value).with_context(|| format!("ui.{}", key))?;
+            *theme.ui.color_mut(key).ok_or_else(|| anyhow!("Unknown ui color: {}", key))? = color;
+        }
+        Ok(theme)
+    }
+
+    fn syntax_color_mut(&mut self, key: &str) -> Option<&mut Color> {
+        Some(match key {
+            "keyword" => &mut self.keyword,
+            "function" => &mut self.function,
+            "type" => &mut self.type_,
+            "string" => &mut self.string,
+            "number" => &mut self.number,
+            "comment" => &mut self.comment,
+            "operator" => &mut self.operator,
+            "variable" => &mut self.variable,
+            "constant" => &mut self.constant,
+            "parameter" => &mut self.parameter,
+            "property" => &mut self.property,
+            "punctuation" => &mut self.punctuation,
+            "error" => &mut self.error,
+            "default" => &mut self.default,
+            _ => return None,
+        })
     }
 
     /// Get color for a token type
@@ -78,8 +384,130 @@ impl Theme {
     }
 }
 
+impl UiColors {
+    fn color_mut(&mut self, key: &str) -> Option<&mut Color> {
+        Some(match key {
+            "foreground" => &mut self.foreground,
+            "background" => &mut self.background,
+            "line_number" => &mut self.line_number,
+            "indent_guide" => &mut self.indent_guide,
+            "ruler" => &mut self.ruler,
+            "selection_bg" => &mut self.selection_bg,
+            "selection_fg" => &mut self.selection_fg,
+            "search_match_bg" => &mut self.search_match_bg,
+            "search_match_fg" => &mut self.search_match_fg,
+            "mark_bg" => &mut self.mark_bg,
+            "mark_fg" => &mut self.mark_fg,
+            "ghost_text" => &mut self.ghost_text,
+            "inactive_text" => &mut self.inactive_text,
+            "separator" => &mut self.separator,
+            "diagnostic_error" => &mut self.diagnostic_error,
+            "diagnostic_warning" => &mut self.diagnostic_warning,
+            "diagnostic_info" => &mut self.diagnostic_info,
+            "diagnostic_hint" => &mut self.diagnostic_hint,
+            "status_bg" => &mut self.status_bg,
+            "status_fg" => &mut self.status_fg,
+            "status_message" => &mut self.status_message,
+            "tab_bar_bg" => &mut self.tab_bar_bg,
+            "tab_active_bg" => &mut self.tab_active_bg,
+            "tab_active_fg" => &mut self.tab_active_fg,
+            "tab_inactive_bg" => &mut self.tab_inactive_bg,
+            "tab_inactive_fg" => &mut self.tab_inactive_fg,
+            "panel_header_bg" => &mut self.panel_header_bg,
+            "panel_header_fg" => &mut self.panel_header_fg,
+            "panel_bg" => &mut self.panel_bg,
+            "panel_fg" => &mut self.panel_fg,
+            "panel_selected_bg" => &mut self.panel_selected_bg,
+            "panel_selected_fg" => &mut self.panel_selected_fg,
+            "panel_footer_bg" => &mut self.panel_footer_bg,
+            "panel_footer_fg" => &mut self.panel_footer_fg,
+            "panel_match" => &mut self.panel_match,
+            "panel_muted" => &mut self.panel_muted,
+            "undo_current" => &mut self.undo_current,
+            "popup_bg" => &mut self.popup_bg,
+            "popup_fg" => &mut self.popup_fg,
+            "popup_selected_bg" => &mut self.popup_selected_bg,
+            "popup_selected_fg" => &mut self.popup_selected_fg,
+            "diff_added" => &mut self.diff_added,
+            "diff_deleted" => &mut self.diff_deleted,
+            "diff_modified" => &mut self.diff_modified,
+            "diff_added_bg" => &mut self.diff_added_bg,
+            "diff_deleted_bg" => &mut self.diff_deleted_bg,
+            "diff_modified_bg" => &mut self.diff_modified_bg,
+            "diff_hunk" => &mut self.diff_hunk,
+            _ => return None,
+        })
+    }
+}
+
+/// Parse a theme color: `#rrggbb`, a color name (`dark_grey`, `blue`...)
+/// or `reset` for the terminal's own color
+fn parse_color(value: &str) -> Result<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            bail!("Invalid color: {}", value);
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        return match (channel(0), channel(2), channel(4)) {
+            (Ok(r), Ok(g), Ok(b)) => Ok(rgb(r, g, b)),
+            _ => Err(anyhow!("Invalid color: {}", value)),
+        };
+    }
+    match value.to_lowercase().as_str() {
+        "reset" | "default" => Ok(Color::Reset),
+        name => Color::try_from(name).map_err(|_| anyhow!("Invalid color: {}", value)),
+    }
+}
+
 impl Default for Theme {
     fn default() -> Self {
         Self::default_dark()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_file_overrides_base() {
+        let theme = Theme::from_toml(
+            "paper",
+            r##"
+base = "light"
+
+[syntax]
+keyword = "#ff0000"
+
+[ui]
+status_bg = "dark_grey"
+background = "reset"
+"##,
+        )
+        .unwrap();
+
+        assert_eq!(theme.name, "paper");
+        assert_eq!(theme.keyword, rgb(255, 0, 0));
+        assert_eq!(theme.ui.status_bg, Color::DarkGrey);
+        assert_eq!(theme.ui.background, Color::Reset);
+        // Everything else comes from the light theme
+        assert_eq!(theme.string, Theme::default_light().string);
+        assert_eq!(theme.ui.panel_bg, Theme::default_light().ui.panel_bg);
+
+        assert!(Theme::from_toml("bad", "[ui]\nstatus_bgg = \"#000000\"").is_err());
+        assert!(Theme::from_toml("bad", "[syntax]\nkeyword = \"#12345\"").is_err());
+    }
+
+    #[test]
+    fn test_user_themes_shadow_builtins() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("dark.toml"), "[syntax]\ncomment = \"#010203\"").unwrap();
+        std::fs::write(dir.path().join("solar.toml"), "base = \"light\"").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        assert_eq!(Theme::available_in(Some(dir.path())), vec!["dark", "light", "solar"]);
+        assert_eq!(Theme::load_from(Some(dir.path()), "dark").unwrap().comment, rgb(1, 2, 3));
+        assert_eq!(Theme::load_from(Some(dir.path()), "light").unwrap().name, "light");
+        assert!(Theme::load_from(Some(dir.path()), "missing").is_err());
+    }
+}
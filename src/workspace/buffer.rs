@@ -1,6 +1,13 @@
-use crate::buffer::{Change, TextBuffer, UndoManager};
+use crate::buffer::{Change, CursorSnapshot, FileEncoding, Indentation, Position, TextBuffer, UndoManager};
+use crate::config::LargeFileConfig;
 use crate::editor::EditorState;
+use crate::editorconfig::{EditorConfig, IndentStyle};
+use crate::language::LanguageDef;
+use crate::lsp::Language as LspLanguage;
+use crate::syntax::SupportedLanguage;
 use anyhow::Result;
+use ropey::Rope;
+use std::ops::Range;
 use std::path::PathBuf;
 
 /// Unique identifier for a buffer
@@ -13,6 +20,13 @@ pub struct Buffer {
     text_buffer: TextBuffer,
     editor_state: EditorState,
     undo_manager: UndoManager,
+    editorconfig: EditorConfig,
+    /// Indentation chosen from the command palette, overriding everything else
+    indent_override: Option<Indentation>,
+    /// Language of the text, detected or chosen with "Set Buffer Language"
+    language: Option<&'static LanguageDef>,
+    /// Tab width used when neither EditorConfig nor tab indentation sets one
+    default_tab_width: usize,
 }
 
 impl Buffer {
@@ -23,17 +37,45 @@ impl Buffer {
             text_buffer: TextBuffer::new(),
             editor_state: EditorState::new(width, height),
             undo_manager: UndoManager::new(1000),
+            editorconfig: EditorConfig::default(),
+            indent_override: None,
+            language: None,
+            default_tab_width: 4,
         }
     }
 
-    /// Create a buffer from a file
-    pub fn from_file(id: BufferId, path: PathBuf, width: u16, height: u16) -> Result<Self> {
-        Ok(Self {
+    /// Create a buffer from a file, applying its EditorConfig settings
+    /// (the charset decides how a file without a BOM is read). Large-file
+    /// mode is decided first, so nothing scans the whole text of a big file.
+    pub fn from_file(
+        id: BufferId,
+        path: PathBuf,
+        width: u16,
+        height: u16,
+        editorconfig: EditorConfig,
+        large_file_config: &LargeFileConfig,
+    ) -> Result<Self> {
+        let mut text_buffer = match editorconfig.charset {
+            Some(charset) => TextBuffer::from_file_preferring(path, charset)?,
+            None => TextBuffer::from_file(path)?,
+        };
+        let large_file = text_buffer.exceeds_limits(
+            large_file_config.size_threshold_bytes(),
+            large_file_config.line_length_threshold,
+        );
+        text_buffer.set_large_file(large_file);
+        let mut buffer = Self {
             id,
-            text_buffer: TextBuffer::from_file(path)?,
+            text_buffer,
             editor_state: EditorState::new(width, height),
             undo_manager: UndoManager::new(1000),
-        })
+            editorconfig: EditorConfig::default(),
+            indent_override: None,
+            language: None,
+            default_tab_width: 4,
+        };
+        buffer.set_editorconfig(editorconfig);
+        Ok(buffer)
     }
 
     /// Get the buffer ID
@@ -41,6 +83,26 @@ impl Buffer {
         self.id
     }
 
+    /// Language of the buffer's text, if known
+    pub fn language(&self) -> Option<&'static LanguageDef> {
+        self.language
+    }
+
+    /// Set the language of the buffer's text
+    pub fn set_language(&mut self, language: Option<&'static LanguageDef>) {
+        self.language = language;
+    }
+
+    /// Grammar used to highlight the buffer
+    pub fn syntax_language(&self) -> Option<SupportedLanguage> {
+        self.language.and_then(|lang| lang.syntax)
+    }
+
+    /// Language server language of the buffer
+    pub fn lsp_language(&self) -> Option<LspLanguage> {
+        self.language.and_then(|lang| lang.lsp)
+    }
+
     /// Get the text buffer
     pub fn text_buffer(&self) -> &TextBuffer {
         &self.text_buffer
@@ -71,6 +133,112 @@ impl Buffer {
         &mut self.undo_manager
     }
 
+    /// Use new EditorConfig settings, e.g. after Save As moved the file
+    pub fn set_editorconfig(&mut self, editorconfig: EditorConfig) {
+        self.editor_state.ruler_column = editorconfig.max_line_length;
+        self.editorconfig = editorconfig;
+        self.update_indentation();
+    }
+
+    /// Indentation used by Tab, auto-indent and dedent
+    pub fn indentation(&self) -> Indentation {
+        self.editor_state.indentation
+    }
+
+    /// Override the indentation, or go back to the automatic one with None
+    pub fn set_indent_override(&mut self, indentation: Option<Indentation>) {
+        self.indent_override = indentation;
+        self.update_indentation();
+    }
+
+    /// Set the tab width used when the file's settings don't give one
+    pub fn set_default_tab_width(&mut self, width: usize) {
+        self.default_tab_width = width.max(1);
+        self.update_indentation();
+    }
+
+    /// Text inserted for one level of indentation
+    pub fn indent_unit(&self) -> String {
+        self.indentation().unit()
+    }
+
+    /// Work out the indentation from, in order of priority: the override,
+    /// EditorConfig, the style detected in the file, and the default. Tabs
+    /// are shown EditorConfig's `tab_width` wide, else one indent level
+    /// wide when indenting with tabs, else the default tab width.
+    fn update_indentation(&mut self) {
+        let config = &self.editorconfig;
+        let detected = self
+            .text_buffer
+            .detected_indentation(config.tab_width.unwrap_or(self.default_tab_width));
+        self.editor_state.indentation = self.indent_override.unwrap_or_else(|| {
+            let use_tabs = match config.indent_style {
+                Some(style) => style == IndentStyle::Tab,
+                None => detected.is_some_and(|d| d.use_tabs),
+            };
+            let width = match config.indent_size.or(config.tab_width) {
+                Some(_) => config.indent_width(),
+                None => detected.map_or(Indentation::default().width, |d| d.width),
+            };
+            if use_tabs {
+                Indentation::tabs(width)
+            } else {
+                Indentation::spaces(width)
+            }
+        });
+        let indentation = self.editor_state.indentation;
+        self.editor_state.tab_width = config
+            .tab_width
+            .or(indentation.use_tabs.then_some(indentation.width))
+            .unwrap_or(self.default_tab_width)
+            .max(1);
+    }
+
+    /// Apply the EditorConfig save-time transforms (trailing whitespace, line
+    /// endings, final newline) as one undoable edit. Only the lines that
+    /// change are edited, so the cursors and selection stay where they are.
+    /// Skipped in large-file mode.
+    pub fn apply_save_transforms(&mut self) -> Result<()> {
+        if self.text_buffer.is_large_file() {
+            return Ok(());
+        }
+        let text = self.text_buffer.to_string();
+        let Some(transformed) = self.editorconfig.transform_for_save(&text) else {
+            return Ok(());
+        };
+
+        let mut changes = Vec::new();
+        for (start, new_text) in line_edits(self.text_buffer.rope(), &Rope::from_str(&transformed)) {
+            let end = self.line_start(start.line + 1);
+            let old_text = self.text_buffer.delete_range(start, end)?;
+            self.text_buffer.insert(start, &new_text)?;
+            changes.push(Change::Delete { pos: start, text: old_text });
+            changes.push(Change::Insert { pos: start, text: new_text });
+        }
+        self.undo_manager.record(Change::Compound(changes));
+        self.clamp_cursors();
+        Ok(())
+    }
+
+    /// Move cursors and the selection that are past the end of their line
+    /// (or of the buffer) back onto the text
+    fn clamp_cursors(&mut self) {
+        let text_buffer = &self.text_buffer;
+        let clamp = |pos: Position| {
+            let line = pos.line.min(text_buffer.len_lines().saturating_sub(1));
+            Position::new(line, pos.column.min(text_buffer.line_len(line)))
+        };
+        let state = &mut self.editor_state;
+        state.cursor.set_position(clamp(state.cursor.position()));
+        for cursor in &mut state.secondary_cursors {
+            cursor.set_position(clamp(cursor.position()));
+        }
+        if let Some(selection) = &mut state.selection {
+            selection.anchor = clamp(selection.anchor);
+            selection.head = clamp(selection.head);
+        }
+    }
+
     /// Record a change to the undo manager
     pub fn record_change(&mut self, change: Change) {
         self.undo_manager.record(change);
@@ -81,11 +249,11 @@ impl Buffer {
         match change {
             Change::Insert { pos, text } => {
                 self.text_buffer.insert(*pos, text)?;
-                let char_idx = self.text_buffer.pos_to_char(*pos)? + text.len();
+                let char_idx = self.text_buffer.pos_to_char(*pos)? + text.chars().count();
                 self.editor_state.cursor.set_position(self.text_buffer.char_to_pos(char_idx));
             }
             Change::Delete { pos, text } => {
-                let end_idx = self.text_buffer.pos_to_char(*pos)? + text.len();
+                let end_idx = self.text_buffer.pos_to_char(*pos)? + text.chars().count();
                 let end_pos = self.text_buffer.char_to_pos(end_idx);
                 self.text_buffer.delete_range(*pos, end_pos)?;
                 self.editor_state.cursor.set_position(*pos);
@@ -100,6 +268,181 @@ impl Buffer {
         Ok(())
     }
 
+    /// Start grouping edits into one undo step, saving where the cursors are
+    pub fn begin_undo_transaction(&mut self) {
+        self.undo_manager.begin_transaction(self.editor_state.cursor_snapshot());
+    }
+
+    /// Finish the undo step started by `begin_undo_transaction`
+    pub fn end_undo_transaction(&mut self) {
+        self.undo_manager.end_transaction(self.editor_state.cursor_snapshot());
+    }
+
+    /// Undo the last step and put the cursors back where they were before it.
+    /// Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> Result<bool> {
+        let Some(change) = self.undo_manager.undo() else {
+            return Ok(false);
+        };
+        let applied = self.apply_change(&change);
+        self.undo_manager.finish_undo_redo();
+        applied?;
+
+        if let Some(cursors) = self.undo_manager.cursors_before_redo().cloned() {
+            self.restore_cursors(&cursors);
+        }
+        Ok(true)
+    }
+
+    /// Redo the next step and put the cursors where they were after it.
+    /// Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> Result<bool> {
+        let Some(change) = self.undo_manager.redo() else {
+            return Ok(false);
+        };
+        let applied = self.apply_change(&change);
+        self.undo_manager.finish_undo_redo();
+        applied?;
+
+        if let Some(cursors) = self.undo_manager.cursors_at_current().cloned() {
+            self.restore_cursors(&cursors);
+        }
+        Ok(true)
+    }
+
+    /// Move the text to another state of the undo tree (see `UndoManager::jump_to`)
+    pub fn jump_to_undo_state(&mut self, node: usize) -> Result<()> {
+        if let Some(change) = self.undo_manager.jump_to(node) {
+            self.editor_state.clear_secondary_cursors();
+            self.editor_state.clear_selection();
+            self.apply_change(&change)?;
+            if let Some(cursors) = self.undo_manager.cursors_at_current().cloned() {
+                self.restore_cursors(&cursors);
+            }
+        }
+        Ok(())
+    }
+
+    /// Restore saved cursors, clamped to the text in case it doesn't match
+    fn restore_cursors(&mut self, cursors: &CursorSnapshot) {
+        let clamp = |pos: Position| {
+            let line = pos.line.min(self.text_buffer.len_lines().saturating_sub(1));
+            Position::new(line, pos.column.min(self.text_buffer.line_len(line)))
+        };
+        let cursors = CursorSnapshot {
+            cursor: clamp(cursors.cursor),
+            secondary_cursors: cursors.secondary_cursors.iter().map(|&pos| clamp(pos)).collect(),
+            selection: cursors.selection.map(|(anchor, head)| (clamp(anchor), clamp(head))),
+        };
+        self.editor_state.restore_cursor_snapshot(&cursors);
+        self.editor_state.ensure_cursor_visible();
+    }
+
+    /// Replace the buffer content with what is now on disk.
+    /// The reload is recorded as one undoable change, and the cursor and
+    /// scroll position are kept (clamped to the new content).
+    pub fn reload_from_disk(&mut self, content: &str) {
+        let old_text = self.text_buffer.to_string();
+        self.text_buffer.reload_from(content);
+        self.after_full_replace(old_text, content);
+    }
+
+    /// Replace the whole buffer content as an ordinary (unsaved) edit,
+    /// e.g. when recovering from a swap file
+    pub fn replace_text(&mut self, content: &str) -> Result<()> {
+        let old_text = self.text_buffer.to_string();
+        self.text_buffer.clear()?;
+        self.text_buffer.insert(Position::zero(), content)?;
+        self.after_full_replace(old_text, content);
+        Ok(())
+    }
+
+    /// Re-read the file from disk, decoding it with `encoding`.
+    /// Recorded as one undoable change like a reload.
+    pub fn reopen_with_encoding(&mut self, encoding: FileEncoding) -> Result<()> {
+        let old_text = self.text_buffer.to_string();
+        self.text_buffer.reload_with_encoding(encoding)?;
+        self.update_indentation();
+        let new_text = self.text_buffer.to_string();
+        self.after_full_replace(old_text, &new_text);
+        Ok(())
+    }
+
+    /// Replace a range of whole lines (0-based, end exclusive) as one undoable
+    /// edit, e.g. when restoring a hunk from the local history
+    pub fn replace_lines(&mut self, lines: Range<usize>, text: &str) -> Result<()> {
+        let start = self.line_start(lines.start);
+        let end = self.line_start(lines.end);
+        let old_text = self.text_buffer.delete_range(start, end)?;
+        self.text_buffer.insert(start, text)?;
+
+        self.undo_manager.record(Change::Compound(vec![
+            Change::Delete { pos: start, text: old_text },
+            Change::Insert { pos: start, text: text.to_string() },
+        ]));
+        self.editor_state.cursor.set_position(start);
+        self.editor_state.clear_secondary_cursors();
+        self.editor_state.clear_selection();
+        self.editor_state.ensure_cursor_visible();
+        Ok(())
+    }
+
+    /// Position of the start of a line, or the end of the buffer past the last line
+    fn line_start(&self, line: usize) -> Position {
+        if line < self.text_buffer.len_lines() {
+            Position::new(line, 0)
+        } else {
+            self.text_buffer.char_to_pos(self.text_buffer.len_chars())
+        }
+    }
+
+    /// Append text read from the end of a followed file. Not undoable, as
+    /// the buffer is read-only while following. While the last line is in
+    /// view the view stays at the end, like `tail -f`; once scrolled up
+    /// (with the cursor or the mouse wheel) it is left alone.
+    pub fn append_from_disk(&mut self, text: &str) {
+        let last_line = self.text_buffer.len_lines().saturating_sub(1);
+        let at_end = self.editor_state.viewport.is_line_visible(last_line);
+        self.text_buffer.append_from_disk(text);
+        if at_end {
+            self.move_to_end();
+        }
+    }
+
+    /// Replace the content of a followed file that was truncated or rotated.
+    /// The undo history is dropped rather than keeping a copy of the old log.
+    pub fn replace_from_disk(&mut self, content: &str) {
+        self.text_buffer.reload_from(content);
+        self.undo_manager.clear();
+        self.move_to_end();
+    }
+
+    /// Put the cursor at the end of the buffer and scroll to it
+    pub fn move_to_end(&mut self) {
+        let end = self.text_buffer.char_to_pos(self.text_buffer.len_chars());
+        self.editor_state.cursor.set_position(end);
+        self.editor_state.clear_secondary_cursors();
+        self.editor_state.clear_selection();
+        self.editor_state.ensure_cursor_visible();
+    }
+
+    /// Record a whole-buffer replacement for undo and keep the cursor in range
+    fn after_full_replace(&mut self, old_text: String, new_text: &str) {
+        self.undo_manager.record(Change::Compound(vec![
+            Change::Delete { pos: Position::zero(), text: old_text },
+            Change::Insert { pos: Position::zero(), text: new_text.to_string() },
+        ]));
+
+        let last_line = self.text_buffer.len_lines().saturating_sub(1);
+        let line = self.editor_state.cursor.line.min(last_line);
+        let column = self.editor_state.cursor.column.min(self.text_buffer.line_len(line));
+        self.editor_state.cursor.set_position(Position::new(line, column));
+        self.editor_state.clear_secondary_cursors();
+        self.editor_state.clear_selection();
+        self.editor_state.viewport.top_line = self.editor_state.viewport.top_line.min(last_line);
+        self.editor_state.ensure_cursor_visible();
+    }
+
     /// Get the file path
     pub fn file_path(&self) -> Option<&PathBuf> {
         self.text_buffer.file_path()
@@ -140,3 +483,93 @@ impl Buffer {
         (&mut self.text_buffer, &mut self.editor_state, &mut self.undo_manager)
     }
 }
+
+/// Edits turning `old` into `new` line by line, last line first so that
+/// applying them in order leaves the positions of the rest valid. Each edit
+/// replaces line `start.line` from `start` to its end, line ending included;
+/// lines of `new` past the last line of `old` are added to that line's edit.
+fn line_edits(old: &Rope, new: &Rope) -> Vec<(Position, String)> {
+    let last = old.len_lines() - 1;
+    let mut edits = Vec::new();
+    for line in (0..=last).rev() {
+        let old_line = old.line(line);
+        let new_line: String = if line == last {
+            (line..new.len_lines()).map(|i| new.line(i).to_string()).collect()
+        } else if line < new.len_lines() {
+            new.line(line).to_string()
+        } else {
+            String::new()
+        };
+        if old_line == new_line.as_str() {
+            continue;
+        }
+        let prefix = old_line
+            .chars()
+            .zip(new_line.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let new_text: String = new_line.chars().skip(prefix).collect();
+        edits.push((Position::new(line, prefix), new_text));
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &str, new: &str) -> (String, Vec<(Position, String)>) {
+        let edits = line_edits(&Rope::from_str(old), &Rope::from_str(new));
+        let mut text = Rope::from_str(old);
+        for (start, new_text) in &edits {
+            let start_idx = text.line_to_char(start.line) + start.column;
+            let end_idx = if start.line + 1 < text.len_lines() {
+                text.line_to_char(start.line + 1)
+            } else {
+                text.len_chars()
+            };
+            text.remove(start_idx..end_idx);
+            text.insert(start_idx, new_text);
+        }
+        (text.to_string(), edits)
+    }
+
+    #[test]
+    fn test_line_edits() {
+        // Only the lines with trailing whitespace are touched
+        let (text, edits) = apply("a  \nb\nc\t\n", "a\nb\nc\n");
+        assert_eq!(text, "a\nb\nc\n");
+        assert_eq!(edits, vec![(Position::new(2, 1), "\n".to_string()), (Position::new(0, 1), "\n".to_string())]);
+
+        // A final newline is added to the last line
+        let (text, edits) = apply("a\nb", "a\nb\n");
+        assert_eq!(text, "a\nb\n");
+        assert_eq!(edits, vec![(Position::new(1, 1), "\n".to_string())]);
+
+        // Trailing blank lines are dropped
+        let (text, _) = apply("x\n\n\n", "x");
+        assert_eq!(text, "x");
+
+        // Line endings are converted
+        let (text, _) = apply("a\r\nb\r\n", "a\nb\n");
+        assert_eq!(text, "a\nb\n");
+    }
+
+    #[test]
+    fn test_append_from_disk_follows_only_at_end() {
+        let mut buffer = Buffer::new(BufferId(0), 80, 10);
+        buffer.text_buffer_mut().append_from_disk(&"line\n".repeat(20));
+        buffer.move_to_end();
+        buffer.text_buffer_mut().set_read_only(true);
+
+        buffer.append_from_disk("new\n");
+        assert_eq!(buffer.editor_state().cursor.line, 21);
+        assert!(buffer.editor_state().viewport.is_line_visible(21));
+
+        // Scrolling up with the mouse wheel leaves the cursor at the end
+        buffer.editor_state_mut().viewport.scroll_up(5);
+        let top_line = buffer.editor_state().viewport.top_line;
+        buffer.append_from_disk("more\n");
+        assert_eq!(buffer.editor_state().viewport.top_line, top_line);
+    }
+}
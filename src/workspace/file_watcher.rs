@@ -0,0 +1,108 @@
+use crate::buffer::atomic_write::resolve_save_target;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+/// Watches the files of open buffers for changes made outside the editor.
+///
+/// Parent directories are watched rather than the files themselves, because
+/// tools that save atomically (including scame) replace the file's inode and
+/// a watch on the old inode would silently stop firing.
+pub struct FileWatcher {
+    watcher: Option<RecommendedWatcher>,
+    receiver: Receiver<notify::Result<Event>>,
+    watched_dirs: HashSet<PathBuf>,
+    watched_files: HashSet<PathBuf>,
+    /// Buffer paths the watch list was last built from
+    synced_paths: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Create a new file watcher. If the platform watcher cannot be created,
+    /// the watcher stays inert and only focus-gained checks will detect changes.
+    pub fn new() -> Self {
+        let (tx, receiver) = channel();
+        let watcher = notify::recommended_watcher(tx).ok();
+
+        Self {
+            watcher,
+            receiver,
+            watched_dirs: HashSet::new(),
+            watched_files: HashSet::new(),
+            synced_paths: Vec::new(),
+        }
+    }
+
+    /// Normalize a buffer path to the key used for matching watcher events
+    pub fn watch_key(path: &Path) -> PathBuf {
+        let target = resolve_save_target(path);
+        let parent = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let parent = parent.canonicalize().unwrap_or(parent);
+        match target.file_name() {
+            Some(name) => parent.join(name),
+            None => target,
+        }
+    }
+
+    /// Update the set of watched files to exactly `paths`. Nothing is
+    /// resolved unless the paths differ from the last call.
+    pub fn sync<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) {
+        let paths: Vec<PathBuf> = paths.into_iter().map(Path::to_path_buf).collect();
+        if paths == self.synced_paths {
+            return;
+        }
+        let files: HashSet<PathBuf> = paths.iter().map(|path| Self::watch_key(path)).collect();
+        self.synced_paths = paths;
+        if files == self.watched_files {
+            return;
+        }
+
+        let dirs: HashSet<PathBuf> = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect();
+
+        if let Some(watcher) = &mut self.watcher {
+            for dir in self.watched_dirs.difference(&dirs) {
+                let _ = watcher.unwatch(dir);
+            }
+            for dir in dirs.difference(&self.watched_dirs) {
+                let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+            }
+        }
+
+        self.watched_dirs = dirs;
+        self.watched_files = files;
+    }
+
+    /// Drain pending events and return the watched files that changed
+    pub fn poll_changes(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+
+        while let Ok(result) = self.receiver.try_recv() {
+            let Ok(event) = result else {
+                continue;
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if self.watched_files.contains(&path) && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
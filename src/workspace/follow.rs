@@ -0,0 +1,124 @@
+use crate::buffer::FileEncoding;
+use anyhow::Result;
+use encoding_rs::Decoder;
+use std::fs::{File, Metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+/// What changed in a followed file since the last poll
+#[derive(Debug, PartialEq, Eq)]
+pub enum FollowUpdate {
+    /// Text appended to the end of the file
+    Appended(String),
+    /// The file was truncated or replaced (log rotation): its whole new content
+    Reset(String),
+}
+
+/// Follows a growing file like `tail -f`, reading only the bytes appended
+/// since the last poll
+pub struct FileFollower {
+    path: PathBuf,
+    encoding: FileEncoding,
+    decoder: Decoder,
+    /// Number of bytes of the file read so far
+    offset: u64,
+    /// Device and inode of the file being read, to notice rotation
+    file_id: Option<(u64, u64)>,
+}
+
+impl FileFollower {
+    /// Start following a file. Returns the follower and the file's current
+    /// content, which the buffer should hold before the first poll.
+    pub fn start(path: PathBuf, encoding: FileEncoding) -> Result<(Self, String)> {
+        let metadata = std::fs::metadata(&path)?;
+        let bytes = std::fs::read(&path)?;
+        let follower = Self {
+            decoder: encoding.new_decoder(),
+            offset: bytes.len() as u64,
+            file_id: file_id(&metadata),
+            path,
+            encoding,
+        };
+        Ok((follower, encoding.decode(&bytes)))
+    }
+
+    /// Check the file for new content
+    pub fn poll(&mut self) -> Result<Option<FollowUpdate>> {
+        let metadata = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Rotated away and not recreated yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if file_id(&metadata) != self.file_id || metadata.len() < self.offset {
+            let bytes = std::fs::read(&self.path)?;
+            self.offset = bytes.len() as u64;
+            self.file_id = file_id(&metadata);
+            self.decoder = self.encoding.new_decoder();
+            return Ok(Some(FollowUpdate::Reset(self.encoding.decode(&bytes))));
+        }
+        if metadata.len() == self.offset {
+            return Ok(None);
+        }
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        self.offset += bytes.len() as u64;
+
+        let mut text = String::with_capacity(
+            self.decoder
+                .max_utf8_buffer_length(bytes.len())
+                .unwrap_or(bytes.len() * 3),
+        );
+        let _ = self.decoder.decode_to_string(&bytes, &mut text, false);
+        Ok((!text.is_empty()).then_some(FollowUpdate::Appended(text)))
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_append_truncate_and_rotate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "one\n").unwrap();
+
+        let (mut follower, content) = FileFollower::start(path.clone(), FileEncoding::UTF8).unwrap();
+        assert_eq!(content, "one\n");
+        assert_eq!(follower.poll().unwrap(), None);
+
+        // A multi-byte character split across two writes comes out whole
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"two \xC3").unwrap();
+        assert_eq!(follower.poll().unwrap(), Some(FollowUpdate::Appended("two ".to_string())));
+        file.write_all(b"\xA9\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), Some(FollowUpdate::Appended("é\n".to_string())));
+
+        // Truncation
+        std::fs::write(&path, "x\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), Some(FollowUpdate::Reset("x\n".to_string())));
+
+        // Rotation: the old file is moved away and a new one created
+        std::fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        assert_eq!(follower.poll().unwrap(), None);
+        std::fs::write(&path, "fresh log\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), Some(FollowUpdate::Reset("fresh log\n".to_string())));
+    }
+}
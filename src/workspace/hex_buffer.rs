@@ -0,0 +1,171 @@
+use crate::buffer::ByteBuffer;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// A binary file opened in the hex editor: the bytes plus cursor and scroll state
+pub struct HexBuffer {
+    data: ByteBuffer,
+    /// Byte offset of the cursor
+    cursor: usize,
+    /// Whether the next hex digit typed goes into the low nibble of the cursor byte
+    low_nibble: bool,
+    /// Whether typing edits the ASCII column instead of the hex digits
+    ascii_column: bool,
+    /// First row shown on screen
+    top_row: usize,
+}
+
+impl HexBuffer {
+    /// Bytes shown per row
+    pub const BYTES_PER_ROW: usize = 16;
+
+    /// Open a file in the hex editor
+    pub fn from_file(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            data: ByteBuffer::from_file(path)?,
+            cursor: 0,
+            low_nibble: false,
+            ascii_column: false,
+            top_row: 0,
+        })
+    }
+
+    /// Get the bytes
+    pub fn data(&self) -> &ByteBuffer {
+        &self.data
+    }
+
+    /// Get the bytes mutably
+    pub fn data_mut(&mut self) -> &mut ByteBuffer {
+        &mut self.data
+    }
+
+    /// Get the file path
+    pub fn file_path(&self) -> &PathBuf {
+        self.data.file_path()
+    }
+
+    /// Get the display name for this buffer
+    pub fn display_name(&self) -> String {
+        self.file_path()
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unnamed")
+            .to_string()
+    }
+
+    /// Check if the bytes were edited since the last save
+    pub fn is_modified(&self) -> bool {
+        self.data.is_modified()
+    }
+
+    /// Byte offset of the cursor
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Whether the cursor is on the low (second) hex digit of its byte
+    pub fn low_nibble(&self) -> bool {
+        self.low_nibble
+    }
+
+    /// Whether typing edits the ASCII column
+    pub fn ascii_column(&self) -> bool {
+        self.ascii_column
+    }
+
+    /// Switch typing between the hex digits and the ASCII column
+    pub fn toggle_column(&mut self) {
+        self.ascii_column = !self.ascii_column;
+        self.low_nibble = false;
+    }
+
+    /// First row shown on screen
+    pub fn top_row(&self) -> usize {
+        self.top_row
+    }
+
+    /// Move the cursor by a number of bytes, clamped to the file
+    pub fn move_cursor(&mut self, delta: isize) {
+        let last = self.data.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+        self.low_nibble = false;
+    }
+
+    /// Put the cursor on a byte offset, clamped to the file
+    pub fn set_cursor(&mut self, offset: usize) {
+        self.cursor = offset.min(self.data.len().saturating_sub(1));
+        self.low_nibble = false;
+    }
+
+    /// Overwrite half of the cursor byte with a hex digit, then move on:
+    /// to the low nibble after the high one, to the next byte after the low one
+    pub fn type_hex_digit(&mut self, digit: u8) -> Result<()> {
+        let Some(byte) = self.data.get(self.cursor) else {
+            return Ok(());
+        };
+        let value = if self.low_nibble {
+            (byte & 0xF0) | digit
+        } else {
+            (byte & 0x0F) | (digit << 4)
+        };
+        self.data.set(self.cursor, value)?;
+        if self.low_nibble {
+            self.move_cursor(1);
+        } else {
+            self.low_nibble = true;
+        }
+        Ok(())
+    }
+
+    /// Overwrite the cursor byte and move to the next one
+    pub fn type_byte(&mut self, value: u8) -> Result<()> {
+        if self.cursor < self.data.len() {
+            self.data.set(self.cursor, value)?;
+            self.move_cursor(1);
+        }
+        Ok(())
+    }
+
+    /// Scroll so the cursor row is within `visible_rows` rows of the top
+    pub fn ensure_cursor_visible(&mut self, visible_rows: usize) {
+        let row = self.cursor / Self::BYTES_PER_ROW;
+        let visible_rows = visible_rows.max(1);
+        if row < self.top_row {
+            self.top_row = row;
+        } else if row >= self.top_row + visible_rows {
+            self.top_row = row + 1 - visible_rows;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nibble_editing_and_scrolling() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, [0u8; 40]).unwrap();
+
+        let mut hex = HexBuffer::from_file(path).unwrap();
+        hex.type_hex_digit(0xa).unwrap();
+        assert!(hex.low_nibble());
+        hex.type_hex_digit(0xb).unwrap();
+        assert_eq!(hex.data().get(0), Some(0xab));
+        assert_eq!(hex.cursor(), 1);
+
+        hex.type_byte(b'Z').unwrap();
+        assert_eq!(hex.data().get(1), Some(b'Z'));
+
+        // Moving is clamped to the last byte
+        hex.move_cursor(100);
+        assert_eq!(hex.cursor(), 39);
+        hex.ensure_cursor_visible(2);
+        assert_eq!(hex.top_row(), 1);
+        hex.move_cursor(-100);
+        hex.ensure_cursor_visible(2);
+        assert_eq!(hex.top_row(), 0);
+    }
+}
@@ -1,8 +1,13 @@
 use super::buffer::{Buffer, BufferId};
-use crate::backup::BackupManager;
+use super::hex_buffer::HexBuffer;
+use crate::backup::{BackupManager, LocalHistory, UndoStore};
+use crate::buffer::byte_buffer::is_binary_file;
+use crate::config::{LanguagesConfig, LargeFileConfig, UndoConfig};
+use crate::editorconfig::EditorConfig;
+use crate::language::LanguageRegistry;
 use anyhow::Result;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Result of opening a file
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -28,11 +33,17 @@ impl OpenFileResult {
 /// Manages multiple buffers in the workspace
 pub struct Workspace {
     buffers: HashMap<BufferId, Buffer>,
+    hex_buffers: HashMap<BufferId, HexBuffer>, // Binary files, sharing ids and tabs with text buffers
     tab_order: Vec<BufferId>, // Tracks insertion order for tab display
     active_buffer: Option<BufferId>,
     next_id: usize,
     buffer_history: Vec<BufferId>, // For navigation (jump back)
     viewport_size: (u16, u16), // width, height
+    large_file_config: LargeFileConfig,
+    project_root: Option<PathBuf>, // Where the search for .editorconfig files stops
+    undo_store: UndoStore, // Undo histories kept across restarts
+    languages: LanguageRegistry, // Decides the language of opened files
+    tab_width: usize, // Default width of tab stops
 }
 
 impl Workspace {
@@ -40,21 +51,72 @@ impl Workspace {
     pub fn new(viewport_width: u16, viewport_height: u16) -> Self {
         Self {
             buffers: HashMap::new(),
+            hex_buffers: HashMap::new(),
             tab_order: Vec::new(),
             active_buffer: None,
             next_id: 0,
             buffer_history: Vec::new(),
             viewport_size: (viewport_width, viewport_height),
+            large_file_config: LargeFileConfig::default(),
+            project_root: None,
+            undo_store: UndoStore::with_dir(None, UndoConfig::default()),
+            languages: LanguageRegistry::default(),
+            tab_width: 4,
         }
     }
 
+    /// Set the thresholds above which files open in large-file mode
+    pub fn set_large_file_config(&mut self, config: LargeFileConfig) {
+        self.large_file_config = config;
+    }
+
+    /// Set how undo histories are limited and kept across restarts
+    pub fn set_undo_config(&mut self, config: UndoConfig) {
+        self.undo_store = UndoStore::new(config);
+    }
+
+    /// Set the user's file-to-language mappings
+    pub fn set_languages_config(&mut self, config: &LanguagesConfig) {
+        self.languages = LanguageRegistry::new(config);
+    }
+
+    /// Set the default width of tab stops, for buffers whose settings don't
+    /// give one
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width;
+        for buffer in self.buffers.values_mut() {
+            buffer.set_default_tab_width(width);
+        }
+    }
+
+    /// Detect the language of a buffer again, e.g. after it was saved
+    /// under a new name
+    pub fn detect_language(&mut self, id: BufferId) {
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            let language = self.languages.detect(buffer.file_path().map(|p| p.as_path()), buffer.text_buffer());
+            buffer.set_language(language);
+        }
+    }
+
+    /// Set the project directory, the last one searched for `.editorconfig` files
+    pub fn set_project_root(&mut self, root: PathBuf) {
+        self.project_root = Some(root);
+    }
+
+    /// Resolve the EditorConfig settings for a file path
+    pub fn editorconfig_for(&self, path: &Path) -> EditorConfig {
+        EditorConfig::resolve(path, self.project_root.as_deref())
+    }
+
     /// Create a new empty buffer and make it active
     pub fn new_buffer(&mut self) -> BufferId {
         let id = BufferId(self.next_id);
         self.next_id += 1;
 
         let (width, height) = self.viewport_size;
-        let buffer = Buffer::new(id, width, height);
+        let mut buffer = Buffer::new(id, width, height);
+        buffer.undo_manager_mut().set_max_history(self.undo_store.max_changes());
+        buffer.set_default_tab_width(self.tab_width);
 
         self.buffers.insert(id, buffer);
         self.tab_order.push(id);
@@ -63,13 +125,20 @@ impl Workspace {
         id
     }
 
-    /// Open a file in a new buffer, or switch to existing buffer if already open
+    /// Open a file in a new buffer, or switch to existing buffer if already open.
+    /// Binary files open in a hex buffer.
     pub fn open_file(&mut self, path: PathBuf) -> Result<OpenFileResult> {
         // Check if file is already open
         let existing_id = self.buffers
             .iter()
             .find(|(_, buffer)| buffer.file_path() == Some(&path))
-            .map(|(id, _)| *id);
+            .map(|(id, _)| *id)
+            .or_else(|| {
+                self.hex_buffers
+                    .iter()
+                    .find(|(_, hex)| *hex.file_path() == path)
+                    .map(|(id, _)| *id)
+            });
 
         if let Some(id) = existing_id {
             self.set_active_buffer(id);
@@ -80,8 +149,33 @@ impl Workspace {
         let id = BufferId(self.next_id);
         self.next_id += 1;
 
+        if is_binary_file(&path) {
+            let hex = HexBuffer::from_file(path)?;
+            self.hex_buffers.insert(id, hex);
+            self.tab_order.push(id);
+            self.set_active_buffer(id);
+            return Ok(OpenFileResult::NewBuffer(id));
+        }
+
         let (width, height) = self.viewport_size;
-        let buffer = Buffer::from_file(id, path, width, height)?;
+        let editorconfig = self.editorconfig_for(&path);
+        let mut buffer = Buffer::from_file(id, path, width, height, editorconfig, &self.large_file_config)?;
+        buffer.set_default_tab_width(self.tab_width);
+        let large_file = buffer.text_buffer().is_large_file();
+        let language = self.languages.detect(buffer.file_path().map(|p| p.as_path()), buffer.text_buffer());
+        buffer.set_language(language);
+
+        // Pick up the undo history from the last time the file was edited
+        let saved_history = if large_file {
+            None
+        } else {
+            let content = buffer.text_buffer().to_string();
+            buffer.file_path().and_then(|path| self.undo_store.load(path, &content))
+        };
+        match saved_history {
+            Some(history) => *buffer.undo_manager_mut() = history,
+            None => buffer.undo_manager_mut().set_max_history(self.undo_store.max_changes()),
+        }
 
         self.buffers.insert(id, buffer);
         self.tab_order.push(id);
@@ -110,14 +204,43 @@ impl Workspace {
         self.buffers.get(&id)
     }
 
+    /// Buffer showing a file, if it is open. Paths are compared once
+    /// canonicalized, so a relative and an absolute path to the file match.
+    pub fn find_buffer(&self, path: &Path) -> Option<BufferId> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.buffers
+            .iter()
+            .find(|(_, buffer)| {
+                buffer.file_path().is_some_and(|file_path| {
+                    file_path == path || file_path.canonicalize().is_ok_and(|file_path| file_path == canonical)
+                })
+            })
+            .map(|(id, _)| *id)
+    }
+
     /// Get a specific buffer mutably
     pub fn get_buffer_mut(&mut self, id: BufferId) -> Option<&mut Buffer> {
         self.buffers.get_mut(&id)
     }
 
+    /// Get a hex buffer
+    pub fn get_hex_buffer(&self, id: BufferId) -> Option<&HexBuffer> {
+        self.hex_buffers.get(&id)
+    }
+
+    /// Get a hex buffer mutably
+    pub fn get_hex_buffer_mut(&mut self, id: BufferId) -> Option<&mut HexBuffer> {
+        self.hex_buffers.get_mut(&id)
+    }
+
+    /// Check if a buffer id belongs to a text or hex buffer
+    fn contains(&self, id: BufferId) -> bool {
+        self.buffers.contains_key(&id) || self.hex_buffers.contains_key(&id)
+    }
+
     /// Set the active buffer
     pub fn set_active_buffer(&mut self, id: BufferId) {
-        if self.buffers.contains_key(&id) {
+        if self.contains(id) {
             // Add current buffer to history before switching
             if let Some(current_id) = self.active_buffer {
                 if current_id != id {
@@ -163,7 +286,7 @@ impl Workspace {
     /// Jump back to previous buffer in history
     pub fn jump_back(&mut self) {
         if let Some(prev_id) = self.buffer_history.pop() {
-            if self.buffers.contains_key(&prev_id) {
+            if self.contains(prev_id) {
                 self.active_buffer = Some(prev_id);
             }
         }
@@ -176,8 +299,15 @@ impl Workspace {
                 anyhow::bail!("Buffer is modified, save or force close");
             }
         }
+        if self.hex_buffers.get(&id).is_some_and(|hex| hex.is_modified()) {
+            anyhow::bail!("Buffer is modified, save or force close");
+        }
 
+        if let Some(buffer) = self.buffers.get(&id) {
+            self.save_undo_history(buffer);
+        }
         self.buffers.remove(&id);
+        self.hex_buffers.remove(&id);
         self.tab_order.retain(|&tid| tid != id);
 
         // If we closed the active buffer, switch to another
@@ -190,7 +320,11 @@ impl Workspace {
 
     /// Close a buffer without checking if modified
     pub fn force_close_buffer(&mut self, id: BufferId) {
+        if let Some(buffer) = self.buffers.get(&id) {
+            self.save_undo_history(buffer);
+        }
         self.buffers.remove(&id);
+        self.hex_buffers.remove(&id);
         self.tab_order.retain(|&tid| tid != id);
 
         if self.active_buffer == Some(id) {
@@ -198,6 +332,28 @@ impl Workspace {
         }
     }
 
+    /// Save the undo history of every open file (when the session is saved)
+    pub fn save_undo_histories(&self) {
+        for buffer in self.buffers.values() {
+            self.save_undo_history(buffer);
+        }
+    }
+
+    /// Save the undo history of a buffer if its text is what is on disk:
+    /// a history ending in unsaved changes could never be restored
+    fn save_undo_history(&self, buffer: &Buffer) {
+        if buffer.is_modified() || buffer.text_buffer().is_large_file() {
+            return;
+        }
+        let Some(path) = buffer.file_path() else {
+            return;
+        };
+        let content = buffer.text_buffer().to_string();
+        if let Err(e) = self.undo_store.save(path, &content, buffer.undo_manager()) {
+            crate::logger::log(&format!("Failed to save undo history of {}: {}", path.display(), e));
+        }
+    }
+
     /// Get all buffer IDs in tab order
     pub fn buffer_ids(&self) -> Vec<BufferId> {
         self.tab_order.clone()
@@ -205,12 +361,13 @@ impl Workspace {
 
     /// Get the number of open buffers
     pub fn buffer_count(&self) -> usize {
-        self.buffers.len()
+        self.buffers.len() + self.hex_buffers.len()
     }
 
     /// Check if any buffer is modified (compares with file on disk)
     pub fn has_modified_buffers(&self) -> bool {
         self.buffers.values().any(|b| b.is_actually_modified())
+            || self.hex_buffers.values().any(|hex| hex.is_modified())
     }
 
     /// Resize all buffers
@@ -227,6 +384,7 @@ impl Workspace {
             .iter()
             .filter(|(_, b)| b.is_actually_modified())
             .map(|(id, _)| *id)
+            .chain(self.hex_buffers.iter().filter(|(_, hex)| hex.is_modified()).map(|(id, _)| *id))
             .collect()
     }
 
@@ -235,6 +393,9 @@ impl Workspace {
         self.tab_order
             .iter()
             .filter_map(|id| {
+                if let Some(hex) = self.hex_buffers.get(id) {
+                    return Some((*id, hex.display_name(), hex.is_modified()));
+                }
                 self.buffers.get(id).map(|buf| {
                     let name = buf.display_name();
                     let modified = buf.is_actually_modified();
@@ -251,16 +412,62 @@ impl Workspace {
         }
     }
 
-    /// Save all modified buffers (compares with file on disk)
-    pub fn save_all_modified_buffers(&mut self, backup_manager: &BackupManager) -> Result<()> {
+    /// Save all modified buffers (compares with file on disk),
+    /// recording each save in the local history
+    pub fn save_all_modified_buffers(
+        &mut self,
+        backup_manager: &BackupManager,
+        history: &LocalHistory,
+        project_root: Option<&Path>,
+    ) -> Result<()> {
         for buffer in self.buffers.values_mut() {
             if buffer.is_actually_modified() {
-                if let Some(path) = buffer.file_path() {
-                    backup_manager.create_backup(path)?;
+                if let Some(path) = buffer.file_path().cloned() {
+                    backup_manager.create_backup(&path)?;
+                    buffer.apply_save_transforms()?;
                     buffer.text_buffer_mut().save()?;
+                    let _ = history.record(project_root, &path, &buffer.text_buffer().to_string());
                 }
             }
         }
+        for hex in self.hex_buffers.values_mut() {
+            if hex.is_modified() {
+                backup_manager.create_backup(hex.file_path())?;
+                hex.data_mut().save()?;
+            }
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+        std::fs::write(dir.path().join("other.rs"), "").unwrap();
+
+        let mut workspace = Workspace::new(80, 24);
+        let id = workspace.open_file(path.clone()).unwrap().buffer_id();
+        assert_eq!(workspace.find_buffer(&path), Some(id));
+
+        // Another spelling of the same file, as a language server may send it
+        let dotted = dir.path().join("./sub/../main.rs");
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        assert_eq!(workspace.find_buffer(&dotted), Some(id));
+
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link.rs");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            assert_eq!(workspace.find_buffer(&link), Some(id));
+        }
+
+        assert_eq!(workspace.find_buffer(&dir.path().join("other.rs")), None);
+        assert_eq!(workspace.find_buffer(&dir.path().join("missing.rs")), None);
+    }
+}
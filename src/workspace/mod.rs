@@ -1,9 +1,15 @@
 pub mod buffer;
 pub mod file_tree;
+pub mod file_watcher;
+pub mod follow;
+pub mod hex_buffer;
 pub mod layout;
 pub mod manager;
 
 pub use buffer::{Buffer, BufferId};
 pub use file_tree::FileTree;
+pub use file_watcher::FileWatcher;
+pub use follow::{FileFollower, FollowUpdate};
+pub use hex_buffer::HexBuffer;
 pub use layout::{LayoutManager, LayoutMode, PaneId, PaneRect};
 pub use manager::{OpenFileResult, Workspace};